@@ -0,0 +1,32 @@
+//! Times the update, count and pixel passes separately on the headless
+//! scenarios. Run with `cargo bench`, optionally followed by a filter on
+//! the benchmark names, e.g. `cargo bench -- drift-1080p-1M/count`.
+
+use std::thread::available_parallelism;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use particles::headless::{Headless, Scenario};
+use particles::scoped_threadpool::Pool;
+
+const WARMUP_FRAMES: u32 = 30;
+
+fn passes(c: &mut Criterion) {
+    let pool = Pool::new(available_parallelism().unwrap().get());
+    for scenario in Scenario::benchmarks() {
+        let mut group = c.benchmark_group(scenario.name);
+        let mut sim = Headless::new(&pool, scenario);
+        for _ in 0..WARMUP_FRAMES {
+            sim.step();
+        }
+
+        group.bench_function("update", |b| b.iter(|| sim.update()));
+        group.bench_function("count", |b| b.iter(|| sim.count()));
+        group.bench_function("count u8", |b| b.iter(|| sim.count_saturating()));
+        group.bench_function("pixels", |b| b.iter(|| sim.colorize()));
+        group.bench_function("frame", |b| b.iter(|| sim.step()));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, passes);
+criterion_main!(benches);
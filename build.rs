@@ -0,0 +1,37 @@
+//! Sets the `nightly` cfg when the compiler accepts unstable features, which
+//! switches `particles::simd` from its scalar stand-in to `std::simd`, and
+//! points the linker to the Spout and Syphon SDKs of the texture sharing
+//! features.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(nightly)");
+    println!("cargo::rerun-if-env-changed=RUSTC");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default();
+    if version.contains("nightly") || version.contains("-dev") {
+        println!("cargo::rustc-cfg=nightly");
+    }
+
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    println!("cargo::rerun-if-env-changed=SPOUT_LIBRARY_DIR");
+    if env::var_os("CARGO_FEATURE_SPOUT").is_some()
+        && target_os == "windows"
+        && let Ok(dir) = env::var("SPOUT_LIBRARY_DIR")
+    {
+        println!("cargo::rustc-link-search=native={dir}");
+    }
+    println!("cargo::rerun-if-env-changed=SYPHON_FRAMEWORK_DIR");
+    if env::var_os("CARGO_FEATURE_SYPHON").is_some() && target_os == "macos" {
+        let dir = env::var("SYPHON_FRAMEWORK_DIR").unwrap_or_else(|_| "/Library/Frameworks".into());
+        println!("cargo::rustc-link-search=framework={dir}");
+        // Syphon.framework is installed with an `@rpath` install name.
+        println!("cargo::rustc-link-arg=-Wl,-rpath,{dir}");
+    }
+}
@@ -0,0 +1,78 @@
+#ifndef PARTICLES_H
+#define PARTICLES_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Longest step taken by `particles_step`, longer ones are clamped to it.
+ */
+#define MAX_STEP_MS 1000.0
+
+/**
+ * Opaque handle owning a threadpool and the particles simulated on it.
+ */
+typedef struct ParticlesSim ParticlesSim;
+
+/**
+ * Creates a simulation of at least `n_particles` particles spawned at the
+ * center of a `width` x `height` area.
+ *
+ * `n_threads == 0` uses one thread per available core.
+ * The returned handle must be released with `particles_destroy`.
+ */
+struct ParticlesSim *particles_create(uint32_t width,
+                                      uint32_t height,
+                                      size_t n_particles,
+                                      size_t n_threads);
+
+/**
+ * Advances the simulation by `dt_ms` milliseconds, clamped to
+ * `0..=MAX_STEP_MS`, with the attractor at (`mouse_x`, `mouse_y`), active
+ * while `mouse_down` is true.
+ *
+ * Returns false without stepping if `dt_ms` is NaN or infinite.
+ *
+ * # Safety
+ * `sim` must be a handle returned by `particles_create`.
+ */
+bool particles_step(struct ParticlesSim *sim,
+                    float dt_ms,
+                    float mouse_x,
+                    float mouse_y,
+                    bool mouse_down);
+
+/**
+ * Returns the number of particles in the simulation.
+ *
+ * # Safety
+ * `sim` must be a handle returned by `particles_create`.
+ */
+size_t particles_len(const struct ParticlesSim *sim);
+
+/**
+ * Writes up to `capacity` positions as interleaved `x, y` pairs into `out`
+ * and returns the number of positions written, `0` if `2 * capacity`
+ * overflows.
+ *
+ * # Safety
+ * `sim` must be a handle returned by `particles_create` and `out` must
+ * point to at least `2 * capacity` writable floats.
+ */
+size_t particles_read_positions(const struct ParticlesSim *sim, float *out, size_t capacity);
+
+/**
+ * Destroys a simulation created by `particles_create`.
+ *
+ * # Safety
+ * `sim` must be a handle returned by `particles_create` or null, and must
+ * not be used afterwards.
+ */
+void particles_destroy(struct ParticlesSim *sim);
+
+#endif  /* PARTICLES_H */
@@ -1,29 +1,314 @@
 use core::{f32, panic};
 use std::collections::VecDeque;
+use std::error::Error;
+use std::io;
 use std::num::NonZeroU32;
+use std::process;
 use std::rc::Rc;
-use std::sync::atomic::{AtomicU16, Ordering};
-use std::time::Instant;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
 
-use crate::scoped_threadpool::Pool;
+use particles::autosave;
+use particles::clipboard;
+use particles::command::{ATTRACTOR_TIMEOUT, BRIGHTNESS_RANGE, SimCommand};
+use particles::demo::{self, Demo};
+use particles::export;
+use particles::exposure::LongExposure;
+use particles::field::{ChargeField, FreezeMask, Heat, HeatField, VelocityField};
+use particles::game::{self, Duel};
+use particles::gesture::{self, Gesture};
+use particles::io_thread::IoThread;
+use particles::keys::{Action, Keybindings};
+use particles::motion::MotionField;
+use particles::obstacle::{Disc, Obstacles, Shape};
+use particles::output::{self, Density, Frame, FrameSink, PixelFormat};
+use particles::overlay;
+use particles::palette::{self, Palette};
+use particles::portal::{Portals, Rect};
+use particles::scoped_threadpool::Pool;
+use particles::timeline::Playback;
+use particles::trail::Trails;
+use particles::units::{self, FrameUnit};
+use particles::view::{self, View};
+use particles::watchdog::{self, Watchdog};
+use particles::watermark::Watermark;
 use softbuffer::{Context, Surface};
 use winit::application::ApplicationHandler;
-use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event::{
+    ElementState, Force, KeyEvent, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent,
+};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{Key, ModifiersState};
 use winit::window::{Window, WindowId};
 
-use crate::particles::Particles;
+use crate::options::Options;
+use particles::particles::{
+    Attractor, Boundary, CountTiles, Lfo, LfoShape, Particles, Pen, RemovalPolicy, Snapshot,
+};
+use particles::render::{
+    self, Accessibility, Background, Blend, BlendMode, BlurField, Count, DirectionHue, Equalizer,
+    Glow, Layers, Metaballs, Tone,
+};
+use particles::scene::{Forces, Parameters, RenderSettings, Scene, SceneWatcher};
+use particles::sdf::{DistanceField, Image, SdfForce, Source};
 use std::thread::available_parallelism;
 
+/// Frametime in milliseconds the auto-scaler aims for where the refresh
+/// rate of the monitor is unknown.
 const TARGET_FRAMETIME: f32 = 20.0;
+/// Refresh durations in milliseconds taken as the target frametime, from
+/// 250 Hz down to 10 Hz. Monitors reporting rates outside are ignored.
+const REFRESH_FRAMETIME_RANGE: (f32, f32) = (4.0, 100.0);
 const N_INITIAL_PARTICELS: usize = 1_000;
+/// Time between the steps while the window is occluded, and the longest
+/// frametime one of them is split into.
+const BACKGROUND_TICK: Duration = Duration::from_millis(100);
+const BACKGROUND_STEP: Duration = Duration::from_millis(33);
+/// Time between checks of the scene file with `--watch`.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+/// Velocity added by the arrow keys to particles around the mouse.
+const GUST_STRENGTH: f32 = 10.0;
+const GUST_RADIUS: f32 = 200.0;
+/// Speed of the vortices drawn with `--gestures`, in pixels per 60 Hz frame,
+/// and of the wind set by a line.
+const VORTEX_SPEED: f32 = 3.0;
+const GESTURE_WIND: f32 = 4.0;
+/// Speed of the keyboard attractor in pixels per second, times
+/// `KEYBOARD_BOOST` while Shift is held.
+const KEYBOARD_SPEED: f32 = 300.0;
+const KEYBOARD_BOOST: f32 = 3.0;
+/// Size in pixels of the cells of the painted velocity field.
+const BRUSH_CELL: f32 = 24.0;
+const BRUSH_RADIUS: f32 = 60.0;
+/// Fraction of the mouse velocity painted into the field.
+const BRUSH_STRENGTH: f32 = 0.3;
+/// Size in pixels of the cells of the freeze mask and radius of its brush.
+const FREEZE_CELL: f32 = 8.0;
+const FREEZE_RADIUS: f32 = 20.0;
+/// Initial radius of the cursor disc and its limits for the mouse wheel.
+const DISC_RADIUS: f32 = 40.0;
+const DISC_RADIUS_RANGE: (f32, f32) = (4.0, 400.0);
+/// Push along a pen lying flat, as a fraction of the attraction.
+const PEN_TILT_BIAS: f32 = 0.8;
+/// Change of the temperature per press of [ or ].
+const TEMPERATURE_STEP: f32 = 0.25;
+/// Size in pixels of the cells charges are summed in.
+const CHARGE_CELL: f32 = 16.0;
+/// Memory the position history of the trails may use.
+const TRAIL_MEMORY: usize = 256 << 20;
+/// Scale of the font and distance to the window edge of the help overlay.
+const HELP_SCALE: u32 = 2;
+const HELP_MARGIN: u32 = 16;
+/// Palette cycles per second when cycling is switched on with P.
+const PALETTE_CYCLE: f32 = 0.1;
 
 struct AppData<'a> {
     window: Rc<Window>,
     surface: Surface<Rc<Window>, Rc<Window>>,
+    /// The surface failed, it is recreated with the next frame.
+    surface_lost: bool,
     size: (u32, u32),
     particles: Particles<'a>,
     count_buffer: Vec<AtomicU16>,
+    /// Used instead of `count_buffer` with `--u8-counts`.
+    count_buffer_u8: Vec<u8>,
+    count_tiles: CountTiles,
+    /// Used by the metaball and glow render modes.
+    blur: BlurField,
+    /// Used for the histogram equalization.
+    equalizer: Equalizer,
+    /// Used instead of `count_buffer` when coloring by tag.
+    count_buffer_tagged: Vec<AtomicU64>,
+    /// Desktop position of the content area with `--desktop-space`, once
+    /// known.
+    position: Option<(i32, i32)>,
+    /// Additional windows, opened with F2.
+    views: Vec<ViewWindow>,
+    /// Second simulation of `Scene::layer`.
+    layer: Option<LayerSim<'a>>,
+    trails: DensityLayer,
+    /// Transparent 0xAARRGGBB layer of the stats and the help.
+    overlay: Vec<u32>,
+    deep: DeepPixels,
+}
+
+/// X2R10G10B10 pixels for the sinks asking for `PixelFormat::Xrgb2101010`,
+/// rendered in the plain and the equalized modes.
+#[derive(Default)]
+struct DeepPixels {
+    pixels: Vec<u32>,
+    /// The 0x00RRGGBB pixels colorized alongside, see `render::merge_10bit`.
+    base: Vec<u32>,
+    /// Whether `pixels` belong to the current frame, reset before the
+    /// pixel pass.
+    rendered: bool,
+}
+
+impl DeepPixels {
+    /// Colorizes `counts` with `tone` like the 0x00RRGGBB `base` pixels
+    /// were.
+    fn colorize<T: Count>(
+        &mut self,
+        threadpool: &Pool,
+        counts: &[T],
+        base: &[u32],
+        width: u32,
+        height: u32,
+        tone: Tone,
+    ) {
+        self.pixels.resize(base.len(), 0);
+        render::colorize_10bit(threadpool, counts, &mut self.pixels, width, height, tone);
+        self.base.clear();
+        self.base.extend_from_slice(base);
+        self.rendered = true;
+    }
+
+    /// The 10-bit version of the final `pixels`, if `colorize` ran this
+    /// frame.
+    fn finish(&mut self, threadpool: &Pool, pixels: &[u32]) -> Option<&[u32]> {
+        if !self.rendered {
+            return None;
+        }
+        render::merge_10bit(threadpool, &self.base, pixels, &mut self.pixels);
+        Some(&self.pixels)
+    }
+}
+
+/// Densities rendered into a layer of their own and blended onto the
+/// frame, see `render::Layers`.
+#[derive(Default)]
+struct DensityLayer {
+    count_buffer: Vec<AtomicU16>,
+    pixels: Vec<u32>,
+}
+
+impl DensityLayer {
+    /// Sizes the densities for a `width` x `height` frame and zeroes them.
+    fn clear(&mut self, width: u32, height: u32) -> &[AtomicU16] {
+        let n_pixels = (width * height) as usize;
+        self.count_buffer
+            .resize_with(n_pixels, || AtomicU16::new(0));
+        self.count_buffer.iter().for_each(|count| {
+            count.store(0, Ordering::Relaxed);
+        });
+        &self.count_buffer
+    }
+
+    /// Colorizes the densities with `tone` and blends them onto
+    /// `pixel_buffer` with `blend`.
+    fn composite(
+        &mut self,
+        threadpool: &Pool,
+        tone: Tone,
+        blend: Blend,
+        pixel_buffer: &mut [u32],
+        width: u32,
+        height: u32,
+    ) {
+        self.pixels.resize(self.count_buffer.len(), 0);
+        render::colorize(
+            threadpool,
+            output::counts_mut(&mut self.count_buffer),
+            &mut self.pixels,
+            width,
+            height,
+            tone,
+        );
+        if blend.mode == BlendMode::Over {
+            render::key(threadpool, &mut self.pixels);
+        }
+        render::composite(threadpool, &self.pixels, pixel_buffer, blend);
+    }
+}
+
+/// An independent simulation blended onto the main one, see `Scene::layer`.
+struct LayerSim<'a> {
+    particles: Particles<'a>,
+    density: DensityLayer,
+    blend: Blend,
+    interactive: bool,
+    boundary: Boundary,
+    tone: Tone,
+}
+
+impl<'a> LayerSim<'a> {
+    /// The layer of `scene` in a `width` x `height` window, if it has one.
+    fn of(threadpool: &'a Pool, scene: &Scene, width: u32, height: u32) -> Option<Self> {
+        let layer = scene.layer.as_ref()?;
+        let mut particles = Particles::new(threadpool);
+        layer.scene.apply(&mut particles, width, height);
+        let render = &layer.scene.render;
+        Some(LayerSim {
+            particles,
+            density: DensityLayer::default(),
+            blend: Blend {
+                mode: layer.blend,
+                opacity: layer.opacity,
+            },
+            interactive: layer.interactive,
+            boundary: layer.scene.boundary,
+            tone: Tone::new(render.brightness, render.palette),
+        })
+    }
+
+    /// Steps the layer like the main particles and blends it onto
+    /// `pixel_buffer`. The mouse, at `mouse_pos` and `attracting` or not,
+    /// only acts on interactive layers.
+    fn step(
+        &mut self,
+        threadpool: &Pool,
+        frametime: &Duration,
+        (mouse_pos, attracting): ((f32, f32), bool),
+        pixel_buffer: &mut [u32],
+        width: u32,
+        height: u32,
+    ) {
+        self.particles.emit(frametime.as_secs_f32(), width, height);
+        self.particles.apply_boundary(self.boundary, width, height);
+        self.particles.update_and_count(
+            frametime,
+            mouse_pos,
+            attracting && self.interactive,
+            self.density.clear(width, height),
+            width,
+            height,
+        );
+        self.density.composite(
+            threadpool,
+            self.tone,
+            self.blend,
+            pixel_buffer,
+            width,
+            height,
+        );
+    }
+}
+
+/// Window showing a `View` of the particles of the main window.
+struct ViewWindow {
+    window: Rc<Window>,
+    surface: Surface<Rc<Window>, Rc<Window>>,
+    size: (u32, u32),
+    cursor: (f32, f32),
+    view: View,
+}
+
+impl ViewWindow {
+    fn open(event_loop: &ActiveEventLoop, palette: Palette) -> Result<Self, Box<dyn Error>> {
+        let window = Rc::new(
+            event_loop.create_window(Window::default_attributes().with_title("particles view"))?,
+        );
+        let context = Context::new(Rc::clone(&window))?;
+        let surface = Surface::new(&context, Rc::clone(&window))?;
+        Ok(ViewWindow {
+            window,
+            surface,
+            size: (0, 0),
+            cursor: (0.0, 0.0),
+            view: View::new(palette),
+        })
+    }
 }
 
 struct App<'a> {
@@ -31,23 +316,618 @@ struct App<'a> {
     last_frametime: Instant,
     frametime_buffer: VecDeque<f32>,
     n_frame: u32,
+    /// Frametime in milliseconds the auto-scaler aims for, one refresh of
+    /// the window's monitor.
+    target_frametime: f32,
+    /// The window is hidden, the particles are stepped at `BACKGROUND_TICK`
+    /// without rendering.
+    occluded: bool,
     threadpool: &'a Pool,
     mouse_pos: (f32, f32),
+    modifiers: ModifiersState,
+    keybindings: Keybindings,
+    /// Held steering keys of the keyboard attractor, see `Action::steering`.
+    steering: [bool; 4],
+    /// Lists the keybindings and parameters on top of the frame, toggled
+    /// with F1 by default.
+    help: bool,
+    /// Particle count, frame time and gravity in a corner, toggled with F6
+    /// by default. F5 switches the frame time between ms and fps.
+    stats: bool,
+    frame_unit: FrameUnit,
     mouse_down: bool,
+    /// Dragging paints currents instead of attracting, toggled with V.
+    painting: bool,
+    /// Dragging draws portals instead of attracting, toggled with T.
+    drawing_portals: bool,
+    /// Dragging draws circular obstacles instead of attracting, with
+    /// `--wind-tunnel`. The drag starts at the center.
+    drawing_obstacles: bool,
+    obstacle_center: Option<(f32, f32)>,
+    /// Dragging freezes regions instead of attracting, toggled with F.
+    freezing: bool,
+    /// The cursor is a solid disc, cycled with F3 from off to instead of the
+    /// attraction to in addition to it. The mouse wheel sets its radius.
+    cursor_disc: bool,
+    disc_attracts: bool,
+    disc_radius: f32,
+    /// Drags are recorded and recognized as gestures on release, with
+    /// `--gestures`.
+    gestures: bool,
+    gesture_path: Vec<(f32, f32)>,
+    /// Recent direction of a pen stroke, which its lean pushes along.
+    pen_direction: (f32, f32),
+    /// Trails behind the particles, toggled with W. Only drawn with the
+    /// default `u16` counts.
+    trails: Option<Trails>,
+    /// Average velocity per pixel, filled with `--motion-vectors` or for
+    /// `direction_hue`.
+    motion: MotionField,
+    motion_vectors: bool,
+    /// Particles stay in place on the desktop while the window moves.
+    desktop_space: bool,
+    direction_hue: Option<DirectionHue>,
+    trail_length: usize,
+    /// Every how many frames non-finite particles are recycled, never if 0.
+    recycle_every: usize,
+    /// Corner where the drag drawing a portal started.
+    portal_corner: Option<(f32, f32)>,
+    /// Entry of the portal whose exit is drawn next.
+    portal_entry: Option<Rect>,
     brightness_multiplier: f32,
+    palette: Palette,
+    /// Renders blobs instead of dots, toggled with M.
+    metaballs: Option<Metaballs>,
+    glow: Option<Glow>,
+    dither: bool,
+    /// Encodes the pixels as sRGB, toggled with F7.
+    srgb: bool,
+    /// Colors cycled with F8 and inverted with F9.
+    accessibility: Accessibility,
+    /// Histogram equalization of the counts instead of the brightness.
+    equalize: bool,
+    /// Duel between the mouse and the keyboard attractor, toggled with Y.
+    duel: Option<Duel>,
+    /// Export the density of the next frame, see `export::save`.
+    export_density: bool,
+    /// Runs the exports, each reporting what it saved where.
+    io: IoThread<(String, io::Result<()>)>,
+    /// Every how many particles are exported.
+    csv_every: usize,
+    background: Background,
+    /// Blending of the trails and the overlay.
+    layers: Layers,
+    /// Palette cycles per second, toggled with P.
+    palette_cycle: f32,
+    palette_phase: f32,
+    /// Colors the particles by the emitter they came from, toggled with C.
+    color_by_tag: bool,
+    removal_policy: RemovalPolicy,
+    boundary: Boundary,
+    /// Scene the particles are spawned from once the window size is known.
+    scene: Option<Scene>,
+    /// Keyframes of the scene, played from when it was applied.
+    timeline: Option<Playback>,
+    /// Reloads the scene file with `--watch`.
+    scene_watcher: Option<SceneWatcher>,
+    /// Scene file dropped onto the window, applied with the next frame.
+    dropped_scene: Option<Scene>,
+    /// Parameters pasted with Ctrl+V, applied with the next frame.
+    pasted: Option<Parameters>,
+    /// File and interval of `--autosave`.
+    autosave: Option<(String, Duration)>,
+    autosaved_at: Instant,
+    /// State of `--resume`, applied with the first frame.
+    resumed: Option<autosave::State>,
+    /// Watchdog of `--watchdog`, it wakes the loop with a user event.
+    watchdog: Option<Watchdog>,
+    watched_at: Instant,
+    /// Logo of `--watermark`.
+    watermark: Option<Watermark>,
+    demo: Option<Demo>,
+    /// Start and first frame of a running cross-fade.
+    fade_from: Option<(Instant, Vec<u32>)>,
+    max_blocks: Option<usize>,
+    u8_counts: bool,
+    #[cfg(feature = "numa")]
+    placement: particles::numa::Placement,
+    commands: Receiver<SimCommand>,
+    attractors_expiry: Option<Instant>,
+    /// State saved with S and restored with R.
+    snapshot: Option<Snapshot>,
+    /// Running long exposure, started and stopped with E and saved with X.
+    exposure: Option<LongExposure>,
+    sinks: Vec<Box<dyn FrameSink>>,
+    /// Served by `--metrics`.
+    #[cfg(feature = "metrics")]
+    metrics: Option<std::sync::Arc<particles::metrics::Metrics>>,
+    /// Sonification of `--audio`.
+    #[cfg(feature = "audio")]
+    audio: Option<particles::audio::AudioOutput>,
+    /// Reported to the clients of the WebSocket remote control.
+    #[cfg(feature = "websocket")]
+    websocket: Option<std::sync::Arc<particles::websocket::StatsFeed>>,
+    #[cfg(feature = "midi")]
+    midi: Option<particles::midi::MidiInput>,
+    #[cfg(feature = "midi")]
+    midi_learn_index: usize,
+    /// Custom force of `--script`.
+    #[cfg(feature = "script")]
+    script: Option<particles::script::Script>,
+}
+
+impl AppData<'_> {
+    /// Replaces the surface of the window by a new one, e.g. after the old
+    /// one failed or the watchdog found the loop stalled.
+    fn recreate_surface(&mut self) {
+        let surface = Context::new(Rc::clone(&self.window))
+            .and_then(|context| Surface::new(&context, Rc::clone(&self.window)));
+        match surface {
+            Ok(surface) => {
+                self.surface = surface;
+                if let (Some(width), Some(height)) =
+                    (NonZeroU32::new(self.size.0), NonZeroU32::new(self.size.1))
+                    && let Err(err) = self.surface.resize(width, height)
+                {
+                    eprintln!("failed to resize the new surface: {err}");
+                }
+                println!("recreated the surface");
+            }
+            Err(err) => eprintln!("failed to recreate the surface: {err}"),
+        }
+        self.window.request_redraw();
+    }
+
+    /// Shifts the particles against the latest move of the window, so they
+    /// keep their desktop position. Does nothing where the platform does
+    /// not report window positions.
+    fn follow_window(&mut self) {
+        let Ok(position) = self.window.inner_position() else {
+            return;
+        };
+        let position = (position.x, position.y);
+        if let Some((x, y)) = self.position {
+            let (dx, dy) = (x - position.0, y - position.1);
+            self.particles.shift(dx as f32, dy as f32);
+            if let Some(layer) = &mut self.layer {
+                layer.particles.shift(dx as f32, dy as f32);
+            }
+        }
+        self.position = Some(position);
+    }
 }
 
 impl<'a> App<'a> {
-    fn new(threadpool: &'a Pool) -> Self {
+    fn new(threadpool: &'a Pool, commands: Receiver<SimCommand>) -> Self {
         App {
             data: None,
             n_frame: 0,
+            target_frametime: TARGET_FRAMETIME,
+            occluded: false,
             last_frametime: Instant::now(),
             frametime_buffer: VecDeque::new(),
             threadpool,
             mouse_pos: (0.0, 0.0),
+            modifiers: ModifiersState::empty(),
+            keybindings: Keybindings::default(),
+            steering: [false; 4],
+            help: false,
+            stats: false,
+            frame_unit: FrameUnit::default(),
             mouse_down: false,
+            painting: false,
+            drawing_portals: false,
+            drawing_obstacles: false,
+            obstacle_center: None,
+            freezing: false,
+            cursor_disc: false,
+            disc_attracts: false,
+            disc_radius: DISC_RADIUS,
+            gestures: false,
+            gesture_path: Vec::new(),
+            pen_direction: (0.0, 0.0),
+            trails: None,
+            motion: MotionField::default(),
+            motion_vectors: false,
+            desktop_space: false,
+            direction_hue: None,
+            trail_length: 8,
+            recycle_every: 0,
+            portal_corner: None,
+            portal_entry: None,
             brightness_multiplier: 10.0,
+            palette: Palette::default(),
+            metaballs: None,
+            glow: None,
+            dither: false,
+            srgb: false,
+            accessibility: Accessibility::default(),
+            equalize: false,
+            duel: None,
+            export_density: false,
+            io: IoThread::spawn(),
+            csv_every: 1,
+            background: Background::default(),
+            layers: Layers::default(),
+            palette_cycle: 0.0,
+            palette_phase: 0.0,
+            color_by_tag: false,
+            removal_policy: RemovalPolicy::default(),
+            boundary: Boundary::default(),
+            scene: None,
+            timeline: None,
+            scene_watcher: None,
+            dropped_scene: None,
+            pasted: None,
+            autosave: None,
+            autosaved_at: Instant::now(),
+            resumed: None,
+            watchdog: None,
+            watermark: None,
+            watched_at: Instant::now(),
+            demo: None,
+            fade_from: None,
+            max_blocks: None,
+            u8_counts: false,
+            #[cfg(feature = "numa")]
+            placement: Default::default(),
+            commands,
+            attractors_expiry: None,
+            snapshot: None,
+            exposure: None,
+            sinks: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "audio")]
+            audio: None,
+            #[cfg(feature = "websocket")]
+            websocket: None,
+            #[cfg(feature = "midi")]
+            midi: None,
+            #[cfg(feature = "midi")]
+            midi_learn_index: 0,
+            #[cfg(feature = "script")]
+            script: None,
+        }
+    }
+
+    /// The current parameters, once the window is open.
+    fn parameters(&self) -> Option<Parameters> {
+        let data = self.data.as_ref()?;
+        let (width, height) = data.size;
+        Some(Parameters {
+            boundary: self.boundary,
+            forces: Forces::of(&data.particles, width, height),
+            render: RenderSettings {
+                palette: self.palette,
+                brightness: self.brightness_multiplier,
+                symmetry: data.particles.symmetry,
+                metaballs: self.metaballs,
+                glow: self.glow,
+                direction_hue: self.direction_hue,
+                background: self.background,
+                cycle: self.palette_cycle,
+                dither: self.dither,
+                srgb: self.srgb,
+                equalize: self.equalize,
+                layers: self.layers,
+                accessibility: self.accessibility,
+            },
+        })
+    }
+
+    /// Runs `action` of a pressed key, see `keys::Keybindings`.
+    fn run_key_action(&mut self, action: Action, event_loop: &ActiveEventLoop) {
+        let Some(data) = &mut self.data else {
+            return;
+        };
+        match action {
+            Action::SaveSnapshot => {
+                let snapshot = data.particles.snapshot();
+                println!("saved {} particles", snapshot.len());
+                self.snapshot = Some(snapshot);
+            }
+            Action::RestoreSnapshot => {
+                if let Some(snapshot) = &self.snapshot {
+                    data.particles.restore(snapshot);
+                }
+            }
+            Action::Metaballs => {
+                self.metaballs = match self.metaballs {
+                    None => Some(Metaballs::default()),
+                    Some(Metaballs { outline: false, .. }) => Some(Metaballs {
+                        outline: true,
+                        ..Metaballs::default()
+                    }),
+                    Some(_) => None,
+                };
+                println!("metaballs: {:?}", self.metaballs);
+            }
+            Action::Glow => {
+                self.glow = match self.glow {
+                    None => Some(Glow::default()),
+                    Some(_) => None,
+                };
+                println!("glow: {:?}", self.glow);
+            }
+            Action::DirectionHue => {
+                self.direction_hue = match self.direction_hue {
+                    None => Some(DirectionHue::default()),
+                    Some(_) => None,
+                };
+                println!("direction hue: {:?}", self.direction_hue);
+            }
+            Action::Equalize => {
+                self.equalize = !self.equalize;
+                println!("auto exposure: {}", self.equalize);
+            }
+            Action::Duel => {
+                self.duel = match self.duel {
+                    None => Some(Duel::default()),
+                    Some(_) => None,
+                };
+                if self.duel.is_some() {
+                    let (width, height) = data.size;
+                    data.particles
+                        .keyboard_attractor
+                        .get_or_insert((width as f32 * 0.75, height as f32 / 2.0));
+                }
+                println!("duel: {}", self.duel.is_some());
+            }
+            Action::Dither => {
+                self.dither = !self.dither;
+                println!("dither: {}", self.dither);
+            }
+            Action::Srgb => {
+                self.srgb = !self.srgb;
+                println!("sRGB encoding: {}", self.srgb);
+            }
+            Action::ColorMode => {
+                self.accessibility.colors = self.accessibility.colors.next();
+                println!("colors: {:?}", self.accessibility.colors);
+            }
+            Action::Invert => {
+                self.accessibility.inverted = !self.accessibility.inverted;
+                println!("inverted: {}", self.accessibility.inverted);
+            }
+            Action::LongExposure => {
+                if self.exposure.take().is_none() {
+                    println!("long exposure started, press X to save");
+                    self.exposure = Some(LongExposure::new());
+                } else {
+                    println!("long exposure stopped");
+                }
+            }
+            Action::SaveExposure => {
+                if let Some(exposure) = &self.exposure {
+                    let path = format!("exposure-{}.pfm", self.n_frame);
+                    match exposure.save(&path, self.brightness_multiplier, self.palette) {
+                        Ok(()) => println!("saved {} frames to {path}", exposure.frames()),
+                        Err(err) => eprintln!("failed to save {path}: {err}"),
+                    }
+                }
+            }
+            Action::ExportDensity => self.export_density = true,
+            Action::ExportCsv => {
+                let path = format!("particles-{}.csv", self.n_frame);
+                let snapshot = data.particles.snapshot();
+                let every = self.csv_every;
+                self.io.run(move || {
+                    let result = export::save_csv(&snapshot, &path, every);
+                    (format!("the particles to {path}"), result)
+                });
+            }
+            Action::PaletteCycle => {
+                self.palette_cycle = if self.palette_cycle == 0.0 {
+                    PALETTE_CYCLE
+                } else {
+                    0.0
+                };
+                println!("palette cycle: {}/s", self.palette_cycle);
+            }
+            Action::PaintCurrents => {
+                self.painting = !self.painting;
+                if self.painting && data.particles.velocity_field.is_none() {
+                    let (width, height) = data.size;
+                    data.particles.velocity_field =
+                        Some(VelocityField::new(width, height, BRUSH_CELL));
+                }
+                println!("painting currents: {}", self.painting);
+            }
+            Action::ClearCurrents => data.particles.velocity_field = None,
+            Action::DrawPortals => {
+                self.drawing_portals = !self.drawing_portals;
+                self.portal_corner = None;
+                self.portal_entry = None;
+                println!("drawing portals: {}", self.drawing_portals);
+            }
+            Action::ClearPortals => data.particles.portals = None,
+            Action::Freeze => {
+                self.freezing = !self.freezing;
+                if self.freezing && data.particles.freeze_mask.is_none() {
+                    let (width, height) = data.size;
+                    data.particles.freeze_mask = Some(FreezeMask::new(width, height, FREEZE_CELL));
+                }
+                println!("freezing: {}", self.freezing);
+            }
+            Action::Thaw => data.particles.freeze_mask = None,
+            Action::Trails => {
+                self.trails = match self.trails {
+                    Some(_) => None,
+                    None => Some(Trails::new(self.trail_length, TRAIL_MEMORY)),
+                };
+                println!("trails: {}", self.trails.is_some());
+            }
+            Action::CenterAttractor => {
+                let (width, height) = data.size;
+                data.particles.center_attractor = match data.particles.center_attractor {
+                    Some(_) => None,
+                    None => Some((width as f32 / 2.0, height as f32 / 2.0)),
+                };
+                println!(
+                    "center attractor: {}",
+                    data.particles.center_attractor.is_some()
+                );
+            }
+            Action::Cooler | Action::Warmer => {
+                let step = if action == Action::Warmer {
+                    TEMPERATURE_STEP
+                } else {
+                    -TEMPERATURE_STEP
+                };
+                data.particles.temperature = (data.particles.temperature + step).max(0.0);
+                println!("temperature: {}", data.particles.temperature);
+            }
+            Action::Lfo => {
+                data.particles.gravity_lfo = match data.particles.gravity_lfo {
+                    None => Some(Lfo::default()),
+                    Some(Lfo {
+                        shape: LfoShape::Sine,
+                        ..
+                    }) => Some(Lfo {
+                        shape: LfoShape::Square,
+                        ..Lfo::default()
+                    }),
+                    Some(_) => None,
+                };
+                println!("attractor LFO: {:?}", data.particles.gravity_lfo);
+            }
+            Action::Charges => {
+                if data.particles.charge_field.take().is_none() {
+                    let (width, height) = data.size;
+                    data.particles.alternate_charges();
+                    data.particles.charge_field =
+                        Some(ChargeField::new(width, height, CHARGE_CELL));
+                }
+                println!("charges: {}", data.particles.charge_field.is_some());
+            }
+            Action::ColorByTag => {
+                self.color_by_tag = !self.color_by_tag;
+                println!("color by emitter: {}", self.color_by_tag);
+            }
+            Action::Symmetry => {
+                data.particles.symmetry = data.particles.symmetry.next();
+                println!("symmetry: {:?}", data.particles.symmetry);
+            }
+            #[cfg(feature = "midi")]
+            Action::MidiLearn => {
+                if let Some(midi) = &self.midi {
+                    use particles::midi::Param;
+                    let param = Param::ALL[self.midi_learn_index];
+                    self.midi_learn_index = (self.midi_learn_index + 1) % Param::ALL.len();
+                    println!("midi: move a control to bind it to {param:?}");
+                    midi.learn(param);
+                }
+            }
+            Action::KeyboardAttractor => {
+                let (width, height) = data.size;
+                let attractor = &mut data.particles.keyboard_attractor;
+                *attractor = match attractor {
+                    None => Some((width as f32 / 2.0, height as f32 / 2.0)),
+                    Some(_) => None,
+                };
+                self.steering = [false; 4];
+                println!("keyboard attractor: {}", attractor.is_some());
+            }
+            Action::OpenView => {
+                let palette = next_palette(self.palette, data.views.len() + 1);
+                match ViewWindow::open(event_loop, palette) {
+                    Ok(view) => {
+                        data.views.push(view);
+                        println!("views: {}", data.views.len());
+                    }
+                    Err(err) => eprintln!("failed to open a view: {err}"),
+                }
+            }
+            Action::CursorDisc => {
+                (self.cursor_disc, self.disc_attracts) =
+                    match (self.cursor_disc, self.disc_attracts) {
+                        (false, _) => (true, false),
+                        (true, false) => (true, true),
+                        (true, true) => (false, false),
+                    };
+                println!(
+                    "cursor disc: {}, attracting: {}",
+                    self.cursor_disc, self.disc_attracts
+                );
+            }
+            Action::Convection => {
+                let (width, height) = data.size;
+                let field = &mut data.particles.heat_field;
+                *field = match field {
+                    None => Some(HeatField::new(width, height, Heat::default())),
+                    Some(_) => None,
+                };
+                println!("convection: {}", field.is_some());
+            }
+            Action::Help => self.help = !self.help,
+            Action::Stats => self.stats = !self.stats,
+            Action::FrameUnit => {
+                self.frame_unit = self.frame_unit.toggled();
+                println!("frame times in {:?}", self.frame_unit);
+            }
+            Action::CopyParameters => {
+                let Some(parameters) = self.parameters() else {
+                    return;
+                };
+                match clipboard::copy(&parameters.to_toml()) {
+                    Ok(()) => println!("copied the parameters"),
+                    Err(err) => eprintln!("failed to copy the parameters: {err}"),
+                }
+            }
+            Action::PasteParameters => {
+                match clipboard::paste().and_then(|text| Parameters::from_toml(&text)) {
+                    Ok(parameters) => self.pasted = Some(parameters),
+                    Err(err) => eprintln!("failed to paste parameters: {err}"),
+                }
+            }
+            Action::GustLeft | Action::GustRight | Action::GustUp | Action::GustDown => {
+                let (dvx, dvy) = match action {
+                    Action::GustLeft => (-GUST_STRENGTH, 0.0),
+                    Action::GustRight => (GUST_STRENGTH, 0.0),
+                    Action::GustUp => (0.0, -GUST_STRENGTH),
+                    _ => (0.0, GUST_STRENGTH),
+                };
+                data.particles
+                    .apply_impulse(self.mouse_pos, GUST_RADIUS, dvx, dvy);
+            }
+            // Handled while the keys are held, see `window_event`.
+            Action::SteerLeft | Action::SteerRight | Action::SteerUp | Action::SteerDown => {}
+        }
+    }
+
+    fn apply_command(&mut self, command: SimCommand) {
+        let Some(data) = &mut self.data else {
+            return;
+        };
+        let Some(command) = command.clone().validated() else {
+            eprintln!("ignoring invalid command {command:?}");
+            return;
+        };
+        match command {
+            SimCommand::SetGravity(gravity) => data.particles.gravity = gravity,
+            SimCommand::SetFriction(friction) => data.particles.friction = friction,
+            SimCommand::SetTimeScale(time_scale) => data.particles.time_scale = time_scale,
+            SimCommand::SetPalette(index) => self.palette = Palette::from_index(index),
+            SimCommand::SetBrightness(brightness) => self.brightness_multiplier = brightness,
+            SimCommand::Spawn(n) => {
+                // Whole blocks through the queue, the rest right away.
+                let (width, height) = data.size;
+                data.particles.queue_particles(n / 64);
+                data.particles.add_lanes(n % 64, width, height);
+            }
+            SimCommand::SetAttractors(attractors) => {
+                let (width, height) = data.size;
+                data.particles.attractors = attractors
+                    .into_iter()
+                    .map(|a| Attractor {
+                        x: a.x * width as f32,
+                        y: a.y * height as f32,
+                        ..a
+                    })
+                    .collect();
+                self.attractors_expiry = Some(Instant::now() + ATTRACTOR_TIMEOUT);
+            }
         }
     }
 }
@@ -61,44 +941,147 @@ impl ApplicationHandler for App<'_> {
         );
         let context = Context::new(Rc::clone(&window)).unwrap();
         let surface = softbuffer::Surface::new(&context, Rc::clone(&window)).unwrap();
-        let particles = Particles::new(self.threadpool);
+        if let Some(frametime) = refresh_frametime(&window) {
+            self.target_frametime = frametime;
+            println!("target frametime: {frametime} ms");
+        }
+        let mut particles = Particles::new(self.threadpool);
+        particles.reserve_budget(self.max_blocks);
+        #[cfg(feature = "numa")]
+        self.placement
+            .prepare(self.threadpool, &mut particles.particles);
+        // Sized once the window reports its size.
+        #[cfg(feature = "script")]
+        {
+            particles.script_force = self
+                .script
+                .clone()
+                .map(|script| particles::script::ScriptForce::new(script, 1, 1));
+        }
         self.data = Some(AppData {
             surface,
+            surface_lost: false,
             window,
             particles,
             count_buffer: Vec::new(),
+            count_buffer_u8: Vec::new(),
+            count_tiles: CountTiles::default(),
+            blur: BlurField::default(),
+            equalizer: Equalizer::default(),
+            count_buffer_tagged: Vec::new(),
+            views: Vec::new(),
+            layer: None,
+            trails: DensityLayer::default(),
+            overlay: Vec::new(),
+            deep: DeepPixels::default(),
+            position: None,
             size: (0, 0),
         })
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
-        let _ = id;
+        if let WindowEvent::RedrawRequested = event {
+            while let Ok(command) = self.commands.try_recv() {
+                self.apply_command(command);
+            }
+            if self
+                .attractors_expiry
+                .is_some_and(|expiry| expiry < Instant::now())
+            {
+                self.attractors_expiry = None;
+                if let Some(data) = &mut self.data {
+                    data.particles.attractors.clear();
+                }
+            }
+        }
         let Some(data) = &mut self.data else {
             panic!();
         };
-        event_loop.set_control_flow(ControlFlow::Poll);
+        if let Some(index) = data.views.iter().position(|view| view.window.id() == id) {
+            view_event(&mut data.views, index, data.size, event);
+            return;
+        }
+        if !self.occluded {
+            event_loop.set_control_flow(ControlFlow::Poll);
+        }
 
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
                 event_loop.exit();
             }
+            WindowEvent::Moved(_) => {
+                // The window may have moved to another monitor.
+                if let Some(frametime) = refresh_frametime(&data.window)
+                    && frametime != self.target_frametime
+                {
+                    self.target_frametime = frametime;
+                    println!("target frametime: {frametime} ms");
+                }
+                if self.desktop_space {
+                    data.follow_window();
+                }
+            }
             WindowEvent::Resized(size) => {
                 self.frametime_buffer.clear();
-                let dx = size.width as f32 - data.size.0 as f32;
-                let dy = size.height as f32 - data.size.1 as f32;
-                data.particles.shift(dx / 2.0, dy / 2.0);
+                if self.desktop_space {
+                    // Only moves of the content area shift the particles.
+                    data.follow_window();
+                } else {
+                    let dx = size.width as f32 - data.size.0 as f32;
+                    let dy = size.height as f32 - data.size.1 as f32;
+                    data.particles.shift(dx / 2.0, dy / 2.0);
+                    if let Some(layer) = &mut data.layer {
+                        layer.particles.shift(dx / 2.0, dy / 2.0);
+                    }
+                }
                 data.size = (size.width, size.height);
+                resize_fields(&mut data.particles, size.width, size.height);
+                if let Some(layer) = &mut data.layer {
+                    resize_fields(&mut layer.particles, size.width, size.height);
+                }
                 if data.particles.particles.is_empty() {
-                    data.particles
-                        .add_particles(N_INITIAL_PARTICELS, size.width, size.height);
+                    match &self.scene {
+                        Some(scene) => {
+                            scene.apply(&mut data.particles, size.width, size.height);
+                            data.layer =
+                                LayerSim::of(self.threadpool, scene, size.width, size.height);
+                            self.timeline = scene.timeline.clone().map(Playback::new);
+                        }
+                        None => data.particles.add_particles(
+                            N_INITIAL_PARTICELS,
+                            size.width,
+                            size.height,
+                        ),
+                    }
                 }
                 let buffer_size = (size.width * size.height) as usize;
                 // data.count_buffer.clear();
                 // data.count_buffer.reserve(buffer_size);
                 // (0..buffer_size).for_each(|_| data.count_buffer.push(AtomicU16::new(0)));
-                data.count_buffer
-                    .resize_with(buffer_size, || AtomicU16::new(0));
+                #[cfg(feature = "numa")]
+                if self.u8_counts {
+                    self.placement.resize(
+                        self.threadpool,
+                        &mut data.count_buffer_u8,
+                        buffer_size,
+                        || 0,
+                    );
+                } else {
+                    self.placement.resize(
+                        self.threadpool,
+                        &mut data.count_buffer,
+                        buffer_size,
+                        || AtomicU16::new(0),
+                    );
+                }
+                #[cfg(not(feature = "numa"))]
+                if self.u8_counts {
+                    data.count_buffer_u8.resize(buffer_size, 0);
+                } else {
+                    data.count_buffer
+                        .resize_with(buffer_size, || AtomicU16::new(0));
+                }
                 data.surface
                     .resize(
                         NonZeroU32::new(size.width).unwrap(),
@@ -110,7 +1093,27 @@ impl ApplicationHandler for App<'_> {
                 device_id: _,
                 position,
             } => {
-                self.mouse_pos = (position.x as f32, position.y as f32);
+                let pos = (position.x as f32, position.y as f32);
+                if self.painting
+                    && self.mouse_down
+                    && let Some(field) = &mut data.particles.velocity_field
+                {
+                    let velocity = (
+                        (pos.0 - self.mouse_pos.0) * BRUSH_STRENGTH,
+                        (pos.1 - self.mouse_pos.1) * BRUSH_STRENGTH,
+                    );
+                    field.paint(pos, velocity, BRUSH_RADIUS);
+                }
+                if self.freezing
+                    && self.mouse_down
+                    && let Some(mask) = &mut data.particles.freeze_mask
+                {
+                    mask.paint(pos, FREEZE_RADIUS);
+                }
+                if self.gestures && self.mouse_down {
+                    self.gesture_path.push(pos);
+                }
+                self.mouse_pos = pos;
             }
             WindowEvent::MouseInput {
                 device_id: _,
@@ -118,20 +1121,205 @@ impl ApplicationHandler for App<'_> {
                 button: MouseButton::Left,
             } => {
                 self.mouse_down = state == ElementState::Pressed;
+                if self.gestures && self.mouse_down {
+                    self.gesture_path.clear();
+                    self.gesture_path.push(self.mouse_pos);
+                } else if self.gestures
+                    && let Some(gesture) = gesture::recognize(&self.gesture_path)
+                {
+                    apply_gesture(data, gesture);
+                }
+                if self.freezing
+                    && self.mouse_down
+                    && let Some(mask) = &mut data.particles.freeze_mask
+                {
+                    mask.paint(self.mouse_pos, FREEZE_RADIUS);
+                }
+                if self.drawing_obstacles && self.mouse_down {
+                    self.obstacle_center = Some(self.mouse_pos);
+                } else if let Some((x, y)) = self.obstacle_center.take() {
+                    let radius = (self.mouse_pos.0 - x).hypot(self.mouse_pos.1 - y);
+                    let (width, height) = data.size;
+                    let (w, h) = (width as f32, height as f32);
+                    if radius >= 1.0 {
+                        let mut shapes = match &data.particles.obstacles {
+                            Some(obstacles) => obstacles.shapes().to_vec(),
+                            None => Vec::new(),
+                        };
+                        shapes.push(Shape::Circle {
+                            x: x / w,
+                            y: y / h,
+                            radius: radius / w.min(h),
+                        });
+                        data.particles.obstacles = Some(Obstacles::new(shapes, width, height));
+                        println!("obstacle added");
+                    }
+                }
+                if self.drawing_portals && self.mouse_down {
+                    self.portal_corner = Some(self.mouse_pos);
+                } else if self.drawing_portals
+                    && let Some(corner) = self.portal_corner.take()
+                    && let rect = Rect::from_corners(corner, self.mouse_pos)
+                    && rect.width >= 1.0
+                    && rect.height >= 1.0
+                {
+                    match self.portal_entry.take() {
+                        None => {
+                            self.portal_entry = Some(rect);
+                            println!("portal entry drawn, draw its exit");
+                        }
+                        Some(entry) => {
+                            let (width, height) = data.size;
+                            data.particles
+                                .portals
+                                .get_or_insert_with(|| Portals::new(Vec::new(), width, height))
+                                .push_pixels(entry, rect);
+                            println!("portal added");
+                        }
+                    }
+                }
+            }
+            // Pens and fingers attract like the mouse, pressing harder pulls
+            // harder.
+            WindowEvent::Touch(Touch {
+                phase,
+                location,
+                force,
+                ..
+            }) => {
+                let pos = (location.x as f32, location.y as f32);
+                if phase == TouchPhase::Moved {
+                    let (dx, dy) = (pos.0 - self.mouse_pos.0, pos.1 - self.mouse_pos.1);
+                    let length = dx.hypot(dy);
+                    if length > 0.0 {
+                        let (x, y) = self.pen_direction;
+                        self.pen_direction =
+                            (x * 0.8 + dx / length * 0.2, y * 0.8 + dy / length * 0.2);
+                    }
+                } else {
+                    self.pen_direction = (0.0, 0.0);
+                }
+                self.mouse_pos = pos;
+                self.mouse_down = matches!(phase, TouchPhase::Started | TouchPhase::Moved);
+                data.particles.pen = self.mouse_down.then(|| pen(force, self.pen_direction));
+            }
+            // Routed by extension: images steer the particles, scenes are
+            // applied, autosaves are resumed, parameter files are applied
+            // like pasted ones and CSV exports restore the particles.
+            WindowEvent::DroppedFile(path) => {
+                let extension = path.extension().and_then(|extension| extension.to_str());
+                let (width, height) = data.size;
+                let result = match extension.map(str::to_ascii_lowercase).as_deref() {
+                    Some("pgm" | "png") => Image::load(&path).map(|image| {
+                        let field = &mut data.particles.distance_field;
+                        let force = field.as_ref().map_or_else(SdfForce::default, |f| f.force);
+                        *field = Some(DistanceField::new(
+                            Source::Image(image),
+                            force,
+                            width,
+                            height,
+                        ));
+                    }),
+                    // Autosaves are JSON as well, but have no scene fields.
+                    Some("json") => autosave::State::load(&path)
+                        .map(|state| self.resumed = Some(state))
+                        .or_else(|_| {
+                            Scene::load(&path).map(|scene| self.dropped_scene = Some(scene))
+                        }),
+                    Some("toml") => std::fs::read_to_string(&path)
+                        .and_then(|text| Parameters::from_toml(&text))
+                        .map(|parameters| self.pasted = Some(parameters)),
+                    Some("csv") => {
+                        export::load_csv(&path).map(|states| data.particles.load_states(&states))
+                    }
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "expected a .pgm or .png image, a .json scene or autosave, a .toml \
+                         parameter file or a .csv export",
+                    )),
+                };
+                match result {
+                    Ok(()) => println!("opened {}", path.display()),
+                    Err(err) => eprintln!("failed to open {}: {err}", path.display()),
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        ref logical_key,
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => {
+                let Some(name) = key_name(logical_key, self.modifiers) else {
+                    return;
+                };
+                let pressed = state == ElementState::Pressed;
+                // Held steering keys move the keyboard attractor while it is
+                // active, instead of their other actions.
+                let steering = self.keybindings.actions(&name).find_map(Action::steering);
+                if let Some(direction) = steering
+                    && data.particles.keyboard_attractor.is_some()
+                {
+                    self.steering[direction] = pressed;
+                    return;
+                }
+                if !pressed {
+                    return;
+                }
+                let actions = self.keybindings.actions(&name).collect::<Vec<_>>();
+                for action in actions {
+                    if repeat && !action.repeats() {
+                        continue;
+                    }
+                    self.run_key_action(action, event_loop);
+                }
             }
             WindowEvent::MouseWheel {
                 device_id: _,
                 delta: MouseScrollDelta::LineDelta(_, vertical),
                 phase: _,
             } => {
-                self.brightness_multiplier *= 1.0 + vertical * 0.1;
-                println!("brightness: {}", self.brightness_multiplier);
+                if self.cursor_disc {
+                    let (min, max) = DISC_RADIUS_RANGE;
+                    self.disc_radius = (self.disc_radius * (1.0 + vertical * 0.1)).clamp(min, max);
+                    println!("disc radius: {}", self.disc_radius);
+                } else {
+                    let (min, max) = BRIGHTNESS_RANGE;
+                    self.brightness_multiplier =
+                        (self.brightness_multiplier * (1.0 + vertical * 0.1)).clamp(min, max);
+                    println!("brightness: {}", self.brightness_multiplier);
+                }
+            }
+            WindowEvent::Occluded(occluded) => {
+                self.occluded = occluded;
+                if !occluded {
+                    self.frametime_buffer.clear();
+                    data.window.request_redraw();
+                }
             }
+            // Stepped in `about_to_wait` instead.
+            WindowEvent::RedrawRequested if self.occluded => {}
             WindowEvent::RedrawRequested => {
                 data.window.request_redraw();
                 let (width, height) = data.size;
+                if let Some(watchdog) = &self.watchdog {
+                    watchdog.beat();
+                }
+                if std::mem::take(&mut data.surface_lost) {
+                    data.recreate_surface();
+                }
 
                 self.n_frame += 1;
+                for (saved, result) in self.io.completed() {
+                    match result {
+                        Ok(()) => println!("saved {saved}"),
+                        Err(err) => eprintln!("failed to save {saved}: {err}"),
+                    }
+                }
                 let now = Instant::now();
                 let frametime = now.duration_since(self.last_frametime);
                 self.last_frametime = now;
@@ -139,131 +1327,1131 @@ impl ApplicationHandler for App<'_> {
                 if self.frametime_buffer.len() > 100 {
                     self.frametime_buffer.pop_back();
                 }
-                self.frametime_buffer.push_front(frametime.as_millis_f32());
+                self.frametime_buffer
+                    .push_front(frametime.as_secs_f32() * 1000.0);
                 let frametime_avg =
                     self.frametime_buffer.iter().sum::<f32>() / self.frametime_buffer.len() as f32;
 
-                if self.n_frame % 100 == 0 {
-                    println!("#{}: FPS = {}", self.n_frame, 1000.0 / frametime_avg);
-                    println!("n_particles = {}", data.particles.len());
+                if self.n_frame.is_multiple_of(100) {
+                    println!(
+                        "#{}: {}, {} particles",
+                        self.n_frame,
+                        units::frametime(frametime_avg, self.frame_unit),
+                        units::count(data.particles.len())
+                    );
+                    let stats = self.threadpool.stats();
+                    let jobs = stats.threads.iter().map(|thread| thread.jobs);
+                    println!(
+                        "pool: {:.0}% busy, jobs per thread {:?}, max queued {}",
+                        stats.utilization() * 100.0,
+                        jobs.collect::<Vec<_>>(),
+                        stats.max_queued
+                    );
+                    self.threadpool.reset_stats();
                 }
 
-                let frametime_ratio = TARGET_FRAMETIME / frametime_avg.clamp(10.0, 100.0);
+                let frametime_ratio =
+                    self.target_frametime / frametime_avg.clamp(self.target_frametime / 2.0, 100.0);
                 if frametime_ratio > 1.1 {
                     let n = data.particles.particles.len() as f32 * (frametime_ratio - 1.0) / 200.0;
-                    data.particles.add_particles(n as usize, width, height);
+                    if data.particles.queued() == 0 {
+                        data.particles.queue_particles(n as usize);
+                    }
                 } else if frametime_ratio < 0.9 {
+                    data.particles.clear_queue();
                     let n = data.particles.particles.len() as f32 * (1.0 - frametime_ratio) / 200.0;
-                    let new_particles_len = data.particles.particles.len() - n as usize;
-                    data.particles.particles.truncate(new_particles_len);
+                    data.particles
+                        .remove_particles(n as usize, self.removal_policy, width, height);
+                }
+                data.particles.spawn_queued(width, height);
+                data.particles.emit(frametime.as_secs_f32(), width, height);
+                data.particles.apply_boundary(self.boundary, width, height);
+                if self.recycle_every > 0
+                    && (self.n_frame as usize).is_multiple_of(self.recycle_every)
+                {
+                    let emitter = self.scene.as_ref().and_then(|scene| scene.emitters.first());
+                    let (x, y) = emitter.map_or((0.5, 0.5), |emitter| (emitter.x, emitter.y));
+                    let emitter = (x * width as f32, y * height as f32);
+                    let recycled = data.particles.recycle_non_finite(emitter);
+                    if recycled > 0 {
+                        eprintln!("recycled {recycled} particles with NaN or infinite state");
+                    }
                 }
 
-                data.particles
-                    .update(&frametime, self.mouse_pos, self.mouse_down);
-
-                data.count_buffer.iter().for_each(|count| {
-                    count.store(0, Ordering::Relaxed);
+                // In a duel the mouse player attracts without holding a button.
+                let attracting = (self.mouse_down || self.duel.is_some())
+                    && !self.painting
+                    && !self.drawing_portals
+                    && !self.drawing_obstacles
+                    && !self.freezing
+                    && (!self.cursor_disc || self.disc_attracts);
+                data.particles.cursor_disc = self.cursor_disc.then_some(Disc {
+                    x: self.mouse_pos.0,
+                    y: self.mouse_pos.1,
+                    radius: self.disc_radius,
                 });
+                let mut pixel_buffer = match data.surface.buffer_mut() {
+                    Ok(buffer) => buffer,
+                    Err(err) => {
+                        eprintln!("failed to get the window buffer: {err}");
+                        data.surface_lost = true;
+                        return;
+                    }
+                };
+                self.palette_phase =
+                    (self.palette_phase + frametime.as_secs_f32() * self.palette_cycle) % 2.0;
+                let tone = Tone {
+                    phase: self.palette_phase,
+                    dither: self.dither,
+                    srgb: self.srgb,
+                    ..Tone::new(self.brightness_multiplier, self.palette)
+                };
+
+                #[cfg(feature = "script")]
+                if let Some(force) = &mut data.particles.script_force {
+                    force.evaluate(self.threadpool, frametime.as_secs_f32(), self.mouse_pos);
+                }
+                if let Some((x, y)) = &mut data.particles.keyboard_attractor {
+                    let boost = if self.modifiers.shift_key() {
+                        KEYBOARD_BOOST
+                    } else {
+                        1.0
+                    };
+                    let step = KEYBOARD_SPEED * boost * frametime.as_secs_f32();
+                    let [left, right, up, down] = self.steering.map(|held| held as u8 as f32);
+                    *x = (*x + (right - left) * step).clamp(0.0, width as f32);
+                    *y = (*y + (down - up) * step).clamp(0.0, height as f32);
+                }
+                // End of the simulate phase, for the metrics.
+                #[cfg(feature = "metrics")]
+                let counted;
+                let deep = self
+                    .sinks
+                    .iter()
+                    .any(|sink| sink.pixel_format() == PixelFormat::Xrgb2101010);
+                data.deep.rendered = false;
+                let density = if self.color_by_tag {
+                    data.count_buffer_tagged
+                        .resize_with((width * height) as usize, || AtomicU64::new(0));
+                    data.count_buffer_tagged.iter().for_each(|count| {
+                        count.store(0, Ordering::Relaxed);
+                    });
+                    data.particles
+                        .update(&frametime, self.mouse_pos, attracting);
+                    data.particles
+                        .count_tagged(&data.count_buffer_tagged, width, height);
+                    #[cfg(feature = "metrics")]
+                    {
+                        counted = Instant::now();
+                    }
+                    render::colorize_tagged(
+                        self.threadpool,
+                        output::tagged_counts_mut(&mut data.count_buffer_tagged),
+                        &mut pixel_buffer,
+                        self.brightness_multiplier,
+                        &palette::TAG_COLORS,
+                    );
+                    Density::Tagged(&data.count_buffer_tagged)
+                } else if self.u8_counts {
+                    data.particles
+                        .update(&frametime, self.mouse_pos, attracting);
+                    data.particles.count_saturating(
+                        &mut data.count_tiles,
+                        &mut data.count_buffer_u8,
+                        width,
+                        height,
+                    );
+                    #[cfg(feature = "metrics")]
+                    {
+                        counted = Instant::now();
+                    }
+                    if let Some(settings) = self.metaballs {
+                        data.blur.blur(
+                            self.threadpool,
+                            &data.count_buffer_u8,
+                            width,
+                            height,
+                            settings.radius,
+                        );
+                        render::metaballs(
+                            self.threadpool,
+                            &data.blur,
+                            &mut pixel_buffer,
+                            tone,
+                            settings,
+                        );
+                    } else if let Some(glow) = self.glow {
+                        data.blur.blur_passes(
+                            self.threadpool,
+                            &data.count_buffer_u8,
+                            width,
+                            height,
+                            glow.radius,
+                            glow.passes,
+                        );
+                        render::glow(self.threadpool, &data.blur, &mut pixel_buffer, tone);
+                    } else if self.equalize {
+                        data.equalizer
+                            .equalize(self.threadpool, &data.count_buffer_u8);
+                        let tone = Tone {
+                            brightness: 1.0,
+                            ..tone
+                        };
+                        let values = data.equalizer.values();
+                        render::colorize(
+                            self.threadpool,
+                            values,
+                            &mut pixel_buffer,
+                            width,
+                            height,
+                            tone,
+                        );
+                        if deep {
+                            let pool = self.threadpool;
+                            data.deep
+                                .colorize(pool, values, &pixel_buffer, width, height, tone);
+                        }
+                    } else {
+                        render::colorize(
+                            self.threadpool,
+                            &data.count_buffer_u8,
+                            &mut pixel_buffer,
+                            width,
+                            height,
+                            tone,
+                        );
+                        if deep {
+                            let counts = &data.count_buffer_u8;
+                            let pool = self.threadpool;
+                            data.deep
+                                .colorize(pool, counts, &pixel_buffer, width, height, tone);
+                        }
+                    }
+                    Density::U8(&data.count_buffer_u8)
+                } else {
+                    data.count_buffer.iter().for_each(|count| {
+                        count.store(0, Ordering::Relaxed);
+                    });
 
-                let particles_chunk_len = usize::max(
-                    data.particles.particles.len() / self.threadpool.thread_count() as usize / 10,
-                    1,
+                    data.particles.update_and_count(
+                        &frametime,
+                        self.mouse_pos,
+                        attracting,
+                        &data.count_buffer,
+                        width,
+                        height,
+                    );
+                    #[cfg(feature = "metrics")]
+                    {
+                        counted = Instant::now();
+                    }
+
+                    let counts = output::counts_mut(&mut data.count_buffer);
+                    if let Some(settings) = self.metaballs {
+                        data.blur
+                            .blur(self.threadpool, counts, width, height, settings.radius);
+                        render::metaballs(
+                            self.threadpool,
+                            &data.blur,
+                            &mut pixel_buffer,
+                            tone,
+                            settings,
+                        );
+                    } else if let Some(glow) = self.glow {
+                        data.blur.blur_passes(
+                            self.threadpool,
+                            counts,
+                            width,
+                            height,
+                            glow.radius,
+                            glow.passes,
+                        );
+                        render::glow(self.threadpool, &data.blur, &mut pixel_buffer, tone);
+                    } else if self.equalize {
+                        data.equalizer.equalize(self.threadpool, counts);
+                        let tone = Tone {
+                            brightness: 1.0,
+                            ..tone
+                        };
+                        let values = data.equalizer.values();
+                        render::colorize(
+                            self.threadpool,
+                            values,
+                            &mut pixel_buffer,
+                            width,
+                            height,
+                            tone,
+                        );
+                        if deep {
+                            let pool = self.threadpool;
+                            data.deep
+                                .colorize(pool, values, &pixel_buffer, width, height, tone);
+                        }
+                    } else {
+                        render::colorize(
+                            self.threadpool,
+                            counts,
+                            &mut pixel_buffer,
+                            width,
+                            height,
+                            tone,
+                        );
+                        if deep {
+                            let pool = self.threadpool;
+                            data.deep
+                                .colorize(pool, counts, &pixel_buffer, width, height, tone);
+                        }
+                    }
+                    Density::U16(&data.count_buffer)
+                };
+                if self.motion_vectors || self.direction_hue.is_some() {
+                    self.motion.clear(width, height);
+                    data.particles.count_motion(&self.motion, width, height);
+                }
+                if let Some(settings) = self.direction_hue {
+                    render::direction_hue(
+                        self.threadpool,
+                        &self.motion,
+                        &mut pixel_buffer,
+                        settings,
+                    );
+                }
+                let players = data
+                    .particles
+                    .keyboard_attractor
+                    .map(|keyboard| [self.mouse_pos, keyboard]);
+                if let Some(duel) = &mut self.duel
+                    && let Some(players) = players
+                {
+                    duel.update(density, width, height, players);
+                    game::tint(self.threadpool, &mut pixel_buffer, width, players);
+                }
+                if let Some(trails) = &mut self.trails {
+                    trails.record(&data.particles.particles);
+                    let counts = data.trails.clear(width, height);
+                    trails.draw(self.threadpool, counts, width, height);
+                    data.trails.composite(
+                        self.threadpool,
+                        tone,
+                        self.layers.trails,
+                        &mut pixel_buffer,
+                        width,
+                        height,
+                    );
+                }
+                if let Some(layer) = &mut data.layer {
+                    layer.step(
+                        self.threadpool,
+                        &frametime,
+                        (self.mouse_pos, attracting),
+                        &mut pixel_buffer,
+                        width,
+                        height,
+                    );
+                }
+                render::add_background(
+                    self.threadpool,
+                    &mut pixel_buffer,
+                    width,
+                    height,
+                    self.background,
                 );
 
-                let particles_chunks = data.particles.particles.chunks(particles_chunk_len);
-
-                let count_buffer_ref = &data.count_buffer;
-
-                self.threadpool.scoped(|scope| {
-                    for particles_chunk in particles_chunks {
-                        scope.execute(move |_| {
-                            for particle in particles_chunk {
-                                for (x, y) in particle
-                                    .x
-                                    .as_array()
-                                    .iter()
-                                    .zip(particle.y.as_array().iter())
-                                {
-                                    let inside = *x >= 0.0
-                                        && *x < (width as f32 - 1.0)
-                                        && *y >= 0.0
-                                        && *y < (height as f32 - 1.0);
-
-                                    let x = (*x as usize).clamp(0, width as usize - 1);
-                                    let y = (*y as usize).clamp(0, height as usize - 1);
-
-                                    count_buffer_ref[x + y * width as usize]
-                                        .fetch_add(inside as u16, Ordering::Relaxed);
+                if let Some((started, from)) = &self.fade_from {
+                    let t = started.elapsed().as_secs_f32() / demo::FADE.as_secs_f32();
+                    if t >= 1.0 || from.len() != pixel_buffer.len() {
+                        self.fade_from = None;
+                    } else {
+                        render::crossfade(self.threadpool, from, &mut pixel_buffer, t);
+                    }
+                }
+                if let Some(duel) = &self.duel
+                    && players.is_some()
+                {
+                    duel.draw_scores(&mut pixel_buffer, width, height);
+                }
+                #[cfg(feature = "metrics")]
+                let rendered = Instant::now();
+                let mut settings = None;
+                if let Some(demo) = &mut self.demo
+                    && let Some((name, scene)) = demo.poll(now)
+                {
+                    println!("demo: {name}");
+                    self.fade_from = Some((now, pixel_buffer.to_vec()));
+                    scene.apply(&mut data.particles, width, height);
+                    data.layer = LayerSim::of(self.threadpool, scene, width, height);
+                    self.timeline = scene.timeline.clone().map(Playback::new);
+                    settings = Some((scene.boundary, scene.render.clone()));
+                }
+                if let Some(state) = self.resumed.take() {
+                    println!("resumed {} particles", units::count(state.states.len()));
+                    data.particles.load_states(&state.states_in(width, height));
+                    state
+                        .parameters
+                        .forces
+                        .apply(&mut data.particles, width, height);
+                    data.particles.symmetry = state.parameters.render.symmetry;
+                    settings = Some((state.parameters.boundary, state.parameters.render));
+                }
+                if let Some(parameters) = self.pasted.take() {
+                    println!("pasted parameters");
+                    parameters.forces.apply(&mut data.particles, width, height);
+                    data.particles.symmetry = parameters.render.symmetry;
+                    settings = Some((parameters.boundary, parameters.render));
+                }
+                if let Some(scene) = self.dropped_scene.take() {
+                    self.fade_from = Some((now, pixel_buffer.to_vec()));
+                    scene.apply(&mut data.particles, width, height);
+                    data.layer = LayerSim::of(self.threadpool, &scene, width, height);
+                    self.timeline = scene.timeline.clone().map(Playback::new);
+                    settings = Some((scene.boundary, scene.render.clone()));
+                    self.scene = Some(scene);
+                }
+                if let Some(watcher) = &mut self.scene_watcher
+                    && now >= self.watched_at + WATCH_INTERVAL
+                {
+                    self.watched_at = now;
+                    match watcher.poll() {
+                        Some(Ok(mut scene)) => {
+                            println!("reloaded {}", watcher.path().display());
+                            match &self.scene {
+                                Some(previous) => {
+                                    // Not part of the file, see `--sdf-image`.
+                                    scene.sdf_image.clone_from(&previous.sdf_image);
+                                    scene.reapply(previous, &mut data.particles, width, height);
                                 }
+                                None => scene.apply(&mut data.particles, width, height),
                             }
-                        });
+                            // An unchanged layer keeps its particles.
+                            if self
+                                .scene
+                                .as_ref()
+                                .is_none_or(|previous| previous.layer != scene.layer)
+                            {
+                                data.layer = LayerSim::of(self.threadpool, &scene, width, height);
+                            }
+                            // An unchanged timeline keeps playing.
+                            if self
+                                .scene
+                                .as_ref()
+                                .is_none_or(|previous| previous.timeline != scene.timeline)
+                            {
+                                self.timeline = scene.timeline.clone().map(Playback::new);
+                            }
+                            settings = Some((scene.boundary, scene.render.clone()));
+                            self.scene = Some(scene);
+                        }
+                        Some(Err(err)) => {
+                            eprintln!("failed to reload {}: {err}", watcher.path().display())
+                        }
+                        None => {}
                     }
-                });
+                }
+                if let Some((boundary, render)) = settings {
+                    self.boundary = boundary;
+                    self.palette = render.palette;
+                    self.brightness_multiplier = render.brightness;
+                    self.metaballs = render.metaballs;
+                    self.glow = render.glow;
+                    self.direction_hue = render.direction_hue;
+                    self.dither = render.dither;
+                    self.srgb = render.srgb;
+                    self.accessibility = render.accessibility;
+                    self.equalize = render.equalize;
+                    self.background = render.background;
+                    self.layers = render.layers;
+                    self.palette_cycle = render.cycle;
+                }
+                if let Some(timeline) = &mut self.timeline {
+                    let values = timeline.advance(frametime.as_secs_f32());
+                    if let Some(gravity) = values.gravity {
+                        data.particles.gravity = gravity;
+                    }
+                    if let Some(time_scale) = values.time_scale {
+                        data.particles.time_scale = time_scale;
+                    }
+                    if let Some(rate) = values.spawn_rate {
+                        for inflow in &mut data.particles.inflows {
+                            inflow.rate = rate;
+                        }
+                    }
+                    if let Some(palette) = values.palette {
+                        self.palette = palette;
+                    }
+                }
 
-                let mut pixel_buffer = data.surface.buffer_mut().unwrap();
+                render::accessibility(self.threadpool, &mut pixel_buffer, self.accessibility);
+                if let Some(watermark) = &self.watermark {
+                    watermark.draw(&mut pixel_buffer, width, height);
+                }
 
-                let pixel_chunk_len = usize::max(
-                    pixel_buffer.len() / self.threadpool.thread_count() as usize / 10,
-                    1,
-                );
+                // The passes write 0x00RRGGBB, which softbuffer takes everywhere.
+                const _: () = assert!(matches!(PixelFormat::SOFTBUFFER, PixelFormat::Xrgb8888));
+                let frame = Frame {
+                    id: self.n_frame.into(),
+                    width,
+                    height,
+                    pixels: &pixel_buffer,
+                    density,
+                    motion: self.motion_vectors.then_some(&self.motion),
+                    pixels_10bit: data.deep.finish(self.threadpool, &pixel_buffer),
+                };
+                for sink in &mut self.sinks {
+                    sink.publish(&frame);
+                }
+                if let Some(exposure) = &mut self.exposure {
+                    exposure.publish(&frame);
+                }
+                #[cfg(feature = "script")]
+                if let Some(force) = &mut data.particles.script_force {
+                    force.set_density(frame.density, width, height);
+                }
+                if std::mem::take(&mut self.export_density) {
+                    let stem = format!("density-{}", self.n_frame);
+                    let counts = frame.density.iter().collect::<Vec<_>>();
+                    self.io.run(move || {
+                        let result = export::save(width, height, &counts, &stem);
+                        (
+                            format!("the density to {stem}.png, {stem}.exr and {stem}.pfm"),
+                            result,
+                        )
+                    });
+                }
 
-                let pixel_buffer_chunks = pixel_buffer
-                    .chunks_exact_mut(pixel_chunk_len)
-                    .collect::<Vec<_>>();
+                // Drawn after the sinks, so they stay out of the recordings.
+                if self.stats || self.help {
+                    data.overlay.clear();
+                    data.overlay.resize((width * height) as usize, 0);
+                }
+                if self.stats {
+                    let stats = format!(
+                        "{}  {}  {}",
+                        units::count(data.particles.len()),
+                        units::frametime(frametime_avg, self.frame_unit),
+                        units::gravity(data.particles.gravity)
+                    );
+                    let left =
+                        width.saturating_sub(overlay::text_width(&stats, HELP_SCALE) + HELP_MARGIN);
+                    let top = height.saturating_sub(overlay::line_height(HELP_SCALE) + HELP_MARGIN);
+                    overlay::draw_text(
+                        &mut data.overlay,
+                        width,
+                        height,
+                        (left, top),
+                        HELP_SCALE,
+                        0xFFFFFFFF,
+                        &stats,
+                    );
+                }
+                if self.help {
+                    let particles = &data.particles;
+                    let mut lines = self.keybindings.help_lines();
+                    lines.extend([
+                        String::new(),
+                        format!("particles: {}", units::count(particles.len())),
+                        format!("gravity: {}", units::gravity(particles.gravity)),
+                        format!("friction: {:.3}", particles.friction),
+                        format!("time scale: {:.2}", particles.time_scale),
+                        format!("temperature: {:.2}", particles.temperature),
+                        format!("brightness: {:.1}", self.brightness_multiplier),
+                        format!("palette: {:?}", self.palette),
+                        format!("boundary: {:?}", self.boundary),
+                    ]);
+                    draw_help(&mut data.overlay, width, height, &lines);
+                }
+                if self.stats || self.help {
+                    render::composite(
+                        self.threadpool,
+                        &data.overlay,
+                        &mut pixel_buffer,
+                        self.layers.overlay,
+                    );
+                }
 
-                let count_buffer_chunks = data
-                    .count_buffer
-                    .chunks_exact_mut(pixel_chunk_len)
-                    .collect::<Vec<_>>();
+                #[cfg(feature = "metrics")]
+                let presenting = Instant::now();
+                if let Err(err) = pixel_buffer.present() {
+                    eprintln!("failed to present the frame: {err}");
+                    data.surface_lost = true;
+                }
 
-                self.threadpool.scoped(|scope| {
-                    let ab = self.brightness_multiplier;
-                    for (i_chunk, (pixel_buffer_chunk, count_buffer_chunk)) in pixel_buffer_chunks
-                        .into_iter()
-                        .zip(count_buffer_chunks.into_iter())
-                        .enumerate()
-                    {
-                        scope.execute(move |_| {
-                            for (i_pixel, (pixel, count)) in pixel_buffer_chunk
-                                .iter_mut()
-                                .zip(count_buffer_chunk.iter_mut())
-                                .enumerate()
-                            {
-                                let count = *count.get_mut() as f32 * ab;
-                                let count_upper = (count - 255.0).max(0.0) / 5.0;
-                                let count = count.min(255.0);
-
-                                let index = i_chunk * pixel_chunk_len + i_pixel;
-                                let x = (index % width as usize) as f32 / width as f32;
-                                let y = (index / width as usize) as f32 / height as f32;
-                                let red = (x * count + count_upper) as u8;
-                                let green = (y * count + count_upper) as u8;
-                                let blue = ((1.0 - x) * (1.0 - y) * count + count_upper) as u8;
-                                *pixel =
-                                    ((red as u32) << 16) + ((green as u32) << 8) + (blue as u32)
-                            }
-                        });
+                // A view whose surface fails is closed, the others go on.
+                let mut failed = Vec::new();
+                let mut buffers = Vec::new();
+                for (index, view) in data.views.iter_mut().enumerate() {
+                    if view.size.0 == 0 || view.size.1 == 0 {
+                        continue;
                     }
-                });
-
-                pixel_buffer.present().unwrap();
+                    match view.surface.buffer_mut() {
+                        Ok(buffer) => buffers.push((index, buffer, &mut view.view, view.size)),
+                        Err(err) => {
+                            eprintln!("failed to get the buffer of a view: {err}");
+                            failed.push(index);
+                        }
+                    }
+                }
+                let mut frames = buffers
+                    .iter_mut()
+                    .map(|(_, buffer, view, size)| (&mut **view, &mut **buffer, *size))
+                    .collect::<Vec<_>>();
+                view::render_views(
+                    self.threadpool,
+                    &mut frames,
+                    density,
+                    (width, height),
+                    self.brightness_multiplier,
+                );
+                drop(frames);
+                for (index, buffer, ..) in buffers {
+                    if let Err(err) = buffer.present() {
+                        eprintln!("failed to present a view: {err}");
+                        failed.push(index);
+                    }
+                }
+                failed.sort_unstable();
+                for index in failed.into_iter().rev() {
+                    data.views.remove(index);
+                    println!("views: {}", data.views.len());
+                }
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &self.metrics {
+                    metrics.record(
+                        frametime,
+                        Duration::from_secs_f32(self.target_frametime / 1000.0),
+                        1000.0 / frametime_avg,
+                        data.particles.len(),
+                        [
+                            counted - now,
+                            rendered - counted,
+                            presenting - rendered,
+                            presenting.elapsed(),
+                        ],
+                    );
+                }
+                #[cfg(feature = "audio")]
+                if let Some(audio) = &self.audio {
+                    let radius = particles::audio::RADIUS;
+                    let (near, energy) = data.particles.activity(self.mouse_pos, radius);
+                    let n = data.particles.len().max(1) as f32;
+                    let area = std::f32::consts::PI * radius * radius / (width * height) as f32;
+                    audio.set(particles::audio::Controls::of(
+                        near as f32 / (n * area),
+                        energy / n,
+                    ));
+                }
+                #[cfg(feature = "websocket")]
+                if let Some(feed) = &self.websocket {
+                    feed.set(particles::websocket::Stats {
+                        fps: 1000.0 / frametime_avg,
+                        particles: data.particles.len(),
+                        gravity: data.particles.gravity,
+                        friction: data.particles.friction,
+                        time_scale: data.particles.time_scale,
+                        brightness: self.brightness_multiplier,
+                        palette: Palette::ALL
+                            .iter()
+                            .position(|&palette| palette == self.palette)
+                            .unwrap_or(0),
+                    });
+                }
+                if let Some((path, every)) = self.autosave.clone()
+                    && now >= self.autosaved_at + every
+                    && let Some(parameters) = self.parameters()
+                    && let Some(data) = &self.data
+                {
+                    self.autosaved_at = now;
+                    // Only the copy is taken here, the states are collected
+                    // off the event loop.
+                    let snapshot = data.particles.snapshot();
+                    self.io.run(move || {
+                        let state = autosave::State {
+                            parameters,
+                            width,
+                            height,
+                            states: snapshot.states().collect(),
+                        };
+                        let result = state.save(&path);
+                        (format!("the autosave to {path}"), result)
+                    });
+                }
             }
             _ => (),
         }
     }
+
+    /// Sent by the watchdog when no frame came for too long.
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, (): ()) {
+        if let Some(data) = &mut self.data {
+            self.frametime_buffer.clear();
+            data.recreate_surface();
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if !self.occluded {
+            return;
+        }
+        let Some(data) = &mut self.data else {
+            return;
+        };
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.beat();
+        }
+        let now = Instant::now();
+        if now >= self.last_frametime + BACKGROUND_TICK {
+            let elapsed = now.duration_since(self.last_frametime);
+            self.last_frametime = now;
+            let steps = elapsed.div_duration_f32(BACKGROUND_STEP).ceil().max(1.0);
+            let step = elapsed.div_f32(steps);
+            for _ in 0..steps as u32 {
+                data.particles.update(&step, self.mouse_pos, false);
+            }
+        }
+        event_loop.set_control_flow(ControlFlow::WaitUntil(
+            self.last_frametime + BACKGROUND_TICK,
+        ));
+    }
+}
+
+/// Fits the fields, shapes and the center attractor of `particles` to a
+/// `width` x `height` window.
+fn resize_fields(particles: &mut Particles, width: u32, height: u32) {
+    if let Some(obstacles) = &mut particles.obstacles {
+        obstacles.resize(width, height);
+    }
+    if let Some(field) = &mut particles.distance_field {
+        field.resize(width, height);
+    }
+    if let Some(portals) = &mut particles.portals {
+        portals.resize(width, height);
+    }
+    if let Some(mask) = &mut particles.freeze_mask {
+        mask.resize(width, height);
+    }
+    #[cfg(feature = "script")]
+    if let Some(force) = &mut particles.script_force {
+        force.resize(width, height);
+    }
+    if let Some(center) = &mut particles.center_attractor {
+        *center = (width as f32 / 2.0, height as f32 / 2.0);
+    }
+    if let Some(field) = &mut particles.velocity_field {
+        field.resize(width, height);
+    }
+    if let Some(field) = &mut particles.charge_field {
+        field.resize(width, height);
+    }
+    if let Some(field) = &mut particles.heat_field {
+        field.resize(width, height);
+    }
+}
+
+/// Handles the events of the view window `views[index]`: the mouse wheel
+/// zooms, P switches the palette, R shows the whole `world` again and
+/// closing the window removes the view. The views are drawn along with
+/// the main window.
+fn view_event(views: &mut Vec<ViewWindow>, index: usize, world: (u32, u32), event: WindowEvent) {
+    let view = &mut views[index];
+    match event {
+        WindowEvent::CloseRequested => {
+            views.remove(index);
+            println!("views: {}", views.len());
+        }
+        WindowEvent::Resized(size) => {
+            view.size = (size.width, size.height);
+            if let (Some(width), Some(height)) =
+                (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+                && let Err(err) = view.surface.resize(width, height)
+            {
+                eprintln!("failed to resize a view: {err}");
+                views.remove(index);
+                println!("views: {}", views.len());
+            }
+        }
+        WindowEvent::CursorMoved { position, .. } => {
+            view.cursor = (position.x as f32, position.y as f32);
+        }
+        WindowEvent::MouseWheel {
+            delta: MouseScrollDelta::LineDelta(_, vertical),
+            ..
+        } => {
+            let anchor = (
+                view.cursor.0 / view.size.0.max(1) as f32,
+                view.cursor.1 / view.size.1.max(1) as f32,
+            );
+            let viewport = view.view.viewport(world.0, world.1);
+            view.view.viewport = Some(viewport.zoom(anchor, 1.0 - vertical * 0.1));
+        }
+        WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    logical_key: Key::Character(ref key),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+            ..
+        } => match key.as_str() {
+            "p" => {
+                view.view.palette = next_palette(view.view.palette, 1);
+                println!("view palette: {:?}", view.view.palette);
+            }
+            "r" => view.view.viewport = None,
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// Pressure and lean of a touch with `force`. Platforms only report the
+/// altitude of the pen, so it leans along the `direction` it is drawn in.
+fn pen(force: Option<Force>, direction: (f32, f32)) -> Pen {
+    let pressure = force.map_or(1.0, |force| force.normalized().clamp(0.0, 1.0) as f32);
+    let lean = match force {
+        Some(Force::Calibrated {
+            altitude_angle: Some(altitude),
+            ..
+        }) => altitude.cos() as f32 * PEN_TILT_BIAS,
+        _ => 0.0,
+    };
+    Pen {
+        pressure,
+        bias: (direction.0 * lean, direction.1 * lean),
+    }
+}
+
+/// Runs the action of a drawn `gesture`: a circle paints a vortex into the
+/// currents, a Z clears the currents, portals, frozen regions and wind, and
+/// a line blows the wind along it.
+fn apply_gesture(data: &mut AppData, gesture: Gesture) {
+    let particles = &mut data.particles;
+    match gesture {
+        Gesture::Circle {
+            center,
+            radius,
+            clockwise,
+        } => {
+            let (width, height) = data.size;
+            let field = particles
+                .velocity_field
+                .get_or_insert_with(|| VelocityField::new(width, height, BRUSH_CELL));
+            let speed = if clockwise {
+                VORTEX_SPEED
+            } else {
+                -VORTEX_SPEED
+            };
+            let dabs = (radius * f32::consts::TAU / BRUSH_CELL).ceil().max(8.0) as usize;
+            for i in 0..dabs {
+                let (sin, cos) = (i as f32 / dabs as f32 * f32::consts::TAU).sin_cos();
+                let pos = (center.0 + radius * cos, center.1 + radius * sin);
+                field.paint(pos, (-sin * speed, cos * speed), BRUSH_CELL);
+            }
+            println!("vortex at {center:?}");
+        }
+        Gesture::Z => {
+            particles.velocity_field = None;
+            particles.portals = None;
+            particles.freeze_mask = None;
+            particles.wind = None;
+            println!("cleared");
+        }
+        Gesture::Line { direction } => {
+            particles.wind = Some((direction.0 * GESTURE_WIND, direction.1 * GESTURE_WIND));
+            println!("wind: {:?}", particles.wind);
+        }
+    }
+}
+
+/// Duration in milliseconds of a refresh of the monitor `window` is on,
+/// if known and within `REFRESH_FRAMETIME_RANGE`.
+fn refresh_frametime(window: &Window) -> Option<f32> {
+    let millihertz = window.current_monitor()?.refresh_rate_millihertz()?;
+    let (min, max) = REFRESH_FRAMETIME_RANGE;
+    Some(1_000_000.0 / millihertz as f32).filter(|frametime| (min..=max).contains(frametime))
+}
+
+/// The palette `steps` after `palette` in `Palette::ALL`.
+fn next_palette(palette: Palette, steps: usize) -> Palette {
+    let index = Palette::ALL.iter().position(|&p| p == palette).unwrap_or(0);
+    Palette::from_index(index + steps)
 }
 
-pub fn run() {
+pub fn run(options: Options) {
     let event_loop = EventLoop::new().unwrap();
 
     // ControlFlow::Poll continuously runs the event loop, even if the OS hasn't
     // dispatched any events. This is ideal for games and similar applications.
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let n_threads = available_parallelism().unwrap().get();
+    let n_threads = options
+        .threads
+        .unwrap_or_else(|| available_parallelism().unwrap().get())
+        .max(1);
+    #[cfg(feature = "numa")]
+    let threadpool = if options.numa {
+        particles::numa::pinned_pool(n_threads)
+    } else {
+        Pool::new(n_threads)
+    };
+    #[cfg(not(feature = "numa"))]
     let threadpool = Pool::new(n_threads);
-    let mut app = App::new(&threadpool);
+
+    let (command_tx, command_rx) = mpsc::channel();
+    #[cfg(feature = "osc")]
+    {
+        let addr = options
+            .osc_addr
+            .as_deref()
+            .unwrap_or(particles::osc::DEFAULT_ADDR);
+        match particles::osc::spawn_listener(addr, command_tx.clone()) {
+            Ok(_) => println!("listening for OSC on {addr}"),
+            Err(err) => eprintln!("failed to start OSC listener on {addr}: {err}"),
+        }
+    }
+    #[cfg(feature = "midi")]
+    let midi =
+        match particles::midi::spawn_listener(options.midi_port.as_deref(), command_tx.clone()) {
+            Ok(midi) => {
+                println!("reading MIDI from {}, press L to learn", midi.port());
+                Some(midi)
+            }
+            Err(err) => {
+                eprintln!("failed to open MIDI input: {err}");
+                if let Ok(names) = particles::midi::port_names() {
+                    eprintln!("MIDI input ports: {names:?}");
+                }
+                None
+            }
+        };
+    #[cfg(feature = "udp")]
+    {
+        let addr = options
+            .udp_addr
+            .as_deref()
+            .unwrap_or(particles::udp::DEFAULT_ADDR);
+        match particles::udp::spawn_listener(addr, command_tx.clone()) {
+            Ok(_) => println!("listening for attractors on {addr}"),
+            Err(err) => eprintln!("failed to start UDP attractor feed on {addr}: {err}"),
+        }
+    }
+    #[cfg(feature = "websocket")]
+    let websocket = {
+        let addr = options
+            .websocket_addr
+            .as_deref()
+            .unwrap_or(particles::websocket::DEFAULT_ADDR);
+        let feed = std::sync::Arc::new(particles::websocket::StatsFeed::default());
+        match particles::websocket::spawn_server(addr, command_tx.clone(), feed.clone()) {
+            Ok(_) => {
+                println!("control panel on http://{addr}/, WebSocket commands on ws://{addr}/");
+                Some(feed)
+            }
+            Err(err) => {
+                eprintln!("failed to start the WebSocket remote control on {addr}: {err}");
+                None
+            }
+        }
+    };
+    drop(command_tx);
+
+    let mut app = App::new(&threadpool, command_rx);
+    app.removal_policy = options.removal_policy;
+    app.demo = options
+        .demo
+        .map(|seconds| Demo::new(Duration::from_secs_f32(seconds.max(1.0))));
+    if let Some(scene) = options.scene {
+        app.boundary = scene.boundary;
+        app.palette = scene.render.palette;
+        app.brightness_multiplier = scene.render.brightness;
+        app.metaballs = scene.render.metaballs;
+        app.glow = scene.render.glow;
+        app.direction_hue = scene.render.direction_hue;
+        app.dither = scene.render.dither;
+        app.srgb = scene.render.srgb;
+        app.accessibility = scene.render.accessibility;
+        app.equalize = scene.render.equalize;
+        app.background = scene.render.background;
+        app.layers = scene.render.layers;
+        app.palette_cycle = scene.render.cycle;
+        app.scene = Some(scene);
+    }
+    app.scene_watcher = options.watch.map(SceneWatcher::new);
+    app.max_blocks = options.max_particles.map(|n| n.div_ceil(64));
+    app.u8_counts = options.u8_counts;
+    app.trail_length = options.trail_length;
+    app.recycle_every = options.recycle_every;
+    app.watermark = options.watermark;
+    app.motion_vectors = options.motion_vectors;
+    app.desktop_space = options.desktop_space;
+    app.csv_every = options.csv_every;
+    app.drawing_obstacles = options.draw_obstacles;
+    app.gestures = options.gestures;
+    app.keybindings = options.keybindings;
+    app.autosave = options.autosave.map(|path| (path, options.autosave_every));
+    app.resumed = options.resume;
+    app.watchdog = options.watchdog.map(|timeout| {
+        let proxy = event_loop.create_proxy();
+        Watchdog::spawn(
+            timeout,
+            move || {
+                // Fails only once the loop exited.
+                let _ = proxy.send_event(());
+            },
+            || process::exit(watchdog::EXIT_CODE),
+        )
+    });
+    #[cfg(feature = "numa")]
+    {
+        app.placement = particles::numa::Placement {
+            first_touch: options.numa,
+            huge_pages: options.huge_pages,
+        };
+    }
+    #[cfg(feature = "metrics")]
+    {
+        let addr = options
+            .metrics_addr
+            .as_deref()
+            .unwrap_or(particles::metrics::DEFAULT_ADDR);
+        let metrics = std::sync::Arc::new(particles::metrics::Metrics::default());
+        match particles::metrics::spawn_server(addr, metrics.clone()) {
+            Ok(_) => {
+                println!("serving metrics on http://{addr}/metrics");
+                app.metrics = Some(metrics);
+            }
+            Err(err) => eprintln!("failed to start the metrics endpoint on {addr}: {err}"),
+        }
+    }
+    #[cfg(feature = "midi")]
+    {
+        app.midi = midi;
+    }
+    #[cfg(feature = "websocket")]
+    {
+        app.websocket = websocket;
+    }
+    #[cfg(feature = "script")]
+    {
+        app.script = options.script;
+    }
+    #[cfg(feature = "shm")]
+    if let Some(name) = &options.shm_name {
+        match particles::shm::SharedDensity::create(name) {
+            Ok(shm) => {
+                println!("publishing density to {}", shm.path().display());
+                app.sinks.push(Box::new(shm));
+            }
+            Err(err) => eprintln!("failed to create shared memory {name}: {err}"),
+        }
+    }
+    #[cfg(feature = "shm")]
+    if let Some(name) = &options.shm_pixels {
+        match particles::shm::SharedPixels::create(name, options.shm_format) {
+            Ok(shm) => {
+                println!("publishing pixels to {}", shm.path().display());
+                app.sinks.push(Box::new(shm));
+            }
+            Err(err) => eprintln!("failed to create shared memory {name}: {err}"),
+        }
+    }
+    #[cfg(all(feature = "spout", windows))]
+    if let Some(name) = &options.spout_name {
+        match particles::spout::SpoutSender::new(name) {
+            Ok(sender) => {
+                println!("sharing the frames as the Spout sender {}", sender.name());
+                app.sinks.push(Box::new(sender));
+            }
+            Err(err) => eprintln!("failed to create the Spout sender {name}: {err}"),
+        }
+    }
+    #[cfg(all(feature = "syphon", target_os = "macos"))]
+    if let Some(name) = &options.syphon_name {
+        match particles::syphon::SyphonServer::new(name) {
+            Ok(server) => {
+                println!("sharing the frames as the Syphon server {}", server.name());
+                app.sinks.push(Box::new(server));
+            }
+            Err(err) => eprintln!("failed to start the Syphon server {name}: {err}"),
+        }
+    }
+    #[cfg(feature = "audio")]
+    if options.audio {
+        match particles::audio::AudioOutput::start() {
+            Ok(audio) => {
+                println!(
+                    "playing audio on {} at {} Hz",
+                    audio.device(),
+                    audio.sample_rate()
+                );
+                app.audio = Some(audio);
+            }
+            Err(err) => eprintln!("failed to start the audio output: {err}"),
+        }
+    }
+    #[cfg(feature = "v4l2")]
+    if let Some(device) = &options.v4l2_device {
+        match particles::v4l2::VirtualCamera::open(device, options.v4l2_size) {
+            Ok(camera) => {
+                let (width, height) = options.v4l2_size;
+                println!("virtual camera at {device}, {width}x{height}");
+                app.sinks.push(Box::new(camera));
+            }
+            Err(err) => eprintln!("failed to open the virtual camera {device}: {err}"),
+        }
+    }
     let _ = event_loop.run_app(&mut app);
+    // Waits for the exports still running, then for the workers.
+    drop(app);
+    threadpool.shutdown();
+}
+
+/// Draws `lines` in columns over a translucent backdrop, from the top left
+/// of the `width` x `height` 0xAARRGGBB `pixels` of the overlay.
+fn draw_help(pixels: &mut [u32], width: u32, height: u32, lines: &[String]) {
+    let line_height = overlay::line_height(HELP_SCALE);
+    let rows = ((height.saturating_sub(2 * HELP_MARGIN)) / line_height).max(1) as usize;
+    let mut left = HELP_MARGIN;
+    for column in lines.chunks(rows) {
+        let text_width = column
+            .iter()
+            .map(|line| overlay::text_width(line, HELP_SCALE))
+            .max()
+            .unwrap_or(0);
+        let backdrop = (
+            left - HELP_MARGIN / 2,
+            HELP_MARGIN / 2,
+            text_width + HELP_MARGIN,
+            column.len() as u32 * line_height + HELP_MARGIN,
+        );
+        overlay::fill(pixels, width, height, backdrop, 0xC0000000);
+        for (i, line) in column.iter().enumerate() {
+            let top = HELP_MARGIN + i as u32 * line_height;
+            overlay::draw_text(
+                pixels,
+                width,
+                height,
+                (left, top),
+                HELP_SCALE,
+                0xFFFFFFFF,
+                line,
+            );
+        }
+        left += text_width + 2 * HELP_MARGIN;
+    }
+}
+
+/// Name of `key` in the keybindings, see `keys`.
+fn key_name(key: &Key, modifiers: ModifiersState) -> Option<String> {
+    let name = match key {
+        Key::Character(key) => key.to_lowercase(),
+        Key::Named(key) => format!("{key:?}"),
+        _ => return None,
+    };
+    Some(if modifiers.control_key() {
+        format!("ctrl+{name}")
+    } else {
+        name
+    })
 }
 
 // unsafe fn make_mutable<T>(reference: &T) -> &mut T {
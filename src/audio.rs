@@ -0,0 +1,264 @@
+//! Sonification of the simulation.
+//!
+//! With `--audio` a synthesizer plays along with the particles on the
+//! default output device: the density around the cursor opens the lowpass
+//! filter of a drone, and the kinetic energy of all particles sets its
+//! loudness, so stirring up the particles swells the sound and gathering
+//! them under the cursor makes it brighter.
+//!
+//! The sound goes through cpal, which plays it with ALSA on Linux,
+//! CoreAudio on macOS and WASAPI on Windows, at the sample rate and in the
+//! sample format the device prefers.
+
+use std::f32::consts::PI;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, StreamConfig};
+
+/// Range of the filter cutoff in Hz, the upper bound keeps the filter
+/// stable.
+pub const CUTOFF: (f32, f32) = (80.0, 8000.0);
+
+/// Radius in pixels of the area around the cursor whose density opens the
+/// filter.
+pub const RADIUS: f32 = 64.0;
+
+/// Mean kinetic energy per particle at half the loudness.
+pub const HALF_ENERGY: f32 = 4.0;
+
+/// Frequencies of the sawtooths of the drone in Hz.
+const VOICES: [f32; 4] = [55.0, 55.3, 82.4, 110.5];
+
+/// Time constant of the smoothing of the controls.
+const SMOOTHING: f32 = 0.02;
+
+/// Parameters of the synthesizer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Controls {
+    /// Cutoff of the lowpass filter in Hz.
+    pub cutoff: f32,
+    /// Loudness from `0` to `1`.
+    pub amplitude: f32,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            cutoff: CUTOFF.0,
+            amplitude: 0.0,
+        }
+    }
+}
+
+impl Controls {
+    /// The controls for a `density` around the cursor relative to the mean
+    /// density of the window, and a mean kinetic `energy` per particle.
+    pub fn of(density: f32, energy: f32) -> Self {
+        let octaves = (CUTOFF.1 / CUTOFF.0).log2();
+        let cutoff = CUTOFF.0 * (1.0 + density.max(0.0)).log2().min(octaves).exp2();
+        let energy = energy.max(0.0);
+        Controls {
+            cutoff,
+            amplitude: energy / (energy + HALF_ENERGY),
+        }
+    }
+}
+
+/// The latest `Controls`, shared between the app and the audio thread.
+#[derive(Debug, Default)]
+pub struct ControlFeed {
+    controls: Mutex<Controls>,
+}
+
+impl ControlFeed {
+    pub fn set(&self, controls: Controls) {
+        *self.controls.lock().unwrap() = controls;
+    }
+
+    pub fn get(&self) -> Controls {
+        *self.controls.lock().unwrap()
+    }
+}
+
+/// Detuned sawtooths through a resonant state variable lowpass filter.
+#[derive(Debug, Clone)]
+pub struct Synth {
+    sample_rate: f32,
+    phases: [f32; VOICES.len()],
+    low: f32,
+    band: f32,
+    /// Smoothed controls, they glide towards the set ones.
+    current: Controls,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Self {
+        Synth {
+            sample_rate: sample_rate as f32,
+            phases: Default::default(),
+            low: 0.0,
+            band: 0.0,
+            current: Controls::default(),
+        }
+    }
+
+    /// Fills `out` with samples from -1 to 1 played with `controls`.
+    pub fn fill(&mut self, controls: Controls, out: &mut [f32]) {
+        let glide = 1.0 - (-1.0 / (SMOOTHING * self.sample_rate)).exp();
+        // Keeps the filter stable at low sample rates.
+        let cutoff = controls
+            .cutoff
+            .clamp(CUTOFF.0, CUTOFF.1.min(self.sample_rate / 6.0));
+        let amplitude = controls.amplitude.clamp(0.0, 1.0);
+        for out in out {
+            self.current.cutoff += (cutoff - self.current.cutoff) * glide;
+            self.current.amplitude += (amplitude - self.current.amplitude) * glide;
+            let mut saw = 0.0;
+            for (phase, frequency) in self.phases.iter_mut().zip(VOICES) {
+                saw += *phase * 2.0 - 1.0;
+                *phase = (*phase + frequency / self.sample_rate).fract();
+            }
+            saw /= VOICES.len() as f32;
+            let f = 2.0 * (PI * self.current.cutoff / self.sample_rate).sin();
+            let damping = 0.6;
+            self.low += f * self.band;
+            let high = saw - self.low - damping * self.band;
+            self.band += f * high;
+            *out = (self.low * self.current.amplitude).clamp(-1.0, 1.0);
+        }
+    }
+}
+
+/// The sonification playing on the default output device, until it is
+/// dropped.
+pub struct AudioOutput {
+    feed: Arc<ControlFeed>,
+    device: String,
+    config: StreamConfig,
+    _stream: cpal::Stream,
+}
+
+impl AudioOutput {
+    /// Starts playing, silent until the first `set`.
+    pub fn start() -> io::Result<Self> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no audio output device"))?;
+        let supported = device.default_output_config().map_err(io::Error::other)?;
+        let config = supported.config();
+        let feed = Arc::new(ControlFeed::default());
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, &feed),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, &feed),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, &feed),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, &feed),
+            format => Err(io::Error::other(format!(
+                "unsupported sample format {format}"
+            ))),
+        }?;
+        stream.play().map_err(io::Error::other)?;
+        Ok(AudioOutput {
+            feed,
+            device: device.name().unwrap_or_else(|_| "unknown".into()),
+            config,
+            _stream: stream,
+        })
+    }
+
+    pub fn set(&self, controls: Controls) {
+        self.feed.set(controls);
+    }
+
+    /// Name of the output device.
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+}
+
+/// An output stream of `T` samples playing the controls of `feed`, the
+/// same sample on every channel.
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    feed: &Arc<ControlFeed>,
+) -> io::Result<cpal::Stream> {
+    let channels = usize::from(config.channels.max(1));
+    let mut synth = Synth::new(config.sample_rate.0);
+    let mut samples = Vec::new();
+    let feed = Arc::clone(feed);
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                // Only allocates while the buffers grow.
+                samples.resize(data.len() / channels, 0.0);
+                synth.fill(feed.get(), &mut samples);
+                for (frame, &sample) in data.chunks_mut(channels).zip(&samples) {
+                    frame.fill(T::from_sample(sample));
+                }
+            },
+            |err| eprintln!("audio: {err}"),
+            None,
+        )
+        .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sonifies_activity() {
+        let quiet = Controls::of(0.0, 0.0);
+        assert_eq!(quiet, Controls::default());
+        let loud = Controls::of(1.0, HALF_ENERGY);
+        assert_eq!(loud.amplitude, 0.5);
+        assert_eq!(loud.cutoff, 2.0 * CUTOFF.0);
+        assert!((Controls::of(1e9, 1e9).cutoff - CUTOFF.1).abs() < 1.0);
+
+        // Root mean square of the second half of a second, and of its
+        // differences, which grow with the harmonics.
+        let rms = |controls: Controls| {
+            let mut synth = Synth::new(48_000);
+            let mut out = vec![0.0; 48_000];
+            synth.fill(controls, &mut out);
+            let tail = &out[out.len() / 2..];
+            let rms = |values: &mut dyn Iterator<Item = f32>| {
+                let (sum, n) = values.fold((0.0, 0.0), |(sum, n), v| (sum + v * v, n + 1.0));
+                (sum / n).sqrt()
+            };
+            let level = rms(&mut tail.iter().copied());
+            let change = rms(&mut tail.windows(2).map(|w| w[1] - w[0]));
+            (level, change)
+        };
+        assert_eq!(rms(quiet), (0.0, 0.0));
+        let dull = rms(Controls {
+            cutoff: CUTOFF.0,
+            amplitude: 1.0,
+        });
+        let bright = rms(Controls {
+            cutoff: CUTOFF.1,
+            amplitude: 1.0,
+        });
+        let half = rms(Controls {
+            cutoff: CUTOFF.1,
+            amplitude: 0.5,
+        });
+        // The open filter lets the harmonics through.
+        assert!(bright.1 > dull.1 * 4.0, "{bright:?} {dull:?}");
+        assert!(
+            (half.0 / bright.0 - 0.5).abs() < 0.01,
+            "{half:?} {bright:?}"
+        );
+
+        let feed = ControlFeed::default();
+        feed.set(loud);
+        assert_eq!(feed.get(), loud);
+    }
+}
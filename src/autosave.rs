@@ -0,0 +1,116 @@
+//! Crash-safe autosave of the simulation.
+//!
+//! With `--autosave <path>` the app periodically writes the particles and
+//! the `Parameters` to `path` as JSON, and `--resume` continues from that
+//! file after a crash or a power loss, e.g. in a gallery installation that
+//! restarts the app on boot. Scene files, obstacles and portals come from
+//! the command line as usual.
+//!
+//! A save never leaves a torn file behind: the state is written to a
+//! temporary file next to `path`, flushed to disk and then renamed over
+//! the previous save.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scene::Parameters;
+
+/// Time between the saves unless set with `--autosave-every`.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Everything an autosave restores.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+pub struct State {
+    pub parameters: Parameters,
+    /// Size of the window the particles were saved in.
+    pub width: u32,
+    pub height: u32,
+    /// `[x, y, dx, dy]` of every particle, see `Particles::load_states`.
+    pub states: Vec<[f32; 4]>,
+}
+
+impl State {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        serde_json::from_reader(file).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Replaces `path` with the state, see the module documentation.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let file = File::create(&temporary)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&temporary, path)?;
+        // Persist the rename, which is an entry of the directory.
+        #[cfg(unix)]
+        if let Some(dir) = path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// The particle states with their positions scaled from the saved
+    /// window size to `width` by `height`.
+    pub fn states_in(&self, width: u32, height: u32) -> Vec<[f32; 4]> {
+        if (width, height) == (self.width, self.height) || self.width == 0 || self.height == 0 {
+            return self.states.clone();
+        }
+        let sx = width as f32 / self.width as f32;
+        let sy = height as f32 / self.height as f32;
+        self.states
+            .iter()
+            .map(|&[x, y, dx, dy]| [x * sx, y * sy, dx, dy])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::Boundary;
+
+    #[test]
+    fn saves_and_resumes() {
+        let dir = std::env::temp_dir().join(format!("particles-autosave-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("autosave.json");
+        let mut state = State {
+            width: 200,
+            height: 100,
+            states: vec![[10.0, 20.0, 1.0, -1.0], [150.0, 50.0, 0.5, 0.0]],
+            ..State::default()
+        };
+        state.parameters.boundary = Boundary::Wrap;
+        state.save(&path).unwrap();
+        // Overwriting leaves no temporary file behind.
+        state.states.pop();
+        state.save(&path).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let resumed = State::load(&path).unwrap();
+        assert_eq!(resumed, state);
+        assert_eq!(resumed.states_in(200, 100), state.states);
+        assert_eq!(resumed.states_in(400, 50), [[20.0, 10.0, 1.0, -1.0]]);
+
+        fs::write(&path, "{\"width\": 1").unwrap();
+        assert_eq!(
+            State::load(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! The `--bench` mode of the app.
+//!
+//! Runs the benchmark scenarios of `headless` one after the other, each
+//! being a phase of the workload, and times the update, count and pixel
+//! passes of every frame. Each phase is warmed up for `WARMUP_FRAMES`
+//! untimed frames first, so the caches and the worker threads are hot,
+//! then measured for its share of the total duration. The `Report`
+//! serializes to JSON, so runs on different machines or with different
+//! settings can be compared by scripts.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::headless::{Headless, Scenario};
+use crate::scoped_threadpool::Pool;
+
+/// Untimed frames stepped before each phase is measured.
+pub const WARMUP_FRAMES: u32 = 30;
+
+/// Statistics of the durations of one pass, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Timing {
+    pub avg_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Timing {
+    /// Statistics of `samples`, which must not be empty. Percentiles use the
+    /// nearest rank.
+    pub fn of(samples: &mut [Duration]) -> Self {
+        samples.sort_unstable();
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        let percentile = |p: f64| {
+            let rank = (p * samples.len() as f64).ceil() as usize;
+            millis(samples[rank.clamp(1, samples.len()) - 1])
+        };
+        let total: Duration = samples.iter().sum();
+        Timing {
+            avg_ms: millis(total) / samples.len() as f64,
+            p50_ms: percentile(0.50),
+            p95_ms: percentile(0.95),
+            p99_ms: percentile(0.99),
+            max_ms: millis(samples[samples.len() - 1]),
+        }
+    }
+}
+
+/// Measurements of one scenario.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Phase {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// Number of simulated particles.
+    pub particles: usize,
+    /// Number of measured frames.
+    pub frames: usize,
+    pub update: Timing,
+    pub count: Timing,
+    pub pixels: Timing,
+    /// The three passes together.
+    pub frame: Timing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub threads: usize,
+    pub seconds: f32,
+    pub phases: Vec<Phase>,
+}
+
+/// Runs `scenarios` for `duration` in total, split evenly among them. Every
+/// phase measures at least one frame.
+pub fn run(pool: &Pool, scenarios: Vec<Scenario>, duration: Duration) -> Report {
+    let budget = duration / scenarios.len().max(1) as u32;
+    let phases = scenarios
+        .into_iter()
+        .map(|scenario| {
+            let (name, width, height) = (scenario.name, scenario.width, scenario.height);
+            let mut sim = Headless::new(pool, scenario);
+            for _ in 0..WARMUP_FRAMES {
+                sim.step();
+            }
+            let mut update = Vec::new();
+            let mut count = Vec::new();
+            let mut pixels = Vec::new();
+            let mut frame = Vec::new();
+            let start = Instant::now();
+            while frame.is_empty() || start.elapsed() < budget {
+                update.push(time(|| sim.update()));
+                count.push(time(|| sim.count()));
+                pixels.push(time(|| sim.colorize()));
+                frame.push(
+                    update[update.len() - 1] + count[count.len() - 1] + pixels[pixels.len() - 1],
+                );
+            }
+            Phase {
+                name,
+                width,
+                height,
+                particles: sim.particles.len(),
+                frames: frame.len(),
+                update: Timing::of(&mut update),
+                count: Timing::of(&mut count),
+                pixels: Timing::of(&mut pixels),
+                frame: Timing::of(&mut frame),
+            }
+        })
+        .collect();
+    Report {
+        threads: pool.thread_count() as usize,
+        seconds: duration.as_secs_f32(),
+        phases,
+    }
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_phases() {
+        let mut samples: Vec<_> = (1..=100).rev().map(Duration::from_millis).collect();
+        let timing = Timing::of(&mut samples);
+        assert_eq!(timing.avg_ms, 50.5);
+        assert_eq!(timing.p50_ms, 50.0);
+        assert_eq!(timing.p95_ms, 95.0);
+        assert_eq!(timing.p99_ms, 99.0);
+        assert_eq!(timing.max_ms, 100.0);
+        assert_eq!(Timing::of(&mut [Duration::from_millis(3)]).p50_ms, 3.0);
+
+        let pool = Pool::new(2);
+        let report = run(&pool, vec![Scenario::small()], Duration::ZERO);
+        let phase = &report.phases[0];
+        assert_eq!((phase.name, phase.frames), ("small", 1));
+        assert!(phase.particles > 0);
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["phases"][0]["frame"]["p99_ms"].is_number());
+    }
+}
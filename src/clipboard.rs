@@ -0,0 +1,34 @@
+//! Text on the system clipboard.
+//!
+//! The clipboard is reached through `arboard`. One `Clipboard` is kept for
+//! the lifetime of the app, since on X11 the copied text is served by that
+//! handle and would vanish with it.
+
+use std::io;
+use std::sync::Mutex;
+
+use arboard::Clipboard;
+
+static CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+/// Puts `text` on the clipboard.
+pub fn copy(text: &str) -> io::Result<()> {
+    with_clipboard(|clipboard| clipboard.set_text(text))
+}
+
+/// The text on the clipboard.
+pub fn paste() -> io::Result<String> {
+    with_clipboard(Clipboard::get_text)
+}
+
+/// Runs `run` with the clipboard, opening it on first use.
+fn with_clipboard<T>(
+    run: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>,
+) -> io::Result<T> {
+    let mut clipboard = CLIPBOARD.lock().unwrap_or_else(|err| err.into_inner());
+    let clipboard = match &mut *clipboard {
+        Some(clipboard) => clipboard,
+        None => clipboard.insert(Clipboard::new().map_err(io::Error::other)?),
+    };
+    run(clipboard).map_err(io::Error::other)
+}
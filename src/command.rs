@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::particles::Attractor;
+
+/// Range of the attractor strength set from remote controls.
+pub const GRAVITY_RANGE: (f32, f32) = (-5.0, 10.0);
+
+/// Range of the brightness multiplier set with the mouse wheel and from
+/// remote controls.
+pub const BRIGHTNESS_RANGE: (f32, f32) = (0.5, 50.0);
+
+/// Range of the fraction of velocity kept per frame set from remote
+/// controls.
+pub const FRICTION_RANGE: (f32, f32) = (0.0, 1.0);
+
+/// Range of the simulation speed set from remote controls.
+pub const TIME_SCALE_RANGE: (f32, f32) = (0.0, 4.0);
+
+/// Most particles spawned by one command from a remote control.
+pub const MAX_SPAWN: usize = 1_000_000;
+
+/// Most external attractors set by one command from a remote control.
+pub const MAX_ATTRACTORS: usize = 64;
+
+/// How long attractors set with `SimCommand::SetAttractors` stay alive.
+pub const ATTRACTOR_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Commands that change the running simulation from outside the event loop,
+/// e.g. from remote control listeners. In JSON they are written like
+/// `{ "set_gravity": 2.5 }`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimCommand {
+    /// Sets the attractor strength, `1.0` being the default.
+    SetGravity(f32),
+    /// Sets the fraction of velocity kept per 60 Hz frame.
+    SetFriction(f32),
+    /// Sets the simulation speed, `1.0` being realtime.
+    SetTimeScale(f32),
+    /// Selects the palette at the given index, see `Palette::from_index`.
+    SetPalette(usize),
+    /// Sets the brightness multiplier of the density field.
+    SetBrightness(f32),
+    /// Spawns (at least) the given number of particles.
+    Spawn(usize),
+    /// Replaces the external attractors. Coordinates are normalized to the
+    /// window; the attractors expire after `ATTRACTOR_TIMEOUT` unless they
+    /// are renewed.
+    SetAttractors(Vec<Attractor>),
+}
+
+impl SimCommand {
+    /// The command with its values clamped to the ranges above, or `None`
+    /// if one of them is not finite. Every command from outside the event
+    /// loop is applied through this, whichever listener it came from.
+    pub fn validated(self) -> Option<SimCommand> {
+        let clamp =
+            |value: f32, (min, max): (f32, f32)| value.is_finite().then(|| value.clamp(min, max));
+        Some(match self {
+            SimCommand::SetGravity(gravity) => {
+                SimCommand::SetGravity(clamp(gravity, GRAVITY_RANGE)?)
+            }
+            SimCommand::SetFriction(friction) => {
+                SimCommand::SetFriction(clamp(friction, FRICTION_RANGE)?)
+            }
+            SimCommand::SetTimeScale(time_scale) => {
+                SimCommand::SetTimeScale(clamp(time_scale, TIME_SCALE_RANGE)?)
+            }
+            SimCommand::SetPalette(index) => SimCommand::SetPalette(index),
+            SimCommand::SetBrightness(brightness) => {
+                SimCommand::SetBrightness(clamp(brightness, BRIGHTNESS_RANGE)?)
+            }
+            SimCommand::Spawn(n) => SimCommand::Spawn(n.min(MAX_SPAWN)),
+            SimCommand::SetAttractors(mut attractors) => {
+                attractors.truncate(MAX_ATTRACTORS);
+                for attractor in &mut attractors {
+                    if !(attractor.x.is_finite() && attractor.y.is_finite()) {
+                        return None;
+                    }
+                    attractor.strength = clamp(attractor.strength, GRAVITY_RANGE)?;
+                }
+                SimCommand::SetAttractors(attractors)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation_clamps_and_rejects() {
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(SimCommand::SetFriction(value).validated(), None);
+            assert_eq!(SimCommand::SetTimeScale(value).validated(), None);
+        }
+        assert_eq!(
+            SimCommand::SetGravity(1e9).validated(),
+            Some(SimCommand::SetGravity(GRAVITY_RANGE.1))
+        );
+        assert_eq!(
+            SimCommand::SetBrightness(0.0).validated(),
+            Some(SimCommand::SetBrightness(BRIGHTNESS_RANGE.0))
+        );
+        assert_eq!(
+            SimCommand::Spawn(usize::MAX).validated(),
+            Some(SimCommand::Spawn(MAX_SPAWN))
+        );
+
+        let attractor = Attractor {
+            x: 0.5,
+            y: 0.5,
+            strength: -1e9,
+        };
+        let Some(SimCommand::SetAttractors(attractors)) =
+            SimCommand::SetAttractors(vec![attractor; 100]).validated()
+        else {
+            panic!("attractors rejected");
+        };
+        assert_eq!(attractors.len(), MAX_ATTRACTORS);
+        assert!(attractors.iter().all(|a| a.strength == GRAVITY_RANGE.0));
+        let attractor = Attractor {
+            x: f32::NAN,
+            ..attractor
+        };
+        assert_eq!(SimCommand::SetAttractors(vec![attractor]).validated(), None);
+    }
+}
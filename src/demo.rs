@@ -0,0 +1,255 @@
+//! Unattended demo mode rotating through built-in presets.
+
+use std::time::{Duration, Instant};
+
+use crate::field::{Currents, Heat};
+use crate::obstacle::Shape;
+use crate::palette::Palette;
+use crate::particles::{Attractor, Boundary, SpawnPattern, Symmetry};
+use crate::render::{Background, Metaballs};
+use crate::scene::{Emitter, Forces, RenderSettings, Scene};
+
+/// Length of the cross-fade between two presets.
+pub const FADE: Duration = Duration::from_secs(2);
+
+/// The built-in presets, by name.
+///
+/// There is no boids preset yet, the particles have no force aligning them
+/// with their neighbours to build it on.
+pub fn presets() -> Vec<(&'static str, Scene)> {
+    let emitter = |pattern, count, x, y| Emitter {
+        pattern,
+        count,
+        x,
+        y,
+        ..Emitter::default()
+    };
+    let attractor = |x, y, strength| Attractor { x, y, strength };
+    vec![
+        (
+            "galaxy",
+            Scene {
+                emitters: vec![
+                    emitter(SpawnPattern::Ring, 600_000, 0.5, 0.5),
+                    emitter(SpawnPattern::Center, 100_000, 0.5, 0.5),
+                ],
+                forces: Forces {
+                    friction: 0.998,
+                    attractors: vec![attractor(0.5, 0.5, 0.6)],
+                    ..Forces::default()
+                },
+                render: RenderSettings {
+                    palette: Palette::Ice,
+                    brightness: 8.0,
+                    background: Background {
+                        top: [0, 0, 12],
+                        bottom: [8, 0, 24],
+                    },
+                    ..RenderSettings::default()
+                },
+                ..Scene::default()
+            },
+        ),
+        (
+            "fireworks",
+            Scene {
+                emitters: [
+                    (0.2, 0.3),
+                    (0.5, 0.2),
+                    (0.8, 0.35),
+                    (0.35, 0.6),
+                    (0.65, 0.55),
+                ]
+                .map(|(x, y)| emitter(SpawnPattern::Center, 60_000, x, y))
+                .to_vec(),
+                forces: Forces {
+                    friction: 0.97,
+                    ..Forces::default()
+                },
+                render: RenderSettings {
+                    palette: Palette::Fire,
+                    brightness: 14.0,
+                    symmetry: Symmetry::Mirror,
+                    ..RenderSettings::default()
+                },
+                ..Scene::default()
+            },
+        ),
+        (
+            "orbits",
+            Scene {
+                emitters: vec![
+                    emitter(SpawnPattern::Ring, 400_000, 0.5, 0.5),
+                    emitter(SpawnPattern::Center, 100_000, 0.3, 0.5),
+                    emitter(SpawnPattern::Center, 100_000, 0.7, 0.5),
+                ],
+                forces: Forces {
+                    friction: 0.995,
+                    attractors: vec![attractor(0.3, 0.5, 0.4), attractor(0.7, 0.5, 0.4)],
+                    ..Forces::default()
+                },
+                boundary: Boundary::Bounce,
+                render: RenderSettings {
+                    palette: Palette::Gradient,
+                    brightness: 6.0,
+                    metaballs: Some(Metaballs::default()),
+                    ..RenderSettings::default()
+                },
+                ..Scene::default()
+            },
+        ),
+        (
+            "storm",
+            Scene {
+                emitters: vec![emitter(SpawnPattern::Uniform, 500_000, 0.5, 0.5)],
+                forces: Forces {
+                    attractors: vec![
+                        attractor(0.25, 0.3, 0.8),
+                        attractor(0.75, 0.7, 0.8),
+                        attractor(0.5, 0.5, -0.5),
+                    ],
+                    ..Forces::default()
+                },
+                boundary: Boundary::Wrap,
+                render: RenderSettings {
+                    palette: Palette::Mono,
+                    brightness: 5.0,
+                    symmetry: Symmetry::Kaleidoscope(4),
+                    cycle: 0.05,
+                    ..RenderSettings::default()
+                },
+                ..Scene::default()
+            },
+        ),
+        (
+            "convection",
+            Scene {
+                emitters: vec![emitter(SpawnPattern::Uniform, 400_000, 0.5, 0.5)],
+                forces: Forces {
+                    friction: 0.97,
+                    heat: Some(Heat::default()),
+                    ..Forces::default()
+                },
+                boundary: Boundary::Bounce,
+                render: RenderSettings {
+                    palette: Palette::Fire,
+                    brightness: 6.0,
+                    ..RenderSettings::default()
+                },
+                ..Scene::default()
+            },
+        ),
+        (
+            "flow field",
+            Scene {
+                emitters: vec![emitter(SpawnPattern::Uniform, 400_000, 0.5, 0.5)],
+                forces: Forces {
+                    gravity: 0.0,
+                    friction: 0.9,
+                    temperature: 0.5,
+                    ..Forces::default()
+                },
+                currents: Some(Currents::default()),
+                boundary: Boundary::Wrap,
+                render: RenderSettings {
+                    palette: Palette::Ice,
+                    brightness: 6.0,
+                    ..RenderSettings::default()
+                },
+                ..Scene::default()
+            },
+        ),
+        (
+            "wind tunnel",
+            Scene {
+                emitters: vec![Emitter {
+                    rate: 60_000.0,
+                    ..emitter(SpawnPattern::Line, 0, 0.0, 0.5)
+                }],
+                forces: Forces {
+                    friction: 0.95,
+                    wind: Some((4.0, 0.0)),
+                    ..Forces::default()
+                },
+                obstacles: vec![Shape::Circle {
+                    x: 0.3,
+                    y: 0.5,
+                    radius: 0.08,
+                }],
+                boundary: Boundary::Kill,
+                render: RenderSettings {
+                    palette: Palette::Mono,
+                    brightness: 6.0,
+                    ..RenderSettings::default()
+                },
+                ..Scene::default()
+            },
+        ),
+    ]
+}
+
+/// The preset called `name`.
+pub fn preset(name: &str) -> Option<Scene> {
+    presets()
+        .into_iter()
+        .find_map(|(preset, scene)| (preset == name).then_some(scene))
+}
+
+/// Decides when to switch to the next preset.
+pub struct Demo {
+    presets: Vec<(&'static str, Scene)>,
+    next: usize,
+    interval: Duration,
+    switched_at: Option<Instant>,
+}
+
+impl Demo {
+    pub fn new(interval: Duration) -> Self {
+        Demo {
+            presets: presets(),
+            next: 0,
+            interval,
+            switched_at: None,
+        }
+    }
+
+    /// Returns the next preset if it is due at `now`, starting with the
+    /// first one on the first call.
+    pub fn poll(&mut self, now: Instant) -> Option<&(&'static str, Scene)> {
+        if self
+            .switched_at
+            .is_some_and(|switched_at| now < switched_at + self.interval)
+        {
+            return None;
+        }
+        self.switched_at = Some(now);
+        let preset = &self.presets[self.next];
+        self.next = (self.next + 1) % self.presets.len();
+        Some(preset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_presets() {
+        let mut demo = Demo::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(demo.poll(start).unwrap().0, "galaxy");
+        assert!(demo.poll(start + Duration::from_secs(9)).is_none());
+        assert_eq!(
+            demo.poll(start + Duration::from_secs(10)).unwrap().0,
+            "fireworks"
+        );
+        let n = presets().len() as u64;
+        for i in 2..=n {
+            assert!(demo.poll(start + Duration::from_secs(i * 10)).is_some());
+        }
+        let wrapped = demo.poll(start + Duration::from_secs((n + 1) * 10));
+        assert_eq!(wrapped.unwrap().0, "fireworks");
+        assert!(preset("wind tunnel").is_some_and(|scene| scene.emitters[0].rate > 0.0));
+        assert!(preset("flow field").is_some_and(|scene| scene.currents.is_some()));
+    }
+}
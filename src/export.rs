@@ -0,0 +1,210 @@
+//! Exports of the raw simulation data.
+//!
+//! Unlike screenshots, the density exports keep the particle counts
+//! instead of the tone-mapped colors, for post-processing in scientific or
+//! photo tools: a 16 bit grayscale PNG with the counts as they are, and an
+//! OpenEXR image and a grayscale portable float map (`.pfm`) with the
+//! counts as `f32`s.
+//!
+//! The particles themselves are exported as CSV with one row per particle,
+//! which loads directly into pandas or R, and back in with `load_csv`.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, Write};
+use std::path::Path;
+
+use crate::particles::Snapshot;
+
+/// Writes the `width` x `height` `counts`, row by row, as a 16 bit
+/// grayscale PNG.
+pub fn write_png16(
+    out: impl Write,
+    width: u32,
+    height: u32,
+    counts: impl IntoIterator<Item = u16>,
+) -> io::Result<()> {
+    let mut data = counts
+        .into_iter()
+        .flat_map(u16::to_be_bytes)
+        .collect::<Vec<_>>();
+    data.resize(width as usize * height as usize * 2, 0);
+
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Writes the `width` x `height` `counts`, row by row, as an OpenEXR image
+/// with a single `f32` luminance channel `Y`.
+pub fn write_exr(
+    out: impl Write + Seek,
+    width: u32,
+    height: u32,
+    counts: &[u16],
+) -> io::Result<()> {
+    use exr::prelude::*;
+
+    let width = width as usize;
+    let channels = SpecificChannels::build()
+        .with_channel("Y")
+        .with_pixel_fn(|pos: Vec2<usize>| {
+            (counts
+                .get(pos.y() * width + pos.x())
+                .map_or(0.0, |&c| c as f32),)
+        });
+    Image::from_channels((width, height as usize), channels)
+        .write()
+        .to_buffered(out)
+        .map_err(|err| match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        })
+}
+
+/// Writes the `width` x `height` `counts`, row by row, as a grayscale
+/// portable float map.
+pub fn write_pfm(
+    mut out: impl Write,
+    width: u32,
+    height: u32,
+    counts: impl IntoIterator<Item = u16>,
+) -> io::Result<()> {
+    write!(out, "Pf\n{width} {height}\n-1.0\n")?;
+    let counts = counts.into_iter().collect::<Vec<_>>();
+    // Rows are stored from the bottom up.
+    for row in counts.chunks(width.max(1) as usize).rev() {
+        for &count in row {
+            out.write_all(&(count as f32).to_le_bytes())?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes the `width` x `height` `counts`, e.g. those of a frame's
+/// density, to `<stem>.png`, `<stem>.exr` and `<stem>.pfm`.
+pub fn save(width: u32, height: u32, counts: &[u16], stem: impl AsRef<Path>) -> io::Result<()> {
+    let stem = stem.as_ref();
+    let png = BufWriter::new(File::create(stem.with_extension("png"))?);
+    write_png16(png, width, height, counts.iter().copied())?;
+    let exr = BufWriter::new(File::create(stem.with_extension("exr"))?);
+    write_exr(exr, width, height, counts)?;
+    let pfm = BufWriter::new(File::create(stem.with_extension("pfm"))?);
+    write_pfm(pfm, width, height, counts.iter().copied())
+}
+
+/// Writes the `[x, y, dx, dy]` `states` of the particles as CSV, keeping
+/// one of every `every` particles.
+pub fn write_csv(
+    mut out: impl Write,
+    states: impl IntoIterator<Item = [f32; 4]>,
+    every: usize,
+) -> io::Result<()> {
+    writeln!(out, "x,y,dx,dy")?;
+    for [x, y, dx, dy] in states.into_iter().step_by(every.max(1)) {
+        writeln!(out, "{x},{y},{dx},{dy}")?;
+    }
+    out.flush()
+}
+
+/// Writes the particles of `snapshot` to the CSV file at `path`, see
+/// `write_csv`.
+pub fn save_csv(snapshot: &Snapshot, path: impl AsRef<Path>, every: usize) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    write_csv(out, snapshot.states(), every)
+}
+
+/// Reads the `[x, y, dx, dy]` states written by `write_csv`.
+pub fn read_csv(input: impl BufRead) -> io::Result<Vec<[f32; 4]>> {
+    let invalid = |line: usize| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {line} is not a particle"),
+        )
+    };
+    let mut states = Vec::new();
+    for (i, line) in input.lines().enumerate().skip(1) {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let mut values = line.split(',').map(|value| value.trim().parse::<f32>());
+        let mut state = [0.0; 4];
+        for value in &mut state {
+            *value = values.next().and_then(Result::ok).ok_or(invalid(i + 1))?;
+        }
+        if values.next().is_some() {
+            return Err(invalid(i + 1));
+        }
+        states.push(state);
+    }
+    Ok(states)
+}
+
+/// Reads the particles of the CSV file at `path`, see `read_csv`.
+pub fn load_csv(path: impl AsRef<Path>) -> io::Result<Vec<[f32; 4]>> {
+    read_csv(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_raw_counts() {
+        let mut png = Vec::new();
+        write_png16(&mut png, 2, 2, [1, 0x1234, 0, 0xFFFF]).unwrap();
+        let mut reader = png::Decoder::new(io::Cursor::new(png)).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (2, 2));
+        assert_eq!(info.color_type, png::ColorType::Grayscale);
+        assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+        assert_eq!(data, [0, 1, 0x12, 0x34, 0, 0, 0xFF, 0xFF]);
+
+        let mut exr = io::Cursor::new(Vec::new());
+        write_exr(&mut exr, 2, 2, &[1, 2, 3, 0xFFFF]).unwrap();
+        exr.set_position(0);
+        use exr::prelude::{ReadChannels, ReadLayers};
+
+        let image = exr::prelude::read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(exr)
+            .unwrap();
+        let channel = &image.layer_data.channel_data.list[0];
+        assert_eq!(channel.name, *"Y");
+        let values = channel.sample_data.values_as_f32().collect::<Vec<_>>();
+        assert_eq!(values, [1.0, 2.0, 3.0, 65535.0]);
+
+        let mut pfm = Vec::new();
+        write_pfm(&mut pfm, 2, 2, [1, 2, 3, 4]).unwrap();
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert_eq!(pfm[..header.len()], *header);
+        let values = pfm[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [3.0, 4.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn writes_csv_rows() {
+        let states = (0..5).map(|i| [i as f32, 0.5, -1.0, 2.0]);
+        let mut csv = Vec::new();
+        write_csv(&mut csv, states, 2).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&csv),
+            "x,y,dx,dy\n0,0.5,-1,2\n2,0.5,-1,2\n4,0.5,-1,2\n"
+        );
+        let states = read_csv(csv.as_slice()).unwrap();
+        assert_eq!(states, [0.0, 2.0, 4.0].map(|x| [x, 0.5, -1.0, 2.0]));
+        assert!(read_csv("x,y,dx,dy\n1,2,3\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Long-exposure accumulation of the density field.
+//!
+//! `LongExposure` sums the particle counts of every published frame into
+//! `f64`s, so it can run for hours without losing precision, and exports
+//! the mean density as a high-dynamic-range image in the portable float
+//! map format (`.pfm`), which most HDR tools read.
+
+use crate::simd::Simd;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::output::{Frame, FrameSink};
+use crate::palette::Palette;
+
+/// `FrameSink` accumulating the density of every frame.
+#[derive(Debug, Default)]
+pub struct LongExposure {
+    width: u32,
+    height: u32,
+    frames: u64,
+    sums: Vec<f64>,
+}
+
+impl LongExposure {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of frames accumulated so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.sums.fill(0.0);
+    }
+
+    /// Mean particle count per pixel, row by row.
+    pub fn mean(&self) -> impl Iterator<Item = f64> + '_ {
+        let scale = 1.0 / self.frames.max(1) as f64;
+        self.sums.iter().map(move |sum| sum * scale)
+    }
+
+    /// Writes the mean density as an RGB portable float map, tinted with
+    /// `palette`. A value of `1.0` is the brightest color of the live
+    /// render at the same `brightness`; brighter pixels are not clipped.
+    pub fn write_pfm(
+        &self,
+        mut out: impl Write,
+        brightness: f32,
+        palette: Palette,
+    ) -> io::Result<()> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        write!(out, "PF\n{width} {height}\n-1.0\n")?;
+        let scale = brightness as f64 / 255.0;
+        let mean = self.mean().collect::<Vec<_>>();
+        // Rows are stored from the bottom up.
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let weights = palette.weights(
+                    Simd::<f32, 1>::splat(x as f32 / width as f32),
+                    Simd::splat(y as f32 / height as f32),
+                );
+                let value = mean[y * width + x] * scale;
+                for weight in weights {
+                    let channel = (weight[0] as f64 * value) as f32;
+                    out.write_all(&channel.to_le_bytes())?;
+                }
+            }
+        }
+        out.flush()
+    }
+
+    /// Writes the image to the file at `path`, see `write_pfm`.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        brightness: f32,
+        palette: Palette,
+    ) -> io::Result<()> {
+        self.write_pfm(BufWriter::new(File::create(path)?), brightness, palette)
+    }
+}
+
+impl FrameSink for LongExposure {
+    /// Adds the density of `frame`, starting over if the size changed.
+    fn publish(&mut self, frame: &Frame) {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            self.width = frame.width;
+            self.height = frame.height;
+            self.sums = vec![0.0; frame.density.len()];
+            self.frames = 0;
+        }
+        for (sum, count) in self.sums.iter_mut().zip(frame.density.iter()) {
+            *sum += count as f64;
+        }
+        self.frames += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Density;
+
+    #[test]
+    fn accumulates_and_exports() {
+        let mut exposure = LongExposure::new();
+        let frame = |density| Frame {
+            id: 0,
+            width: 2,
+            height: 1,
+            pixels: &[0, 0],
+            density: Density::U8(density),
+            motion: None,
+            pixels_10bit: None,
+        };
+        exposure.publish(&frame(&[1, 255]));
+        exposure.publish(&frame(&[3, 255]));
+        assert_eq!(exposure.frames(), 2);
+        assert_eq!(exposure.mean().collect::<Vec<_>>(), [2.0, 255.0]);
+
+        let mut pfm = Vec::new();
+        exposure.write_pfm(&mut pfm, 2.0, Palette::Mono).unwrap();
+        let header = b"PF\n2 1\n-1.0\n";
+        assert_eq!(&pfm[..header.len()], header);
+        let values = pfm[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [4.0 / 255.0; 3]
+                .into_iter()
+                .chain([2.0; 3])
+                .collect::<Vec<_>>()
+        );
+
+        exposure.clear();
+        assert_eq!(exposure.frames(), 0);
+        assert!(exposure.mean().all(|value| value == 0.0));
+    }
+}
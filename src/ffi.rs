@@ -0,0 +1,160 @@
+//! C interface for embedding the simulation in other applications.
+//!
+//! The matching header lives in `include/particles.h` and is generated with
+//! `cbindgen --config cbindgen.toml --output include/particles.h`. The
+//! shared library is built with `cargo rustc --release --lib --crate-type
+//! cdylib`, as a `cdylib` in the manifest has no hash in its file name and
+//! collides between the unwinding bench and the aborting release builds.
+
+use std::slice;
+use std::thread::available_parallelism;
+use std::time::Duration;
+
+use crate::particles::Particles;
+use crate::scoped_threadpool::Pool;
+
+/// Longest step taken by `particles_step`, longer ones are clamped to it.
+pub const MAX_STEP_MS: f32 = 1000.0;
+
+/// Opaque handle owning a threadpool and the particles simulated on it.
+pub struct ParticlesSim {
+    /// Borrows `pool` until `particles_destroy`, the `'static` lifetime
+    /// stands in for the lifetime of the handle.
+    particles: Particles<'static>,
+    /// Allocated with `Box::into_raw`, freed by `particles_destroy`.
+    pool: *mut Pool,
+}
+
+/// Creates a simulation of at least `n_particles` particles spawned at the
+/// center of a `width` x `height` area.
+///
+/// `n_threads == 0` uses one thread per available core.
+/// The returned handle must be released with `particles_destroy`.
+#[unsafe(no_mangle)]
+pub extern "C" fn particles_create(
+    width: u32,
+    height: u32,
+    n_particles: usize,
+    n_threads: usize,
+) -> *mut ParticlesSim {
+    let n_threads = match n_threads {
+        0 => available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let pool = Box::into_raw(Box::new(Pool::new(n_threads)));
+    // SAFETY: `pool` stays allocated until `particles_destroy`, which drops
+    // the particles first.
+    let particles = Particles::builder()
+        .count(n_particles)
+        .size(width, height)
+        .build(unsafe { &*pool });
+    Box::into_raw(Box::new(ParticlesSim { particles, pool }))
+}
+
+/// Advances the simulation by `dt_ms` milliseconds, clamped to
+/// `0..=MAX_STEP_MS`, with the attractor at (`mouse_x`, `mouse_y`), active
+/// while `mouse_down` is true.
+///
+/// Returns false without stepping if `dt_ms` is NaN or infinite.
+///
+/// # Safety
+/// `sim` must be a handle returned by `particles_create`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn particles_step(
+    sim: *mut ParticlesSim,
+    dt_ms: f32,
+    mouse_x: f32,
+    mouse_y: f32,
+    mouse_down: bool,
+) -> bool {
+    if !dt_ms.is_finite() {
+        return false;
+    }
+    let sim = unsafe { &mut *sim };
+    let frametime = Duration::from_secs_f32(dt_ms.clamp(0.0, MAX_STEP_MS) / 1000.0);
+    sim.particles
+        .update(&frametime, (mouse_x, mouse_y), mouse_down);
+    true
+}
+
+/// Returns the number of particles in the simulation.
+///
+/// # Safety
+/// `sim` must be a handle returned by `particles_create`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn particles_len(sim: *const ParticlesSim) -> usize {
+    let sim = unsafe { &*sim };
+    sim.particles.len()
+}
+
+/// Writes up to `capacity` positions as interleaved `x, y` pairs into `out`
+/// and returns the number of positions written, `0` if `2 * capacity`
+/// overflows.
+///
+/// # Safety
+/// `sim` must be a handle returned by `particles_create` and `out` must
+/// point to at least `2 * capacity` writable floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn particles_read_positions(
+    sim: *const ParticlesSim,
+    out: *mut f32,
+    capacity: usize,
+) -> usize {
+    let Some(len) = capacity.checked_mul(2).filter(|_| !out.is_null()) else {
+        return 0;
+    };
+    let sim = unsafe { &*sim };
+    let out = unsafe { slice::from_raw_parts_mut(out, len) };
+    let mut written = 0;
+    for ((x, y), dst) in sim.particles.iter_positions().zip(out.chunks_exact_mut(2)) {
+        dst[0] = x;
+        dst[1] = y;
+        written += 1;
+    }
+    written
+}
+
+/// Destroys a simulation created by `particles_create`.
+///
+/// # Safety
+/// `sim` must be a handle returned by `particles_create` or null, and must
+/// not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn particles_destroy(sim: *mut ParticlesSim) {
+    if sim.is_null() {
+        return;
+    }
+    let ParticlesSim { particles, pool } = *unsafe { Box::from_raw(sim) };
+    // The particles borrow the pool, so they have to go first.
+    drop(particles);
+    drop(unsafe { Box::from_raw(pool) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_step_read_destroy() {
+        let sim = particles_create(200, 100, 100, 2);
+        unsafe {
+            assert_eq!(particles_len(sim), 100);
+            assert!(particles_step(sim, 16.0, 0.0, 0.0, true));
+            // Rejected or clamped instead of panicking across the boundary.
+            assert!(!particles_step(sim, f32::INFINITY, 0.0, 0.0, true));
+            assert!(!particles_step(sim, f32::NAN, 0.0, 0.0, true));
+            assert!(particles_step(sim, f32::MAX, 0.0, 0.0, false));
+            assert_eq!(
+                particles_read_positions(sim, [0.0].as_mut_ptr(), usize::MAX),
+                0
+            );
+
+            let mut out = vec![f32::NAN; 2 * 256];
+            assert_eq!(particles_read_positions(sim, out.as_mut_ptr(), 256), 100);
+            assert!(out[..200].iter().all(|v| v.is_finite()));
+            assert!(out[200..].iter().all(|v| v.is_nan()));
+
+            particles_destroy(sim);
+        }
+    }
+}
@@ -0,0 +1,725 @@
+//! Coarse grids sampled by the particles every update.
+//!
+//! `VelocityField` stores one velocity per `cell` x `cell` pixels, painted
+//! with the mouse. Particles are carried along by it, so painted currents
+//! keep flowing after the mouse is released. With a `Flow`, the currents
+//! spread out like a viscous fluid, while vorticity confinement feeds the
+//! swirls that the viscosity would otherwise smooth away.
+//!
+//! `FreezeMask` marks painted cells in which particles stop moving.
+//!
+//! `ChargeField` sums the charges of the particles per cell and derives an
+//! electric field from them, so that like charges repel and opposite
+//! charges attract without comparing every pair of particles.
+//!
+//! `HeatField` is a temperature per cell, heated by the particles in it and
+//! by a heater along the bottom edge. The heat diffuses and lifts the
+//! particles in cells warmer than the average, so they carry it upwards
+//! until it cools off, forming convection cells.
+
+use crate::simd::cmp::{SimdPartialEq, SimdPartialOrd};
+use crate::simd::num::{SimdFloat, SimdInt};
+use crate::simd::{Simd, StdFloat};
+
+use serde::{Deserialize, Serialize};
+
+use crate::particles::{Lanes, Particle, Real, narrow, splat};
+use crate::scoped_threadpool::Pool;
+
+type F32s = Simd<Real, 64>;
+type Usizes = Simd<usize, 64>;
+
+/// Evolution of a `VelocityField` per 60 Hz frame, still without either.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Flow {
+    /// Fraction of the difference to the average of its neighbours a cell
+    /// loses, from `0` to `1`.
+    pub viscosity: f32,
+    /// Strength of the vorticity confinement, spinning up existing swirls.
+    pub vorticity: f32,
+}
+
+/// Steady currents a `VelocityField` starts out with: a grid of
+/// counter-rotating eddies, `sin(kx) cos(ky)` and `-cos(kx) sin(ky)`, whose
+/// streamlines close on themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Currents {
+    /// Size of the cells in pixels.
+    pub cell: f32,
+    /// Number of eddies along the shorter side of the window.
+    pub eddies: u32,
+    /// Largest speed in pixels per 60 Hz frame.
+    pub speed: f32,
+}
+
+impl Default for Currents {
+    fn default() -> Self {
+        Currents {
+            cell: 24.0,
+            eddies: 3,
+            speed: 2.0,
+        }
+    }
+}
+
+impl Currents {
+    /// A field covering `width` x `height` pixels flowing along the eddies.
+    pub fn field(&self, width: u32, height: u32) -> VelocityField {
+        let mut field = VelocityField::new(width, height, self.cell);
+        let k = std::f32::consts::PI * self.eddies as f32 / width.min(height).max(1) as f32;
+        for (i, (vx, vy)) in field.vx.iter_mut().zip(&mut field.vy).enumerate() {
+            let x = ((i % field.cols) as f32 + 0.5) * field.cell;
+            let y = ((i / field.cols) as f32 + 0.5) * field.cell;
+            *vx = self.speed * (k * x).sin() * (k * y).cos();
+            *vy = -self.speed * (k * x).cos() * (k * y).sin();
+        }
+        field.currents = Some(*self);
+        field
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VelocityField {
+    cell: f32,
+    cols: usize,
+    rows: usize,
+    vx: Vec<f32>,
+    vy: Vec<f32>,
+    /// The currents the field was generated from, regenerated on resizes.
+    currents: Option<Currents>,
+}
+
+impl VelocityField {
+    /// A still field covering `width` x `height` pixels.
+    pub fn new(width: u32, height: u32, cell: f32) -> Self {
+        let cell = cell.max(1.0);
+        let cols = (width as f32 / cell).ceil().max(1.0) as usize;
+        let rows = (height as f32 / cell).ceil().max(1.0) as usize;
+        VelocityField {
+            cell,
+            cols,
+            rows,
+            vx: vec![0.0; cols * rows],
+            vy: vec![0.0; cols * rows],
+            currents: None,
+        }
+    }
+
+    /// Resizes the field to `width` x `height` pixels, clearing it, or
+    /// regenerating its `Currents`, if the number of cells changes.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let resized = VelocityField::new(width, height, self.cell);
+        if (resized.cols, resized.rows) != (self.cols, self.rows) {
+            *self = match self.currents {
+                Some(currents) => currents.field(width, height),
+                None => resized,
+            };
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vx.fill(0.0);
+        self.vy.fill(0.0);
+    }
+
+    /// Blends `velocity`, in pixels per 60 Hz frame, into the cells within
+    /// `radius` pixels of `pos`, fully at the center and fading out
+    /// towards the edge.
+    pub fn paint(&mut self, pos: (f32, f32), velocity: (f32, f32), radius: f32) {
+        let radius = radius.max(self.cell);
+        let reach = (radius / self.cell).ceil() as isize;
+        let col = (pos.0 / self.cell) as isize;
+        let row = (pos.1 / self.cell) as isize;
+        for r in row - reach..=row + reach {
+            for c in col - reach..=col + reach {
+                if !(0..self.cols as isize).contains(&c) || !(0..self.rows as isize).contains(&r) {
+                    continue;
+                }
+                let dx = (c as f32 + 0.5) * self.cell - pos.0;
+                let dy = (r as f32 + 0.5) * self.cell - pos.1;
+                let weight = 1.0 - (dx * dx + dy * dy).sqrt() / radius;
+                if weight <= 0.0 {
+                    continue;
+                }
+                let i = r as usize * self.cols + c as usize;
+                self.vx[i] += (velocity.0 - self.vx[i]) * weight;
+                self.vy[i] += (velocity.1 - self.vy[i]) * weight;
+            }
+        }
+    }
+
+    /// Advances the currents by `time_norm` 60 Hz frames according to
+    /// `flow`.
+    pub fn step(&mut self, flow: Flow, time_norm: f32) {
+        let (cols, rows) = (self.cols, self.rows);
+        // Central differences, one-sided at the edges.
+        let neighbours = |i: usize| {
+            let (col, row) = (i % cols, i / cols);
+            [
+                i - (col > 0) as usize,
+                i + (col + 1 < cols) as usize,
+                i - (row > 0) as usize * cols,
+                i + (row + 1 < rows) as usize * cols,
+            ]
+        };
+        if flow.viscosity > 0.0 {
+            let blend = (flow.viscosity * time_norm).min(1.0);
+            for v in [&mut self.vx, &mut self.vy] {
+                let previous = v.clone();
+                for (i, v) in v.iter_mut().enumerate() {
+                    let average = neighbours(i).map(|j| previous[j]).iter().sum::<f32>() / 4.0;
+                    *v += (average - *v) * blend;
+                }
+            }
+        }
+        if flow.vorticity > 0.0 {
+            let curl = (0..cols * rows)
+                .map(|i| {
+                    let [left, right, up, down] = neighbours(i);
+                    (self.vy[right] - self.vy[left] - self.vx[down] + self.vx[up]) / 2.0
+                })
+                .collect::<Vec<_>>();
+            let strength = flow.vorticity * time_norm;
+            for (i, &w) in curl.iter().enumerate() {
+                // Towards the center of the swirl, where |curl| peaks.
+                let [left, right, up, down] = neighbours(i);
+                let nx = curl[right].abs() - curl[left].abs();
+                let ny = curl[down].abs() - curl[up].abs();
+                let length = nx.hypot(ny);
+                if length > f32::EPSILON {
+                    self.vx[i] += strength * ny / length * w;
+                    self.vy[i] -= strength * nx / length * w;
+                }
+            }
+        }
+    }
+
+    /// Bilinearly interpolated velocity at every lane of (`x`, `y`).
+    /// Positions outside of the field take the velocity of the nearest edge.
+    #[inline(always)]
+    pub fn sample(&self, x: F32s, y: F32s) -> (F32s, F32s) {
+        let [vx, vy] = sample(self.cell, self.cols, self.rows, [&self.vx, &self.vy], x, y);
+        (vx, vy)
+    }
+}
+
+/// Cells in which particles are frozen in place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreezeMask {
+    cell: f32,
+    cols: usize,
+    rows: usize,
+    frozen: Vec<u8>,
+}
+
+impl FreezeMask {
+    /// A mask without frozen cells covering `width` x `height` pixels.
+    pub fn new(width: u32, height: u32, cell: f32) -> Self {
+        let grid = VelocityField::new(width, height, cell);
+        FreezeMask {
+            cell: grid.cell,
+            cols: grid.cols,
+            rows: grid.rows,
+            frozen: vec![0; grid.cols * grid.rows],
+        }
+    }
+
+    /// Resizes the mask to `width` x `height` pixels, thawing it if the
+    /// number of cells changes.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let resized = FreezeMask::new(width, height, self.cell);
+        if (resized.cols, resized.rows) != (self.cols, self.rows) {
+            *self = resized;
+        }
+    }
+
+    /// Freezes the cells whose centers are within `radius` pixels of `pos`.
+    pub fn paint(&mut self, pos: (f32, f32), radius: f32) {
+        let radius = radius.max(self.cell / 2.0);
+        let reach = (radius / self.cell).ceil() as isize;
+        let col = (pos.0 / self.cell) as isize;
+        let row = (pos.1 / self.cell) as isize;
+        for r in (row - reach).max(0)..(row + reach + 1).min(self.rows as isize) {
+            for c in (col - reach).max(0)..(col + reach + 1).min(self.cols as isize) {
+                let dx = (c as f32 + 0.5) * self.cell - pos.0;
+                let dy = (r as f32 + 0.5) * self.cell - pos.1;
+                if dx * dx + dy * dy <= radius * radius {
+                    self.frozen[r as usize * self.cols + c as usize] = 1;
+                }
+            }
+        }
+    }
+
+    /// Lanes of (`x`, `y`) in frozen cells. Positions outside of the mask
+    /// are never frozen.
+    #[inline(always)]
+    pub fn contains(&self, x: F32s, y: F32s) -> Lanes {
+        let scale = splat(1.0 / self.cell);
+        let (col, row) = ((x * scale).floor(), (y * scale).floor());
+        let zero = F32s::splat(0.0);
+        let inside = col.simd_ge(zero)
+            & col.simd_lt(splat(self.cols as f32))
+            & row.simd_ge(zero)
+            & row.simd_lt(splat(self.rows as f32));
+        let index = (row * splat(self.cols as f32) + col).cast::<usize>();
+        let frozen =
+            Simd::<u8, 64>::gather_select(&self.frozen, inside.cast(), index, Simd::splat(0));
+        frozen.simd_ne(Simd::splat(0)).cast()
+    }
+}
+
+/// Bilinearly interpolates the `cols` x `rows` grids `values` at every
+/// lane of (`x`, `y`), clamping to the edge cells.
+#[inline(always)]
+pub(crate) fn sample<const N: usize>(
+    cell: f32,
+    cols: usize,
+    rows: usize,
+    values: [&[f32]; N],
+    x: F32s,
+    y: F32s,
+) -> [F32s; N] {
+    let (c0, c1, fx) = axis(x, cell, cols);
+    let (r0, r1, fy) = axis(y, cell, rows);
+    let cols = Usizes::splat(cols);
+    let (i00, i01) = (r0 * cols + c0, r0 * cols + c1);
+    let (i10, i11) = (r1 * cols + c0, r1 * cols + c1);
+    let lerp = |a: F32s, b: F32s, t: F32s| a + (b - a) * t;
+    values.map(|values| {
+        let gather = |i| Simd::<f32, 64>::gather_or_default(values, i).cast();
+        let top = lerp(gather(i00), gather(i01), fx);
+        let bottom = lerp(gather(i10), gather(i11), fx);
+        lerp(top, bottom, fy)
+    })
+}
+
+/// Lower and upper cell index and the fraction between their centers
+/// along one axis.
+#[inline(always)]
+fn axis(pos: F32s, cell: f32, len: usize) -> (Usizes, Usizes, F32s) {
+    let max = (len - 1) as f32;
+    let pos = (pos / splat(cell) - splat(0.5)).simd_clamp(splat(0.0), splat(max));
+    // NaN positions clamp to NaN and cast to index 0.
+    let lower = pos.floor();
+    let upper = (lower + splat(1.0)).simd_min(splat(max));
+    (lower.cast(), upper.cast(), pos - lower)
+}
+
+/// Grid-accelerated interaction between charged particles, see
+/// `Particle::charge`.
+#[derive(Debug, Clone)]
+pub struct ChargeField {
+    /// Acceleration per unit of field, negative to make like charges
+    /// attract.
+    pub strength: f32,
+    /// Number of cells around a cell whose charges it feels.
+    pub reach: usize,
+    cell: f32,
+    cols: usize,
+    rows: usize,
+    /// Summed charge per cell.
+    charges: Vec<f32>,
+    /// Per-worker charge grids of `deposit`, kept to avoid reallocating
+    /// them.
+    tiles: Vec<Vec<f32>>,
+    ex: Vec<f32>,
+    ey: Vec<f32>,
+}
+
+impl ChargeField {
+    /// An empty field covering `width` x `height` pixels.
+    pub fn new(width: u32, height: u32, cell: f32) -> Self {
+        let grid = VelocityField::new(width, height, cell);
+        ChargeField {
+            strength: 0.05,
+            reach: 6,
+            cell: grid.cell,
+            cols: grid.cols,
+            rows: grid.rows,
+            charges: vec![0.0; grid.cols * grid.rows],
+            tiles: Vec::new(),
+            ex: vec![0.0; grid.cols * grid.rows],
+            ey: vec![0.0; grid.cols * grid.rows],
+        }
+    }
+
+    /// Resizes the field to `width` x `height` pixels.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let resized = ChargeField {
+            strength: self.strength,
+            reach: self.reach,
+            ..ChargeField::new(width, height, self.cell)
+        };
+        if (resized.cols, resized.rows) != (self.cols, self.rows) {
+            *self = resized;
+        }
+    }
+
+    /// Sums the charges of `particles` per cell and updates the field.
+    /// Particles outside of the grid are ignored.
+    pub fn deposit(&mut self, threadpool: &Pool, particles: &[Particle]) {
+        let grid = (self.cell, self.cols, self.rows);
+        sum_per_cell(
+            threadpool,
+            particles,
+            grid,
+            &mut self.tiles,
+            &mut self.charges,
+            |p| p.charge.cast(),
+        );
+        self.solve(threadpool);
+    }
+
+    /// Sums the field of the charges within `reach` cells of every cell,
+    /// falling off with the squared distance in cells, softened by one
+    /// cell.
+    fn solve(&mut self, threadpool: &Pool) {
+        let (cols, rows, reach) = (self.cols, self.rows, self.reach as isize);
+        let charges = &self.charges;
+        let rows_per_chunk = usize::max(rows / threadpool.thread_count() as usize / 4, 1);
+        threadpool.scoped(|scope| {
+            let ex = self.ex.chunks_mut(cols * rows_per_chunk);
+            let ey = self.ey.chunks_mut(cols * rows_per_chunk);
+            for (i_chunk, (ex, ey)) in ex.zip(ey).enumerate() {
+                scope.execute(move |_| {
+                    let start = i_chunk * cols * rows_per_chunk;
+                    for (i, (ex, ey)) in (start..).zip(ex.iter_mut().zip(ey)) {
+                        let (col, row) = ((i % cols) as isize, (i / cols) as isize);
+                        let (mut sum_x, mut sum_y) = (0.0, 0.0);
+                        for r in (row - reach).max(0)..(row + reach + 1).min(rows as isize) {
+                            for c in (col - reach).max(0)..(col + reach + 1).min(cols as isize) {
+                                let charge = charges[r as usize * cols + c as usize];
+                                let (dx, dy) = ((col - c) as f32, (row - r) as f32);
+                                let d2 = dx * dx + dy * dy + 1.0;
+                                let scale = charge / (d2 * d2.sqrt());
+                                sum_x += dx * scale;
+                                sum_y += dy * scale;
+                            }
+                        }
+                        *ex = sum_x;
+                        *ey = sum_y;
+                    }
+                });
+            }
+        });
+    }
+
+    /// Acceleration of a unit charge at every lane of (`x`, `y`), already
+    /// multiplied by `strength`.
+    #[inline(always)]
+    pub fn sample(&self, x: F32s, y: F32s) -> (F32s, F32s) {
+        let [ex, ey] = sample(self.cell, self.cols, self.rows, [&self.ex, &self.ey], x, y);
+        let strength = splat(self.strength);
+        (ex * strength, ey * strength)
+    }
+}
+
+/// Sums `weight` of the active lanes of `particles` per cell of the
+/// (`cell`, `cols`, `rows`) grid into `sums`, using one of `tiles` per
+/// worker. Particles outside of the grid are ignored.
+fn sum_per_cell(
+    threadpool: &Pool,
+    particles: &[Particle],
+    (cell, cols, rows): (f32, usize, usize),
+    tiles: &mut Vec<Vec<f32>>,
+    sums: &mut [f32],
+    weight: impl Fn(&Particle) -> Simd<f32, 64> + Sync,
+) {
+    let n_tiles = threadpool.thread_count() as usize;
+    let n_cells = sums.len();
+    tiles.resize_with(n_tiles, Vec::new);
+    let chunk_len = particles.len().div_ceil(n_tiles).max(1);
+    let weight = &weight;
+    threadpool.scoped(|scope| {
+        for (chunk, tile) in particles.chunks(chunk_len).zip(tiles.iter_mut()) {
+            scope.execute(move |_| {
+                tile.clear();
+                tile.resize(n_cells, 0.0);
+                for particle in chunk {
+                    let weights = weight(particle);
+                    let lanes = particle.x.as_array().iter().zip(particle.y.as_array());
+                    for (lane, ((x, y), weight)) in lanes.zip(weights.as_array()).enumerate() {
+                        let (col, row) = ((narrow(*x) / cell).floor(), (narrow(*y) / cell).floor());
+                        if *weight != 0.0
+                            && particle.is_active(lane)
+                            && (0.0..cols as f32).contains(&col)
+                            && (0.0..rows as f32).contains(&row)
+                        {
+                            tile[row as usize * cols + col as usize] += weight;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    sums.fill(0.0);
+    for tile in &tiles[..particles.len().div_ceil(chunk_len).min(n_tiles)] {
+        sums.iter_mut().zip(tile).for_each(|(s, t)| *s += t);
+    }
+}
+
+/// Parameters of a `HeatField`, per update unless noted otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Heat {
+    /// Size of the cells in pixels.
+    pub cell: f32,
+    /// Heat every particle adds to its cell.
+    pub deposit: f32,
+    /// Heat added to every cell of the bottom row.
+    pub heater: f32,
+    /// Fraction of the difference to the average of its neighbours a cell
+    /// loses, from `0` to `1`.
+    pub diffusion: f32,
+    /// Fraction of its heat a cell loses.
+    pub cooling: f32,
+    /// Upwards acceleration per unit of heat above the average.
+    pub buoyancy: f32,
+}
+
+impl Default for Heat {
+    fn default() -> Self {
+        Heat {
+            cell: 16.0,
+            deposit: 0.02,
+            heater: 4.0,
+            diffusion: 0.2,
+            cooling: 0.02,
+            buoyancy: 0.002,
+        }
+    }
+}
+
+/// Temperature grid coupled to the particles, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct HeatField {
+    pub heat: Heat,
+    cell: f32,
+    cols: usize,
+    rows: usize,
+    temperature: Vec<f32>,
+    /// Scratch space of `update`.
+    deposits: Vec<f32>,
+    tiles: Vec<Vec<f32>>,
+    /// Average of `temperature`, which neither lifts nor sinks particles.
+    mean: f32,
+}
+
+impl HeatField {
+    /// A cold field covering `width` x `height` pixels.
+    pub fn new(width: u32, height: u32, heat: Heat) -> Self {
+        let grid = VelocityField::new(width, height, heat.cell);
+        HeatField {
+            heat,
+            cell: grid.cell,
+            cols: grid.cols,
+            rows: grid.rows,
+            temperature: vec![0.0; grid.cols * grid.rows],
+            deposits: vec![0.0; grid.cols * grid.rows],
+            tiles: Vec::new(),
+            mean: 0.0,
+        }
+    }
+
+    /// Resizes the field to `width` x `height` pixels, cooling it down if
+    /// the grid changes.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let resized = HeatField::new(width, height, self.heat);
+        if (resized.cols, resized.rows) != (self.cols, self.rows) {
+            *self = resized;
+        }
+    }
+
+    /// Adds the heat of `particles` and the heater, then lets it diffuse
+    /// and cool off.
+    pub fn update(&mut self, threadpool: &Pool, particles: &[Particle]) {
+        let grid = (self.cell, self.cols, self.rows);
+        let deposit = Simd::splat(self.heat.deposit);
+        sum_per_cell(
+            threadpool,
+            particles,
+            grid,
+            &mut self.tiles,
+            &mut self.deposits,
+            |_| deposit,
+        );
+        let bottom = (self.rows - 1) * self.cols;
+        self.deposits[bottom..]
+            .iter_mut()
+            .for_each(|d| *d += self.heat.heater);
+
+        let (cols, rows) = (self.cols, self.rows);
+        let Heat {
+            diffusion, cooling, ..
+        } = self.heat;
+        let temperature = &self.temperature;
+        let rows_per_chunk = usize::max(rows / threadpool.thread_count() as usize / 4, 1);
+        threadpool.scoped(|scope| {
+            let chunks = self.deposits.chunks_mut(cols * rows_per_chunk);
+            for (i_chunk, chunk) in chunks.enumerate() {
+                scope.execute(move |_| {
+                    let start = i_chunk * cols * rows_per_chunk;
+                    for (i, next) in (start..).zip(chunk) {
+                        let (col, row) = (i % cols, i / cols);
+                        // The edges are insulated, they only exchange heat
+                        // with the cells inside.
+                        let neighbours = [
+                            (col > 0).then(|| i - 1),
+                            (col + 1 < cols).then(|| i + 1),
+                            (row > 0).then(|| i - cols),
+                            (row + 1 < rows).then(|| i + cols),
+                        ];
+                        let (sum, n) = neighbours
+                            .into_iter()
+                            .flatten()
+                            .fold((0.0, 0.0), |(sum, n), j| (sum + temperature[j], n + 1.0));
+                        let t = temperature[i];
+                        let t = t + diffusion * (sum / f32::max(n, 1.0) - t);
+                        *next = (t + *next) * (1.0 - cooling);
+                    }
+                });
+            }
+        });
+        std::mem::swap(&mut self.temperature, &mut self.deposits);
+        self.mean = self.temperature.iter().sum::<f32>() / self.temperature.len() as f32;
+    }
+
+    /// Vertical acceleration at every lane of (`x`, `y`), upwards where it
+    /// is warmer than average.
+    #[inline(always)]
+    pub fn sample(&self, x: F32s, y: F32s) -> F32s {
+        let [t] = sample(self.cell, self.cols, self.rows, [&self.temperature], x, y);
+        (splat(self.mean) - t) * splat(self.heat.buoyancy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn currents_circle_without_sources() {
+        let currents = Currents {
+            cell: 10.0,
+            eddies: 2,
+            speed: 3.0,
+        };
+        let field = currents.field(200, 100);
+        // The centers of the eddies are still, their edges flow fastest.
+        let (vx, vy) = field.sample(F32s::splat(25.0), F32s::splat(25.0));
+        assert!(vx[0].abs() < 1e-5 && vy[0].abs() < 1e-5);
+        let (vx, vy) = field.sample(F32s::splat(25.0), F32s::splat(5.0));
+        assert!(vx[0] > 2.5 && vy[0].abs() < 1e-5);
+        // No cell gains or loses particles on the whole.
+        let divergence = (1..field.rows - 1)
+            .flat_map(|r| (1..field.cols - 1).map(move |c| (r, c)))
+            .map(|(r, c)| {
+                let i = r * field.cols + c;
+                (field.vx[i + 1] - field.vx[i - 1] + field.vy[i + field.cols]
+                    - field.vy[i - field.cols])
+                    .abs()
+            })
+            .fold(0.0, f32::max);
+        assert!(divergence < 1e-3, "{divergence}");
+
+        let mut resized = field.clone();
+        resized.resize(400, 100);
+        assert_eq!(resized, currents.field(400, 100));
+    }
+
+    #[test]
+    fn like_charges_repel() {
+        let pool = Pool::new(2);
+        let mut field = ChargeField::new(100, 100, 10.0);
+        let mut particle = Particle::ZERO;
+        particle.x = F32s::splat(55.0);
+        particle.y = F32s::splat(55.0);
+        particle.charge = Simd::splat(1);
+        field.deposit(&pool, &[particle]);
+        assert_eq!(field.charges[5 * 10 + 5], 64.0);
+
+        let (ex, ey) = field.sample(F32s::splat(75.0), F32s::splat(55.0));
+        assert!(ex[0] > 0.0 && ey[0].abs() < 1e-6);
+        let (ex, ey) = field.sample(F32s::splat(55.0), F32s::splat(35.0));
+        assert!(ey[0] < 0.0 && ex[0].abs() < 1e-6);
+    }
+
+    #[test]
+    fn heater_lifts_particles_at_the_bottom() {
+        let pool = Pool::new(2);
+        let heat = Heat {
+            cell: 10.0,
+            deposit: 1.0,
+            ..Heat::default()
+        };
+        let mut field = HeatField::new(100, 100, heat);
+        for _ in 0..50 {
+            field.update(&pool, &[]);
+        }
+        let top = field.sample(F32s::splat(50.0), F32s::splat(5.0))[0];
+        let bottom = field.sample(F32s::splat(50.0), F32s::splat(95.0))[0];
+        assert!(bottom < 0.0 && top > 0.0, "{bottom} {top}");
+
+        // Particles warm their own cell.
+        let mut particle = Particle::ZERO;
+        particle.x = F32s::splat(55.0);
+        particle.y = F32s::splat(25.0);
+        let before = field.temperature[2 * 10 + 5];
+        field.update(&pool, &[particle]);
+        assert!(field.temperature[2 * 10 + 5] > before + 50.0);
+        assert!(field.temperature[2 * 10 + 5] > field.temperature[2 * 10 + 1]);
+    }
+
+    #[test]
+    fn vorticity_keeps_swirls_spinning() {
+        // A vortex around the center, as fast as `viscosity` erodes it.
+        let spin = |flow| {
+            let mut field = VelocityField::new(200, 200, 10.0);
+            for i in 0..20 {
+                let angle = i as f32 / 20.0 * std::f32::consts::TAU;
+                let (sin, cos) = angle.sin_cos();
+                field.paint((100.0 + 30.0 * cos, 100.0 + 30.0 * sin), (-sin, cos), 15.0);
+            }
+            for _ in 0..100 {
+                field.step(flow, 1.0);
+            }
+            let (vx, vy) = field.sample(F32s::splat(100.0), F32s::splat(70.0));
+            vx[0].hypot(vy[0])
+        };
+        let viscous = Flow {
+            viscosity: 0.2,
+            vorticity: 0.0,
+        };
+        let confined = Flow {
+            vorticity: 0.1,
+            ..viscous
+        };
+        let still = spin(Flow::default());
+        assert!(spin(viscous) < 0.5 * still);
+        assert!(spin(confined) > 1.5 * spin(viscous), "{}", spin(confined));
+    }
+
+    #[test]
+    fn paints_and_interpolates() {
+        let mut field = VelocityField::new(100, 50, 10.0);
+        field.paint((15.0, 15.0), (4.0, -2.0), 10.0);
+        // The cell centered on the brush takes the full velocity.
+        let (vx, vy) = field.sample(F32s::splat(15.0), F32s::splat(15.0));
+        assert_eq!((vx[0], vy[0]), (4.0, -2.0));
+        // Cells beyond the radius stay still.
+        let (vx, _) = field.sample(F32s::splat(55.0), F32s::splat(35.0));
+        assert_eq!(vx[0], 0.0);
+
+        field.clear();
+        field.vx.fill(1.0);
+        field.vx[0] = 3.0;
+        // Halfway between the centers of the first two cells.
+        let (vx, _) = field.sample(F32s::splat(10.0), F32s::splat(5.0));
+        assert_eq!(vx[0], 2.0);
+        // Outside of the field, the edge cell applies.
+        let (vx, _) = field.sample(F32s::splat(-50.0), F32s::splat(-50.0));
+        assert_eq!(vx[0], 3.0);
+    }
+}
@@ -0,0 +1,134 @@
+//! Duel mode, a two player game on top of the simulation.
+//!
+//! One player attracts with the mouse and the other with the keyboard
+//! attractor. Every pixel takes the color of the nearer player, and a
+//! player's score is the number of particles captured within
+//! `CAPTURE_RADIUS` of their attractor.
+
+use crate::output::Density;
+use crate::overlay;
+use crate::scoped_threadpool::Pool;
+
+/// Colors of the mouse and the keyboard player.
+pub const COLORS: [u32; 2] = [0xFF7020, 0x30A0FF];
+
+/// Distance from an attractor within which particles count as captured.
+pub const CAPTURE_RADIUS: f32 = 80.0;
+
+/// Scale of the score digits, see `overlay::draw_text`.
+const SCORE_SCALE: u32 = 4;
+
+/// Margin between the scores and the window edges.
+const SCORE_MARGIN: u32 = 12;
+
+/// State of a duel between the mouse and the keyboard player.
+#[derive(Debug, Default)]
+pub struct Duel {
+    /// Particles captured by each player in the last frame.
+    pub scores: [u32; 2],
+}
+
+impl Duel {
+    /// Counts the particles of the `width` x `height` `density` captured
+    /// by the `players`.
+    pub fn update(&mut self, density: Density, width: u32, height: u32, players: [(f32, f32); 2]) {
+        self.scores = players.map(|(px, py)| {
+            let x_range = pixel_range(px, width);
+            let mut score = 0;
+            for y in pixel_range(py, height) {
+                for x in x_range.clone() {
+                    let (dx, dy) = (x as f32 + 0.5 - px, y as f32 + 0.5 - py);
+                    if dx * dx + dy * dy <= CAPTURE_RADIUS * CAPTURE_RADIUS {
+                        score += density.get((y * width + x) as usize) as u32;
+                    }
+                }
+            }
+            score
+        });
+    }
+
+    /// Draws the scores in the player colors into the top corners of the
+    /// `width` x `height` `pixels`.
+    pub fn draw_scores(&self, pixels: &mut [u32], width: u32, height: u32) {
+        let [left, right] = self.scores.map(|score| score.to_string());
+        let right_x = width.saturating_sub(SCORE_MARGIN + overlay::text_width(&right, SCORE_SCALE));
+        for (text, x, color) in [(left, SCORE_MARGIN, COLORS[0]), (right, right_x, COLORS[1])] {
+            overlay::draw_text(
+                pixels,
+                width,
+                height,
+                (x, SCORE_MARGIN),
+                SCORE_SCALE,
+                color,
+                &text,
+            );
+        }
+    }
+}
+
+/// Recolors the `width` x `height` 0x00RRGGBB `pixels` with the color of
+/// the nearer of the `players`, keeping their brightness.
+pub fn tint(threadpool: &Pool, pixels: &mut [u32], width: u32, players: [(f32, f32); 2]) {
+    if width == 0 {
+        return;
+    }
+    let w = width as usize;
+    let rows_per_chunk = usize::max(
+        pixels.len() / w / threadpool.thread_count() as usize / 10,
+        1,
+    );
+    let [(ax, ay), (bx, by)] = players;
+    threadpool.scoped(|scope| {
+        for (i_chunk, pixels) in pixels.chunks_mut(w * rows_per_chunk).enumerate() {
+            scope.execute(move |_| {
+                for (y, row) in (i_chunk * rows_per_chunk..).zip(pixels.chunks_mut(w)) {
+                    let y = y as f32 + 0.5;
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        let x = x as f32 + 0.5;
+                        let to_a = (x - ax).powi(2) + (y - ay).powi(2);
+                        let to_b = (x - bx).powi(2) + (y - by).powi(2);
+                        let color = COLORS[(to_b < to_a) as usize];
+                        let [_, r, g, b] = pixel.to_be_bytes();
+                        let value = r.max(g).max(b) as u32;
+                        let [_, r, g, b] = color.to_be_bytes();
+                        let scale = |c: u8| c as u32 * value / 255;
+                        *pixel = (scale(r) << 16) | (scale(g) << 8) | scale(b);
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Pixels within `CAPTURE_RADIUS` of `center` along an axis of `len`.
+fn pixel_range(center: f32, len: u32) -> std::ops::Range<u32> {
+    let start = (center - CAPTURE_RADIUS).max(0.0) as u32;
+    let end = ((center + CAPTURE_RADIUS).ceil().max(0.0) as u32).min(len);
+    start.min(end)..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_captured_particles() {
+        let (width, height) = (400, 100);
+        let mut counts = vec![0_u8; 400 * 100];
+        // Three particles next to the mouse, one next to the keyboard
+        // player and one out of reach of both.
+        counts[50 * 400 + 60] = 2;
+        counts[40 * 400 + 30] = 1;
+        counts[50 * 400 + 330] = 1;
+        counts[50 * 400 + 200] = 5;
+        let mut duel = Duel::default();
+        let players = [(50.0, 50.0), (350.0, 50.0)];
+        duel.update(Density::U8(&counts), width, height, players);
+        assert_eq!(duel.scores, [3, 1]);
+
+        let pool = Pool::new(2);
+        let mut pixels = vec![0x808080, 0x808080, 0x000000, 0xFFFFFF];
+        tint(&pool, &mut pixels, 4, [(0.0, 0.0), (4.0, 0.0)]);
+        assert_eq!(pixels, [0x803810, 0x803810, 0x000000, COLORS[1]]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Shapes drawn with the mouse or a finger.
+//!
+//! `recognize` classifies the path of a drag as a circle, a Z or a straight
+//! line, so common actions are reachable without a keyboard. The path is
+//! resampled to evenly spaced points first, which makes the tests below
+//! independent of how fast it was drawn.
+
+use std::f32::consts::{PI, TAU};
+
+/// Paths shorter than this many pixels are clicks, not gestures.
+pub const MIN_LENGTH: f32 = 80.0;
+/// Number of segments the path is resampled to.
+const SEGMENTS: usize = 32;
+/// Smallest change of direction over two segments that counts as a corner.
+const CORNER: f32 = 1.75;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    /// A loop around `center`, `clockwise` as seen on the screen.
+    Circle {
+        center: (f32, f32),
+        radius: f32,
+        clockwise: bool,
+    },
+    /// Three strokes joined by two sharp turns in opposite directions.
+    Z,
+    /// A straight stroke, `direction` being a unit vector from its start to
+    /// its end.
+    Line { direction: (f32, f32) },
+}
+
+/// Classifies `path`, in pixels, as a gesture. `None` if it is too short or
+/// resembles none of them.
+pub fn recognize(path: &[(f32, f32)]) -> Option<Gesture> {
+    let length: f32 = path.windows(2).map(|pair| distance(pair[0], pair[1])).sum();
+    if length < MIN_LENGTH {
+        return None;
+    }
+    let points = resample(path, length);
+    let headings: Vec<f32> = points
+        .windows(2)
+        .map(|pair| (pair[1].1 - pair[0].1).atan2(pair[1].0 - pair[0].0))
+        .collect();
+
+    // Sharp turns, each counted once.
+    let mut corners = Vec::new();
+    let mut i = 0;
+    while i + 2 < headings.len() {
+        let turn = wrap(headings[i + 2] - headings[i]);
+        if turn.abs() > CORNER {
+            corners.push(turn.signum());
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    let n = points.len() as f32;
+    let center = points
+        .iter()
+        .fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+    let radii: Vec<f32> = points.iter().map(|&p| distance(p, center)).collect();
+    let radius = radii.iter().sum::<f32>() / n;
+    let spread = radii
+        .iter()
+        .fold(0.0f32, |max, r| max.max((r - radius).abs()));
+    let winding: f32 = points
+        .windows(2)
+        .map(|pair| {
+            let a = (pair[0].1 - center.1).atan2(pair[0].0 - center.0);
+            let b = (pair[1].1 - center.1).atan2(pair[1].0 - center.0);
+            wrap(b - a)
+        })
+        .sum();
+
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let chord = distance(first, last);
+    if corners.is_empty() && winding.abs() > 0.75 * TAU && spread < 0.6 * radius {
+        // y points down, so increasing angles turn clockwise on the screen.
+        Some(Gesture::Circle {
+            center,
+            radius,
+            clockwise: winding > 0.0,
+        })
+    } else if corners.len() == 2 && corners[0] != corners[1] {
+        Some(Gesture::Z)
+    } else if corners.is_empty() && chord > 0.9 * length {
+        let direction = ((last.0 - first.0) / chord, (last.1 - first.1) / chord);
+        Some(Gesture::Line { direction })
+    } else {
+        None
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+/// Wraps an angle difference to [-PI, PI].
+fn wrap(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// `SEGMENTS + 1` points spaced evenly along `path` of total `length`.
+fn resample(path: &[(f32, f32)], length: f32) -> Vec<(f32, f32)> {
+    let spacing = length / SEGMENTS as f32;
+    let mut points = vec![path[0]];
+    let mut carried = 0.0;
+    for pair in path.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let segment = distance(a, b);
+        let mut along = spacing - carried;
+        while along <= segment && points.len() <= SEGMENTS {
+            let t = along / segment;
+            points.push((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t));
+            along += spacing;
+        }
+        carried = (carried + segment) % spacing;
+    }
+    // Rounding may lose the end.
+    while points.len() <= SEGMENTS {
+        points.push(path[path.len() - 1]);
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `n` points from `from` to `to`, excluding `from`.
+    fn stroke(from: (f32, f32), to: (f32, f32), n: usize) -> Vec<(f32, f32)> {
+        (1..=n)
+            .map(|i| {
+                let t = i as f32 / n as f32;
+                (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recognizes_shapes() {
+        let circle: Vec<_> = (0..=40)
+            .map(|i| {
+                let (sin, cos) = (i as f32 / 40.0 * TAU).sin_cos();
+                (200.0 + 50.0 * cos, 100.0 + 50.0 * sin)
+            })
+            .collect();
+        let Some(Gesture::Circle {
+            center,
+            radius,
+            clockwise,
+        }) = recognize(&circle)
+        else {
+            panic!("{:?}", recognize(&circle));
+        };
+        assert!(distance(center, (200.0, 100.0)) < 5.0);
+        assert!((radius - 50.0).abs() < 5.0);
+        assert!(clockwise);
+        let reversed: Vec<_> = circle.iter().rev().copied().collect();
+        assert!(matches!(
+            recognize(&reversed),
+            Some(Gesture::Circle {
+                clockwise: false,
+                ..
+            })
+        ));
+
+        let mut z = vec![(0.0, 0.0)];
+        z.extend(stroke((0.0, 0.0), (100.0, 0.0), 7));
+        z.extend(stroke((100.0, 0.0), (0.0, 100.0), 3));
+        z.extend(stroke((0.0, 100.0), (100.0, 100.0), 20));
+        assert_eq!(recognize(&z), Some(Gesture::Z));
+
+        let mut line = vec![(10.0, 10.0)];
+        line.extend(stroke((10.0, 10.0), (10.0, 210.0), 5));
+        assert_eq!(
+            recognize(&line),
+            Some(Gesture::Line {
+                direction: (0.0, 1.0)
+            })
+        );
+
+        // Too short, and a single turn.
+        assert_eq!(recognize(&[(0.0, 0.0), (50.0, 0.0)]), None);
+        let mut corner = vec![(0.0, 0.0)];
+        corner.extend(stroke((0.0, 0.0), (100.0, 0.0), 4));
+        corner.extend(stroke((100.0, 0.0), (100.0, 100.0), 4));
+        assert_eq!(recognize(&corner), None);
+    }
+}
@@ -0,0 +1,347 @@
+//! Windowless simulation driver for benchmarks and tests.
+//!
+//! A `Scenario` describes a reproducible workload: a fixed number of
+//! particles, a seed, a fixed frametime and a scripted attractor path.
+//! `Headless` runs it with the same update, count and pixel passes as the
+//! app, each of which can be invoked (and timed) separately.
+
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+use crate::output;
+use crate::palette::Palette;
+use crate::particles::{CountTiles, Particles};
+use crate::render;
+use crate::scoped_threadpool::Pool;
+
+/// Movement of the scripted mouse attractor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MousePath {
+    /// The mouse is never pressed.
+    Released,
+    /// Pressed, resting at the center.
+    Center,
+    /// Pressed, circling the center once per `period` frames.
+    Circle { radius: f32, period: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: &'static str,
+    /// Number of particle blocks, see `Particles::add_particles`.
+    pub n_particles: usize,
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    pub mouse: MousePath,
+    pub frametime: Duration,
+}
+
+impl Scenario {
+    pub const DEFAULT_FRAMETIME: Duration = Duration::from_micros(16_666);
+
+    /// A small attractor workload that is cheap enough for tests.
+    pub fn small() -> Self {
+        Scenario {
+            name: "small",
+            n_particles: 64,
+            width: 320,
+            height: 240,
+            seed: 42,
+            mouse: MousePath::Circle {
+                radius: 60.0,
+                period: 120,
+            },
+            frametime: Self::DEFAULT_FRAMETIME,
+        }
+    }
+
+    /// Workloads used by the benchmarks.
+    pub fn benchmarks() -> Vec<Self> {
+        let base = Scenario {
+            name: "",
+            n_particles: 0,
+            width: 1920,
+            height: 1080,
+            seed: 42,
+            mouse: MousePath::Released,
+            frametime: Self::DEFAULT_FRAMETIME,
+        };
+        vec![
+            Scenario {
+                name: "drift-1080p-1M",
+                n_particles: 16_384,
+                ..base.clone()
+            },
+            Scenario {
+                name: "circle-1080p-1M",
+                n_particles: 16_384,
+                mouse: MousePath::Circle {
+                    radius: 300.0,
+                    period: 240,
+                },
+                ..base.clone()
+            },
+            Scenario {
+                name: "collapse-4k-4M",
+                n_particles: 65_536,
+                width: 3840,
+                height: 2160,
+                mouse: MousePath::Center,
+                ..base
+            },
+        ]
+    }
+}
+
+pub struct Headless<'a> {
+    pub scenario: Scenario,
+    pub particles: Particles<'a>,
+    pub count_buffer: Vec<AtomicU16>,
+    /// Filled by `count_saturating`.
+    pub count_buffer_u8: Vec<u8>,
+    count_tiles: CountTiles,
+    pub pixel_buffer: Vec<u32>,
+    pub brightness: f32,
+    pub palette: Palette,
+    frame: u32,
+    threadpool: &'a Pool,
+}
+
+impl<'a> Headless<'a> {
+    pub fn new(threadpool: &'a Pool, scenario: Scenario) -> Self {
+        let mut particles = Particles::new(threadpool);
+        particles.seed(scenario.seed);
+        particles.add_particles(scenario.n_particles, scenario.width, scenario.height);
+        let n_pixels = (scenario.width * scenario.height) as usize;
+        Headless {
+            particles,
+            count_buffer: (0..n_pixels).map(|_| AtomicU16::new(0)).collect(),
+            count_buffer_u8: vec![0; n_pixels],
+            count_tiles: CountTiles::default(),
+            pixel_buffer: vec![0; n_pixels],
+            brightness: 10.0,
+            palette: Palette::default(),
+            frame: 0,
+            threadpool,
+            scenario,
+        }
+    }
+
+    /// Number of frames stepped so far.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Position and button state of the scripted mouse for the current frame.
+    pub fn mouse(&self) -> ((f32, f32), bool) {
+        let center = (
+            self.scenario.width as f32 / 2.0,
+            self.scenario.height as f32 / 2.0,
+        );
+        match self.scenario.mouse {
+            MousePath::Released => (center, false),
+            MousePath::Center => (center, true),
+            MousePath::Circle { radius, period } => {
+                let angle = TAU * (self.frame % period) as f32 / period as f32;
+                let x = center.0 + radius * angle.cos();
+                let y = center.1 + radius * angle.sin();
+                ((x, y), true)
+            }
+        }
+    }
+
+    /// Physics pass.
+    pub fn update(&mut self) {
+        let (mouse_pos, mouse_down) = self.mouse();
+        self.particles
+            .update(&self.scenario.frametime, mouse_pos, mouse_down);
+        self.frame += 1;
+    }
+
+    /// Clears the count buffer and counts the particles per pixel.
+    pub fn count(&mut self) {
+        for count in &self.count_buffer {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.particles.count(
+            &self.count_buffer,
+            self.scenario.width,
+            self.scenario.height,
+        );
+    }
+
+    /// Counts the particles per pixel into the saturating `u8` buffer.
+    pub fn count_saturating(&mut self) {
+        self.particles.count_saturating(
+            &mut self.count_tiles,
+            &mut self.count_buffer_u8,
+            self.scenario.width,
+            self.scenario.height,
+        );
+    }
+
+    /// Pixel pass, converting the counts into colors.
+    pub fn colorize(&mut self) {
+        render::colorize(
+            self.threadpool,
+            output::counts_mut(&mut self.count_buffer),
+            &mut self.pixel_buffer,
+            self.scenario.width,
+            self.scenario.height,
+            render::Tone::new(self.brightness, self.palette),
+        );
+    }
+
+    /// Runs one complete frame like the app does, with the fused update and
+    /// count pass followed by the pixel pass.
+    pub fn step(&mut self) {
+        for count in &self.count_buffer {
+            count.store(0, Ordering::Relaxed);
+        }
+        let (mouse_pos, mouse_down) = self.mouse();
+        self.particles.update_and_count(
+            &self.scenario.frametime,
+            mouse_pos,
+            mouse_down,
+            &self.count_buffer,
+            self.scenario.width,
+            self.scenario.height,
+        );
+        self.frame += 1;
+        self.colorize();
+    }
+
+    /// The particle counts of the last count pass.
+    pub fn counts(&mut self) -> &[u16] {
+        output::counts_mut(&mut self.count_buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Golden-image regression tests.
+    //!
+    //! Each scenario is stepped for `FRAMES` frames and the density and the
+    //! brightness of the final frame are summed into a coarse grid, which is
+    //! compared against `tests/golden/<name>.txt`. The comparison allows a
+    //! small relative error, so rounding differences (e.g. with and without
+    //! FMA) pass, while changes to the physics or the raster do not.
+    //!
+    //! Run with `UPDATE_GOLDEN=1` to rewrite the files after an intended
+    //! change.
+
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const FRAMES: u32 = 120;
+    const GRID: (u32, u32) = (16, 12);
+    const TOLERANCE: f64 = 0.02;
+
+    fn grid(headless: &Headless, value: impl Fn(usize) -> u64) -> Vec<u64> {
+        let (width, height) = (headless.scenario.width, headless.scenario.height);
+        let mut cells = vec![0; (GRID.0 * GRID.1) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let cell = (y * GRID.1 / height) * GRID.0 + x * GRID.0 / width;
+                cells[cell as usize] += value((y * width + x) as usize);
+            }
+        }
+        cells
+    }
+
+    fn render(scenario: Scenario) -> String {
+        let pool = Pool::new(2);
+        let mut headless = Headless::new(&pool, scenario);
+        for _ in 0..FRAMES {
+            headless.step();
+        }
+        let density = grid(&headless, |i| {
+            headless.count_buffer[i].load(Ordering::Relaxed) as u64
+        });
+        let brightness = grid(&headless, |i| {
+            let pixel = headless.pixel_buffer[i];
+            [16, 8, 0]
+                .map(|shift| (pixel >> shift) as u64 & 0xFF)
+                .iter()
+                .sum()
+        });
+        let mut text = String::new();
+        for (name, cells) in [("density", density), ("brightness", brightness)] {
+            text.push_str(name);
+            text.push('\n');
+            for row in cells.chunks(GRID.0 as usize) {
+                let row = row.iter().map(u64::to_string).collect::<Vec<_>>();
+                text.push_str(&row.join(" "));
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Vec<Vec<u64>> {
+        text.split(char::is_alphabetic)
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.split_whitespace().map(|v| v.parse().unwrap()).collect())
+            .collect()
+    }
+
+    /// Sum of the absolute differences relative to the total.
+    fn relative_error(expected: &[u64], actual: &[u64]) -> f64 {
+        let diff: u64 = expected
+            .iter()
+            .zip(actual)
+            .map(|(a, b)| a.abs_diff(*b))
+            .sum();
+        diff as f64 / expected.iter().sum::<u64>().max(1) as f64
+    }
+
+    fn check(scenario: Scenario) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(scenario.name)
+            .with_extension("txt");
+        let actual = render(scenario);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        for (expected, actual) in parse(&expected).iter().zip(&parse(&actual)) {
+            let error = relative_error(expected, actual);
+            assert!(
+                error <= TOLERANCE,
+                "{} differs by {:.1}%, expected\n{expected:?}\ngot\n{actual:?}",
+                path.display(),
+                error * 100.0
+            );
+        }
+    }
+
+    #[test]
+    fn golden_circle() {
+        check(Scenario::small());
+    }
+
+    #[test]
+    fn golden_collapse() {
+        check(Scenario {
+            name: "small-collapse",
+            mouse: MousePath::Center,
+            ..Scenario::small()
+        });
+    }
+
+    #[test]
+    fn golden_drift() {
+        check(Scenario {
+            name: "small-drift",
+            mouse: MousePath::Released,
+            ..Scenario::small()
+        });
+    }
+}
@@ -0,0 +1,94 @@
+//! A dedicated thread for IO-bound work.
+//!
+//! Encoding and writing files takes milliseconds to seconds, which would
+//! stall a frame on the event loop and waste a simulation worker on the
+//! threadpool. `IoThread` runs such tasks one after the other on its own
+//! thread and hands their results back to the event loop, which collects
+//! them with `IoThread::completed` once per frame.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+type Task<T> = Box<dyn FnOnce() -> T + Send>;
+
+/// Handle to the IO thread, see the module documentation. Dropping it
+/// waits for the queued tasks.
+pub struct IoThread<T> {
+    tasks: Option<Sender<Task<T>>>,
+    completed: Receiver<T>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> IoThread<T> {
+    pub fn spawn() -> Self {
+        let (tasks, task_rx) = mpsc::channel::<Task<T>>();
+        let (completed_tx, completed) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("io".into())
+            .spawn(move || {
+                for task in task_rx {
+                    // The event loop may be gone during shutdown.
+                    let _ = completed_tx.send(task());
+                }
+            })
+            .expect("failed to spawn the IO thread");
+        IoThread {
+            tasks: Some(tasks),
+            completed,
+            handle: Some(handle),
+        }
+    }
+
+    /// Queues `task`, its result shows up in `completed` once it ran.
+    pub fn run(&self, task: impl FnOnce() -> T + Send + 'static) {
+        if let Some(tasks) = &self.tasks {
+            tasks.send(Box::new(task)).expect("the IO thread panicked");
+        }
+    }
+
+    /// Results of the tasks completed since the last call, without
+    /// blocking.
+    pub fn completed(&self) -> impl Iterator<Item = T> + '_ {
+        self.completed.try_iter()
+    }
+}
+
+impl<T> Drop for IoThread<T> {
+    fn drop(&mut self) {
+        // Closing the queue ends the thread after the last task.
+        self.tasks = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn runs_tasks_in_order() {
+        let io = IoThread::spawn();
+        for i in 0..4 {
+            io.run(move || i * 10);
+        }
+        let results = (0..4)
+            .map(|_| io.completed.recv().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, [0, 10, 20, 30]);
+        assert_eq!(io.completed().count(), 0);
+
+        // Dropping waits for the queued tasks.
+        let ran = Arc::new(AtomicUsize::new(0));
+        let io = IoThread::spawn();
+        for _ in 0..3 {
+            let ran = Arc::clone(&ran);
+            io.run(move || ran.fetch_add(1, Ordering::Relaxed));
+        }
+        drop(io);
+        assert_eq!(ran.load(Ordering::Relaxed), 3);
+    }
+}
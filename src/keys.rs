@@ -0,0 +1,343 @@
+//! Keybindings of the app.
+//!
+//! Every key press is looked up by its name in `Keybindings`: characters
+//! in lower case (`"s"`, `"["`), other keys by their winit name (`"F1"`,
+//! `"Tab"`, `"ArrowLeft"`), prefixed with `"ctrl+"` while Control is held.
+//! A key may trigger several actions, the app decides which one applies,
+//! e.g. the arrows steer the keyboard attractor while it is active and
+//! blow gusts otherwise. The help overlay is generated from the same table.
+//!
+//! A TOML file given with `--keys` rebinds actions by their snake case
+//! names, to one key or a list of them; the actions it leaves out keep
+//! their default keys:
+//!
+//! ```toml
+//! help = "F12"
+//! save_snapshot = "ctrl+s"
+//! steer_left = ["ArrowLeft", "j"]
+//! clear_portals = []
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// Everything a key can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    SaveSnapshot,
+    RestoreSnapshot,
+    Metaballs,
+    Glow,
+    DirectionHue,
+    Equalize,
+    Duel,
+    Dither,
+    Srgb,
+    ColorMode,
+    Invert,
+    LongExposure,
+    SaveExposure,
+    ExportDensity,
+    ExportCsv,
+    PaletteCycle,
+    PaintCurrents,
+    ClearCurrents,
+    DrawPortals,
+    ClearPortals,
+    Freeze,
+    Thaw,
+    Trails,
+    CenterAttractor,
+    Cooler,
+    Warmer,
+    Lfo,
+    Charges,
+    ColorByTag,
+    Symmetry,
+    #[cfg(feature = "midi")]
+    MidiLearn,
+    KeyboardAttractor,
+    OpenView,
+    CursorDisc,
+    Convection,
+    Help,
+    Stats,
+    FrameUnit,
+    CopyParameters,
+    PasteParameters,
+    GustLeft,
+    GustRight,
+    GustUp,
+    GustDown,
+    SteerLeft,
+    SteerRight,
+    SteerUp,
+    SteerDown,
+}
+
+impl Action {
+    /// What the action does, for the help overlay.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::SaveSnapshot => "save a snapshot",
+            Action::RestoreSnapshot => "restore the snapshot",
+            Action::Metaballs => "cycle metaballs",
+            Action::Glow => "toggle glow",
+            Action::DirectionHue => "toggle direction hue",
+            Action::Equalize => "toggle auto exposure",
+            Action::Duel => "toggle the duel",
+            Action::Dither => "toggle dithering",
+            Action::Srgb => "toggle sRGB encoding",
+            Action::ColorMode => "cycle full color, grayscale and high contrast",
+            Action::Invert => "toggle the light background",
+            Action::LongExposure => "start or stop a long exposure",
+            Action::SaveExposure => "save the long exposure",
+            Action::ExportDensity => "export the density",
+            Action::ExportCsv => "export the particles as csv",
+            Action::PaletteCycle => "toggle palette cycling",
+            Action::PaintCurrents => "toggle painting currents",
+            Action::ClearCurrents => "clear the currents",
+            Action::DrawPortals => "toggle drawing portals",
+            Action::ClearPortals => "clear the portals",
+            Action::Freeze => "toggle freezing",
+            Action::Thaw => "thaw everything",
+            Action::Trails => "toggle trails",
+            Action::CenterAttractor => "toggle the center attractor",
+            Action::Cooler => "lower the temperature",
+            Action::Warmer => "raise the temperature",
+            Action::Lfo => "cycle the attractor lfo",
+            Action::Charges => "toggle charges",
+            Action::ColorByTag => "toggle coloring by emitter",
+            Action::Symmetry => "cycle the symmetry",
+            #[cfg(feature = "midi")]
+            Action::MidiLearn => "bind the next midi control",
+            Action::KeyboardAttractor => "toggle the keyboard attractor",
+            Action::OpenView => "open a view window",
+            Action::CursorDisc => "cycle the cursor disc",
+            Action::Convection => "toggle convection",
+            Action::Help => "toggle this help",
+            Action::Stats => "toggle the stats",
+            Action::FrameUnit => "show frame times in ms or fps",
+            Action::CopyParameters => "copy the parameters",
+            Action::PasteParameters => "paste parameters",
+            Action::GustLeft => "gust to the left",
+            Action::GustRight => "gust to the right",
+            Action::GustUp => "gust upwards",
+            Action::GustDown => "gust downwards",
+            Action::SteerLeft => "steer the keyboard attractor left",
+            Action::SteerRight => "steer the keyboard attractor right",
+            Action::SteerUp => "steer the keyboard attractor up",
+            Action::SteerDown => "steer the keyboard attractor down",
+        }
+    }
+
+    /// Index into the held steering keys, left, right, up and down, if the
+    /// action steers the keyboard attractor. Steering lasts while the key
+    /// is held.
+    pub fn steering(self) -> Option<usize> {
+        match self {
+            Action::SteerLeft => Some(0),
+            Action::SteerRight => Some(1),
+            Action::SteerUp => Some(2),
+            Action::SteerDown => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Whether holding the key repeats the action.
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::GustLeft | Action::GustRight | Action::GustUp | Action::GustDown
+        )
+    }
+}
+
+/// The built-in bindings.
+const DEFAULTS: &[(&str, Action)] = &[
+    ("F1", Action::Help),
+    ("s", Action::SaveSnapshot),
+    ("r", Action::RestoreSnapshot),
+    ("m", Action::Metaballs),
+    ("b", Action::Glow),
+    ("h", Action::DirectionHue),
+    ("n", Action::Equalize),
+    ("y", Action::Duel),
+    ("d", Action::Dither),
+    ("e", Action::LongExposure),
+    ("x", Action::SaveExposure),
+    ("i", Action::ExportDensity),
+    ("j", Action::ExportCsv),
+    ("p", Action::PaletteCycle),
+    ("v", Action::PaintCurrents),
+    ("z", Action::ClearCurrents),
+    ("t", Action::DrawPortals),
+    ("g", Action::ClearPortals),
+    ("f", Action::Freeze),
+    ("u", Action::Thaw),
+    ("w", Action::Trails),
+    ("a", Action::CenterAttractor),
+    ("[", Action::Cooler),
+    ("]", Action::Warmer),
+    ("o", Action::Lfo),
+    ("q", Action::Charges),
+    ("c", Action::ColorByTag),
+    ("k", Action::Symmetry),
+    #[cfg(feature = "midi")]
+    ("l", Action::MidiLearn),
+    ("Tab", Action::KeyboardAttractor),
+    ("F2", Action::OpenView),
+    ("F3", Action::CursorDisc),
+    ("F4", Action::Convection),
+    ("F5", Action::FrameUnit),
+    ("F6", Action::Stats),
+    ("F7", Action::Srgb),
+    ("F8", Action::ColorMode),
+    ("F9", Action::Invert),
+    ("ctrl+c", Action::CopyParameters),
+    ("ctrl+v", Action::PasteParameters),
+    ("ArrowLeft", Action::GustLeft),
+    ("ArrowRight", Action::GustRight),
+    ("ArrowUp", Action::GustUp),
+    ("ArrowDown", Action::GustDown),
+    ("ArrowLeft", Action::SteerLeft),
+    ("ArrowRight", Action::SteerRight),
+    ("ArrowUp", Action::SteerUp),
+    ("ArrowDown", Action::SteerDown),
+    ("a", Action::SteerLeft),
+    ("d", Action::SteerRight),
+    ("w", Action::SteerUp),
+    ("s", Action::SteerDown),
+];
+
+/// Which keys trigger which actions, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keybindings {
+    bindings: Vec<(String, Action)>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Keybindings {
+            bindings: DEFAULTS
+                .iter()
+                .map(|&(key, action)| (key.to_string(), action))
+                .collect(),
+        }
+    }
+}
+
+/// One key or several, see the module documentation.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Keybindings {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// The default bindings with the actions of `toml` rebound.
+    pub fn from_toml(toml: &str) -> io::Result<Self> {
+        let rebound: HashMap<Action, Keys> =
+            toml::from_str(toml).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut keybindings = Keybindings::default();
+        for (action, keys) in rebound {
+            let keys = match keys {
+                Keys::One(key) => vec![key],
+                Keys::Many(keys) => keys,
+            };
+            keybindings.rebind(action, keys);
+        }
+        Ok(keybindings)
+    }
+
+    /// Replaces the keys of `action`.
+    pub fn rebind(&mut self, action: Action, keys: impl IntoIterator<Item = String>) {
+        self.bindings.retain(|&(_, bound)| bound != action);
+        self.bindings
+            .extend(keys.into_iter().map(|key| (key, action)));
+    }
+
+    /// The actions bound to the key named `key`, in the order they were
+    /// bound.
+    pub fn actions<'a>(&'a self, key: &'a str) -> impl Iterator<Item = Action> + 'a {
+        self.bindings
+            .iter()
+            .filter(move |(bound, _)| bound == key)
+            .map(|&(_, action)| action)
+    }
+
+    /// One line per bound action, its keys followed by its description.
+    pub fn help_lines(&self) -> Vec<String> {
+        let mut lines: Vec<(Action, Vec<&str>)> = Vec::new();
+        for (key, action) in &self.bindings {
+            match lines.iter_mut().find(|(bound, _)| bound == action) {
+                Some((_, keys)) => keys.push(key),
+                None => lines.push((*action, vec![key])),
+            }
+        }
+        lines
+            .into_iter()
+            .map(|(action, keys)| format!("{}  {}", keys.join("/"), action.description()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_and_lists_bindings() {
+        let keys = Keybindings::default();
+        assert_eq!(keys.actions("F1").collect::<Vec<_>>(), [Action::Help]);
+        assert_eq!(
+            keys.actions("ArrowUp").collect::<Vec<_>>(),
+            [Action::GustUp, Action::SteerUp]
+        );
+        assert_eq!(keys.actions("ctrl+s").count(), 0);
+
+        let help = keys.help_lines();
+        assert_eq!(help[0], "F1  toggle this help");
+        assert!(help.contains(&"ArrowLeft/a  steer the keyboard attractor left".to_string()));
+        assert_eq!(help.iter().filter(|line| line.contains("gust")).count(), 4);
+    }
+
+    #[test]
+    fn rebinds_from_toml() {
+        let docs = include_str!("keys.rs")
+            .lines()
+            .skip_while(|line| !line.starts_with("//! ```toml"))
+            .skip(1)
+            .take_while(|line| !line.starts_with("//! ```"))
+            .map(|line| line.trim_start_matches("//!").trim_start())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let keys = Keybindings::from_toml(&docs).unwrap();
+        assert_eq!(keys.actions("F12").collect::<Vec<_>>(), [Action::Help]);
+        assert_eq!(keys.actions("F1").count(), 0);
+        assert_eq!(
+            keys.actions("ctrl+s").collect::<Vec<_>>(),
+            [Action::SaveSnapshot]
+        );
+        // `s` still steers down.
+        assert_eq!(keys.actions("s").collect::<Vec<_>>(), [Action::SteerDown]);
+        assert_eq!(
+            keys.actions("j").collect::<Vec<_>>(),
+            [Action::ExportCsv, Action::SteerLeft]
+        );
+        assert_eq!(keys.actions("g").count(), 0);
+
+        assert!(Keybindings::from_toml("halp = \"F1\"").is_err());
+        assert!(Keybindings::from_toml("help = 1").is_err());
+    }
+}
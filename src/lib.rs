@@ -0,0 +1,59 @@
+#![cfg_attr(nightly, feature(portable_simd, mpmc_channel))]
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod autosave;
+pub mod bench;
+pub mod clipboard;
+pub mod command;
+pub mod demo;
+pub mod export;
+pub mod exposure;
+pub mod ffi;
+pub mod field;
+pub mod game;
+pub mod gesture;
+pub mod headless;
+pub mod io_thread;
+pub mod keys;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "midi")]
+pub mod midi;
+pub mod motion;
+#[cfg(feature = "numa")]
+pub mod numa;
+pub mod obstacle;
+#[cfg(feature = "osc")]
+pub mod osc;
+pub mod output;
+pub mod overlay;
+pub mod palette;
+pub mod particles;
+pub mod portal;
+pub mod render;
+pub mod scene;
+pub mod scoped_threadpool;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod sdf;
+#[cfg(feature = "shm")]
+pub mod shm;
+pub mod simd;
+pub mod soak;
+#[cfg(all(feature = "spout", windows))]
+pub mod spout;
+pub mod svg;
+#[cfg(all(feature = "syphon", target_os = "macos"))]
+pub mod syphon;
+pub mod timeline;
+pub mod trail;
+#[cfg(feature = "udp")]
+pub mod udp;
+pub mod units;
+#[cfg(feature = "v4l2")]
+pub mod v4l2;
+pub mod view;
+pub mod watchdog;
+pub mod watermark;
+#[cfg(feature = "websocket")]
+pub mod websocket;
@@ -1,10 +1,50 @@
-#![feature(portable_simd, sync_unsafe_cell, mpmc_channel, duration_millis_float)]
 mod app_softbuffer;
-mod scoped_threadpool;
+mod options;
 // mod app_minifb;
-mod particles;
 
-fn main() {
-    app_softbuffer::run();
+use std::process::ExitCode;
+use std::thread::available_parallelism;
+use std::time::Duration;
+
+use options::Options;
+use particles::headless::Scenario;
+use particles::scoped_threadpool::Pool;
+
+fn main() -> ExitCode {
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {err}");
+            return ExitCode::from(2);
+        }
+    };
+    let threads = options
+        .threads
+        .unwrap_or_else(|| available_parallelism().unwrap().get())
+        .max(1);
+    if let Some(seconds) = options.bench {
+        let pool = Pool::new(threads);
+        let report = particles::bench::run(
+            &pool,
+            Scenario::benchmarks(),
+            Duration::from_secs_f32(seconds),
+        );
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return ExitCode::SUCCESS;
+    }
+    if let Some(seconds) = options.soak {
+        let pool = Pool::new(threads);
+        let scenario = Scenario::benchmarks().swap_remove(1);
+        particles::soak::run(
+            &pool,
+            scenario,
+            (seconds > 0.0).then(|| Duration::from_secs_f32(seconds)),
+            Duration::from_secs_f32(options.soak_every),
+            |sample| println!("{}", serde_json::to_string(sample).unwrap()),
+        );
+        return ExitCode::SUCCESS;
+    }
+    app_softbuffer::run(options);
     // app_minifb::run();
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,178 @@
+//! Prometheus metrics endpoint.
+//!
+//! For installations running as a persistent display, the app serves its
+//! frame rate, particle count, the time spent in each phase of a frame and
+//! the number of dropped frames at `http://<addr>/metrics` in the Prometheus
+//! text format:
+//!
+//! ```text
+//! particles_fps 59.8
+//! particles_count 1048576
+//! particles_frames_total 35881
+//! particles_dropped_frames_total 12
+//! particles_phase_seconds_total{phase="simulate"} 151.2
+//! ```
+//!
+//! The phase totals are counters, so `rate()` over them gives the share of
+//! each phase in the frame time.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const DEFAULT_ADDR: &str = "0.0.0.0:9187";
+
+/// The phases of a frame, in order.
+pub const PHASES: [&str; 4] = ["simulate", "render", "output", "present"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Values {
+    fps: f32,
+    particles: usize,
+    frames: u64,
+    dropped_frames: u64,
+    phase_seconds: [f64; PHASES.len()],
+}
+
+/// Metrics shared between the app and the server thread.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    values: Mutex<Values>,
+}
+
+impl Metrics {
+    /// Records a frame that took `frametime`, with the averaged `fps`, while
+    /// the app aims for `target` per frame. Every whole `target` the frame
+    /// took longer counts as a dropped frame. `phases` are the durations of
+    /// `PHASES`.
+    pub fn record(
+        &self,
+        frametime: Duration,
+        target: Duration,
+        fps: f32,
+        particles: usize,
+        phases: [Duration; PHASES.len()],
+    ) {
+        let mut values = self.values.lock().unwrap();
+        values.fps = fps;
+        values.particles = particles;
+        values.frames += 1;
+        if !target.is_zero() {
+            let intervals = (frametime.as_secs_f64() / target.as_secs_f64()).round() as u64;
+            values.dropped_frames += intervals.saturating_sub(1);
+        }
+        for (total, phase) in values.phase_seconds.iter_mut().zip(phases) {
+            *total += phase.as_secs_f64();
+        }
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap().clone();
+        let mut text = format!(
+            "\
+# HELP particles_fps Frames per second, averaged over the last 100 frames.
+# TYPE particles_fps gauge
+particles_fps {}
+# HELP particles_count Number of simulated particles.
+# TYPE particles_count gauge
+particles_count {}
+# HELP particles_frames_total Frames drawn.
+# TYPE particles_frames_total counter
+particles_frames_total {}
+# HELP particles_dropped_frames_total Target frame intervals that passed without a frame.
+# TYPE particles_dropped_frames_total counter
+particles_dropped_frames_total {}
+# HELP particles_phase_seconds_total Time spent in each phase of a frame.
+# TYPE particles_phase_seconds_total counter
+",
+            values.fps, values.particles, values.frames, values.dropped_frames
+        );
+        for (phase, seconds) in PHASES.iter().zip(values.phase_seconds) {
+            text.push_str(&format!(
+                "particles_phase_seconds_total{{phase=\"{phase}\"}} {seconds}\n"
+            ));
+        }
+        text
+    }
+}
+
+/// Binds `addr` and spawns a thread answering `GET /metrics` with the
+/// rendered `metrics`.
+pub fn spawn_server(addr: impl ToSocketAddrs, metrics: Arc<Metrics>) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    let handle = thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if let Err(err) = respond(stream, &metrics) {
+                eprintln!("metrics: {err}");
+            }
+        }
+    });
+    Ok(handle)
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "not found, try /metrics\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn serves_metrics() {
+        let metrics = Arc::new(Metrics::default());
+        let ms = Duration::from_millis;
+        metrics.record(ms(16), ms(16), 62.5, 640, [ms(4), ms(8), ms(2), ms(2)]);
+        // Three intervals, two of them dropped.
+        metrics.record(ms(50), ms(16), 40.0, 1280, [ms(10), ms(20), ms(10), ms(10)]);
+        let text = metrics.render();
+        for line in [
+            "particles_fps 40\n",
+            "particles_count 1280\n",
+            "particles_frames_total 2\n",
+            "particles_dropped_frames_total 2\n",
+            "particles_phase_seconds_total{phase=\"render\"} 0.028\n",
+        ] {
+            assert!(text.contains(line), "{line:?} missing from\n{text}");
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        spawn_server(addr, metrics).unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(&text));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
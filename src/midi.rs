@@ -0,0 +1,230 @@
+//! MIDI controller input.
+//!
+//! Connects to a MIDI input port through midir, so ALSA on Linux, CoreMIDI
+//! on macOS and WinMM on Windows, and maps control changes to
+//! `SimCommand`s. Controllers 1 to 4 on the first channel are bound to the
+//! parameters in `Param::ALL` by default; any control can be rebound with
+//! `MidiInput::learn`.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::command::SimCommand;
+use crate::palette::Palette;
+
+/// Name of the client, as shown by the MIDI system.
+const CLIENT_NAME: &str = "particles";
+
+/// Simulation parameters that can be bound to a MIDI control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Gravity,
+    Friction,
+    TimeScale,
+    Palette,
+}
+
+impl Param {
+    pub const ALL: [Param; 4] = [
+        Param::Gravity,
+        Param::Friction,
+        Param::TimeScale,
+        Param::Palette,
+    ];
+
+    /// Maps a 7 bit control value onto the range of the parameter.
+    pub fn command(self, value: u8) -> SimCommand {
+        let norm = value.min(127) as f32 / 127.0;
+        match self {
+            Param::Gravity => SimCommand::SetGravity(norm * 4.0),
+            Param::Friction => SimCommand::SetFriction(0.9 + norm * 0.1),
+            Param::TimeScale => SimCommand::SetTimeScale(norm * 2.0),
+            Param::Palette => SimCommand::SetPalette(value as usize * Palette::ALL.len() / 128),
+        }
+    }
+}
+
+/// Handle to a running MIDI listener. The port is closed when it is
+/// dropped.
+pub struct MidiInput {
+    port: String,
+    learn: Sender<Param>,
+    _connection: midir::MidiInputConnection<()>,
+}
+
+impl MidiInput {
+    /// Binds the next control that is moved to `param`.
+    pub fn learn(&self, param: Param) {
+        let _ = self.learn.send(param);
+    }
+
+    /// Name of the connected port.
+    pub fn port(&self) -> &str {
+        &self.port
+    }
+}
+
+/// Names of the MIDI input ports, in the order `spawn_listener` picks
+/// from.
+pub fn port_names() -> io::Result<Vec<String>> {
+    let input = midir::MidiInput::new(CLIENT_NAME).map_err(io::Error::other)?;
+    Ok(input
+        .ports()
+        .iter()
+        .filter_map(|port| input.port_name(port).ok())
+        .collect())
+}
+
+/// Connects to the first input port whose name contains `name`, ignoring
+/// case, or to the first port at all without a `name`, and forwards mapped
+/// control changes to `commands`.
+pub fn spawn_listener(name: Option<&str>, commands: Sender<SimCommand>) -> io::Result<MidiInput> {
+    let input = midir::MidiInput::new(CLIENT_NAME).map_err(io::Error::other)?;
+    let needle = name.map(str::to_lowercase);
+    let (port, port_name) = input
+        .ports()
+        .into_iter()
+        .filter_map(|port| Some((input.port_name(&port).ok()?, port)))
+        .find(|(port_name, _)| {
+            needle
+                .as_ref()
+                .is_none_or(|needle| port_name.to_lowercase().contains(needle))
+        })
+        .map(|(port_name, port)| (port, port_name))
+        .ok_or_else(|| match name {
+            Some(name) => io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no MIDI input port matches `{name}`"),
+            ),
+            None => io::Error::new(io::ErrorKind::NotFound, "no MIDI input port"),
+        })?;
+
+    let (learn_tx, learn_rx) = mpsc::channel();
+    let mut mapping = Mapping::default();
+    let connection = input
+        .connect(
+            &port,
+            CLIENT_NAME,
+            move |_, message, _| {
+                let Some(command) =
+                    control_change(message).and_then(|cc| mapping.handle(cc, &learn_rx))
+                else {
+                    return;
+                };
+                // Fails once the app was dropped, which closes the port.
+                let _ = commands.send(command);
+            },
+            (),
+        )
+        .map_err(|err| io::Error::other(err.kind().to_string()))?;
+    Ok(MidiInput {
+        port: port_name,
+        learn: learn_tx,
+        _connection: connection,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ControlChange {
+    channel: u8,
+    controller: u8,
+    value: u8,
+}
+
+struct Mapping {
+    bindings: HashMap<(u8, u8), Param>,
+    learning: Option<Param>,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        let bindings = (1..).zip(Param::ALL).map(|(cc, param)| ((0, cc), param));
+        Mapping {
+            bindings: bindings.collect(),
+            learning: None,
+        }
+    }
+}
+
+impl Mapping {
+    fn handle(&mut self, cc: ControlChange, learn: &Receiver<Param>) -> Option<SimCommand> {
+        if let Some(param) = learn.try_iter().last() {
+            self.learning = Some(param);
+        }
+        let key = (cc.channel, cc.controller);
+        if let Some(param) = self.learning.take() {
+            self.bindings.retain(|_, bound| *bound != param);
+            self.bindings.insert(key, param);
+            println!(
+                "midi: bound controller {} on channel {} to {param:?}",
+                cc.controller,
+                cc.channel + 1
+            );
+        }
+        Some(self.bindings.get(&key)?.command(cc.value))
+    }
+}
+
+/// The control change in `message`, a complete MIDI message as midir
+/// delivers them.
+fn control_change(message: &[u8]) -> Option<ControlChange> {
+    match *message {
+        [
+            status @ 0xB0..=0xBF,
+            controller @ 0..=0x7F,
+            value @ 0..=0x7F,
+        ] => Some(ControlChange {
+            channel: status & 0x0F,
+            controller,
+            value,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cc(channel: u8, controller: u8, value: u8) -> ControlChange {
+        ControlChange {
+            channel,
+            controller,
+            value,
+        }
+    }
+
+    #[test]
+    fn parses_control_changes() {
+        assert_eq!(control_change(&[0xB0, 1, 64]), Some(cc(0, 1, 64)));
+        assert_eq!(control_change(&[0xB3, 7, 0]), Some(cc(3, 7, 0)));
+        // Note on, clock tick, sysex and a truncated message.
+        assert_eq!(control_change(&[0x91, 60, 100]), None);
+        assert_eq!(control_change(&[0xF8]), None);
+        assert_eq!(control_change(&[0xF0, 0x7E, 0x01, 0xF7]), None);
+        assert_eq!(control_change(&[0xB0, 4]), None);
+    }
+
+    #[test]
+    fn learn_rebinds_parameter() {
+        let mut mapping = Mapping::default();
+        let (learn_tx, learn_rx) = mpsc::channel();
+        assert_eq!(
+            mapping.handle(cc(0, 1, 127), &learn_rx),
+            Some(SimCommand::SetGravity(4.0))
+        );
+
+        learn_tx.send(Param::TimeScale).unwrap();
+        assert_eq!(
+            mapping.handle(cc(2, 20, 0), &learn_rx),
+            Some(SimCommand::SetTimeScale(0.0))
+        );
+        assert_eq!(
+            mapping.handle(cc(2, 20, 127), &learn_rx),
+            Some(SimCommand::SetTimeScale(2.0))
+        );
+        // The old default binding of the time scale is gone.
+        assert_eq!(mapping.handle(cc(0, 3, 127), &learn_rx), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! Average particle velocity per pixel.
+//!
+//! `MotionField` sums the velocities of the particles in every pixel as
+//! fixed point atomics next to their count, so it can be filled from the
+//! threadpool like the count buffers. Render passes and frame sinks read
+//! the averages as motion vectors in pixels per 60 Hz frame.
+
+use std::sync::atomic::{AtomicI32, AtomicU16, Ordering};
+
+/// Fixed point scale of the summed velocities.
+const SCALE: f32 = 256.0;
+
+/// Velocity sums and counts of a `width` x `height` area, row by row.
+#[derive(Debug, Default)]
+pub struct MotionField {
+    width: u32,
+    height: u32,
+    sums: Vec<[AtomicI32; 2]>,
+    counts: Vec<AtomicU16>,
+}
+
+impl MotionField {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Empties the field and resizes it to `width` x `height`.
+    pub fn clear(&mut self, width: u32, height: u32) {
+        let len = (width * height) as usize;
+        (self.width, self.height) = (width, height);
+        self.sums.resize_with(len, Default::default);
+        self.counts.resize_with(len, Default::default);
+        for [x, y] in &mut self.sums {
+            *x.get_mut() = 0;
+            *y.get_mut() = 0;
+        }
+        for count in &mut self.counts {
+            *count.get_mut() = 0;
+        }
+    }
+
+    /// Adds a particle moving by (`dx`, `dy`) to pixel `index`.
+    #[inline(always)]
+    pub(crate) fn add(&self, index: usize, dx: f32, dy: f32) {
+        let [x, y] = &self.sums[index];
+        x.fetch_add((dx * SCALE) as i32, Ordering::Relaxed);
+        y.fetch_add((dy * SCALE) as i32, Ordering::Relaxed);
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Average velocity of the particles in pixel `index`, zero if it is
+    /// empty.
+    #[inline(always)]
+    pub fn velocity(&self, index: usize) -> (f32, f32) {
+        let count = self.counts[index].load(Ordering::Relaxed);
+        if count == 0 {
+            return (0.0, 0.0);
+        }
+        let [x, y] = &self.sums[index];
+        let scale = SCALE * count as f32;
+        (
+            x.load(Ordering::Relaxed) as f32 / scale,
+            y.load(Ordering::Relaxed) as f32 / scale,
+        )
+    }
+
+    /// The average velocities, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        (0..self.counts.len()).map(|index| self.velocity(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::Particle;
+    use crate::simd::Simd;
+
+    #[test]
+    fn averages_velocities() {
+        let mut motion = MotionField::default();
+        motion.clear(4, 4);
+        let mut particle = Particle::ZERO;
+        // Every lane outside except for three.
+        particle.x = Simd::splat(-1.0);
+        (particle.x[0], particle.y[0], particle.dx[0]) = (1.5, 1.5, 2.0);
+        (particle.x[1], particle.y[1], particle.dy[1]) = (1.2, 1.7, -2.0);
+        (particle.x[2], particle.y[2], particle.dx[2]) = (2.5, 0.5, 0.5);
+        particle.count_motion(&motion, 4, 4);
+        assert_eq!(motion.velocity(5), (1.0, -1.0));
+        assert_eq!(motion.velocity(2), (0.5, 0.0));
+        assert_eq!(motion.velocity(0), (0.0, 0.0));
+        assert_eq!(motion.iter().count(), 16);
+
+        motion.clear(2, 2);
+        assert!(motion.iter().all(|velocity| velocity == (0.0, 0.0)));
+    }
+}
@@ -0,0 +1,191 @@
+//! Memory placement helpers for multi-socket machines.
+//!
+//! Linux places a page on the NUMA node of the thread that first writes to
+//! it. Buffers zeroed by the main thread therefore end up on a single node,
+//! and every worker on the other nodes pays for remote accesses. Pinning the
+//! workers and letting them initialize the buffers spreads the pages over
+//! the nodes the workers run on.
+
+use std::io;
+use std::mem::{self, MaybeUninit};
+use std::thread::available_parallelism;
+
+use crate::scoped_threadpool::Pool;
+
+/// How the large per-frame buffers are allocated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Placement {
+    /// Initialize the buffers from the workers, see `first_touch`.
+    pub first_touch: bool,
+    /// Back the buffers with transparent huge pages.
+    pub huge_pages: bool,
+}
+
+impl Placement {
+    /// Resizes `buffer` to `len` elements created by `init`, reallocating it
+    /// to place the pages as configured.
+    pub fn resize<T: Send>(
+        self,
+        threadpool: &Pool,
+        buffer: &mut Vec<T>,
+        len: usize,
+        init: impl Fn() -> T + Sync,
+    ) {
+        if !self.first_touch && !self.huge_pages {
+            buffer.resize_with(len, init);
+            return;
+        }
+        // Growing in place would copy the old elements from this thread.
+        *buffer = Vec::with_capacity(len);
+        self.prepare(threadpool, buffer);
+        if self.first_touch {
+            first_touch(threadpool, buffer, len, init);
+        } else {
+            buffer.resize_with(len, init);
+        }
+    }
+
+    /// Places the pages of the capacity `buffer` has reserved beyond its
+    /// length.
+    pub fn prepare<T>(self, threadpool: &Pool, buffer: &mut Vec<T>) {
+        if self.huge_pages
+            && let Err(err) = advise_huge_pages(buffer)
+        {
+            eprintln!("numa: huge pages unavailable: {err}");
+        }
+        if self.first_touch {
+            touch_spare_capacity(threadpool, buffer);
+        }
+    }
+}
+
+/// Pins the calling thread to a single core, wrapping `cpu` around the
+/// number of cores the process may run on.
+pub fn pin_current_thread(cpu: usize) -> io::Result<()> {
+    unsafe {
+        let mut allowed: libc::cpu_set_t = mem::zeroed();
+        if libc::sched_getaffinity(0, mem::size_of_val(&allowed), &mut allowed) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let cores = (0..libc::CPU_SETSIZE as usize)
+            .filter(|&core| libc::CPU_ISSET(core, &allowed))
+            .collect::<Vec<_>>();
+        let Some(&core) = cores.get(cpu % cores.len().max(1)) else {
+            return Ok(());
+        };
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, mem::size_of_val(&set), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// A threadpool of `n` workers, each pinned to its own core.
+pub fn pinned_pool(n: usize) -> Pool {
+    let n_cores = available_parallelism().map_or(1, |n| n.get());
+    if n > n_cores {
+        eprintln!("numa: {n} threads on {n_cores} cores, some cores are shared");
+    }
+    Pool::with_thread_start(n, |id| {
+        if let Err(err) = pin_current_thread(id) {
+            eprintln!("numa: failed to pin worker {id}: {err}");
+        }
+    })
+}
+
+/// Asks the kernel to back the whole allocation of `buffer`, including its
+/// spare capacity, with transparent huge pages. Only pages that have not
+/// been touched yet are affected.
+pub fn advise_huge_pages<T>(buffer: &Vec<T>) -> io::Result<()> {
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = buffer.as_ptr() as usize;
+    let end = start + buffer.capacity() * mem::size_of::<T>();
+    // madvise wants page aligned ranges, the partial pages at the ends are
+    // left alone.
+    let start = start.next_multiple_of(page);
+    let end = end / page * page;
+    if end <= start {
+        return Ok(());
+    }
+    let ret = unsafe { libc::madvise(start as *mut _, end - start, libc::MADV_HUGEPAGE) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Grows `buffer` to `len` elements created by `init`. The new elements are
+/// written by the workers of `threadpool`, one contiguous part each, so
+/// that their pages are placed on the workers' nodes.
+///
+/// The buffer has to have enough capacity already: growing it here would
+/// copy the existing elements from the calling thread.
+pub fn first_touch<T: Send>(
+    threadpool: &Pool,
+    buffer: &mut Vec<T>,
+    len: usize,
+    init: impl Fn() -> T + Sync,
+) {
+    let n = len.saturating_sub(buffer.len());
+    assert!(n <= buffer.capacity() - buffer.len(), "not enough capacity");
+    let chunk_len = n.div_ceil(threadpool.thread_count() as usize).max(1);
+    let init = &init;
+    threadpool.scoped(|scope| {
+        for chunk in buffer.spare_capacity_mut()[..n].chunks_mut(chunk_len) {
+            scope.execute(move |_| {
+                for element in chunk {
+                    element.write(init());
+                }
+            });
+        }
+    });
+    // All `n` elements were initialized by the jobs above.
+    unsafe { buffer.set_len(buffer.len() + n) };
+}
+
+/// Writes the spare capacity of `buffer` from the workers of `threadpool`,
+/// placing its pages before the elements are created, e.g. for storage
+/// reserved up front with `Particles::reserve_budget`.
+pub fn touch_spare_capacity<T>(threadpool: &Pool, buffer: &mut Vec<T>) {
+    let spare = buffer.spare_capacity_mut();
+    let spare = unsafe {
+        std::slice::from_raw_parts_mut(
+            spare.as_mut_ptr().cast::<MaybeUninit<u8>>(),
+            mem::size_of_val(spare),
+        )
+    };
+    let chunk_len = spare
+        .len()
+        .div_ceil(threadpool.thread_count() as usize)
+        .max(1);
+    threadpool.scoped(|scope| {
+        for chunk in spare.chunks_mut(chunk_len) {
+            scope.execute(move |_| {
+                for byte in chunk {
+                    byte.write(0);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_touch_initializes_every_element() {
+        let pool = pinned_pool(3);
+        let mut buffer = vec![7_u32; 5];
+        buffer.reserve_exact(10_000);
+        // Fails if transparent huge pages are disabled.
+        let _ = advise_huge_pages(&buffer);
+        touch_spare_capacity(&pool, &mut buffer);
+        first_touch(&pool, &mut buffer, 10_005, || 1);
+        assert_eq!(buffer.len(), 10_005);
+        assert_eq!(buffer[..5], [7; 5]);
+        assert!(buffer[5..].iter().all(|&v| v == 1));
+    }
+}
@@ -0,0 +1,331 @@
+//! Static obstacles the particles bounce off.
+//!
+//! Shapes are declared in normalized window coordinates and converted to
+//! pixels whenever the window is resized. A coarse grid records which
+//! shapes overlap every cell, so only the lanes in such cells are tested
+//! against the shapes, and blocks far away from any obstacle skip the
+//! collision entirely.
+
+use crate::simd::cmp::{SimdPartialEq, SimdPartialOrd};
+use crate::simd::num::{SimdFloat, SimdUint};
+use crate::simd::{Select, Simd, StdFloat};
+
+use serde::{Deserialize, Serialize};
+
+use crate::particles::{Lanes, Particle, Real, splat};
+
+type F32s = Simd<Real, 64>;
+type U64s = Simd<u64, 64>;
+
+/// Size of the cells of the overlap grid in pixels.
+const CELL: f32 = 32.0;
+
+/// Distance in pixels particles are pushed past the surface, so that they
+/// are outside of the shape after rounding.
+const SKIN: f32 = 0.01;
+
+/// An obstacle in normalized window coordinates.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    /// `{ "circle": { "x": 0.5, "y": 0.5, "radius": 0.1 } }`, the radius is
+    /// relative to the shorter side of the window.
+    Circle { x: f32, y: f32, radius: f32 },
+    /// `{ "polygon": [[0.1, 0.9], [0.3, 0.9], [0.2, 0.7]] }`, closed
+    /// automatically. Self-intersecting outlines use the even-odd rule.
+    Polygon(Vec<[f32; 2]>),
+}
+
+/// A `Shape` in pixels.
+#[derive(Debug, Clone)]
+enum Solid {
+    Circle { x: f32, y: f32, radius: f32 },
+    Polygon(Vec<(f32, f32)>),
+}
+
+impl Solid {
+    fn new(shape: &Shape, width: f32, height: f32) -> Self {
+        match shape {
+            Shape::Circle { x, y, radius } => Solid::Circle {
+                x: x * width,
+                y: y * height,
+                radius: radius * width.min(height),
+            },
+            Shape::Polygon(points) => Solid::Polygon(
+                points
+                    .iter()
+                    .map(|[x, y]| (x * width, y * height))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Bounding box as (min x, min y, max x, max y).
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        match self {
+            Solid::Circle { x, y, radius } => (x - radius, y - radius, x + radius, y + radius),
+            Solid::Polygon(points) => points.iter().fold(
+                (
+                    f32::INFINITY,
+                    f32::INFINITY,
+                    f32::NEG_INFINITY,
+                    f32::NEG_INFINITY,
+                ),
+                |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+            ),
+        }
+    }
+
+    /// Moves the lanes in `lanes` that are inside of the solid onto its
+    /// surface and reflects their velocity if it points inwards.
+    #[inline(always)]
+    fn collide(&self, particle: &mut Particle, lanes: Lanes) {
+        let zero = F32s::splat(0.0);
+        // Outward normal and the surface point of every lane.
+        let (hit, nx, ny, sx, sy) = match self {
+            Solid::Circle { x, y, radius } => {
+                let (cx, cy) = (splat(*x), splat(*y));
+                let (dx, dy) = (particle.x - cx, particle.y - cy);
+                let d2 = dx * dx + dy * dy;
+                let hit = lanes & d2.simd_lt(splat(radius * radius));
+                if !hit.any() {
+                    return;
+                }
+                // Particles exactly at the center leave to the right.
+                let centered = d2.simd_eq(zero);
+                let d_inv = centered.select(F32s::splat(1.0), d2.sqrt().recip());
+                let nx = centered.select(F32s::splat(1.0), dx * d_inv);
+                let ny = dy * d_inv;
+                let r = splat(radius + SKIN);
+                (hit, nx, ny, cx + nx * r, cy + ny * r)
+            }
+            Solid::Polygon(points) => {
+                let mut inside = Lanes::splat(false);
+                let mut best = splat(f32::INFINITY);
+                let (mut qx, mut qy) = (particle.x, particle.y);
+                let edges = points.iter().zip(points.iter().cycle().skip(1));
+                for (&(ax, ay), &(bx, by)) in edges {
+                    let (ex, ey) = (bx - ax, by - ay);
+                    let len2 = ex * ex + ey * ey;
+                    if len2 == 0.0 {
+                        continue;
+                    }
+                    let (px, py) = (particle.x - splat(ax), particle.y - splat(ay));
+                    let (ex, ey) = (splat(ex), splat(ey));
+                    let t = ((px * ex + py * ey) / splat(len2)).simd_clamp(zero, F32s::splat(1.0));
+                    let (ox, oy) = (px - t * ex, py - t * ey);
+                    let d2 = ox * ox + oy * oy;
+                    let closer = d2.simd_lt(best);
+                    best = closer.select(d2, best);
+                    qx = closer.select(splat(ax) + t * ex, qx);
+                    qy = closer.select(splat(ay) + t * ey, qy);
+                    // Even-odd rule with a ray towards positive x, the
+                    // comparison flips for edges going up.
+                    let straddles = py.simd_lt(zero) ^ py.simd_lt(ey);
+                    let left = (px * ey).simd_lt(py * ex) ^ ey.simd_lt(zero);
+                    inside ^= straddles & left;
+                }
+                let hit = lanes & inside;
+                if !hit.any() {
+                    return;
+                }
+                let (dx, dy) = (qx - particle.x, qy - particle.y);
+                let d = (dx * dx + dy * dy).sqrt();
+                let on_edge = d.simd_eq(zero);
+                let d_inv = on_edge.select(zero, d.recip());
+                let (nx, ny) = (dx * d_inv, dy * d_inv);
+                let skin = splat(SKIN);
+                (hit, nx, ny, qx + nx * skin, qy + ny * skin)
+            }
+        };
+        particle.x = hit.select(sx, particle.x);
+        particle.y = hit.select(sy, particle.y);
+        let vn = particle.dx * nx + particle.dy * ny;
+        let reflect = hit & vn.simd_lt(zero);
+        let vn2 = reflect.select(vn + vn, zero);
+        particle.dx -= vn2 * nx;
+        particle.dy -= vn2 * ny;
+    }
+}
+
+/// A circle in pixels, moved freely between updates, e.g. with the cursor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disc {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+impl Disc {
+    /// Moves the lanes of `particle` inside of the disc onto its edge and
+    /// reflects their velocity if it points inwards.
+    #[inline(always)]
+    pub fn collide(&self, particle: &mut Particle) {
+        let solid = Solid::Circle {
+            x: self.x,
+            y: self.y,
+            radius: self.radius,
+        };
+        solid.collide(particle, Lanes::splat(true));
+    }
+}
+
+/// The obstacles of a window, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Obstacles {
+    shapes: Vec<Shape>,
+    solids: Vec<Solid>,
+    cols: usize,
+    rows: usize,
+    /// Per cell, bit `i % 64` is set if solid `i` overlaps the cell.
+    cells: Vec<u64>,
+}
+
+impl Obstacles {
+    /// The `shapes` in a `width` x `height` window.
+    pub fn new(shapes: Vec<Shape>, width: u32, height: u32) -> Self {
+        let mut obstacles = Obstacles {
+            shapes,
+            solids: Vec::new(),
+            cols: 0,
+            rows: 0,
+            cells: Vec::new(),
+        };
+        obstacles.resize(width, height);
+        obstacles
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    /// Converts the shapes to pixels of a `width` x `height` window.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (w, h) = (width as f32, height as f32);
+        self.solids = self
+            .shapes
+            .iter()
+            .map(|shape| Solid::new(shape, w, h))
+            .collect();
+        self.cols = (w / CELL).ceil().max(1.0) as usize;
+        self.rows = (h / CELL).ceil().max(1.0) as usize;
+        self.cells = vec![0; self.cols * self.rows];
+        for (i, solid) in self.solids.iter().enumerate() {
+            let (x0, y0, x1, y1) = solid.bounds();
+            if x1 < 0.0 || y1 < 0.0 || x0 >= w || y0 >= h {
+                continue;
+            }
+            let cell = |pos: f32, len: usize| ((pos / CELL).max(0.0) as usize).min(len - 1);
+            let (c0, c1) = (cell(x0, self.cols), cell(x1, self.cols));
+            let (r0, r1) = (cell(y0, self.rows), cell(y1, self.rows));
+            for row in r0..=r1 {
+                for col in c0..=c1 {
+                    self.cells[row * self.cols + col] |= 1 << (i % 64);
+                }
+            }
+        }
+    }
+
+    /// Lanes of the pixel positions (`x`, `y`) inside of an obstacle.
+    pub fn contains(&self, x: F32s, y: F32s) -> Lanes {
+        let mut particle = Particle::ZERO;
+        particle.x = x;
+        particle.y = y;
+        // Collisions move exactly the lanes inside of an obstacle.
+        self.collide(&mut particle);
+        particle.x.simd_ne(x) | particle.y.simd_ne(y)
+    }
+
+    /// Resolves the collisions of the lanes of `particle` in cells
+    /// overlapped by an obstacle.
+    #[inline(always)]
+    pub fn collide(&self, particle: &mut Particle) {
+        let col = (particle.x * splat(1.0 / CELL)).floor();
+        let row = (particle.y * splat(1.0 / CELL)).floor();
+        let zero = F32s::splat(0.0);
+        let in_grid = col.simd_ge(zero)
+            & col.simd_lt(splat(self.cols as f32))
+            & row.simd_ge(zero)
+            & row.simd_lt(splat(self.rows as f32));
+        if !in_grid.any() {
+            return;
+        }
+        let index = (row * splat(self.cols as f32) + col).cast::<usize>();
+        let bits = U64s::gather_select(&self.cells, in_grid.cast(), index, U64s::splat(0));
+        let any = bits.reduce_or();
+        if any == 0 {
+            return;
+        }
+        for (i, solid) in self.solids.iter().enumerate() {
+            let bit = 1 << (i % 64);
+            if any & bit != 0 {
+                let lanes = (bits & U64s::splat(bit)).simd_ne(U64s::splat(0));
+                solid.collide(particle, lanes.cast());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounces_off_shapes() {
+        let obstacles = Obstacles::new(
+            vec![
+                Shape::Circle {
+                    x: 0.25,
+                    y: 0.5,
+                    radius: 0.1,
+                },
+                Shape::Polygon(vec![[0.6, 0.4], [0.8, 0.4], [0.8, 0.6], [0.6, 0.6]]),
+            ],
+            200,
+            100,
+        );
+        let mut particle = Particle::ZERO;
+        // Inside the circle of radius 10 around (50, 50), moving inwards.
+        particle.x[0] = 45.0;
+        particle.y[0] = 50.0;
+        particle.dx[0] = 2.0;
+        // Inside the square from (120, 40) to (160, 60), moving right.
+        particle.x[1] = 122.0;
+        particle.y[1] = 50.0;
+        particle.dx[1] = 3.0;
+        particle.dy[1] = 1.0;
+        // Far away from both.
+        particle.x[2] = 190.0;
+        particle.y[2] = 90.0;
+        particle.dx[2] = 1.0;
+        obstacles.collide(&mut particle);
+
+        assert!((particle.x[0] - (40.0 - SKIN) as Real).abs() < 1e-3);
+        assert_eq!((particle.y[0], particle.dx[0]), (50.0, -2.0));
+        assert!((particle.x[1] - (120.0 - SKIN) as Real).abs() < 1e-3);
+        assert!((particle.dx[1] + 3.0).abs() < 1e-6 && (particle.dy[1] - 1.0).abs() < 1e-6);
+        assert_eq!((particle.x[2], particle.dx[2]), (190.0, 1.0));
+        // Lanes outside of every cell with an obstacle are left alone.
+        assert_eq!(particle.x[3], 0.0);
+    }
+
+    #[test]
+    fn disc_pushes_particles_out() {
+        let disc = Disc {
+            x: 10.0,
+            y: 10.0,
+            radius: 5.0,
+        };
+        let mut particle = Particle::ZERO;
+        particle.x = Simd::splat(10.0);
+        particle.y = Simd::splat(30.0);
+        // Inside, moving towards the center.
+        particle.y[0] = 13.0;
+        particle.dy[0] = -1.0;
+        disc.collide(&mut particle);
+
+        assert!((particle.y[0] - (15.0 + SKIN) as Real).abs() < 1e-3);
+        assert_eq!((particle.x[0], particle.dy[0]), (10.0, 1.0));
+        assert_eq!((particle.y[1], particle.dy[1]), (30.0, 0.0));
+    }
+}
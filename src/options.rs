@@ -0,0 +1,385 @@
+use std::env;
+use std::io;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use particles::autosave::{self, State};
+use particles::demo;
+use particles::keys::Keybindings;
+use particles::particles::{RemovalPolicy, SpawnPattern};
+use particles::scene::{Emitter, Scene};
+use particles::sdf::Image;
+use particles::svg;
+use particles::watermark::{Corner, Watermark};
+
+/// Number of attractors placed along the outlines of `--svg` shapes.
+const SVG_ATTRACTORS: usize = 32;
+
+const USAGE: &str = "\
+usage: particles [options]
+
+options:
+    -h, --help          print this help
+    --scene <path>      start from a JSON scene file
+    --watch             re-apply the scene file whenever it changes
+    --svg <path>        add the shapes of an SVG file as obstacles
+    --svg-attract <s>   place attractors of strength <s> along the SVG outlines
+    --sdf-image <path>  steer particles around the bright pixels of a .pgm or .png image
+    --demo <seconds>    rotate through the built-in presets
+    --bench <seconds>   run the benchmark workload without a window and print
+                        its frame times as JSON
+    --soak <seconds>    run without a window, 0 forever, printing the memory use
+                        and the drift of the particle states as JSON lines
+    --soak-every <s>    seconds between the --soak reports (default: 60)
+    --wind-tunnel       start from the wind tunnel preset, dragging draws obstacles
+    --gestures          draw a circle to add a vortex, a Z to clear, a line to set the wind
+    --keys <path>       TOML file rebinding keys, e.g. help = \"F12\"
+    --autosave <path>   save the particles and parameters to <path> periodically
+    --autosave-every <s> seconds between the autosaves (default: 60)
+    --resume            continue from the --autosave file if there is one
+    --watchdog <s>      recreate the surface after <s> seconds without a frame,
+                        exit with code 3 after six times as long
+    --watermark <path>  blend a PNG logo into a corner of every frame
+    --watermark-corner <corner>
+                        top-left, top-right, bottom-left or bottom-right (default)
+    --watermark-opacity <o> opacity of the watermark from 0 to 1 (default: 1)
+    --threads <n>       number of worker threads (default: all cores)
+    --max-particles <n> upper bound on the particle count, allocated up front
+    --u8-counts         count into saturating 8 bit buffers, faster on large windows
+    --desktop-space     keep the particles in place on the desktop when the window moves
+    --motion-vectors    accumulate the average velocity per pixel for the outputs
+    --csv-every <n>     export every <n>th particle with J (default: 1)
+    --trail-length <n>  positions per trail shown with W (default: 8)
+    --recycle-every <n> move NaN particles back to the first emitter every <n> frames,
+                        0 never (default: 1 in debug builds, 0 otherwise)
+    --removal <policy>  particles removed first when the frame rate drops:
+                        newest, random (default), oldest or offscreen
+    --audio             play the sonification on the default output device (feature `audio`)
+    --metrics <addr>    address of the Prometheus metrics endpoint (feature `metrics`)
+    --midi <name>       MIDI input port to read, by part of its name (feature `midi`)
+    --numa              pin workers to cores and let them allocate the buffers (feature `numa`)
+    --huge-pages        back the buffers with transparent huge pages (feature `numa`)
+    --osc <addr>        address of the OSC listener (feature `osc`)
+    --script <path>     add the force defined by a Rhai script (feature `script`)
+    --shm <name>        publish the density field to /dev/shm/<name> (feature `shm`)
+    --shm-pixels <name> publish the pixels to /dev/shm/<name> (feature `shm`)
+    --shm-format <format>
+                        layout of the --shm-pixels: xrgb8888 (default), xbgr8888,
+                        rgba8, rgba16 or xrgb2101010, 10 bits per channel
+                        unless metaballs, glow or tag colors are on
+    --spout <name>      share the frames as the Spout sender <name> (feature `spout`, Windows)
+    --syphon <name>     share the frames as the Syphon server <name> (feature `syphon`, macOS)
+    --udp <addr>        address of the UDP attractor feed (feature `udp`)
+    --v4l2 <device>     write the frames to a v4l2loopback virtual camera (feature `v4l2`)
+    --v4l2-size <WxH>   size of the virtual camera, even width (default: 1280x720)
+    --websocket <addr>  address of the WebSocket remote control (feature `websocket`)";
+
+/// Command line options of the app.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Scene to start from.
+    pub scene: Option<Scene>,
+    /// Scene file to re-apply when it changes.
+    pub watch: Option<String>,
+    /// Seconds between presets in demo mode.
+    pub demo: Option<f32>,
+    /// Seconds to run the headless benchmark for instead of the app.
+    pub bench: Option<f32>,
+    /// Seconds to soak test for instead of running the app, forever if 0.
+    pub soak: Option<f32>,
+    /// Seconds between the soak test reports.
+    pub soak_every: f32,
+    /// Dragging draws obstacles, for the wind tunnel preset.
+    pub draw_obstacles: bool,
+    /// Shapes drawn with the mouse trigger actions, see `particles::gesture`.
+    pub gestures: bool,
+    /// Key table with the rebindings of `--keys`.
+    pub keybindings: Keybindings,
+    /// File the state is autosaved to.
+    pub autosave: Option<String>,
+    /// Time between the autosaves.
+    pub autosave_every: Duration,
+    /// Autosaved state to continue from.
+    pub resume: Option<State>,
+    /// Time without a frame after which the watchdog steps in.
+    pub watchdog: Option<Duration>,
+    /// Logo blended into every frame.
+    pub watermark: Option<Watermark>,
+    /// Number of threadpool workers, all cores if unset.
+    pub threads: Option<usize>,
+    /// Upper bound on the particle count, unbounded if unset.
+    pub max_particles: Option<usize>,
+    /// Count into saturating `u8` buffers instead of atomic `u16` ones.
+    pub u8_counts: bool,
+    /// Keep the particles in desktop coordinates.
+    pub desktop_space: bool,
+    /// Accumulate the average particle velocity per pixel.
+    pub motion_vectors: bool,
+    /// Every how many particles the CSV export keeps.
+    pub csv_every: usize,
+    /// Positions per particle trail.
+    pub trail_length: usize,
+    /// Every how many frames non-finite particles are recycled, never if 0.
+    pub recycle_every: usize,
+    /// Which particles the auto-scaler removes first.
+    pub removal_policy: RemovalPolicy,
+    /// Play the sonification.
+    #[cfg(feature = "audio")]
+    pub audio: bool,
+    /// Address the Prometheus metrics endpoint binds to.
+    #[cfg(feature = "metrics")]
+    pub metrics_addr: Option<String>,
+    /// Part of the name of the MIDI input port to read controls from.
+    #[cfg(feature = "midi")]
+    pub midi_port: Option<String>,
+    /// Pin the workers and place the buffers on their nodes.
+    #[cfg(feature = "numa")]
+    pub numa: bool,
+    /// Back the buffers with transparent huge pages.
+    #[cfg(feature = "numa")]
+    pub huge_pages: bool,
+    /// Address the OSC listener binds to.
+    #[cfg(feature = "osc")]
+    pub osc_addr: Option<String>,
+    /// Custom force, see `particles::script`.
+    #[cfg(feature = "script")]
+    pub script: Option<particles::script::Script>,
+    /// Name of the shared-memory segment the density is published to.
+    #[cfg(feature = "shm")]
+    pub shm_name: Option<String>,
+    /// Name of the shared-memory segment the pixels are published to.
+    #[cfg(feature = "shm")]
+    pub shm_pixels: Option<String>,
+    /// Layout the pixels are published in.
+    #[cfg(feature = "shm")]
+    pub shm_format: particles::output::PixelFormat,
+    /// Name of the Spout sender the frames are shared as.
+    #[cfg(all(feature = "spout", windows))]
+    pub spout_name: Option<String>,
+    /// Name of the Syphon server the frames are shared as.
+    #[cfg(all(feature = "syphon", target_os = "macos"))]
+    pub syphon_name: Option<String>,
+    /// Address the UDP attractor feed binds to.
+    #[cfg(feature = "udp")]
+    pub udp_addr: Option<String>,
+    /// v4l2loopback device the frames are written to.
+    #[cfg(feature = "v4l2")]
+    pub v4l2_device: Option<String>,
+    /// Size of the virtual camera.
+    #[cfg(feature = "v4l2")]
+    pub v4l2_size: (u32, u32),
+    /// Address the WebSocket remote control binds to.
+    #[cfg(feature = "websocket")]
+    pub websocket_addr: Option<String>,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        let mut options = Options {
+            csv_every: 1,
+            trail_length: 8,
+            soak_every: 60.0,
+            autosave_every: autosave::DEFAULT_INTERVAL,
+            recycle_every: if cfg!(debug_assertions) { 1 } else { 0 },
+            #[cfg(feature = "v4l2")]
+            v4l2_size: particles::v4l2::DEFAULT_SIZE,
+            ..Options::default()
+        };
+        let mut args = env::args().skip(1);
+        let mut svg_shapes = None;
+        let mut svg_attraction = 0.0;
+        let mut sdf_image = None;
+        let mut scene_path = None;
+        let mut watch = false;
+        let mut resume = false;
+        let mut watermark = None;
+        let mut watermark_corner = Corner::default();
+        let mut watermark_opacity = 1.0;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    process::exit(0);
+                }
+                "--u8-counts" => options.u8_counts = true,
+                "--gestures" => options.gestures = true,
+                "--watch" => watch = true,
+                "--desktop-space" => options.desktop_space = true,
+                "--motion-vectors" => options.motion_vectors = true,
+                "--csv-every" => options.csv_every = parse(&value(&mut args, &arg)?, &arg)?,
+                "--trail-length" => options.trail_length = parse(&value(&mut args, &arg)?, &arg)?,
+                "--recycle-every" => options.recycle_every = parse(&value(&mut args, &arg)?, &arg)?,
+                "--keys" => {
+                    let path = value(&mut args, &arg)?;
+                    options.keybindings = Keybindings::load(&path)
+                        .map_err(|err| format!("failed to load keybindings `{path}`: {err}"))?;
+                }
+                "--autosave" => options.autosave = Some(value(&mut args, &arg)?),
+                "--autosave-every" => {
+                    let seconds = seconds(&value(&mut args, &arg)?, &arg)?;
+                    options.autosave_every = Duration::from_secs_f32(seconds.max(1.0));
+                }
+                "--resume" => resume = true,
+                "--watchdog" => {
+                    let seconds = seconds(&value(&mut args, &arg)?, &arg)?;
+                    options.watchdog = Some(Duration::from_secs_f32(seconds.max(1.0)));
+                }
+                "--watermark" => watermark = Some(value(&mut args, &arg)?),
+                "--watermark-corner" => watermark_corner = value(&mut args, &arg)?.parse()?,
+                "--watermark-opacity" => {
+                    watermark_opacity = parse(&value(&mut args, &arg)?, &arg)
+                        .ok()
+                        .filter(|opacity: &f32| (0.0..=1.0).contains(opacity))
+                        .ok_or_else(|| format!("`{arg}` must be between 0 and 1"))?;
+                }
+                "--removal" => options.removal_policy = value(&mut args, &arg)?.parse()?,
+                "--scene" => {
+                    let path = value(&mut args, &arg)?;
+                    let scene = Scene::load(&path)
+                        .map_err(|err| format!("failed to load scene `{path}`: {err}"))?;
+                    options.scene = Some(scene);
+                    scene_path = Some(path);
+                }
+                "--svg" => {
+                    let path = value(&mut args, &arg)?;
+                    let shapes = svg::load(&path)
+                        .map_err(|err| format!("failed to load SVG `{path}`: {err}"))?;
+                    svg_shapes = Some(shapes);
+                }
+                "--sdf-image" => {
+                    let path = value(&mut args, &arg)?;
+                    let image = Image::load(&path)
+                        .map_err(|err| format!("failed to load image `{path}`: {err}"))?;
+                    sdf_image = Some(image);
+                }
+                "--svg-attract" => svg_attraction = parse(&value(&mut args, &arg)?, &arg)?,
+                "--wind-tunnel" => {
+                    options.scene = demo::preset("wind tunnel");
+                    options.draw_obstacles = true;
+                }
+                "--demo" => options.demo = Some(parse(&value(&mut args, &arg)?, &arg)?),
+                "--bench" => options.bench = Some(seconds(&value(&mut args, &arg)?, &arg)?),
+                "--soak" => options.soak = Some(seconds(&value(&mut args, &arg)?, &arg)?),
+                "--soak-every" => options.soak_every = seconds(&value(&mut args, &arg)?, &arg)?,
+                "--max-particles" => {
+                    options.max_particles = Some(parse(&value(&mut args, &arg)?, &arg)?)
+                }
+                "--threads" => options.threads = Some(parse(&value(&mut args, &arg)?, &arg)?),
+                #[cfg(feature = "metrics")]
+                "--metrics" => options.metrics_addr = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "midi")]
+                "--midi" => options.midi_port = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "numa")]
+                "--numa" => options.numa = true,
+                #[cfg(feature = "numa")]
+                "--huge-pages" => options.huge_pages = true,
+                #[cfg(feature = "osc")]
+                "--osc" => options.osc_addr = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "script")]
+                "--script" => {
+                    let path = value(&mut args, &arg)?;
+                    let script = particles::script::Script::load(&path)
+                        .map_err(|err| format!("failed to load script `{path}`: {err}"))?;
+                    options.script = Some(script);
+                }
+                #[cfg(feature = "audio")]
+                "--audio" => options.audio = true,
+                #[cfg(feature = "shm")]
+                "--shm" => options.shm_name = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "shm")]
+                "--shm-pixels" => options.shm_pixels = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "shm")]
+                "--shm-format" => options.shm_format = value(&mut args, &arg)?.parse()?,
+                #[cfg(all(feature = "spout", windows))]
+                "--spout" => options.spout_name = Some(value(&mut args, &arg)?),
+                #[cfg(all(feature = "syphon", target_os = "macos"))]
+                "--syphon" => options.syphon_name = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "udp")]
+                "--udp" => options.udp_addr = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "websocket")]
+                "--websocket" => options.websocket_addr = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "v4l2")]
+                "--v4l2" => options.v4l2_device = Some(value(&mut args, &arg)?),
+                #[cfg(feature = "v4l2")]
+                "--v4l2-size" => {
+                    let size = value(&mut args, &arg)?;
+                    options.v4l2_size = size
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .filter(|&(w, h): &(u32, u32)| w > 0 && h > 0 && w % 2 == 0)
+                        .ok_or_else(|| format!("invalid value `{size}` for `{arg}`"))?;
+                }
+                _ => return Err(format!("unknown argument `{arg}`\n\n{USAGE}")),
+            }
+        }
+        if watch {
+            options.watch = Some(scene_path.ok_or("--watch needs --scene")?);
+        }
+        if let Some(path) = watermark {
+            let watermark = Watermark::load(&path, watermark_corner, watermark_opacity)
+                .map_err(|err| format!("failed to load watermark `{path}`: {err}"))?;
+            options.watermark = Some(watermark);
+        }
+        if resume {
+            let path = options
+                .autosave
+                .as_ref()
+                .ok_or("--resume needs --autosave")?;
+            // A missing or unreadable save must not keep an installation
+            // from starting.
+            match State::load(path) {
+                Ok(state) => options.resume = Some(state),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    println!("no autosave at `{path}` yet, starting afresh")
+                }
+                Err(err) => eprintln!("failed to resume from `{path}`: {err}"),
+            }
+        }
+        // Without a scene, particles spread over the window flow around the
+        // shapes.
+        let uniform = || Scene {
+            emitters: vec![Emitter {
+                pattern: SpawnPattern::Uniform,
+                ..Emitter::default()
+            }],
+            ..Scene::default()
+        };
+        if let Some(image) = sdf_image {
+            let scene = options.scene.get_or_insert_with(uniform);
+            scene.forces.sdf.get_or_insert_default();
+            scene.sdf_image = Some(image);
+        }
+        if let Some(shapes) = svg_shapes {
+            let scene = options.scene.get_or_insert_with(uniform);
+            if svg_attraction != 0.0 {
+                scene.forces.attractors.extend(svg::outline_attractors(
+                    &shapes,
+                    SVG_ATTRACTORS,
+                    svg_attraction,
+                ));
+            }
+            scene.obstacles.extend(shapes);
+        }
+        Ok(options)
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("missing value for `{flag}`"))
+}
+
+fn parse<T: FromStr>(value: &str, flag: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for `{flag}`"))
+}
+
+/// A non-negative, finite number of seconds.
+fn seconds(value: &str, flag: &str) -> Result<f32, String> {
+    parse(value, flag)
+        .ok()
+        .filter(|seconds: &f32| seconds.is_finite() && *seconds >= 0.0)
+        .ok_or_else(|| format!("invalid value `{value}` for `{flag}`"))
+}
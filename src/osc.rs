@@ -0,0 +1,245 @@
+//! OSC remote control.
+//!
+//! Listens for OSC messages on a UDP socket and forwards them as
+//! `SimCommand`s. Supported addresses:
+//!
+//! - `/gravity <float>`: attractor strength
+//! - `/palette <int>`: palette index
+//! - `/brightness <float>`: brightness multiplier
+//! - `/spawn <int>`: number of particles to spawn
+//!
+//! Non-finite arguments are ignored. The others are forwarded as they are
+//! and clamped by `SimCommand::validated`, like the commands of every other
+//! listener.
+
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+
+use crate::command::SimCommand;
+
+pub const DEFAULT_ADDR: &str = "0.0.0.0:9000";
+
+/// Binds `addr` and spawns a thread forwarding received commands to
+/// `commands`. The thread exits once the receiving side is dropped.
+pub fn spawn_listener(
+    addr: impl ToSocketAddrs,
+    commands: Sender<SimCommand>,
+) -> io::Result<JoinHandle<()>> {
+    let socket = UdpSocket::bind(addr)?;
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 1536];
+        loop {
+            let Ok(len) = socket.recv(&mut buf) else {
+                continue;
+            };
+            let mut messages = Vec::new();
+            if decode_packet(&buf[..len], &mut messages).is_none() {
+                eprintln!("osc: invalid packet");
+                continue;
+            }
+            for message in messages {
+                let Some(command) = to_command(&message) else {
+                    eprintln!(
+                        "osc: unsupported message {} {:?}",
+                        message.addr, message.args
+                    );
+                    continue;
+                };
+                if commands.send(command).is_err() {
+                    // The app was dropped.
+                    return;
+                }
+            }
+        }
+    });
+    Ok(handle)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Int(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    Bool(bool),
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    addr: String,
+    args: Vec<Arg>,
+}
+
+fn to_command(message: &Message) -> Option<SimCommand> {
+    let arg = as_f32(message.args.first()?)?;
+    match message.addr.as_str() {
+        "/gravity" => Some(SimCommand::SetGravity(arg)),
+        "/palette" => Some(SimCommand::SetPalette(arg as usize)),
+        "/brightness" => Some(SimCommand::SetBrightness(arg)),
+        "/spawn" => Some(SimCommand::Spawn(arg as usize)),
+        _ => None,
+    }
+}
+
+/// TouchOSC and most VJ tools send faders as floats and buttons as ints or
+/// booleans, so any finite numeric argument is accepted.
+fn as_f32(arg: &Arg) -> Option<f32> {
+    let value = match *arg {
+        Arg::Int(v) => v as f32,
+        Arg::Float(v) => v,
+        Arg::Long(v) => v as f32,
+        Arg::Double(v) => v as f32,
+        Arg::Bool(v) => v as u32 as f32,
+        Arg::Other => return None,
+    };
+    value.is_finite().then_some(value)
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+/// Decodes an OSC 1.0 packet, flattening bundles into `out`.
+fn decode_packet(packet: &[u8], out: &mut Vec<Message>) -> Option<()> {
+    let mut reader = Reader(packet);
+    if packet.starts_with(b"#bundle\0") {
+        reader.take(16)?; // "#bundle\0" and the time tag
+        while !reader.0.is_empty() {
+            let len = reader.i32()?;
+            decode_packet(reader.take(usize::try_from(len).ok()?)?, out)?;
+        }
+        return Some(());
+    }
+
+    let addr = reader.string()?.to_string();
+    let tags = reader.string()?.strip_prefix(',')?;
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.bytes() {
+        args.push(match tag {
+            b'i' => Arg::Int(reader.i32()?),
+            b'f' => Arg::Float(f32::from_bits(reader.i32()? as u32)),
+            b'h' => Arg::Long(reader.i64()?),
+            b'd' => Arg::Double(f64::from_bits(reader.i64()? as u64)),
+            b'T' => Arg::Bool(true),
+            b'F' => Arg::Bool(false),
+            b's' | b'S' => {
+                reader.string()?;
+                Arg::Other
+            }
+            b'b' => {
+                let len = usize::try_from(reader.i32()?).ok()?;
+                reader.take(len.next_multiple_of(4))?;
+                Arg::Other
+            }
+            b't' | b'c' | b'r' | b'm' => {
+                reader.take(if tag == b't' { 8 } else { 4 })?;
+                Arg::Other
+            }
+            b'N' | b'I' => Arg::Other,
+            _ => return None,
+        });
+    }
+    out.push(Message { addr, args });
+    Some(())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let (head, tail) = self.0.split_at_checked(n)?;
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// Null terminated string, padded to a multiple of 4 bytes.
+    fn string(&mut self) -> Option<&'a str> {
+        let len = self.0.iter().position(|&b| b == 0)?;
+        let bytes = self.take((len + 1).next_multiple_of(4))?;
+        std::str::from_utf8(&bytes[..len]).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(packet: &[u8]) -> Vec<SimCommand> {
+        let mut messages = Vec::new();
+        decode_packet(packet, &mut messages).unwrap();
+        messages.iter().filter_map(to_command).collect()
+    }
+
+    #[test]
+    fn decodes_messages() {
+        let mut packet = b"/gravity\0\0\0\0,f\0\0".to_vec();
+        packet.extend(2.5f32.to_be_bytes());
+        assert_eq!(decode(&packet), [SimCommand::SetGravity(2.5)]);
+
+        let mut packet = b"/palette\0\0\0\0,i\0\0".to_vec();
+        packet.extend(3i32.to_be_bytes());
+        assert_eq!(decode(&packet), [SimCommand::SetPalette(3)]);
+
+        assert_eq!(decode(b"/spawn\0\0,T\0\0"), [SimCommand::Spawn(1)]);
+        assert_eq!(decode(b"/unknown\0\0\0\0,T\0\0"), []);
+    }
+
+    #[test]
+    fn rejects_and_clamps_arguments() {
+        use crate::command::{BRIGHTNESS_RANGE, GRAVITY_RANGE, MAX_SPAWN};
+
+        let to_command = |message: &Message| to_command(message).and_then(SimCommand::validated);
+        let message = |addr: &str, arg: Arg| Message {
+            addr: addr.to_string(),
+            args: vec![arg],
+        };
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(to_command(&message("/gravity", Arg::Float(value))), None);
+        }
+        assert_eq!(
+            to_command(&message("/brightness", Arg::Double(1e300))),
+            None
+        );
+        assert_eq!(
+            to_command(&message("/gravity", Arg::Float(1e9))),
+            Some(SimCommand::SetGravity(GRAVITY_RANGE.1))
+        );
+        assert_eq!(
+            to_command(&message("/brightness", Arg::Int(0))),
+            Some(SimCommand::SetBrightness(BRIGHTNESS_RANGE.0))
+        );
+        assert_eq!(
+            to_command(&message("/spawn", Arg::Long(i64::MAX))),
+            Some(SimCommand::Spawn(MAX_SPAWN))
+        );
+    }
+
+    #[test]
+    fn decodes_bundles() {
+        let mut message = b"/brightness\0,f\0\0".to_vec();
+        message.extend(4.0f32.to_be_bytes());
+
+        let mut packet = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        for _ in 0..2 {
+            packet.extend((message.len() as i32).to_be_bytes());
+            packet.extend(&message);
+        }
+        assert_eq!(decode(&packet), vec![SimCommand::SetBrightness(4.0); 2]);
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let mut messages = Vec::new();
+        assert!(decode_packet(b"/gravity\0\0\0\0,f\0\0\0\0", &mut messages).is_none());
+        assert!(decode_packet(b"/gravity", &mut messages).is_none());
+    }
+}
@@ -0,0 +1,225 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+
+use crate::motion::MotionField;
+use crate::particles::MAX_TAGS;
+use crate::render;
+
+/// A rendered frame handed to the outputs after the pixel pass.
+pub struct Frame<'a> {
+    /// Number of the frame, increasing by one per rendered frame.
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    /// 0x00RRGGBB pixels, row by row.
+    pub pixels: &'a [u32],
+    /// Particle count per pixel, row by row.
+    pub density: Density<'a>,
+    /// Average particle velocity per pixel, if motion vectors are enabled.
+    pub motion: Option<&'a MotionField>,
+    /// X2R10G10B10 pixels, row by row, if a sink asked for
+    /// `PixelFormat::Xrgb2101010` and the render mode supports them.
+    pub pixels_10bit: Option<&'a [u32]>,
+}
+
+/// Particle counts of a frame, depending on the count buffer in use.
+#[derive(Clone, Copy)]
+pub enum Density<'a> {
+    U16(&'a [AtomicU16]),
+    /// Counts saturated at 255.
+    U8(&'a [u8]),
+    /// Counts per tag, see `Particles::count_tagged`.
+    Tagged(&'a [AtomicU64]),
+}
+
+impl Density<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Density::U16(counts) => counts.len(),
+            Density::U8(counts) => counts.len(),
+            Density::Tagged(counts) => counts.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Count of the pixel at `index`, see `iter`.
+    pub fn get(&self, index: usize) -> u16 {
+        match self {
+            Density::U16(counts) => counts[index].load(Ordering::Relaxed),
+            Density::U8(counts) => counts[index].into(),
+            Density::Tagged(counts) => {
+                let count = counts[index].load(Ordering::Relaxed);
+                (0..MAX_TAGS).fold(0_u16, |sum, tag| {
+                    sum.wrapping_add((count >> (16 * tag)) as u16)
+                })
+            }
+        }
+    }
+
+    /// The counts as `u16`, row by row. Tagged counts are summed over the
+    /// tags.
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        let (wide, narrow, tagged): (&[AtomicU16], &[u8], &[AtomicU64]) = match *self {
+            Density::U16(counts) => (counts, &[], &[]),
+            Density::U8(counts) => (&[], counts, &[]),
+            Density::Tagged(counts) => (&[], &[], counts),
+        };
+        let sum_tags = |count: u64| {
+            (0..MAX_TAGS).fold(0_u16, |sum, tag| {
+                sum.wrapping_add((count >> (16 * tag)) as u16)
+            })
+        };
+        wide.iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .chain(narrow.iter().map(|&count| count.into()))
+            .chain(
+                tagged
+                    .iter()
+                    .map(move |count| sum_tags(count.load(Ordering::Relaxed))),
+            )
+    }
+}
+
+/// The count buffer as plain integers, for passes with exclusive access.
+/// Stands in for the unstable `AtomicU16::get_mut_slice`.
+pub fn counts_mut(counts: &mut [AtomicU16]) -> &mut [u16] {
+    // SAFETY: `AtomicU16` has the size and bit validity of `u16` and at
+    // least its alignment, and the exclusive borrow rules out concurrent
+    // atomic accesses for its lifetime.
+    unsafe { std::slice::from_raw_parts_mut(counts.as_mut_ptr().cast(), counts.len()) }
+}
+
+/// The tagged count buffer as plain integers, see `counts_mut`.
+pub fn tagged_counts_mut(counts: &mut [AtomicU64]) -> &mut [u64] {
+    // SAFETY: as in `counts_mut`.
+    unsafe { std::slice::from_raw_parts_mut(counts.as_mut_ptr().cast(), counts.len()) }
+}
+
+/// Memory layouts the 0x00RRGGBB pixels of a `Frame` can be encoded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// Native-endian `u32` 0x00RRGGBB, the layout of the render passes.
+    #[default]
+    Xrgb8888,
+    /// Native-endian `u32` 0x00BBGGRR.
+    Xbgr8888,
+    /// Bytes red, green, blue and an opaque alpha.
+    Rgba8,
+    /// Little-endian `u16` red, green, blue and an opaque alpha.
+    Rgba16,
+    /// Native-endian `u32` X2R10G10B10, the layout of 10-bit surfaces, see
+    /// `render::colorize_10bit`. Sinks asking for it get the 10-bit
+    /// `Frame::pixels_10bit`, `encode` widens the 8-bit channels.
+    Xrgb2101010,
+}
+
+impl PixelFormat {
+    /// Layout of softbuffer surfaces. softbuffer 0.4 takes 0x00RRGGBB on
+    /// every backend and converts to the native format itself, so the
+    /// render passes can write into its buffers directly. It has no 10-bit
+    /// surfaces, `Xrgb2101010` is for the outputs.
+    pub const SOFTBUFFER: PixelFormat = PixelFormat::Xrgb8888;
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Xrgb8888
+            | PixelFormat::Xbgr8888
+            | PixelFormat::Rgba8
+            | PixelFormat::Xrgb2101010 => 4,
+            PixelFormat::Rgba16 => 8,
+        }
+    }
+
+    /// Appends the 0x00RRGGBB `pixels` to `out` in this format.
+    pub fn encode(self, pixels: &[u32], out: &mut Vec<u8>) {
+        out.reserve(pixels.len() * self.bytes_per_pixel());
+        for &pixel in pixels {
+            let [_, r, g, b] = pixel.to_be_bytes();
+            match self {
+                PixelFormat::Xrgb8888 => out.extend_from_slice(&pixel.to_ne_bytes()),
+                PixelFormat::Xbgr8888 => {
+                    out.extend_from_slice(&u32::from_be_bytes([0, b, g, r]).to_ne_bytes())
+                }
+                PixelFormat::Rgba8 => out.extend_from_slice(&[r, g, b, 0xFF]),
+                PixelFormat::Rgba16 => {
+                    // Scaling by 257 maps 0xFF to 0xFFFF.
+                    for channel in [r, g, b, 0xFF] {
+                        out.extend_from_slice(&(channel as u16 * 257).to_le_bytes());
+                    }
+                }
+                PixelFormat::Xrgb2101010 => {
+                    out.extend_from_slice(&render::widen_10bit(pixel).to_ne_bytes())
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xrgb8888" => Ok(PixelFormat::Xrgb8888),
+            "xbgr8888" => Ok(PixelFormat::Xbgr8888),
+            "rgba8" => Ok(PixelFormat::Rgba8),
+            "rgba16" => Ok(PixelFormat::Rgba16),
+            "xrgb2101010" => Ok(PixelFormat::Xrgb2101010),
+            _ => Err(format!("unknown pixel format `{s}`")),
+        }
+    }
+}
+
+/// Destination that receives every rendered frame, e.g. for sharing the
+/// visuals with other applications.
+///
+/// `publish` runs on the event loop thread before the frame is presented,
+/// so implementations should hand expensive work off to another thread.
+pub trait FrameSink {
+    fn publish(&mut self, frame: &Frame);
+
+    /// Format the sink encodes the pixels in. The 10-bit pixels are only
+    /// rendered while a sink asks for `PixelFormat::Xrgb2101010`.
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::Xrgb8888
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_pixel_formats() {
+        let pixels = [0x123456, 0xFF0001];
+        let encode = |format: PixelFormat| {
+            let mut out = Vec::new();
+            format.encode(&pixels, &mut out);
+            assert_eq!(out.len(), pixels.len() * format.bytes_per_pixel());
+            out
+        };
+        let words = |bytes: Vec<u8>| {
+            bytes
+                .chunks(4)
+                .map(|b| u32::from_ne_bytes(b.try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(words(encode(PixelFormat::Xrgb8888)), pixels);
+        assert_eq!(words(encode(PixelFormat::Xbgr8888)), [0x563412, 0x0100FF]);
+        assert_eq!(
+            words(encode(PixelFormat::Xrgb2101010)),
+            [0x48 << 20 | 0xD0 << 10 | 0x159, 0x3FF << 20 | 0x004]
+        );
+        assert_eq!(
+            encode(PixelFormat::Rgba8),
+            [0x12, 0x34, 0x56, 0xFF, 0xFF, 0x00, 0x01, 0xFF]
+        );
+        assert_eq!(
+            encode(PixelFormat::Rgba16)[..8],
+            [0x12, 0x12, 0x34, 0x34, 0x56, 0x56, 0xFF, 0xFF]
+        );
+    }
+}
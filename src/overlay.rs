@@ -0,0 +1,153 @@
+//! Text drawn on top of the rendered frame.
+//!
+//! Glyphs come from a built-in 3x5 pixel font and are scaled up by an
+//! integer factor, which keeps them sharp without a font rasterizer. The
+//! app draws them into the transparent overlay layer, in 0xAARRGGBB, which
+//! is then blended onto the frame, see `render::Layers`.
+
+/// Width and height of a glyph in font pixels.
+const GLYPH: (u32, u32) = (3, 5);
+
+/// Rows of the glyph of `c` from top to bottom, three bits each with the
+/// leftmost pixel in the highest bit, or `None` if the font lacks it.
+/// Letters are drawn in upper case.
+fn glyph(c: char) -> Option<[u8; 5]> {
+    Some(match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '^' => [0b010, 0b101, 0b000, 0b000, 0b000],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => return None,
+    })
+}
+
+/// Height in pixels of a line of text drawn at `scale`, including the gap
+/// to the next line.
+pub fn line_height(scale: u32) -> u32 {
+    (GLYPH.1 + 2) * scale
+}
+
+/// Fills the `rect` (left, top, width, height) of the `width` x `height`
+/// `pixels` with `color`, e.g. a translucent black as a backdrop for text.
+pub fn fill(pixels: &mut [u32], width: u32, height: u32, rect: (u32, u32, u32, u32), color: u32) {
+    let (left, top, w, h) = rect;
+    for y in top.min(height)..(top + h).min(height) {
+        let row = (y * width) as usize;
+        pixels[row + left.min(width) as usize..row + (left + w).min(width) as usize].fill(color);
+    }
+}
+
+/// Width in pixels of `text` drawn at `scale`, see `draw_text`.
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let n = text.chars().count() as u32;
+    (n * (GLYPH.0 + 1)).saturating_sub(1) * scale
+}
+
+/// Draws `text` with its top left corner at `pos` into the `width` x
+/// `height` `pixels`, every font pixel as a `scale` x `scale` square of
+/// `color`. Characters missing from the font are left blank.
+pub fn draw_text(
+    pixels: &mut [u32],
+    width: u32,
+    height: u32,
+    pos: (u32, u32),
+    scale: u32,
+    color: u32,
+    text: &str,
+) {
+    for (i, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else {
+            continue;
+        };
+        let left = pos.0 + i as u32 * (GLYPH.0 + 1) * scale;
+        for (row, bits) in (0..).zip(rows) {
+            for col in 0..GLYPH.0 {
+                if bits & (1 << (GLYPH.0 - 1 - col)) == 0 {
+                    continue;
+                }
+                let (x0, y0) = (left + col * scale, pos.1 + row * scale);
+                for y in y0..(y0 + scale).min(height) {
+                    for x in x0..(x0 + scale).min(width) {
+                        pixels[(y * width + x) as usize] = color;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_scaled_glyphs() {
+        let (width, height) = (22, 12);
+        let mut pixels = vec![0; 22 * 12];
+        draw_text(&mut pixels, width, height, (1, 1), 2, 0xFFFFFF, "1?7");
+        let lit = |x: u32, y: u32| pixels[(y * width + x) as usize] != 0;
+        // The top of the 1 is a 2x2 square, its base spans the glyph.
+        assert!(lit(3, 1) && lit(4, 2) && !lit(2, 1) && !lit(5, 1));
+        assert!(lit(1, 9) && lit(6, 10) && !lit(1, 11));
+        // `?` is missing and left blank.
+        assert!((9..16).all(|x| (0..height).all(|y| !lit(x, y))));
+        // The 7 is clipped at the right edge.
+        assert!(lit(17, 1) && lit(21, 10) && !lit(17, 3));
+        assert_eq!(text_width("12", 2), 14);
+
+        // Letters in either case, and a backdrop.
+        let mut pixels = vec![0xFF_FF_FF; 8 * 6];
+        fill(&mut pixels, 8, 6, (4, 0, 10, 6), 0xC0000000);
+        draw_text(&mut pixels, 8, 6, (0, 0), 1, 0xFF0000, "Lz");
+        let at = |x: u32, y: u32| pixels[(y * 8 + x) as usize];
+        assert_eq!(
+            (at(0, 0), at(1, 0), at(0, 4), at(2, 4)),
+            (0xFF0000, 0xFFFFFF, 0xFF0000, 0xFF0000)
+        );
+        assert_eq!(
+            (at(4, 0), at(6, 0), at(7, 5)),
+            (0xFF0000, 0xFF0000, 0xC0000000)
+        );
+    }
+}
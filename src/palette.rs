@@ -0,0 +1,52 @@
+use crate::simd::Simd;
+
+use serde::{Deserialize, Serialize};
+
+/// Color schemes used to tint the density field.
+///
+/// Each palette maps a normalized screen position to per-channel weights
+/// that scale the tone-mapped particle count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    #[default]
+    Gradient,
+    Fire,
+    Ice,
+    Mono,
+}
+
+/// Colors of the particle tags, see `render::colorize_tagged`.
+pub const TAG_COLORS: [[f32; 3]; crate::particles::MAX_TAGS] = [
+    [1.0, 0.45, 0.1],
+    [0.1, 0.7, 1.0],
+    [0.9, 0.2, 0.8],
+    [0.5, 1.0, 0.2],
+];
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Gradient,
+        Palette::Fire,
+        Palette::Ice,
+        Palette::Mono,
+    ];
+
+    /// Returns the palette at `index`, wrapping around.
+    pub fn from_index(index: usize) -> Self {
+        Self::ALL[index % Self::ALL.len()]
+    }
+
+    /// Red, green and blue weights for pixels at normalized positions
+    /// (`x`, `y`).
+    #[inline(always)]
+    pub fn weights<const N: usize>(self, x: Simd<f32, N>, y: Simd<f32, N>) -> [Simd<f32, N>; 3] {
+        let c = Simd::splat;
+        match self {
+            Palette::Gradient => [x, y, (c(1.0) - x) * (c(1.0) - y)],
+            Palette::Fire => [c(1.0), c(0.3) + c(0.5) * y, c(0.1) * x],
+            Palette::Ice => [c(0.2) * x, c(0.5) + c(0.4) * y, c(1.0)],
+            Palette::Mono => [c(1.0), c(1.0), c(1.0)],
+        }
+    }
+}
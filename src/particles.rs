@@ -1,17 +1,544 @@
+use crate::field::{ChargeField, Flow, FreezeMask, HeatField, VelocityField};
+use crate::motion::MotionField;
+use crate::obstacle::{Disc, Obstacles};
+use crate::portal::Portals;
+use crate::scoped_threadpool::{ChunkTuner, Pool};
+#[cfg(feature = "script")]
+use crate::script::ScriptForce;
+use crate::sdf::DistanceField;
+use crate::simd::{
+    Mask, Select, Simd, SimdElement, StdFloat,
+    cmp::SimdPartialOrd,
+    f32x64, i8x64,
+    num::{SimdFloat, SimdInt, SimdUint},
+    u8x64, u32x64,
+};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::{
     f32::consts::TAU,
     ops::Mul,
-    simd::{StdFloat, f32x64},
+    str::FromStr,
+    sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicUsize, Ordering},
     time::Duration,
 };
 
-type F32s = f32x64;
+/// Element type of the particle positions and velocities. The `f64`
+/// feature trades speed for precision in long runs, where the rounding of
+/// `f32` positions makes orbits drift.
+#[cfg(not(feature = "f64"))]
+pub type Real = f32;
+#[cfg(feature = "f64")]
+pub type Real = f64;
+
+type F32s = Simd<Real, 64>;
+
+/// `value` narrowed to `f32`, for consumers of the positions like the
+/// renderer.
+#[inline(always)]
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn narrow(value: Real) -> f32 {
+    value as f32
+}
+
+/// Lanes selected by comparing particle coordinates.
+pub type Lanes = Mask<<Real as SimdElement>::Mask, 64>;
+
+/// Lanes of `value`, widened to `Real`.
+#[inline(always)]
+pub(crate) fn splat(value: f32) -> F32s {
+    F32s::splat(value as Real)
+}
+type U32s = u32x64;
+
+/// Number of particle blocks sharing one bounding box for culling.
+const CULL_BLOCKS: usize = 16;
+
+/// `Particle::drag` of particles with the nominal friction.
+const DRAG_ONE: u8 = 128;
+
+/// Most substeps an update is split into, see `Particles::substep_distance`.
+const MAX_SUBSTEPS: u32 = 8;
+
+/// Number of distinct particle tags, see `Particles::spawn_tag`.
+pub const MAX_TAGS: usize = 4;
+
+/// A point attracting particles in addition to the mouse. Negative strengths
+/// repel.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Attractor {
+    pub x: f32,
+    pub y: f32,
+    pub strength: f32,
+}
+
+/// Stylus held on the window, modulating the mouse attractor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pen {
+    /// From 0 to 1, scaling the attraction.
+    pub pressure: f32,
+    /// Push applied along with the attraction, as a fraction of its
+    /// strength, e.g. along the lean of the pen.
+    pub bias: (f32, f32),
+}
+
+/// A source spawning particles continuously, see `Particles::emit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inflow {
+    pub pattern: SpawnPattern,
+    /// Center of the pattern in pixels.
+    pub center: (f32, f32),
+    /// Particles per simulated second.
+    pub rate: f32,
+    /// Tag of the spawned particles, see `Particles::spawn_tag`.
+    pub tag: u8,
+    /// Particles due but not spawned yet, less than a block.
+    pending: f32,
+}
+
+impl Inflow {
+    pub fn new(pattern: SpawnPattern, center: (f32, f32), rate: f32, tag: u8) -> Self {
+        Inflow {
+            pattern,
+            center,
+            rate,
+            tag,
+            pending: 0.0,
+        }
+    }
+}
+
+/// Which particles are removed first when the particle count shrinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RemovalPolicy {
+    /// The most recently spawned ones.
+    Newest,
+    /// Randomly chosen ones.
+    #[default]
+    Random,
+    /// The longest living ones.
+    Oldest,
+    /// The ones with the most lanes outside of the window.
+    Offscreen,
+}
+
+impl FromStr for RemovalPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newest" => Ok(RemovalPolicy::Newest),
+            "random" => Ok(RemovalPolicy::Random),
+            "oldest" => Ok(RemovalPolicy::Oldest),
+            "offscreen" => Ok(RemovalPolicy::Offscreen),
+            _ => Err(format!("unknown removal policy `{s}`")),
+        }
+    }
+}
+
+/// Initial arrangement of spawned particles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpawnPattern {
+    /// At the center, bursting outwards.
+    #[default]
+    Center,
+    /// At rest on a circle around the center.
+    Ring,
+    /// At rest, spread over the whole area.
+    Uniform,
+    /// At rest on a vertical line through the center, e.g. along the left
+    /// edge to feed a wind tunnel.
+    Line,
+}
+
+impl FromStr for SpawnPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "center" => Ok(SpawnPattern::Center),
+            "ring" => Ok(SpawnPattern::Ring),
+            "uniform" => Ok(SpawnPattern::Uniform),
+            "line" => Ok(SpawnPattern::Line),
+            _ => Err(format!("unknown spawn pattern `{s}`")),
+        }
+    }
+}
+
+/// What happens to particles leaving the visible area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    /// They keep going.
+    #[default]
+    Open,
+    /// They reenter on the opposite side.
+    Wrap,
+    /// They are reflected back.
+    Bounce,
+    /// They are removed.
+    Kill,
+}
+
+/// How the forces advance the velocities and positions every update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// The velocity is updated first and moves the particle, which keeps
+    /// the energy bounded at constant frame times.
+    #[default]
+    SemiImplicitEuler,
+    /// Velocity Verlet: half of the forces before and half after moving,
+    /// evaluated at both positions. Twice the force evaluations, but stable
+    /// orbits even with varying frame times and little friction.
+    Verlet,
+}
 
-use crate::scoped_threadpool::Pool;
-use rand::Rng;
+/// Waveform of an `Lfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Square,
+}
+
+/// Low-frequency oscillation of the mouse attractor strength.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Lfo {
+    pub shape: LfoShape,
+    /// Cycles per simulated second.
+    pub frequency: f32,
+    /// `0` keeps the strength steady, `0.5` breathes between zero and full
+    /// strength and `1` alternates between attracting and repelling.
+    pub depth: f32,
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Lfo {
+            shape: LfoShape::Sine,
+            frequency: 0.5,
+            depth: 0.5,
+        }
+    }
+}
+
+impl Lfo {
+    /// Factor applied to the strength `seconds` into the oscillation.
+    pub fn factor(self, seconds: f32) -> f32 {
+        let phase = (seconds * self.frequency).fract();
+        let wave = match self.shape {
+            LfoShape::Sine => (phase * TAU).cos(),
+            LfoShape::Square if phase < 0.5 => 1.0,
+            LfoShape::Square => -1.0,
+        };
+        1.0 - self.depth + self.depth * wave
+    }
+}
+
+/// Copies of every particle drawn in addition to the particle itself,
+/// mirrored or rotated around the center of the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Symmetry {
+    #[default]
+    None,
+    /// Mirrored left to right.
+    Mirror,
+    /// Mirrored left to right and top to bottom.
+    Quad,
+    /// Rotated into `n` evenly spaced directions.
+    Radial(u32),
+    /// `Radial` with every direction mirrored, like a kaleidoscope.
+    Kaleidoscope(u32),
+}
+
+impl Symmetry {
+    /// The next mode for cycling through them with a key.
+    pub fn next(self) -> Self {
+        match self {
+            Symmetry::None => Symmetry::Mirror,
+            Symmetry::Mirror => Symmetry::Quad,
+            Symmetry::Quad => Symmetry::Radial(6),
+            Symmetry::Radial(n) => Symmetry::Kaleidoscope(n),
+            Symmetry::Kaleidoscope(_) => Symmetry::None,
+        }
+    }
+
+    /// Row-major 2x2 matrices mapping a position relative to the center to
+    /// its images, empty without symmetry.
+    pub fn transforms(self) -> Vec<[Real; 4]> {
+        let rotations = |n: u32| {
+            (0..n.max(1)).map(move |i| {
+                let tau = std::f64::consts::TAU as Real;
+                let (sin, cos) = (tau * i as Real / n.max(1) as Real).sin_cos();
+                [cos, -sin, sin, cos]
+            })
+        };
+        match self {
+            Symmetry::None => Vec::new(),
+            Symmetry::Mirror => vec![[1.0, 0.0, 0.0, 1.0], [-1.0, 0.0, 0.0, 1.0]],
+            Symmetry::Quad => vec![
+                [1.0, 0.0, 0.0, 1.0],
+                [-1.0, 0.0, 0.0, 1.0],
+                [1.0, 0.0, 0.0, -1.0],
+                [-1.0, 0.0, 0.0, -1.0],
+            ],
+            Symmetry::Radial(n) => rotations(n).collect(),
+            Symmetry::Kaleidoscope(n) => rotations(n)
+                .flat_map(|[a, b, c, d]| [[a, b, c, d], [-a, b, -c, d]])
+                .collect(),
+        }
+    }
+}
+
+/// Calls `f` with every image of `particle` under `transforms` around
+/// `center`, or with `particle` itself if there are none. Only the
+/// positions and tags of the images are set.
+#[inline(always)]
+fn for_each_image(
+    particle: &Particle,
+    transforms: &[[Real; 4]],
+    center: (F32s, F32s),
+    mut f: impl FnMut(&Particle),
+) {
+    if transforms.is_empty() {
+        return f(particle);
+    }
+    let dx = particle.x - center.0;
+    let dy = particle.y - center.1;
+    for &[a, b, c, d] in transforms {
+        f(&Particle {
+            x: mul_add(F32s::splat(a), dx, mul_add(F32s::splat(b), dy, center.0)),
+            y: mul_add(F32s::splat(c), dx, mul_add(F32s::splat(d), dy, center.1)),
+            dx: mul_add(F32s::splat(a), particle.dx, F32s::splat(b) * particle.dy),
+            dy: mul_add(F32s::splat(c), particle.dx, F32s::splat(d) * particle.dy),
+            tag: particle.tag,
+            active: particle.active,
+            ..Particle::ZERO
+        });
+    }
+}
+
+/// Configures and spawns a simulation, see `Particles::builder`.
+#[derive(Debug, Clone)]
+pub struct ParticlesBuilder {
+    count: usize,
+    size: (u32, u32),
+    seed: Option<u64>,
+    pattern: SpawnPattern,
+    gravity: f32,
+    friction: f32,
+    time_scale: f32,
+    max_particles: Option<usize>,
+}
+
+impl Default for ParticlesBuilder {
+    fn default() -> Self {
+        ParticlesBuilder {
+            count: 0,
+            size: (1, 1),
+            seed: None,
+            pattern: SpawnPattern::default(),
+            gravity: 1.0,
+            friction: 0.988,
+            time_scale: 1.0,
+            max_particles: None,
+        }
+    }
+}
+
+impl ParticlesBuilder {
+    /// Number of particles spawned, the lanes of the last block beyond it
+    /// stay inactive.
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Size of the area the particles are spawned in.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    /// Makes spawning deterministic.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn spawn(mut self, pattern: SpawnPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    pub fn gravity(mut self, gravity: f32) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn friction(mut self, friction: f32) -> Self {
+        self.friction = friction;
+        self
+    }
+
+    pub fn time_scale(mut self, time_scale: f32) -> Self {
+        self.time_scale = time_scale;
+        self
+    }
+
+    /// Upper bound on the particle count, allocated up front, see
+    /// `Particles::reserve_budget`.
+    pub fn max_particles(mut self, max_particles: usize) -> Self {
+        self.max_particles = Some(max_particles);
+        self
+    }
+
+    pub fn build(self, threadpool: &Pool) -> Particles<'_> {
+        let mut particles = Particles::new(threadpool);
+        if let Some(seed) = self.seed {
+            particles.seed(seed);
+        }
+        particles.gravity = self.gravity;
+        particles.friction = self.friction;
+        particles.time_scale = self.time_scale;
+        particles.reserve_budget(self.max_particles.map(|n| n.div_ceil(F32s::LEN)));
+        let (width, height) = self.size;
+        let blocks = self.count.div_ceil(F32s::LEN);
+        particles.spawn(blocks, self.pattern, width, height);
+        if particles.particles.len() == blocks {
+            particles.fill_last_block(self.count % F32s::LEN);
+        }
+        particles
+    }
+}
+
+/// Copy of the simulation state taken by `Particles::snapshot`.
+#[derive(Clone)]
+pub struct Snapshot {
+    particles: Vec<Particle>,
+    gravity: f32,
+    friction: f32,
+    time_scale: f32,
+    attractors: Vec<Attractor>,
+    spawn_queue: usize,
+    fastest: f32,
+    rng: SmallRng,
+}
+
+impl Snapshot {
+    /// Number of particles in the snapshot.
+    pub fn len(&self) -> usize {
+        active_len(&self.particles)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Position and velocity `[x, y, dx, dy]` of every particle.
+    pub fn states(&self) -> impl Iterator<Item = [f32; 4]> + '_ {
+        self.particles.iter().flat_map(|p| {
+            (0..F32s::LEN)
+                .filter(|&i| p.is_active(i))
+                .map(move |i| [p.x[i], p.y[i], p.dx[i], p.dy[i]].map(narrow))
+        })
+    }
+}
 
 pub struct Particles<'a> {
     pub particles: Vec<Particle>,
+    /// Strength of the mouse attractor.
+    pub gravity: f32,
+    /// Fraction of velocity kept per 60 Hz frame.
+    pub friction: f32,
+    /// Fraction of vertical velocity kept per 60 Hz frame, `friction` if
+    /// unset.
+    pub friction_y: Option<f32>,
+    /// Velocity of the air in pixels per frame, which the friction slows
+    /// the particles down to instead of to rest.
+    pub wind: Option<(f32, f32)>,
+    /// How much the friction of the particles created by `spawn` and
+    /// `spawn_at` varies, from `0` for none to `1` for particles that coast
+    /// without friction up to ones losing velocity twice as fast.
+    pub friction_spread: f32,
+    /// Multiplier applied to the frametime.
+    pub time_scale: f32,
+    /// Attractors applied in addition to the mouse.
+    pub attractors: Vec<Attractor>,
+    /// Sources spawning particles with every `emit` call.
+    pub inflows: Vec<Inflow>,
+    /// Maximum number of particles spawned per `spawn_queued` call.
+    pub spawn_budget: usize,
+    /// Images of every particle drawn by the count passes.
+    pub symmetry: Symmetry,
+    /// Tag of the particles created by `spawn` and `spawn_at`, below
+    /// `MAX_TAGS`. Particles added from existing ones keep their tag.
+    pub spawn_tag: u8,
+    /// Painted currents carrying the particles along.
+    pub velocity_field: Option<VelocityField>,
+    /// How the painted currents evolve.
+    pub flow: Flow,
+    /// Interaction between charged particles.
+    pub charge_field: Option<ChargeField>,
+    /// Temperature lifting the particles in warm regions.
+    pub heat_field: Option<HeatField>,
+    /// Modulation of `gravity` for the mouse attractor.
+    pub gravity_lfo: Option<Lfo>,
+    /// Strength of the random kicks making the particles diffuse like a
+    /// gas, `0` for none.
+    pub temperature: f32,
+    /// Position of an attractor as strong as the mouse that stays in place
+    /// without any input, usually the center of the window.
+    pub center_attractor: Option<(f32, f32)>,
+    /// Position of an attractor as strong as the mouse that is steered
+    /// with the keyboard.
+    pub keyboard_attractor: Option<(f32, f32)>,
+    /// Shapes the particles bounce off.
+    pub obstacles: Option<Obstacles>,
+    /// A solid disc around the cursor the particles bounce off.
+    pub cursor_disc: Option<Disc>,
+    /// Pressure and lean of a pen used instead of the mouse.
+    pub pen: Option<Pen>,
+    /// Steers the particles around shapes.
+    pub distance_field: Option<DistanceField>,
+    /// Force defined by a user script.
+    #[cfg(feature = "script")]
+    pub script_force: Option<ScriptForce>,
+    /// Rectangles teleporting the particles between each other.
+    pub portals: Option<Portals>,
+    /// Regions in which particles stop and stay until thawed.
+    pub freeze_mask: Option<FreezeMask>,
+    pub integrator: Integrator,
+    /// Farthest a particle moves per substep in pixels. Updates in which
+    /// the fastest particle would move farther are split into up to
+    /// `MAX_SUBSTEPS` substeps, so that it neither tunnels through
+    /// obstacles nor leaves gaps. Never split if unset.
+    pub substep_distance: Option<f32>,
+    /// Largest velocity component of the last update.
+    fastest: f32,
+    /// Number of updates, decorrelating the kicks of successive frames.
+    n_steps: u32,
+    /// Simulated seconds the LFO has been running for.
+    lfo_time: f32,
+    spawn_queue: usize,
+    /// Upper bound on the number of particle blocks, see `reserve_budget`.
+    max_blocks: Option<usize>,
+    // Scratch space reused across frames, so that the steady state does not
+    // allocate.
+    attractor_lanes: Vec<(F32s, F32s, F32s)>,
+    removal_order: Vec<(u32, usize)>,
+    /// Bounding boxes of every `CULL_BLOCKS` particles as of the last
+    /// update, empty if particles were added or removed since.
+    bounds: Vec<Bounds>,
+    /// Chunks per worker of the update, adapted to its load balance.
+    chunk_tuner: ChunkTuner,
+    rng: SmallRng,
     threadpool: &'a Pool,
 }
 
@@ -19,100 +546,1211 @@ impl<'a> Particles<'a> {
     pub fn new(threadpool: &'a Pool) -> Self {
         Self {
             particles: Vec::new(),
+            gravity: 1.0,
+            friction: 0.988,
+            friction_y: None,
+            wind: None,
+            friction_spread: 0.0,
+            time_scale: 1.0,
+            attractors: Vec::new(),
+            inflows: Vec::new(),
+            spawn_budget: 2_000,
+            symmetry: Symmetry::None,
+            spawn_tag: 0,
+            velocity_field: None,
+            flow: Flow::default(),
+            charge_field: None,
+            heat_field: None,
+            gravity_lfo: None,
+            lfo_time: 0.0,
+            temperature: 0.0,
+            center_attractor: None,
+            keyboard_attractor: None,
+            obstacles: None,
+            cursor_disc: None,
+            pen: None,
+            distance_field: None,
+            #[cfg(feature = "script")]
+            script_force: None,
+            portals: None,
+            freeze_mask: None,
+            integrator: Integrator::default(),
+            substep_distance: Some(4.0),
+            fastest: 0.0,
+            n_steps: 0,
+            spawn_queue: 0,
+            max_blocks: None,
+            attractor_lanes: Vec::new(),
+            removal_order: Vec::new(),
+            bounds: Vec::new(),
+            chunk_tuner: ChunkTuner::default(),
+            rng: SmallRng::from_entropy(),
             threadpool,
         }
     }
 
-    pub fn add_particles(&mut self, n: usize, width: u32, height: u32) {
-        let mut n = n;
-        if self.particles.is_empty() {
-            self.particles.push(Particle::new_random(width, height));
-            n = n.saturating_sub(1);
-        }
-        let mut i = rand::thread_rng().gen_range(0..self.particles.len());
-        let part_len = self.particles.len();
-        for _ in 0..n {
-            self.particles
-                .push(Particle::new_from_existing(&self.particles[i % part_len]));
-            i += 1;
-        }
+    pub fn builder() -> ParticlesBuilder {
+        ParticlesBuilder::default()
+    }
+
+    /// Reseeds the random number generator, making spawning deterministic.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    /// Limits the particles to `max_blocks` blocks of 64 and allocates the
+    /// storage for all of them up front, so that growing up to the budget
+    /// never reallocates mid-frame. `None` lifts the limit.
+    pub fn reserve_budget(&mut self, max_blocks: Option<usize>) {
+        self.max_blocks = max_blocks;
+        if let Some(max_blocks) = max_blocks {
+            self.particles.truncate(max_blocks);
+            self.particles
+                .reserve_exact(max_blocks - self.particles.len());
+        }
+    }
+
+    /// The particle budget in blocks of 64, if any.
+    pub fn max_blocks(&self) -> Option<usize> {
+        self.max_blocks
+    }
+
+    /// `n` limited to the blocks left in the budget.
+    fn clamp_to_budget(&self, n: usize) -> usize {
+        match self.max_blocks {
+            Some(max_blocks) => n.min(max_blocks.saturating_sub(self.particles.len())),
+            None => n,
+        }
+    }
+
+    /// Charges the blocks alternately with `+1` and `-1`, see
+    /// `charge_field`.
+    pub fn alternate_charges(&mut self) {
+        for (i, particle) in self.particles.iter_mut().enumerate() {
+            particle.charge = i8x64::splat(if i % 2 == 0 { 1 } else { -1 });
+        }
+    }
+
+    /// Spawns `n` new blocks of particles arranged by `pattern` in a
+    /// `width` x `height` area.
+    pub fn spawn(&mut self, n: usize, pattern: SpawnPattern, width: u32, height: u32) {
+        let center = (width as f32 / 2.0, height as f32 / 2.0);
+        self.spawn_at(n, pattern, center, width, height);
+    }
+
+    /// Like `spawn`, with center and ring patterns around `center` instead
+    /// of the center of the area.
+    pub fn spawn_at(
+        &mut self,
+        n: usize,
+        pattern: SpawnPattern,
+        center: (f32, f32),
+        width: u32,
+        height: u32,
+    ) {
+        let n = self.clamp_to_budget(n);
+        if n == 0 {
+            return;
+        }
+        self.bounds.clear();
+        let part_len = self.particles.len();
+        let particles_chunk_len = usize::max(n / self.threadpool.thread_count() as usize / 10, 1);
+        self.particles.resize(part_len + n, Particle::ZERO);
+        let tag = u8x64::splat(self.spawn_tag.min(MAX_TAGS as u8 - 1));
+        let spread = self.friction_spread.clamp(0.0, 1.0);
+
+        let stream = self.rng.r#gen();
+
+        self.threadpool.scoped(|scope| {
+            let chunks = self.particles[part_len..].chunks_mut(particles_chunk_len);
+            for (i_chunk, particles_chunk) in chunks.enumerate() {
+                scope.execute(move |_| {
+                    let offset = i_chunk * particles_chunk_len;
+                    for (i, particle) in particles_chunk.iter_mut().enumerate() {
+                        let mut rng = block_rng(stream, offset + i);
+                        *particle = Particle {
+                            tag,
+                            ..Particle::new_in_pattern(pattern, center, width, height, &mut rng)
+                        };
+                        if spread > 0.0 {
+                            let offset = (random_unit(&mut rng) * F32s::splat(2.0)
+                                - F32s::splat(1.0))
+                                * splat(spread * DRAG_ONE as f32);
+                            particle.drag = (splat(DRAG_ONE as f32) + offset)
+                                .simd_min(F32s::splat(255.0))
+                                .cast();
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    pub fn add_particles(&mut self, n: usize, width: u32, height: u32) {
+        let mut n = self.clamp_to_budget(n);
+        if n == 0 {
+            return;
+        }
+        self.bounds.clear();
+        if self.particles.is_empty() {
+            self.particles
+                .push(Particle::new_random(width, height, &mut self.rng));
+            n = n.saturating_sub(1);
+        }
+        let part_len = self.particles.len();
+        let start = self.rng.gen_range(0..part_len);
+
+        let particles_chunk_len = usize::max(n / self.threadpool.thread_count() as usize / 10, 1);
+
+        self.particles
+            .resize(part_len + n, self.particles[0].clone());
+        let (existing, spawned) = self.particles.split_at_mut(part_len);
+        let existing = &*existing;
+        let stream = self.rng.r#gen();
+
+        self.threadpool.scoped(|scope| {
+            for (i_chunk, particles_chunk) in spawned.chunks_mut(particles_chunk_len).enumerate() {
+                scope.execute(move |_| {
+                    let offset = i_chunk * particles_chunk_len;
+                    for (i, particle) in particles_chunk.iter_mut().enumerate() {
+                        let source = &existing[(start + offset + i) % part_len];
+                        let mut rng = block_rng(stream, offset + i);
+                        *particle = Particle::new_from_existing(source, &mut rng);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Adds `n` particles without rounding up to whole blocks: the inactive
+    /// lanes of the last block are filled first, and the lanes of the new
+    /// last block beyond `n` stay inactive.
+    pub fn add_lanes(&mut self, mut n: usize, width: u32, height: u32) {
+        if let Some(last) = self.particles.last_mut() {
+            // The inactive lanes hold copies of active ones, see `retain`.
+            let fresh = Particle::new_from_existing(last, &mut self.rng);
+            for lane in 0..F32s::LEN {
+                if n > 0 && !last.is_active(lane) {
+                    last.copy_lane(lane, &fresh, lane);
+                    n -= 1;
+                }
+            }
+        }
+        let blocks = self.particles.len() + n.div_ceil(F32s::LEN);
+        self.add_particles(n.div_ceil(F32s::LEN), width, height);
+        if self.particles.len() == blocks {
+            self.fill_last_block(n % F32s::LEN);
+        }
+    }
+
+    /// Deactivates the lanes of the last block from `lanes` on, unless
+    /// `lanes` is 0.
+    pub(crate) fn fill_last_block(&mut self, lanes: usize) {
+        if let Some(last) = self.particles.last_mut()
+            && lanes > 0
+        {
+            last.active = u64::MAX >> (F32s::LEN - lanes);
+        }
+    }
+
+    /// Spawns the particles the `inflows` produce in `seconds` in a `width`
+    /// x `height` area. Fractions of a block are carried over to the next
+    /// call.
+    pub fn emit(&mut self, seconds: f32, width: u32, height: u32) {
+        let seconds = seconds * self.time_scale;
+        let spawn_tag = self.spawn_tag;
+        for i in 0..self.inflows.len() {
+            let inflow = &mut self.inflows[i];
+            inflow.pending += inflow.rate * seconds;
+            let blocks = (inflow.pending / F32s::LEN as f32) as usize;
+            inflow.pending -= (blocks * F32s::LEN) as f32;
+            let Inflow {
+                pattern,
+                center,
+                tag,
+                ..
+            } = *inflow;
+            self.spawn_tag = tag;
+            self.spawn_at(blocks, pattern, center, width, height);
+        }
+        self.spawn_tag = spawn_tag;
+    }
+
+    /// Queues `n` particles to be spawned over the next `spawn_queued`
+    /// calls, avoiding a hitch when a large batch is added at once.
+    pub fn queue_particles(&mut self, n: usize) {
+        self.spawn_queue = self.spawn_queue.saturating_add(n);
+    }
+
+    /// Number of particles still waiting in the spawn queue.
+    pub fn queued(&self) -> usize {
+        self.spawn_queue
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.spawn_queue = 0;
+    }
+
+    /// Spawns up to `spawn_budget` particles from the queue.
+    pub fn spawn_queued(&mut self, width: u32, height: u32) {
+        let n = self.spawn_queue.min(self.spawn_budget);
+        if n > 0 {
+            self.spawn_queue -= n;
+            self.add_particles(n, width, height);
+        }
+    }
+
+    /// Removes `n` particles chosen by `policy`. `width` and `height` are
+    /// the size of the visible area.
+    pub fn remove_particles(&mut self, n: usize, policy: RemovalPolicy, width: u32, height: u32) {
+        let n = n.min(self.particles.len());
+        self.bounds.clear();
+        match policy {
+            RemovalPolicy::Newest => self.particles.truncate(self.particles.len() - n),
+            RemovalPolicy::Random => {
+                let remove = rand::seq::index::sample(&mut self.rng, self.particles.len(), n);
+                self.remove_blocks(remove);
+            }
+            RemovalPolicy::Oldest => {
+                self.particles.drain(..n);
+            }
+            RemovalPolicy::Offscreen => {
+                if n == 0 {
+                    return;
+                }
+                let order = &mut self.removal_order;
+                order.clear();
+                order.extend(
+                    self.particles
+                        .iter()
+                        .enumerate()
+                        .map(|(i, particle)| (particle.onscreen_lanes(width, height), i)),
+                );
+                order.select_nth_unstable(n - 1);
+                let remove = order[..n].iter().map(|&(_, i)| i).collect::<Vec<_>>();
+                self.remove_blocks(remove);
+            }
+        }
+    }
+
+    /// Removes the blocks at the indices `remove`, keeping the others in
+    /// spawn order, which `RemovalPolicy::Oldest` and `Newest` rely on.
+    fn remove_blocks(&mut self, remove: impl IntoIterator<Item = usize>) {
+        let mut removed = vec![false; self.particles.len()];
+        for i in remove {
+            removed[i] = true;
+        }
+        let mut removed = removed.into_iter();
+        self.particles.retain(|_| !removed.next().unwrap_or(false));
+    }
+
+    /// Adds (`dvx`, `dvy`) to the velocity of every particle within `radius`
+    /// of `center`, e.g. for gusts or explosions.
+    pub fn apply_impulse(&mut self, center: (f32, f32), radius: f32, dvx: f32, dvy: f32) {
+        let center_x = splat(center.0);
+        let center_y = splat(center.1);
+        let radius_sq = splat(radius * radius);
+        let dvx = splat(dvx);
+        let dvy = splat(dvy);
+
+        let particles_chunk_len = self.chunk_len();
+        self.threadpool.scoped(|scope| {
+            for particles_chunk in self.particles.chunks_mut(particles_chunk_len) {
+                scope.execute(move |_| {
+                    for particle in particles_chunk {
+                        let dx = particle.x - center_x;
+                        let dy = particle.y - center_y;
+                        let inside = mul_add(dx, dx, dy * dy).simd_le(radius_sq);
+                        particle.dx = inside.select(particle.dx + dvx, particle.dx);
+                        particle.dy = inside.select(particle.dy + dvy, particle.dy);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Moves the lanes with a non-finite position or velocity to `emitter`,
+    /// with a random velocity like the center pattern, and returns how many
+    /// there were.
+    ///
+    /// A particle exactly on an attractor gets a NaN velocity, which sticks
+    /// and hides it forever. This is a separate pass since checking every
+    /// lane is not free, run it in debug builds or every few frames.
+    pub fn recycle_non_finite(&mut self, emitter: (f32, f32)) -> usize {
+        let recycled = AtomicUsize::new(0);
+        let origin = Particle {
+            x: splat(emitter.0),
+            y: splat(emitter.1),
+            ..Particle::ZERO
+        };
+        let particles_chunk_len = self.chunk_len();
+        let stream = self.rng.r#gen();
+        self.threadpool.scoped(|scope| {
+            for (i_chunk, particles_chunk) in
+                self.particles.chunks_mut(particles_chunk_len).enumerate()
+            {
+                let (recycled, origin) = (&recycled, &origin);
+                scope.execute(move |_| {
+                    let offset = i_chunk * particles_chunk_len;
+                    for (i, particle) in particles_chunk.iter_mut().enumerate() {
+                        let finite = particle.x.is_finite()
+                            & particle.y.is_finite()
+                            & particle.dx.is_finite()
+                            & particle.dy.is_finite();
+                        let broken = !finite & Lanes::from_bitmask(particle.active);
+                        if !broken.any() {
+                            continue;
+                        }
+                        let mut rng = block_rng(stream, offset + i);
+                        let fresh = Particle::new_from_existing(origin, &mut rng);
+                        particle.x = broken.select(fresh.x, particle.x);
+                        particle.y = broken.select(fresh.y, particle.y);
+                        particle.dx = broken.select(fresh.dx, particle.dx);
+                        particle.dy = broken.select(fresh.dy, particle.dy);
+                        let n = broken.to_bitmask().count_ones() as usize;
+                        recycled.fetch_add(n, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        let recycled = recycled.into_inner();
+        if recycled > 0 {
+            self.bounds.clear();
+        }
+        recycled
+    }
+
+    /// Copies the particles and parameters, including the random number
+    /// generator, so that `restore` continues exactly from this point.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut particles = Vec::new();
+        copy_particles(self.threadpool, &mut particles, &self.particles);
+        Snapshot {
+            particles,
+            gravity: self.gravity,
+            friction: self.friction,
+            time_scale: self.time_scale,
+            attractors: self.attractors.clone(),
+            spawn_queue: self.spawn_queue,
+            fastest: self.fastest,
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Returns to the state of `snapshot`, reusing the current allocation.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        copy_particles(self.threadpool, &mut self.particles, &snapshot.particles);
+        self.bounds.clear();
+        self.gravity = snapshot.gravity;
+        self.friction = snapshot.friction;
+        self.time_scale = snapshot.time_scale;
+        self.attractors.clone_from(&snapshot.attractors);
+        self.spawn_queue = snapshot.spawn_queue;
+        self.fastest = snapshot.fastest;
+        self.rng = snapshot.rng.clone();
+    }
+
+    /// Replaces the particles by ones with the `[x, y, dx, dy]` `states`,
+    /// e.g. read back from a CSV export. They are tagged `spawn_tag`.
+    pub fn load_states(&mut self, states: &[[f32; 4]]) {
+        self.particles.clear();
+        self.clear_queue();
+        self.bounds.clear();
+        let tag = u8x64::splat(self.spawn_tag.min(MAX_TAGS as u8 - 1));
+        self.particles.extend(states.chunks(F32s::LEN).map(|chunk| {
+            let mut particle = Particle {
+                tag,
+                active: u64::MAX >> (F32s::LEN - chunk.len()),
+                ..Particle::ZERO
+            };
+            for (i, &[x, y, dx, dy]) in chunk.iter().enumerate() {
+                particle.x[i] = x as Real;
+                particle.y[i] = y as Real;
+                particle.dx[i] = dx as Real;
+                particle.dy[i] = dy as Real;
+            }
+            particle
+        }));
+        self.fastest = states
+            .iter()
+            .fold(0.0, |max, [_, _, dx, dy]| max.max(dx.abs()).max(dy.abs()));
+    }
+
+    /// Applies `boundary` to the particles outside of a `width` x `height`
+    /// area.
+    pub fn apply_boundary(&mut self, boundary: Boundary, width: u32, height: u32) {
+        let apply: fn(&mut Particle, F32s, F32s) = match boundary {
+            Boundary::Open => return,
+            Boundary::Kill => {
+                self.retain(|particle| particle.onscreen_mask(width, height));
+                return;
+            }
+            Boundary::Wrap => Particle::wrap,
+            Boundary::Bounce => Particle::bounce,
+        };
+        let width = splat(width as f32);
+        let height = splat(height as f32);
+        let particles_chunk_len = self.chunk_len();
+        self.threadpool.scoped(|scope| {
+            for particles_chunk in self.particles.chunks_mut(particles_chunk_len) {
+                scope.execute(move |_| {
+                    for particle in particles_chunk {
+                        apply(particle, width, height);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Keeps only the particles whose lanes are set in the mask returned by
+    /// `keep`, packing the survivors into full blocks.
+    ///
+    /// Inactive lanes are dropped as well. The vacant lanes of the last
+    /// block are filled with inactive copies of its survivors.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Particle) -> Lanes) {
+        self.bounds.clear();
+        // Lanes written so far, the write position never overtakes the
+        // block being read.
+        let mut kept = 0;
+        for i in 0..self.particles.len() {
+            let block = self.particles[i].clone();
+            let mask = keep(&block) & Lanes::from_bitmask(block.active);
+            if mask.all() && kept % F32s::LEN == 0 {
+                self.particles[kept / F32s::LEN] = block;
+                kept += F32s::LEN;
+                continue;
+            }
+            for lane in (0..F32s::LEN).filter(|&lane| mask.test(lane)) {
+                let dst = &mut self.particles[kept / F32s::LEN];
+                dst.copy_lane(kept % F32s::LEN, &block, lane);
+                kept += 1;
+            }
+        }
+
+        self.particles.truncate(kept.div_ceil(F32s::LEN));
+        let used = kept % F32s::LEN;
+        if let Some(last) = self.particles.last_mut()
+            && used != 0
+        {
+            for lane in used..F32s::LEN {
+                let src = last.clone();
+                last.copy_lane(lane, &src, lane % used);
+            }
+            last.active = (1 << used) - 1;
+        }
+    }
+
+    pub fn shift(&mut self, dx: f32, dy: f32) {
+        let dx = splat(dx);
+        let dy = splat(dy);
+        for particle in self.particles.iter_mut() {
+            particle.x += dx;
+            particle.y += dy;
+        }
+        for bounds in &mut self.bounds {
+            bounds.min_x += dx[0];
+            bounds.max_x += dx[0];
+            bounds.min_y += dy[0];
+            bounds.max_y += dy[0];
+        }
+    }
+
+    /// Number of particles, not counting the inactive lanes.
+    pub fn len(&self) -> usize {
+        active_len(&self.particles)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// The `(x, y)` positions of all particles.
+    pub fn iter_positions(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.particles.iter().flat_map(|particle| {
+            let (x, y) = (particle.x.cast::<f32>(), particle.y.cast::<f32>());
+            let lanes = x.to_array().into_iter().zip(y.to_array()).enumerate();
+            lanes.filter_map(|(lane, xy)| particle.is_active(lane).then_some(xy))
+        })
+    }
+
+    /// Calls `visit` with the lane index and position of every particle, in
+    /// parallel on the threadpool.
+    pub fn visit_positions(&self, visit: impl Fn(usize, f32, f32) + Sync) {
+        let particles_chunk_len = self.chunk_len();
+        let visit = &visit;
+        self.threadpool.scoped(|scope| {
+            for (i_chunk, particles_chunk) in self.particles.chunks(particles_chunk_len).enumerate()
+            {
+                scope.execute(move |_| {
+                    let mut index = i_chunk * particles_chunk_len * F32s::LEN;
+                    for particle in particles_chunk {
+                        let lanes = particle.x.as_array().iter().zip(particle.y.as_array());
+                        for (lane, (&x, &y)) in lanes.enumerate() {
+                            if particle.is_active(lane) {
+                                visit(index, narrow(x), narrow(y));
+                            }
+                            index += 1;
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Number of particles within `radius` of `center` and the kinetic
+    /// energy of all particles, half the squared velocity summed over them,
+    /// in parallel on the threadpool.
+    pub fn activity(&self, center: (f32, f32), radius: f32) -> (usize, f32) {
+        let total = std::sync::Mutex::new((0, 0.0));
+        let particles_chunk_len = self.chunk_len();
+        let total_ref = &total;
+        self.threadpool.scoped(|scope| {
+            for particles_chunk in self.particles.chunks(particles_chunk_len) {
+                scope.execute(move |_| {
+                    let (mut near, mut energy) = (0, 0.0);
+                    for particle in particles_chunk {
+                        for lane in 0..F32s::LEN {
+                            if !particle.is_active(lane) {
+                                continue;
+                            }
+                            let (x, y) = (narrow(particle.x[lane]), narrow(particle.y[lane]));
+                            let (dx, dy) = (narrow(particle.dx[lane]), narrow(particle.dy[lane]));
+                            if (x - center.0).hypot(y - center.1) < radius {
+                                near += 1;
+                            }
+                            energy += (dx * dx + dy * dy) / 2.0;
+                        }
+                    }
+                    let mut total = total_ref.lock().unwrap();
+                    total.0 += near;
+                    total.1 += energy;
+                });
+            }
+        });
+        total.into_inner().unwrap()
+    }
+
+    /// Replaces the contents of `buffer` with the positions of all particles
+    /// as interleaved `x, y` pairs.
+    pub fn positions_to_buffer(&self, buffer: &mut Vec<f32>) {
+        buffer.clear();
+        buffer.reserve(self.len() * 2);
+        for particle in &self.particles {
+            let lanes = particle.x.as_array().iter().zip(particle.y.as_array());
+            for (lane, (&x, &y)) in lanes.enumerate() {
+                if particle.is_active(lane) {
+                    buffer.extend([narrow(x), narrow(y)]);
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, frametime: &Duration, mouse_pos: (f32, f32), mouse_down: bool) {
+        self.step(frametime, mouse_pos, mouse_down, None);
+    }
+
+    /// Same as `update`, but additionally counts the particles per pixel
+    /// into `count_buffer` (`width * height`, row by row) while their
+    /// positions are still hot in the cache. The buffer is not cleared.
+    pub fn update_and_count(
+        &mut self,
+        frametime: &Duration,
+        mouse_pos: (f32, f32),
+        mouse_down: bool,
+        count_buffer: &[AtomicU16],
+        width: u32,
+        height: u32,
+    ) {
+        self.step(
+            frametime,
+            mouse_pos,
+            mouse_down,
+            Some((count_buffer, width, height)),
+        );
+    }
+
+    /// Counts the particles per pixel into `count_buffer` (`width * height`,
+    /// row by row). The buffer is not cleared.
+    ///
+    /// Groups of particles that were entirely outside of the area after the
+    /// last update are skipped, so positions changed through `particles`
+    /// since then may be missed.
+    pub fn count(&self, count_buffer: &[AtomicU16], width: u32, height: u32) {
+        self.for_each_visible(width, height, |particle| {
+            particle.count(count_buffer, width, height);
+        });
+    }
+
+    /// Like `count`, but counts the particles of every tag separately, in
+    /// the 16 bit fields `tag * 16..` of `count_buffer`.
+    pub fn count_tagged(&self, count_buffer: &[AtomicU64], width: u32, height: u32) {
+        self.for_each_visible(width, height, |particle| {
+            particle.count_tagged(count_buffer, width, height);
+        });
+    }
+
+    /// Adds the velocities of the particles per pixel to `motion`, which
+    /// has to be cleared to `width` x `height`. See `count` for the blocks
+    /// that are skipped.
+    pub fn count_motion(&self, motion: &MotionField, width: u32, height: u32) {
+        self.for_each_visible(width, height, |particle| {
+            particle.count_motion(motion, width, height);
+        });
+    }
+
+    /// Calls `f` on the threadpool with every block, or its symmetric
+    /// images, that may be inside the `width` x `height` area.
+    fn for_each_visible(&self, width: u32, height: u32, f: impl Fn(&Particle) + Sync) {
+        let particles_chunk_len = self.chunk_len();
+        let f = &f;
+
+        let particles_chunks = self.particles.chunks(particles_chunk_len);
+        let transforms = &self.symmetry.transforms();
+        let center = self.symmetry_center(width, height);
+
+        self.threadpool.scoped(|scope| {
+            for (i_chunk, particles_chunk) in particles_chunks.enumerate() {
+                let bounds = self.chunk_bounds(i_chunk, particles_chunk_len);
+                scope.execute(move |_| {
+                    for particle in visible(particles_chunk, bounds, width, height) {
+                        for_each_image(particle, transforms, center, f);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Counts the particles per pixel into `count_buffer` (`width * height`,
+    /// row by row), saturating at 255. The buffer is overwritten.
+    ///
+    /// Every worker counts into its own tile without atomics, and the tiles
+    /// are summed into `count_buffer` afterwards.
+    pub fn count_saturating(
+        &self,
+        tiles: &mut CountTiles,
+        count_buffer: &mut [u8],
+        width: u32,
+        height: u32,
+    ) {
+        let n_tiles = self.threadpool.thread_count() as usize;
+        let n_pixels = count_buffer.len();
+        tiles.tiles.resize_with(n_tiles, Vec::new);
+        for tile in &mut tiles.tiles {
+            // Merging leaves the tiles zeroed, so this only clears on resize.
+            tile.resize(n_pixels, 0);
+        }
+
+        let particles_chunk_len = self
+            .particles
+            .len()
+            .div_ceil(n_tiles)
+            .max(1)
+            .next_multiple_of(CULL_BLOCKS);
+        let transforms = &self.symmetry.transforms();
+        let center = self.symmetry_center(width, height);
+        self.threadpool.scoped(|scope| {
+            for (i_chunk, (particles_chunk, tile)) in self
+                .particles
+                .chunks(particles_chunk_len)
+                .zip(&mut tiles.tiles)
+                .enumerate()
+            {
+                let bounds = self.chunk_bounds(i_chunk, particles_chunk_len);
+                scope.execute(move |_| {
+                    for particle in visible(particles_chunk, bounds, width, height) {
+                        for_each_image(particle, transforms, center, |image| {
+                            image.count_saturating(tile, width, height);
+                        });
+                    }
+                });
+            }
+        });
+
+        let pixel_chunk_len = usize::max(n_pixels / n_tiles / 10, 1);
+        let mut tile_chunks = tiles
+            .tiles
+            .iter_mut()
+            .map(|tile| tile.chunks_mut(pixel_chunk_len))
+            .collect::<Vec<_>>();
+        self.threadpool.scoped(|scope| {
+            for count_chunk in count_buffer.chunks_mut(pixel_chunk_len) {
+                let mut tiles = tile_chunks
+                    .iter_mut()
+                    .map(|chunks| chunks.next().unwrap())
+                    .collect::<Vec<_>>();
+                scope.execute(move |_| merge_tiles(count_chunk, &mut tiles));
+            }
+        });
+    }
+
+    /// Length of the chunks handed to the workers, a multiple of
+    /// `CULL_BLOCKS` so that every chunk starts at a bounding box.
+    fn chunk_len(&self) -> usize {
+        let n_chunks = self.threadpool.thread_count() as usize * self.chunk_tuner.per_thread();
+        let len = self.particles.len() / n_chunks;
+        len.max(1).next_multiple_of(CULL_BLOCKS)
     }
 
-    pub fn shift(&mut self, dx: f32, dy: f32) {
-        let dx = F32s::splat(dx);
-        let dy = F32s::splat(dy);
-        for particle in self.particles.iter_mut() {
-            particle.x += dx;
-            particle.y += dy;
+    /// Bounding boxes of the `i_chunk`th chunk of `chunk_len` blocks, if they
+    /// are up to date and culling is possible.
+    fn chunk_bounds(&self, i_chunk: usize, chunk_len: usize) -> Option<&[Bounds]> {
+        // Images of offscreen particles may well be visible.
+        if self.bounds.len() != self.particles.len().div_ceil(CULL_BLOCKS)
+            || self.symmetry != Symmetry::None
+        {
+            return None;
         }
+        let start = i_chunk * chunk_len / CULL_BLOCKS;
+        let end = usize::min(start + chunk_len / CULL_BLOCKS, self.bounds.len());
+        Some(&self.bounds[start..end])
     }
 
-    pub fn len(&self) -> usize {
-        self.particles.len() * F32s::LEN
+    fn symmetry_center(&self, width: u32, height: u32) -> (F32s, F32s) {
+        (splat(width as f32 / 2.0), splat(height as f32 / 2.0))
     }
 
     #[inline(never)]
-    pub fn update(&mut self, frametime: &Duration, mouse_pos: (f32, f32), mouse_down: bool) {
-        let time_norm = frametime.as_micros() as f32 / 16666.0;
-        let fric_norm = f32::powf(0.988, time_norm);
-        let grav_norm = 1.0 * time_norm;
+    fn step(
+        &mut self,
+        frametime: &Duration,
+        mouse_pos: (f32, f32),
+        mouse_down: bool,
+        count: Option<(&[AtomicU16], u32, u32)>,
+    ) {
+        let substeps = self.substeps(frametime);
+        let frametime = *frametime / substeps;
+        for i in 1..=substeps {
+            // Only the final positions are counted.
+            let count = count.filter(|_| i == substeps);
+            self.substep(&frametime, mouse_pos, mouse_down, count);
+        }
+    }
+
+    /// Frametime in 60 Hz frames, scaled by `time_scale`.
+    fn time_norm(&self, frametime: &Duration) -> f32 {
+        frametime.as_micros() as f32 / 16666.0 * self.time_scale
+    }
+
+    /// Number of substeps for an update of `frametime`, assuming the
+    /// particles are as fast as during the last one.
+    fn substeps(&self, frametime: &Duration) -> u32 {
+        let Some(distance) = self.substep_distance else {
+            return 1;
+        };
+        let displacement = self.fastest * self.time_norm(frametime);
+        // NaN saturates to 0 and infinity to `u32::MAX`.
+        ((displacement / distance).ceil() as u32).clamp(1, MAX_SUBSTEPS)
+    }
+
+    fn substep(
+        &mut self,
+        frametime: &Duration,
+        mouse_pos: (f32, f32),
+        mouse_down: bool,
+        count: Option<(&[AtomicU16], u32, u32)>,
+    ) {
+        let time_norm = self.time_norm(frametime);
+        if let Some(field) = &mut self.velocity_field {
+            field.step(self.flow, time_norm);
+        }
+        let fric_norm = f32::powf(self.friction, time_norm);
+        let fric_norm_y = f32::powf(self.friction_y.unwrap_or(self.friction), time_norm);
+        let grav_norm = self.gravity * time_norm;
+        self.lfo_time += frametime.as_secs_f32() * self.time_scale;
+        let mouse_grav = match self.gravity_lfo {
+            Some(lfo) => grav_norm * lfo.factor(self.lfo_time),
+            None => {
+                self.lfo_time = 0.0;
+                grav_norm
+            }
+        };
 
-        let time_norm = F32s::splat(time_norm);
-        let fric_norm = F32s::splat(fric_norm);
-        let grav_norm = F32s::splat(grav_norm);
+        // Verlet splits every kick into halves around the drift.
+        let verlet = self.integrator == Integrator::Verlet;
+        let kick_norm = if verlet { 0.5 } else { 1.0 };
+        let time_kick = splat(time_norm * kick_norm);
+        let mouse_grav = splat(mouse_grav * kick_norm);
 
-        let mouse_down = F32s::splat(mouse_down as u32 as f32);
-        let mouse_x = F32s::splat(mouse_pos.0);
-        let mouse_y = F32s::splat(mouse_pos.1);
+        let time_norm = splat(time_norm);
+        let loss = (splat(1.0 - fric_norm), splat(1.0 - fric_norm_y));
+        let wind = self.wind.map(|(x, y)| (splat(x), splat(y)));
+        let grav_norm = splat(grav_norm * kick_norm);
+        // Random walks grow with the square root of time.
+        let jitter = (self.temperature > 0.0).then(|| splat(self.temperature) * time_norm.sqrt());
+        self.n_steps = self.n_steps.wrapping_add(1);
+        let step_seed = U32s::splat(self.n_steps.wrapping_mul(0x9E37_79B9));
+
+        let pressure = self.pen.map_or(1.0, |pen| pen.pressure);
+        let mouse_down = splat(mouse_down as u32 as f32 * pressure);
+        let pen_bias = self.pen.map(|Pen { bias: (x, y), .. }| {
+            let push = mouse_down * mouse_grav;
+            (push * splat(x), push * splat(y))
+        });
+        let mouse_x = splat(mouse_pos.0);
+        let mouse_y = splat(mouse_pos.1);
 
-        let particles_chunk_len = usize::max(
-            self.particles.len() / self.threadpool.thread_count() as usize / 10,
-            1,
+        let one = F32s::splat(1.0);
+        let zero = F32s::splat(0.0);
+        self.attractor_lanes.clear();
+        self.attractor_lanes.extend(
+            self.attractors
+                .iter()
+                .map(|a| (splat(a.x), splat(a.y), grav_norm * splat(a.strength))),
         );
+        for (x, y) in [self.center_attractor, self.keyboard_attractor]
+            .into_iter()
+            .flatten()
+        {
+            self.attractor_lanes.push((splat(x), splat(y), mouse_grav));
+        }
+        if let Some(field) = &mut self.charge_field {
+            field.deposit(self.threadpool, &self.particles);
+        }
+        if let Some(field) = &mut self.heat_field {
+            field.update(self.threadpool, &self.particles);
+        }
+
+        let attractors = &self.attractor_lanes;
+        let velocity_field = &self.velocity_field;
+        let charge_field = &self.charge_field;
+        let heat_field = &self.heat_field;
+        let obstacles = &self.obstacles;
+        let cursor_disc = self.cursor_disc;
+        let distance_field = &self.distance_field;
+        #[cfg(feature = "script")]
+        let script_force = &self.script_force;
+        let portals = &self.portals;
+        let freeze_mask = &self.freeze_mask;
+        let transforms = &self.symmetry.transforms();
+        let center = count.map_or((one, one), |(_, width, height)| {
+            self.symmetry_center(width, height)
+        });
+
+        let particles_chunk_len = self.chunk_len();
 
+        self.bounds
+            .resize(self.particles.len().div_ceil(CULL_BLOCKS), Bounds::EMPTY);
+        self.chunk_tuner
+            .start(self.particles.len().div_ceil(particles_chunk_len));
+        let chunk_tuner = &self.chunk_tuner;
+        let fastest = &AtomicU32::new(0);
         let particles_chunks = self.particles.chunks_mut(particles_chunk_len);
+        let bounds_chunks = self.bounds.chunks_mut(particles_chunk_len / CULL_BLOCKS);
 
         self.threadpool.scoped(|scope| {
-            for particles_chunk in particles_chunks {
+            for (i_chunk, (particles_chunk, bounds_chunk)) in
+                particles_chunks.zip(bounds_chunks).enumerate()
+            {
                 scope.execute(move |_| {
-                    for particle in particles_chunk {
-                        particle.apply_grav(&mouse_x, &mouse_y, &mouse_down, &grav_norm);
+                    let _timer = chunk_tuner.time(i_chunk);
+                    // Forces depending on the position, scaled to one kick.
+                    let kick = |particle: &mut Particle| {
+                        particle.apply_grav(&mouse_x, &mouse_y, &mouse_down, &mouse_grav);
+                        if let Some((bx, by)) = pen_bias {
+                            particle.dx += bx;
+                            particle.dy += by;
+                        }
+                        for (x, y, strength) in attractors {
+                            particle.apply_grav(x, y, &one, strength);
+                        }
+                        if let Some(field) = charge_field {
+                            let (ex, ey) = field.sample(particle.x, particle.y);
+                            let charge = particle.charge.cast::<Real>() * time_kick;
+                            particle.dx = mul_add(charge, ex, particle.dx);
+                            particle.dy = mul_add(charge, ey, particle.dy);
+                        }
+                        if let Some(field) = heat_field {
+                            let ay = field.sample(particle.x, particle.y);
+                            particle.dy = mul_add(ay, time_kick, particle.dy);
+                        }
+                        if let Some(field) = distance_field {
+                            let (ax, ay) = field.sample(particle.x, particle.y);
+                            particle.dx = mul_add(ax, time_kick, particle.dx);
+                            particle.dy = mul_add(ay, time_kick, particle.dy);
+                        }
+                        #[cfg(feature = "script")]
+                        if let Some(force) = script_force {
+                            let (ax, ay) = force.sample(particle.x, particle.y);
+                            particle.dx = mul_add(ax, time_kick, particle.dx);
+                            particle.dy = mul_add(ay, time_kick, particle.dy);
+                        }
+                    };
+                    let mut index = (i_chunk * particles_chunk_len) as u32;
+                    let groups = particles_chunk.chunks_mut(CULL_BLOCKS);
+                    let mut speed = zero;
+                    for (group, bounds) in groups.zip(bounds_chunk) {
+                        let mut min_x = splat(f32::INFINITY);
+                        let mut min_y = splat(f32::INFINITY);
+                        let mut max_x = splat(f32::NEG_INFINITY);
+                        let mut max_y = splat(f32::NEG_INFINITY);
+                        for particle in group {
+                            kick(particle);
+
+                            if let Some(jitter) = jitter {
+                                let seed = step_seed ^ (U32s::splat(index * 64) + lane_indices());
+                                particle.dx = mul_add(hash_signed(seed), jitter, particle.dx);
+                                particle.dy = mul_add(hash_signed(!seed), jitter, particle.dy);
+                            }
+                            index = index.wrapping_add(1);
 
-                        particle.apply_fric(&fric_norm);
+                            match wind {
+                                Some((x, y)) => {
+                                    particle.dx -= x;
+                                    particle.dy -= y;
+                                    particle.apply_fric(loss);
+                                    particle.dx += x;
+                                    particle.dy += y;
+                                }
+                                None => particle.apply_fric(loss),
+                            }
+                            let frozen = freeze_mask
+                                .as_ref()
+                                .map(|mask| mask.contains(particle.x, particle.y));
+                            if let Some(frozen) = frozen {
+                                particle.dx = frozen.select(zero, particle.dx);
+                                particle.dy = frozen.select(zero, particle.dy);
+                            }
 
-                        particle.x += particle.dx * time_norm;
-                        particle.y += particle.dy * time_norm;
+                            particle.x += particle.dx * time_norm;
+                            particle.y += particle.dy * time_norm;
+                            if let Some(field) = velocity_field {
+                                let (mut vx, mut vy) = field.sample(particle.x, particle.y);
+                                if let Some(frozen) = frozen {
+                                    vx = frozen.select(zero, vx);
+                                    vy = frozen.select(zero, vy);
+                                }
+                                particle.x += vx * time_norm;
+                                particle.y += vy * time_norm;
+                            }
+                            if let Some(obstacles) = obstacles {
+                                obstacles.collide(particle);
+                            }
+                            if let Some(disc) = cursor_disc {
+                                disc.collide(particle);
+                            }
+                            if let Some(portals) = portals {
+                                portals.teleport(particle);
+                            }
+                            if verlet {
+                                kick(particle);
+                                if let Some(frozen) = frozen {
+                                    particle.dx = frozen.select(zero, particle.dx);
+                                    particle.dy = frozen.select(zero, particle.dy);
+                                }
+                            }
+
+                            min_x = min_x.simd_min(particle.x);
+                            min_y = min_y.simd_min(particle.y);
+                            max_x = max_x.simd_max(particle.x);
+                            max_y = max_y.simd_max(particle.y);
+                            speed = speed.simd_max(particle.dx.abs().simd_max(particle.dy.abs()));
+
+                            if let Some((count_buffer, width, height)) = count
+                                && (!transforms.is_empty() || particle.any_onscreen(width, height))
+                            {
+                                for_each_image(particle, transforms, center, |image| {
+                                    image.count(count_buffer, width, height);
+                                });
+                            }
+                        }
+                        *bounds = Bounds {
+                            min_x: min_x.reduce_min(),
+                            min_y: min_y.reduce_min(),
+                            max_x: max_x.reduce_max(),
+                            max_y: max_y.reduce_max(),
+                        };
                     }
+                    // Non-negative floats order like their bits.
+                    let speed = narrow(speed.reduce_max()).to_bits();
+                    fastest.fetch_max(speed, Ordering::Relaxed);
                 });
             }
         });
+        self.fastest = f32::from_bits(fastest.load(Ordering::Relaxed));
+        self.chunk_tuner.adapt(self.threadpool.thread_count());
+    }
+}
+
+/// Replaces the contents of `dst` with those of `src`, copying in parallel.
+fn copy_particles(threadpool: &Pool, dst: &mut Vec<Particle>, src: &[Particle]) {
+    dst.clear();
+    dst.reserve(src.len());
+    let chunk_len = usize::max(src.len() / threadpool.thread_count() as usize / 10, 1);
+    threadpool.scoped(|scope| {
+        let dst_chunks = dst.spare_capacity_mut()[..src.len()].chunks_mut(chunk_len);
+        for (dst_chunk, src_chunk) in dst_chunks.zip(src.chunks(chunk_len)) {
+            scope.execute(move |_| {
+                for (dst, src) in dst_chunk.iter_mut().zip(src_chunk) {
+                    dst.write(src.clone());
+                }
+            });
+        }
+    });
+    // Every element up to `src.len()` was written above.
+    unsafe { dst.set_len(src.len()) };
+}
+
+/// Axis-aligned bounding box of a group of particles.
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min_x: Real,
+    min_y: Real,
+    max_x: Real,
+    max_y: Real,
+}
+
+impl Bounds {
+    const EMPTY: Bounds = Bounds {
+        min_x: Real::INFINITY,
+        min_y: Real::INFINITY,
+        max_x: Real::NEG_INFINITY,
+        max_y: Real::NEG_INFINITY,
+    };
+
+    fn intersects(&self, width: u32, height: u32) -> bool {
+        self.max_x >= 0.0
+            && self.min_x < width as Real
+            && self.max_y >= 0.0
+            && self.min_y < height as Real
+    }
+}
+
+/// The particles of `chunk` whose group's bounding box intersects the
+/// `width` x `height` area. Without `bounds` every particle is visible.
+fn visible<'p>(
+    chunk: &'p [Particle],
+    bounds: Option<&'p [Bounds]>,
+    width: u32,
+    height: u32,
+) -> impl Iterator<Item = &'p Particle> {
+    let groups = chunk.chunks(CULL_BLOCKS);
+    let visible = bounds
+        .into_iter()
+        .flatten()
+        .map(move |bounds| bounds.intersects(width, height))
+        .chain(std::iter::repeat(true));
+    groups
+        .zip(visible)
+        .filter(|&(_, visible)| visible)
+        .flat_map(|(group, _)| group)
+}
+
+/// Per-worker count buffers used by `Particles::count_saturating`, kept
+/// across frames to avoid reallocating them.
+#[derive(Default)]
+pub struct CountTiles {
+    tiles: Vec<Vec<u8>>,
+}
+
+/// Sums the `tiles` into `counts` with saturation and zeroes the tiles.
+fn merge_tiles(counts: &mut [u8], tiles: &mut [&mut [u8]]) {
+    let mut i = 0;
+    while i + u8x64::LEN <= counts.len() {
+        let mut sum = u8x64::splat(0);
+        for tile in tiles.iter_mut() {
+            let tile = &mut tile[i..i + u8x64::LEN];
+            sum = sum.saturating_add(u8x64::from_slice(tile));
+            tile.fill(0);
+        }
+        sum.copy_to_slice(&mut counts[i..i + u8x64::LEN]);
+        i += u8x64::LEN;
+    }
+    for (j, count) in counts.iter_mut().enumerate().skip(i) {
+        *count = 0;
+        for tile in tiles.iter_mut() {
+            *count = count.saturating_add(std::mem::take(&mut tile[j]));
+        }
     }
 }
 
+#[derive(Clone)]
 pub struct Particle {
     pub x: F32s,
     pub y: F32s,
-    dx: F32s,
-    dy: F32s,
+    pub(crate) dx: F32s,
+    pub(crate) dy: F32s,
+    /// Per-lane tag, see `Particles::spawn_tag`.
+    pub tag: u8x64,
+    /// Per-lane charge, `0` for particles not taking part in the
+    /// interaction of `Particles::charge_field`.
+    pub charge: i8x64,
+    /// Per-lane friction multiplier in units of `DRAG_ONE`, see
+    /// `Particles::friction_spread`.
+    drag: u8x64,
+    /// Lanes holding a particle, bit `i` for lane `i`. The others pad a
+    /// partially filled block, they move along but are neither counted nor
+    /// rendered.
+    pub(crate) active: u64,
 }
 
 impl Particle {
-    pub fn new_random(width: u32, height: u32) -> Self {
-        Particle::new_from_existing(&Self {
-            x: F32s::splat(width as f32 / 2.0),
-            y: F32s::splat(height as f32 / 2.0),
-            dx: F32s::splat(0.0),
-            dy: F32s::splat(0.0),
-        })
+    pub(crate) const ZERO: Particle = Particle {
+        x: F32s::from_array([0.0; 64]),
+        y: F32s::from_array([0.0; 64]),
+        dx: F32s::from_array([0.0; 64]),
+        dy: F32s::from_array([0.0; 64]),
+        tag: u8x64::from_array([0; 64]),
+        charge: i8x64::from_array([0; 64]),
+        drag: u8x64::from_array([DRAG_ONE; 64]),
+        active: u64::MAX,
+    };
+
+    /// A block arranged by `pattern` in a `width` x `height` area, with
+    /// center and ring patterns around `center`.
+    pub fn new_in_pattern(
+        pattern: SpawnPattern,
+        center: (f32, f32),
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let center_x = splat(center.0);
+        let center_y = splat(center.1);
+        match pattern {
+            SpawnPattern::Center => Particle::new_from_existing(
+                &Particle {
+                    x: center_x,
+                    y: center_y,
+                    ..Particle::ZERO
+                },
+                rng,
+            ),
+            SpawnPattern::Ring => {
+                let angle = random_unit(rng) * splat(TAU);
+                let radius = splat(0.35 * width.min(height) as f32);
+                Particle {
+                    x: center_x + angle.cos() * radius,
+                    y: center_y + angle.sin() * radius,
+                    ..Particle::ZERO
+                }
+            }
+            SpawnPattern::Uniform => Particle {
+                x: random_unit(rng) * splat(width as f32),
+                y: random_unit(rng) * splat(height as f32),
+                ..Particle::ZERO
+            },
+            SpawnPattern::Line => Particle {
+                x: center_x + random_unit(rng),
+                y: random_unit(rng) * splat(height as f32),
+                ..Particle::ZERO
+            },
+        }
+    }
+
+    pub fn new_random(width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        Particle::new_from_existing(
+            &Self {
+                x: splat(width as f32 / 2.0),
+                y: splat(height as f32 / 2.0),
+                ..Particle::ZERO
+            },
+            rng,
+        )
     }
 
-    pub fn new_from_existing(particle: &Self) -> Self {
-        let mut rng = rand::thread_rng();
-        let mut tmp = [0_f32; 64];
-        rng.fill(&mut tmp);
-        let d = F32s::from_slice(&tmp).mul(F32s::splat(TAU));
-        rng.fill(&mut tmp);
-        let r = F32s::from_slice(&tmp) * F32s::splat(1.0);
+    pub fn new_from_existing(particle: &Self, rng: &mut impl Rng) -> Self {
+        let d = random_unit(rng).mul(splat(TAU));
+        let r = random_unit(rng) * F32s::splat(1.0);
         let dx = particle.dx + d.sin() * r;
         let dy = particle.dy + d.cos() * r;
         Self {
@@ -120,6 +1758,10 @@ impl Particle {
             y: particle.y,
             dx,
             dy,
+            tag: particle.tag,
+            charge: particle.charge,
+            drag: particle.drag,
+            active: u64::MAX,
         }
     }
 
@@ -133,14 +1775,1039 @@ impl Particle {
     ) {
         let dx = self.x - mouse_x;
         let dy = self.y - mouse_y;
-        let dist_inv_sqr = f32x64::sqrt(dx * dx + dy * dy);
+        // portable_simd has no rsqrt, but one reciprocal is still cheaper
+        // than dividing both components by the distance.
+        let dist_inv = mul_add(dx, dx, dy * dy).sqrt().recip();
+        let force = -(mouse_down * grav_norm * dist_inv);
+
+        self.dx = mul_add(force, dx, self.dx);
+        self.dy = mul_add(force, dy, self.dy);
+    }
+
+    /// Removes the fractions `loss` of the horizontal and the vertical
+    /// velocity, scaled by the drag of every lane.
+    #[inline(always)]
+    pub fn apply_fric(&mut self, loss: (F32s, F32s)) {
+        let drag = self.drag.cast::<Real>() * splat(1.0 / DRAG_ONE as f32);
+        let one = F32s::splat(1.0);
+        self.dx *= mul_add(-drag, loss.0, one);
+        self.dy *= mul_add(-drag, loss.1, one);
+    }
+
+    fn wrap(&mut self, width: F32s, height: F32s) {
+        self.x -= (self.x / width).floor() * width;
+        self.y -= (self.y / height).floor() * height;
+    }
+
+    fn bounce(&mut self, width: F32s, height: F32s) {
+        let zero = F32s::splat(0.0);
+        let (below, above) = (self.x.simd_lt(zero), self.x.simd_ge(width));
+        self.x = below.select(-self.x, above.select(width + width - self.x, self.x));
+        self.dx = (below | above).select(-self.dx, self.dx);
+        let (below, above) = (self.y.simd_lt(zero), self.y.simd_ge(height));
+        self.y = below.select(-self.y, above.select(height + height - self.y, self.y));
+        self.dy = (below | above).select(-self.dy, self.dy);
+    }
+
+    fn copy_lane(&mut self, lane: usize, src: &Particle, src_lane: usize) {
+        self.x[lane] = src.x[src_lane];
+        self.y[lane] = src.y[src_lane];
+        self.dx[lane] = src.dx[src_lane];
+        self.dy[lane] = src.dy[src_lane];
+        self.tag[lane] = src.tag[src_lane];
+        self.charge[lane] = src.charge[src_lane];
+        self.drag[lane] = src.drag[src_lane];
+        let bit = (src.active >> src_lane & 1) << lane;
+        self.active = self.active & !(1 << lane) | bit;
+    }
+
+    /// Whether `lane` holds a particle, see `Particle::active`.
+    #[inline(always)]
+    pub fn is_active(&self, lane: usize) -> bool {
+        self.active >> lane & 1 != 0
+    }
+
+    /// Number of lanes holding a particle.
+    #[inline(always)]
+    pub fn active_lanes(&self) -> u32 {
+        self.active.count_ones()
+    }
+
+    /// Number of active lanes inside a `width` x `height` area.
+    pub fn onscreen_lanes(&self, width: u32, height: u32) -> u32 {
+        self.onscreen_mask(width, height).to_bitmask().count_ones()
+    }
+
+    /// Whether any active lane is inside a `width` x `height` area.
+    #[inline(always)]
+    pub fn any_onscreen(&self, width: u32, height: u32) -> bool {
+        self.onscreen_mask(width, height).any()
+    }
+
+    #[inline(always)]
+    fn onscreen_mask(&self, width: u32, height: u32) -> Lanes {
+        let zero = F32s::splat(0.0);
+        self.x.simd_ge(zero)
+            & self.x.simd_lt(splat(width as f32))
+            & self.y.simd_ge(zero)
+            & self.y.simd_lt(splat(height as f32))
+            & Lanes::from_bitmask(self.active)
+    }
+
+    #[inline(always)]
+    pub fn count(&self, count_buffer: &[AtomicU16], width: u32, height: u32) {
+        let lanes = self.x.as_array().iter().zip(self.y.as_array().iter());
+        for (lane, (x, y)) in lanes.enumerate() {
+            let inside = self.is_active(lane)
+                && *x >= 0.0
+                && *x < (width as Real - 1.0)
+                && *y >= 0.0
+                && *y < (height as Real - 1.0);
+
+            let x = (*x as usize).clamp(0, width as usize - 1);
+            let y = (*y as usize).clamp(0, height as usize - 1);
+
+            count_buffer[x + y * width as usize].fetch_add(inside as u16, Ordering::Relaxed);
+        }
+    }
+
+    /// Like `count`, but adds one to the 16 bit field of each lane's tag.
+    #[inline(always)]
+    pub fn count_tagged(&self, count_buffer: &[AtomicU64], width: u32, height: u32) {
+        let lanes = self.x.as_array().iter().zip(self.y.as_array());
+        for (lane, ((x, y), tag)) in lanes.zip(self.tag.as_array()).enumerate() {
+            let inside = self.is_active(lane)
+                && *x >= 0.0
+                && *x < (width as Real - 1.0)
+                && *y >= 0.0
+                && *y < (height as Real - 1.0);
+
+            let x = (*x as usize).clamp(0, width as usize - 1);
+            let y = (*y as usize).clamp(0, height as usize - 1);
+
+            let one = (inside as u64) << (16 * (*tag as usize % MAX_TAGS));
+            count_buffer[x + y * width as usize].fetch_add(one, Ordering::Relaxed);
+        }
+    }
+
+    /// Like `count`, but adds the velocity of each lane to `motion`.
+    #[inline(always)]
+    pub fn count_motion(&self, motion: &MotionField, width: u32, height: u32) {
+        let lanes = self.x.as_array().iter().zip(self.y.as_array());
+        let velocities = self.dx.as_array().iter().zip(self.dy.as_array());
+        for (lane, ((x, y), (dx, dy))) in lanes.zip(velocities).enumerate() {
+            let inside = self.is_active(lane)
+                && *x >= 0.0
+                && *x < (width as Real - 1.0)
+                && *y >= 0.0
+                && *y < (height as Real - 1.0);
+            if inside {
+                motion.add(
+                    *x as usize + *y as usize * width as usize,
+                    narrow(*dx),
+                    narrow(*dy),
+                );
+            }
+        }
+    }
+
+    /// Like `count`, but into a private `u8` buffer, saturating at 255.
+    #[inline(always)]
+    pub fn count_saturating(&self, count_buffer: &mut [u8], width: u32, height: u32) {
+        let lanes = self.x.as_array().iter().zip(self.y.as_array().iter());
+        for (lane, (x, y)) in lanes.enumerate() {
+            let inside = self.is_active(lane)
+                && *x >= 0.0
+                && *x < (width as Real - 1.0)
+                && *y >= 0.0
+                && *y < (height as Real - 1.0);
+
+            let x = (*x as usize).clamp(0, width as usize - 1);
+            let y = (*y as usize).clamp(0, height as usize - 1);
+
+            let count = &mut count_buffer[x + y * width as usize];
+            *count = count.saturating_add(inside as u8);
+        }
+    }
+}
+
+/// Number of active lanes of `particles`.
+fn active_len(particles: &[Particle]) -> usize {
+    particles.iter().map(|p| p.active_lanes() as usize).sum()
+}
+
+/// `0, 1, .., 63`.
+#[inline(always)]
+fn lane_indices() -> U32s {
+    U32s::from_array(std::array::from_fn(|i| i as u32))
+}
+
+/// Pseudo random lanes in `[-1, 1)` hashed from `seed` with the PCG output
+/// permutation, cheap enough to draw fresh numbers for every particle and
+/// frame without keeping any generator state.
+#[inline(always)]
+fn hash_signed(seed: U32s) -> F32s {
+    let state = seed * U32s::splat(747_796_405) + U32s::splat(2_891_336_453);
+    let word = ((state >> ((state >> 28) + U32s::splat(4))) ^ state) * U32s::splat(277_803_737);
+    let word = (word >> 22) ^ word;
+    // 23 random bits in the mantissa of a float in `[2, 4)`.
+    let bits = (word >> 9) | U32s::splat(2.0_f32.to_bits());
+    f32x64::from_bits(bits).cast() - splat(3.0)
+}
+
+/// Generator for the `index`th block of a parallel pass drawing from
+/// `stream`, so the numbers depend on the seed and the block but not on how
+/// the blocks are split between the threads.
+#[inline(always)]
+fn block_rng(stream: u64, index: usize) -> SmallRng {
+    SmallRng::seed_from_u64(stream ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// Uniformly distributed lanes in `[0, 1)`, built from the upper 23 random
+/// bits of each lane placed into the mantissa of a float in `[1, 2)`.
+#[inline(always)]
+fn random_unit(rng: &mut impl Rng) -> F32s {
+    let mut bits = [0_u32; 64];
+    rng.fill(&mut bits);
+    let bits = (U32s::from_array(bits) >> 9) | U32s::splat(1.0_f32.to_bits());
+    f32x64::from_bits(bits).cast() - splat(1.0)
+}
+
+/// `a * b + c`, fused if the target supports FMA. Without hardware support
+/// `mul_add` falls back to a software fma that is much slower than the
+/// separate operations.
+#[inline(always)]
+fn mul_add(a: F32s, b: F32s, c: F32s) -> F32s {
+    if cfg!(target_feature = "fma") {
+        a.mul_add(b, c)
+    } else {
+        a * b + c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simd::cmp::SimdPartialEq;
+    use std::sync::atomic::AtomicU32;
+
+    use proptest::prelude::*;
+
+    use super::*;
 
-        self.dx -= mouse_down * grav_norm * dx / dist_inv_sqr;
-        self.dy -= mouse_down * grav_norm * dy / dist_inv_sqr;
+    #[test]
+    fn budget_caps_growth_without_reallocating() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.reserve_budget(Some(100));
+        let storage = particles.particles.as_ptr();
+
+        particles.add_particles(60, 100, 100);
+        particles.add_particles(60, 100, 100);
+        assert_eq!(particles.particles.len(), 100);
+        particles.remove_particles(30, RemovalPolicy::Offscreen, 100, 100);
+        particles.add_particles(50, 100, 100);
+        assert_eq!(particles.particles.len(), 100);
+        assert_eq!(particles.particles.as_ptr(), storage);
+    }
+
+    #[test]
+    fn removal_keeps_spawn_order() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.add_particles(20, 100, 100);
+        for (i, particle) in particles.particles.iter_mut().enumerate() {
+            particle.tag = u8x64::splat(i as u8);
+        }
+        let order = |particles: &Particles| {
+            particles
+                .particles
+                .iter()
+                .map(|particle| particle.tag[0])
+                .collect::<Vec<_>>()
+        };
+
+        particles.remove_particles(5, RemovalPolicy::Random, 100, 100);
+        particles.remove_particles(5, RemovalPolicy::Offscreen, 100, 100);
+        let remaining = order(&particles);
+        assert!(remaining.is_sorted(), "{remaining:?}");
+        particles.remove_particles(3, RemovalPolicy::Oldest, 100, 100);
+        assert_eq!(order(&particles), remaining[3..]);
+        particles.remove_particles(3, RemovalPolicy::Newest, 100, 100);
+        assert_eq!(order(&particles), remaining[3..7]);
+    }
+
+    #[test]
+    fn retain_repacks_lanes() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.add_particles(5, 10, 10);
+        for (i, particle) in particles.particles.iter_mut().enumerate() {
+            for lane in 0..64 {
+                particle.x[lane] = (i * 64 + lane) as Real;
+            }
+        }
+
+        // Keep every third particle, 107 of 320.
+        particles.retain(|particle| (particle.x % F32s::splat(3.0)).simd_eq(F32s::splat(0.0)));
+        assert_eq!(particles.particles.len(), 2);
+        let x = particles
+            .iter_positions()
+            .map(|(x, _)| x)
+            .collect::<Vec<_>>();
+        let expected = (0..320).step_by(3).map(|x| x as f32).collect::<Vec<_>>();
+        assert_eq!(x, expected);
+        assert_eq!(particles.len(), 107);
+        // The rest of the last block repeats survivors of that block.
+        let last = &particles.particles[1];
+        assert_eq!(last.active, (1 << 43) - 1);
+        assert!((43..64).all(|lane| expected[64..].contains(&narrow(last.x[lane]))));
+    }
+
+    #[test]
+    fn partial_blocks_count_active_lanes() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::builder().count(70).size(100, 100).build(&pool);
+        assert_eq!(particles.particles.len(), 2);
+        assert_eq!(particles.len(), 70);
+        assert_eq!(particles.particles[1].onscreen_lanes(100, 100), 6);
+
+        // Filling the vacant lanes first, then rounding up a new block.
+        particles.add_lanes(60, 100, 100);
+        assert_eq!((particles.particles.len(), particles.len()), (3, 130));
+        assert_eq!(particles.particles[1].active, u64::MAX);
+        assert_eq!(particles.iter_positions().count(), 130);
+
+        let counts = (0..100 * 100)
+            .map(|_| AtomicU16::new(0))
+            .collect::<Vec<_>>();
+        for particle in &particles.particles {
+            particle.count(&counts, 100, 100);
+        }
+        let total = counts.iter().map(|c| c.load(Ordering::Relaxed) as usize);
+        assert!(total.sum::<usize>() <= 130);
+
+        // Inactive lanes never survive `retain`.
+        particles.retain(|_| Lanes::splat(true));
+        assert_eq!((particles.particles.len(), particles.len()), (3, 130));
+    }
+
+    #[test]
+    fn recycles_non_finite_lanes() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::builder().count(100).size(100, 100).build(&pool);
+        particles.particles[0].dx[3] = Real::NAN;
+        particles.particles[1].y[5] = Real::INFINITY;
+        // Inactive lanes are left alone.
+        particles.particles[1].x[50] = Real::NAN;
+        let before = particles.particles[0].x[4];
+
+        assert_eq!(particles.recycle_non_finite((10.0, 20.0)), 2);
+        assert_eq!(
+            (particles.particles[0].x[3], particles.particles[0].y[3]),
+            (10.0, 20.0)
+        );
+        assert_eq!(
+            (particles.particles[1].x[5], particles.particles[1].y[5]),
+            (10.0, 20.0)
+        );
+        assert!(particles.particles[0].dx[3].is_finite());
+        assert_eq!(particles.particles[0].x[4], before);
+        assert!(particles.particles[1].x[50].is_nan());
+        assert_eq!(particles.recycle_non_finite((10.0, 20.0)), 0);
+    }
+
+    #[test]
+    fn impulse_only_hits_particles_in_radius() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.add_particles(2, 100, 100);
+        particles.friction = 1.0;
+        for (i, x) in particles.particles[0]
+            .x
+            .as_mut_array()
+            .iter_mut()
+            .enumerate()
+        {
+            *x = i as Real;
+        }
+        particles.particles[0].y = F32s::splat(0.0);
+        particles.particles[0].dx = F32s::splat(0.0);
+        particles.particles[0].dy = F32s::splat(0.0);
+        particles.particles.truncate(1);
+
+        particles.apply_impulse((10.0, 0.0), 5.0, 1.0, -2.0);
+        particles.update(&Duration::from_micros(16666), (-100.0, -100.0), false);
+        for (i, (x, y)) in particles.iter_positions().enumerate() {
+            if (5..=15).contains(&i) {
+                assert_eq!((x, y), (i as f32 + 1.0, -2.0));
+            } else {
+                assert_eq!((x, y), (i as f32, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn builder_spawns_patterns() {
+        let pool = Pool::new(2);
+        let ring = Particles::builder()
+            .count(1000)
+            .size(200, 100)
+            .seed(42)
+            .spawn(SpawnPattern::Ring)
+            .friction(0.9)
+            .build(&pool);
+        assert_eq!(ring.len(), 1000);
+        assert_eq!(ring.friction, 0.9);
+        for (x, y) in ring.iter_positions() {
+            let radius = (x - 100.0).hypot(y - 50.0);
+            assert!((radius - 35.0).abs() < 0.01, "{radius}");
+        }
+
+        let uniform = Particles::builder()
+            .count(640)
+            .size(200, 100)
+            .spawn(SpawnPattern::Uniform)
+            .max_particles(128)
+            .build(&pool);
+        assert_eq!(uniform.len(), 128);
+        let inside = |(x, y): (f32, f32)| (0.0..200.0).contains(&x) && (0.0..100.0).contains(&y);
+        assert!(uniform.iter_positions().all(inside));
+    }
+
+    #[test]
+    fn restore_replays_from_snapshot() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::builder().count(2000).size(100, 100).build(&pool);
+        let frametime = Duration::from_millis(16);
+        let step = |particles: &mut Particles| {
+            particles.queue_particles(3);
+            particles.spawn_queued(100, 100);
+            particles.update(&frametime, (20.0, 30.0), true);
+            particles.iter_positions().collect::<Vec<_>>()
+        };
+
+        let snapshot = particles.snapshot();
+        let first = step(&mut particles);
+        particles.gravity = 3.0;
+        step(&mut particles);
+        particles.restore(&snapshot);
+        assert_eq!(snapshot.len(), 2000);
+        assert_eq!(step(&mut particles), first);
+    }
+
+    #[test]
+    fn boundaries_keep_particles_inside() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::builder().count(64).size(10, 10).build(&pool);
+        let place = |particles: &mut Particles| {
+            let particle = &mut particles.particles[0];
+            particle.x = F32s::splat(-2.0);
+            particle.y = F32s::splat(13.0);
+            particle.dx = F32s::splat(-1.0);
+            particle.dy = F32s::splat(1.0);
+        };
+
+        place(&mut particles);
+        particles.apply_boundary(Boundary::Wrap, 10, 10);
+        assert!(particles.iter_positions().all(|p| p == (8.0, 3.0)));
+
+        place(&mut particles);
+        particles.apply_boundary(Boundary::Bounce, 10, 10);
+        assert!(particles.iter_positions().all(|p| p == (2.0, 7.0)));
+        assert_eq!(particles.particles[0].dx, F32s::splat(1.0));
+        assert_eq!(particles.particles[0].dy, F32s::splat(-1.0));
+
+        particles.apply_boundary(Boundary::Kill, 10, 10);
+        assert_eq!(particles.len(), 64);
+        place(&mut particles);
+        particles.apply_boundary(Boundary::Kill, 10, 10);
+        assert!(particles.is_empty());
+    }
+
+    #[test]
+    fn symmetry_counts_every_image() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::builder().count(64).size(20, 10).build(&pool);
+        particles.particles[0].x = F32s::splat(8.0);
+        particles.particles[0].y = F32s::splat(4.0);
+        let count = |particles: &Particles| {
+            let counts = (0..20 * 10).map(|_| AtomicU16::new(0)).collect::<Vec<_>>();
+            particles.count(&counts, 20, 10);
+            let mut hits = counts
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.load(Ordering::Relaxed) > 0)
+                .map(|(i, c)| (i % 20, i / 20, c.load(Ordering::Relaxed)))
+                .collect::<Vec<_>>();
+            hits.sort();
+            hits
+        };
+
+        particles.symmetry = Symmetry::Quad;
+        assert_eq!(
+            count(&particles),
+            [(8, 4, 64), (8, 6, 64), (12, 4, 64), (12, 6, 64)]
+        );
+        particles.symmetry = Symmetry::Radial(2);
+        assert_eq!(count(&particles), [(8, 4, 64), (12, 6, 64)]);
+        particles.symmetry = Symmetry::Kaleidoscope(3);
+        assert_eq!(Symmetry::Kaleidoscope(3).transforms().len(), 6);
+        assert_eq!(
+            count(&particles)
+                .iter()
+                .map(|&(_, _, n)| n as usize)
+                .sum::<usize>(),
+            6 * 64
+        );
+    }
+
+    #[test]
+    fn tags_count_separately() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.spawn_tag = 1;
+        particles.spawn(2, SpawnPattern::Center, 10, 10);
+        particles.spawn_tag = 2;
+        particles.spawn(1, SpawnPattern::Center, 10, 10);
+        // Added particles keep the tag of the block they are copied from.
+        particles.add_particles(3, 10, 10);
+
+        let counts = (0..100).map(|_| AtomicU64::new(0)).collect::<Vec<_>>();
+        particles.count_tagged(&counts, 10, 10);
+        let count = counts[5 * 10 + 5].load(Ordering::Relaxed);
+        let per_tag = [0, 1, 2, 3].map(|tag| (count >> (16 * tag)) & 0xFFFF);
+        assert_eq!(per_tag.iter().sum::<u64>(), 6 * 64);
+        assert!(per_tag[1] >= 2 * 64 && per_tag[2] >= 64);
+        assert_eq!((per_tag[0], per_tag[3]), (0, 0));
+    }
+
+    #[test]
+    fn velocity_field_advects() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::builder().count(64).size(100, 100).build(&pool);
+        particles.friction = 0.0;
+        let mut field = VelocityField::new(100, 100, 10.0);
+        field.paint((50.0, 50.0), (3.0, -1.0), 30.0);
+        particles.velocity_field = Some(field.clone());
+        let before = particles.iter_positions().collect::<Vec<_>>();
+        particles.update(&Duration::from_micros(16_666), (0.0, 0.0), false);
+        for ((x0, y0), (x1, y1)) in before.into_iter().zip(particles.iter_positions()) {
+            let (vx, vy) = field.sample(splat(x0), splat(y0));
+            assert!(
+                (x1 - x0 - narrow(vx[0])).abs() < 1e-3 && (y1 - y0 - narrow(vy[0])).abs() < 1e-3
+            );
+        }
+        let (vx, vy) = field.sample(F32s::splat(50.0), F32s::splat(50.0));
+        assert!(vx[0] > 1.0 && vy[0] < -0.3);
+    }
+
+    #[test]
+    fn charges_interact() {
+        let pool = Pool::new(2);
+        let separation = |charges: [i8; 2]| {
+            let mut particles = Particles::new(&pool);
+            particles.spawn_at(1, SpawnPattern::Center, (45.0, 50.0), 100, 100);
+            particles.spawn_at(1, SpawnPattern::Center, (55.0, 50.0), 100, 100);
+            for (particle, charge) in particles.particles.iter_mut().zip(charges) {
+                particle.dx = F32s::splat(0.0);
+                particle.dy = F32s::splat(0.0);
+                particle.charge = i8x64::splat(charge);
+            }
+            particles.charge_field = Some(ChargeField::new(100, 100, 5.0));
+            particles.update(&Duration::from_micros(16_666), (0.0, 0.0), false);
+            particles.particles[1].x[0] - particles.particles[0].x[0]
+        };
+        assert!(separation([1, 1]) > 10.0);
+        assert!(separation([1, -1]) < 10.0);
+        assert_eq!(separation([0, 0]), 10.0);
+    }
+
+    #[test]
+    fn lfo_waveforms() {
+        let sine = Lfo {
+            shape: LfoShape::Sine,
+            frequency: 2.0,
+            depth: 1.0,
+        };
+        assert_eq!(sine.factor(0.0), 1.0);
+        assert!((sine.factor(0.25) + 1.0).abs() < 1e-6);
+        assert!((sine.factor(0.125)).abs() < 1e-6);
+        let square = Lfo {
+            shape: LfoShape::Square,
+            depth: 0.5,
+            ..sine
+        };
+        assert_eq!([0.1, 0.3, 0.6].map(|t| square.factor(t)), [1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn temperature_diffuses() {
+        let pool = Pool::new(2);
+        let spread = |temperature| {
+            let mut particles = Particles::new(&pool);
+            particles.spawn(16, SpawnPattern::Center, 100, 100);
+            particles.particles.iter_mut().for_each(|particle| {
+                particle.dx = F32s::splat(0.0);
+                particle.dy = F32s::splat(0.0);
+            });
+            particles.temperature = temperature;
+            for _ in 0..10 {
+                particles.update(&Duration::from_micros(16_666), (0.0, 0.0), false);
+            }
+            let positions = particles.iter_positions().collect::<Vec<_>>();
+            let mean = positions.iter().map(|p| p.0).sum::<f32>() / positions.len() as f32;
+            let variance = positions.iter().map(|p| (p.0 - mean).powi(2)).sum::<f32>()
+                / positions.len() as f32;
+            (mean, variance)
+        };
+        assert_eq!(spread(0.0), (50.0, 0.0));
+        let (mean, variance) = spread(1.0);
+        assert!((mean - 50.0).abs() < 1.0);
+        assert!(variance > 1.0);
+        assert!(hash_signed(lane_indices()).reduce_max() < 1.0);
+        assert!(hash_signed(lane_indices()).reduce_min() >= -1.0);
+    }
+
+    #[test]
+    fn frozen_regions_hold_particles() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        let mut particle = Particle::ZERO;
+        particle.x = F32s::splat(15.0);
+        particle.y = F32s::splat(15.0);
+        particle.dx = F32s::splat(2.0);
+        particle.x[1] = 75.0;
+        particles.particles.push(particle);
+        let mut mask = FreezeMask::new(100, 100, 10.0);
+        mask.paint((15.0, 15.0), 5.0);
+        particles.freeze_mask = Some(mask);
+        for _ in 0..3 {
+            particles.update(&Duration::from_micros(16_666), (0.0, 0.0), false);
+        }
+        let particle = &particles.particles[0];
+        assert_eq!(
+            (particle.x[0], particle.y[0], particle.dx[0]),
+            (15.0, 15.0, 0.0)
+        );
+        assert!(particle.x[1] > 75.0 && particle.dx[1] > 0.0);
+    }
+
+    #[test]
+    fn center_attractor_pulls() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.particles.push(Particle {
+            x: F32s::splat(10.0),
+            y: F32s::splat(50.0),
+            ..Particle::ZERO
+        });
+        particles.center_attractor = Some((50.0, 50.0));
+        particles.update(&Duration::from_micros(16_666), (0.0, 0.0), false);
+        assert!(particles.particles[0].dx.reduce_min() > 0.0);
+        assert!(particles.particles[0].dy.reduce_max().abs() < 1e-6);
+    }
+
+    #[test]
+    fn friction_per_axis_and_particle() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.seed(7);
+        particles.friction = 0.5;
+        particles.friction_y = Some(1.0);
+        particles.spawn(1, SpawnPattern::Center, 100, 100);
+        particles.friction_spread = 1.0;
+        particles.spawn(1, SpawnPattern::Center, 100, 100);
+        for particle in &mut particles.particles {
+            particle.dx = F32s::splat(1.0);
+            particle.dy = F32s::splat(1.0);
+        }
+        particles.update(&Duration::from_micros(16_666), (0.0, 0.0), false);
+
+        let [uniform, spread] = [&particles.particles[0], &particles.particles[1]];
+        assert!(
+            uniform
+                .dx
+                .as_array()
+                .iter()
+                .all(|dx| (dx - 0.5).abs() < 1e-3)
+        );
+        assert_eq!(uniform.dy, F32s::splat(1.0));
+        assert_eq!(spread.dy, F32s::splat(1.0));
+        let (min, max) = (spread.dx.reduce_min(), spread.dx.reduce_max());
+        assert!((0.0..0.3).contains(&min) && max > 0.7 && max <= 1.0);
+    }
+
+    #[test]
+    fn positions_agree() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.add_particles(30, 100, 100);
+        particles.update(&Duration::from_secs(1), (0.0, 0.0), false);
+
+        let positions = particles.iter_positions().collect::<Vec<_>>();
+        assert_eq!(positions.len(), particles.len());
+        let mut buffer = vec![1.0; 3];
+        particles.positions_to_buffer(&mut buffer);
+        let pairs = buffer.chunks_exact(2).map(|xy| (xy[0], xy[1]));
+        assert!(pairs.eq(positions.iter().copied()));
+
+        let visited = (0..particles.len())
+            .map(|_| AtomicU32::new(0))
+            .collect::<Vec<_>>();
+        particles.visit_positions(|i, x, y| {
+            assert_eq!(positions[i], (x, y));
+            visited[i].fetch_add(1, Ordering::Relaxed);
+        });
+        assert!(visited.iter().all(|v| v.load(Ordering::Relaxed) == 1));
+
+        let (near, energy) = particles.activity((50.0, 50.0), 30.0);
+        let inside = |&&(x, y): &&(f32, f32)| (x - 50.0).hypot(y - 50.0) < 30.0;
+        assert_eq!(near, positions.iter().filter(inside).count());
+        let expected = particles
+            .particles
+            .iter()
+            .flat_map(|p| {
+                (0..F32s::LEN)
+                    .filter(|&l| p.is_active(l))
+                    .map(|l| (p.dx[l], p.dy[l]))
+            })
+            .map(|(dx, dy)| narrow(dx * dx + dy * dy) / 2.0)
+            .sum::<f32>();
+        assert!(energy > 0.0 && (energy - expected).abs() < expected * 1e-4);
+    }
+
+    #[test]
+    fn culling_keeps_visible_counts() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.seed(3);
+        particles.add_particles(100, 40, 40);
+        particles.update(&Duration::from_secs(1), (0.0, 0.0), false);
+        // Move every other group of blocks far away.
+        for (i, group) in particles.particles.chunks_mut(CULL_BLOCKS).enumerate() {
+            if i % 2 == 1 {
+                for particle in group {
+                    particle.x += F32s::splat(1000.0);
+                }
+            }
+        }
+
+        let count = |particles: &Particles| {
+            let counts = (0..40 * 40).map(|_| AtomicU16::new(0)).collect::<Vec<_>>();
+            particles.count(&counts, 40, 40);
+            counts
+                .into_iter()
+                .map(AtomicU16::into_inner)
+                .collect::<Vec<_>>()
+        };
+        particles.bounds.clear();
+        let uncounted = count(&particles);
+        particles.update(&Duration::ZERO, (0.0, 0.0), false);
+        let culled = particles.bounds.iter().filter(|b| !b.intersects(40, 40));
+        assert!(culled.count() >= 3);
+        assert_eq!(count(&particles), uncounted);
+    }
+
+    #[test]
+    fn saturating_count_matches_atomic_count() {
+        let pool = Pool::new(3);
+        let mut particles = Particles::new(&pool);
+        particles.seed(1);
+        particles.add_particles(20, 50, 30);
+        let (width, height) = (50, 30);
+        // Spread the particles out, some of them leave the area.
+        particles.update(&Duration::from_secs(2), (0.0, 0.0), false);
+        // And 320 of them share a single pixel.
+        for _ in 0..5 {
+            let particle = Particle::new_random(20, 20, &mut particles.rng);
+            particles.particles.push(particle);
+        }
+
+        let counts = (0..width * height)
+            .map(|_| AtomicU16::new(0))
+            .collect::<Vec<_>>();
+        particles.count(&counts, width, height);
+        let mut tiles = CountTiles::default();
+        let mut saturated = vec![0; (width * height) as usize];
+        for _ in 0..2 {
+            particles.count_saturating(&mut tiles, &mut saturated, width, height);
+        }
+
+        assert!(counts.iter().any(|c| c.load(Ordering::Relaxed) > 255));
+        for (count, saturated) in counts.iter().zip(&saturated) {
+            let count = count.load(Ordering::Relaxed).min(255) as u8;
+            assert_eq!(count, *saturated);
+        }
+    }
+
+    #[test]
+    fn saturating_count_without_particles() {
+        let pool = Pool::new(2);
+        let particles = Particles::new(&pool);
+        let mut tiles = CountTiles::default();
+        let mut saturated = vec![7; 20 * 10];
+        particles.count_saturating(&mut tiles, &mut saturated, 20, 10);
+        assert!(saturated.iter().all(|&count| count == 0));
+    }
+
+    /// `blocks` blocks spread out from the center of a 200 x 200 area,
+    /// deterministic for every `seed`.
+    fn spread(pool: &Pool, seed: u64, blocks: usize) -> Particles<'_> {
+        let mut particles = Particles::new(pool);
+        particles.seed(seed);
+        particles.add_particles(blocks, 200, 200);
+        particles.update(&Duration::from_secs(1), FAR_AWAY, false);
+        particles
+    }
+
+    /// Position and velocity `[x, y, dx, dy]` of every lane.
+    fn states(particles: &Particles) -> Vec<[Real; 4]> {
+        let lanes = particles
+            .particles
+            .iter()
+            .flat_map(|p| (0..64).map(move |i| (p, i)));
+        lanes
+            .map(|(p, i)| [p.x[i], p.y[i], p.dx[i], p.dy[i]])
+            .collect()
+    }
+
+    /// A mouse position away from every particle of `spread`.
+    const FAR_AWAY: (f32, f32) = (-10_000.0, -10_000.0);
+
+    #[test]
+    fn seeded_spawning_ignores_thread_count() {
+        let spawn = |pool: &Pool| {
+            let mut particles = spread(pool, 5, 37);
+            particles.spawn_at(40, SpawnPattern::Ring, (50.0, 50.0), 200, 200);
+            states(&particles)
+        };
+        let (one, four) = (Pool::new(1), Pool::new(4));
+        assert_eq!(spawn(&one), spawn(&four));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn friction_never_increases_speed(
+            seed in 0..1000_u64,
+            friction in 0.0_f32..=1.0,
+            friction_y in 0.0_f32..=1.0,
+            micros in 0..100_000_u64,
+        ) {
+            let pool = Pool::new(2);
+            let mut particles = spread(&pool, seed, 4);
+            particles.friction = friction;
+            particles.friction_y = Some(friction_y);
+            let before = states(&particles);
+            particles.update(&Duration::from_micros(micros), FAR_AWAY, false);
+            for (before, after) in before.iter().zip(states(&particles)) {
+                prop_assert!(after[2].abs() <= before[2].abs(), "{before:?} {after:?}");
+                prop_assert!(after[3].abs() <= before[3].abs(), "{before:?} {after:?}");
+            }
+        }
+
+        #[test]
+        fn zero_dt_update_is_noop(
+            seed in 0..1000_u64,
+            friction in 0.0_f32..=1.0,
+            mouse in (-100.0_f32..300.0, -100.0_f32..300.0),
+        ) {
+            let pool = Pool::new(2);
+            let mut particles = spread(&pool, seed, 4);
+            particles.friction = friction;
+            particles.temperature = 3.0;
+            let before = states(&particles);
+            particles.update(&Duration::ZERO, mouse, true);
+            prop_assert_eq!(states(&particles), before);
+        }
+
+        #[test]
+        fn shift_translates_every_lane_exactly(
+            seed in 0..1000_u64,
+            dx in -500.0_f32..500.0,
+            dy in -500.0_f32..500.0,
+        ) {
+            let pool = Pool::new(2);
+            let mut particles = spread(&pool, seed, 4);
+            let before = particles.particles.clone();
+            particles.shift(dx, dy);
+            for (before, after) in before.iter().zip(&particles.particles) {
+                prop_assert_eq!(after.x, before.x + splat(dx));
+                prop_assert_eq!(after.y, before.y + splat(dy));
+                prop_assert_eq!((after.dx, after.dy), (before.dx, before.dy));
+            }
+        }
+
+        #[test]
+        fn attraction_is_symmetric_under_mirroring(
+            seed in 0..1000_u64,
+            mouse in (0.0_f32..200.0, 0.0_f32..200.0),
+        ) {
+            let pool = Pool::new(2);
+            let mut particles = spread(&pool, seed, 4);
+            // The same particles mirrored at x = 0.
+            let mut mirrored = spread(&pool, seed, 4);
+            for particle in &mut mirrored.particles {
+                (particle.x, particle.dx) = (-particle.x, -particle.dx);
+            }
+            for _ in 0..10 {
+                particles.update(&Duration::from_millis(16), mouse, true);
+                mirrored.update(&Duration::from_millis(16), (-mouse.0, mouse.1), true);
+            }
+            for (a, b) in states(&particles).into_iter().zip(states(&mirrored)) {
+                prop_assert_eq!([-a[0], a[1], -a[2], a[3]], b);
+            }
+        }
     }
 
-    pub fn apply_fric(&mut self, fric_norm: &F32s) {
-        self.dx *= fric_norm;
-        self.dy *= fric_norm;
+    #[test]
+    fn verlet_keeps_orbits_stable() {
+        let pool = Pool::new(1);
+        // Largest deviation from the initial energy of a circular orbit of
+        // radius 10 around the mouse, at alternating frame times.
+        let energy_drift = |integrator| {
+            let mut particles = Particles::new(&pool);
+            particles.friction = 1.0;
+            particles.integrator = integrator;
+            particles.particles.push(Particle {
+                x: splat(60.0),
+                y: splat(50.0),
+                dy: splat(10.0_f32.sqrt()),
+                ..Particle::ZERO
+            });
+            let energy = |particles: &Particles| {
+                let p = &particles.particles[0];
+                let (x, y, dx, dy) = (p.x[0] - 50.0, p.y[0] - 50.0, p.dx[0], p.dy[0]);
+                (dx * dx + dy * dy) / 2.0 + x.hypot(y)
+            };
+            let start = energy(&particles);
+            (0..2000)
+                .map(|i| {
+                    let frametime = Duration::from_millis(if i % 2 == 0 { 8 } else { 30 });
+                    particles.update(&frametime, (50.0, 50.0), true);
+                    (energy(&particles) - start).abs()
+                })
+                .fold(0.0, Real::max)
+        };
+        let euler = energy_drift(Integrator::SemiImplicitEuler);
+        let verlet = energy_drift(Integrator::Verlet);
+        assert!(verlet < euler / 4.0, "{verlet} vs {euler}");
+        assert!(verlet < 0.1, "{verlet}");
+    }
+
+    #[test]
+    fn loads_states() {
+        let pool = Pool::new(1);
+        let mut particles = Particles::builder().count(1000).build(&pool);
+        let states: Vec<_> = (0..70).map(|i| [i as f32, 1.0, 2.0, -3.0]).collect();
+        particles.load_states(&states);
+        assert_eq!((particles.particles.len(), particles.len()), (2, 70));
+        assert_eq!(particles.snapshot().states().collect::<Vec<_>>(), states);
+        assert_eq!(particles.fastest, 3.0);
+    }
+
+    #[test]
+    fn pen_scales_and_biases_the_attraction() {
+        let pool = Pool::new(1);
+        // Velocity after one frame of a particle 10 pixels right of the
+        // cursor.
+        let pulled = |pen| {
+            let mut particles = Particles::new(&pool);
+            particles.friction = 1.0;
+            particles.pen = pen;
+            particles.particles.push(Particle {
+                x: splat(60.0),
+                y: splat(50.0),
+                ..Particle::ZERO
+            });
+            particles.update(&Duration::from_millis(16), (50.0, 50.0), true);
+            (particles.particles[0].dx[0], particles.particles[0].dy[0])
+        };
+        let (dx, dy) = pulled(None);
+        assert!(dx < 0.0 && dy == 0.0);
+        let light = pulled(Some(Pen {
+            pressure: 0.5,
+            bias: (0.0, 0.0),
+        }));
+        assert!((light.0 - dx / 2.0).abs() < 1e-6 && light.1 == 0.0);
+        let leaning = pulled(Some(Pen {
+            pressure: 1.0,
+            bias: (0.0, 0.5),
+        }));
+        assert!((leaning.0 - dx).abs() < 1e-6 && (leaning.1 + dx / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn substeps_keep_fast_particles_from_tunneling() {
+        let pool = Pool::new(1);
+        let shoot = |substep_distance| {
+            let mut particles = Particles::new(&pool);
+            particles.friction = 1.0;
+            particles.substep_distance = substep_distance;
+            let circle = crate::obstacle::Shape::Circle {
+                x: 0.5,
+                y: 0.5,
+                radius: 0.05,
+            };
+            particles.obstacles = Some(Obstacles::new(vec![circle], 100, 100));
+            particles.particles.push(Particle {
+                x: splat(24.0),
+                y: splat(50.0),
+                dx: splat(40.0),
+                ..Particle::ZERO
+            });
+            // Measures the speed without moving.
+            particles.update(&Duration::ZERO, (0.0, 0.0), false);
+            particles.update(&Duration::from_micros(16666), (0.0, 0.0), false);
+            (particles.particles[0].x[0], particles.particles[0].dx[0])
+        };
+        // Jumps across the circle from (45, 50) to (55, 50) in one step.
+        assert_eq!(shoot(None), (64.0, 40.0));
+        let (x, dx) = shoot(Some(4.0));
+        assert!(x < 45.0 && dx == -40.0, "{x} {dx}");
+    }
+
+    #[test]
+    fn wind_tunnel_flows_and_emits() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.friction = 0.9;
+        particles.wind = Some((4.0, 0.0));
+        particles.inflows = vec![Inflow::new(SpawnPattern::Line, (0.0, 50.0), 100.0, 2)];
+        // 150 particles are due, the 22 left over wait for the next call.
+        particles.emit(1.5, 200, 100);
+        assert_eq!(particles.particles.len(), 2);
+        assert_eq!(particles.spawn_tag, 0);
+        particles.emit(0.5, 200, 100);
+        assert_eq!(particles.particles.len(), 3);
+        for particle in &particles.particles {
+            assert_eq!(particle.tag, u8x64::splat(2));
+            assert!(particle.x.simd_lt(splat(1.0)).all());
+        }
+
+        // The friction slows the particles down to the wind.
+        for _ in 0..200 {
+            particles.update(&Duration::from_millis(16), (0.0, 0.0), false);
+        }
+        for [_, _, dx, dy] in states(&particles) {
+            assert!((dx - 4.0).abs() < 1e-3 && dy.abs() < 1e-3, "{dx} {dy}");
+        }
+    }
+
+    #[cfg(feature = "f64")]
+    #[test]
+    fn keeps_small_steps_far_from_origin() {
+        let pool = Pool::new(1);
+        let mut particles = Particles::new(&pool);
+        particles.particles.push(Particle {
+            x: splat(100_000.0),
+            ..Particle::ZERO
+        });
+        for _ in 0..1000 {
+            particles.shift(0.001, 0.0);
+        }
+        // `f32` positions this far out round every one of these steps away.
+        assert!((particles.particles[0].x[0] - 100_001.0).abs() < 1e-6);
     }
 }
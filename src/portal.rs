@@ -0,0 +1,221 @@
+//! Paired rectangles teleporting particles.
+//!
+//! A particle inside either rectangle of a `Portal` is moved to the same
+//! relative position in the other one and carried through it along its
+//! velocity, so it emerges on the far side with its velocity unchanged.
+//! Particles at rest are not teleported, otherwise they would bounce
+//! between the two rectangles.
+
+use crate::simd::cmp::{SimdPartialEq, SimdPartialOrd};
+use crate::simd::num::SimdFloat;
+use crate::simd::{Select, Simd};
+
+use serde::{Deserialize, Serialize};
+
+use crate::particles::{Particle, Real, splat};
+
+type F32s = Simd<Real, 64>;
+
+/// Distance in pixels particles are moved past the edge they leave by.
+const SKIN: f32 = 0.01;
+
+/// An axis-aligned rectangle.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    /// The rectangle spanned by two corners.
+    pub fn from_corners(a: (f32, f32), b: (f32, f32)) -> Self {
+        Rect {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: (a.0 - b.0).abs(),
+            height: (a.1 - b.1).abs(),
+        }
+    }
+
+    fn scale(self, sx: f32, sy: f32) -> Self {
+        Rect {
+            x: self.x * sx,
+            y: self.y * sy,
+            width: self.width * sx,
+            height: self.height * sy,
+        }
+    }
+}
+
+/// Two linked rectangles in normalized window coordinates, e.g.
+/// `{ "from": { "x": 0.4, "y": 0.9, "width": 0.2, "height": 0.1 }, "to": ... }`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Portal {
+    pub from: Rect,
+    pub to: Rect,
+}
+
+/// The portals of a window.
+#[derive(Debug, Clone, Default)]
+pub struct Portals {
+    portals: Vec<Portal>,
+    /// `portals` in pixels, in both directions.
+    links: Vec<(Rect, Rect)>,
+    size: (f32, f32),
+}
+
+impl Portals {
+    /// The `portals` in a `width` x `height` window.
+    pub fn new(portals: Vec<Portal>, width: u32, height: u32) -> Self {
+        let mut portals = Portals {
+            portals,
+            ..Portals::default()
+        };
+        portals.resize(width, height);
+        portals
+    }
+
+    pub fn portals(&self) -> &[Portal] {
+        &self.portals
+    }
+
+    /// Adds a portal between the pixel rectangles `from` and `to`.
+    pub fn push_pixels(&mut self, from: Rect, to: Rect) {
+        let (w, h) = (self.size.0.max(1.0), self.size.1.max(1.0));
+        let normalize = |rect: Rect| Rect {
+            x: rect.x / w,
+            y: rect.y / h,
+            width: rect.width / w,
+            height: rect.height / h,
+        };
+        self.portals.push(Portal {
+            from: normalize(from),
+            to: normalize(to),
+        });
+        self.links.extend([(from, to), (to, from)]);
+    }
+
+    /// Converts the portals to pixels of a `width` x `height` window.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (w, h) = (width as f32, height as f32);
+        self.size = (w, h);
+        self.links = self
+            .portals
+            .iter()
+            .flat_map(|portal| {
+                let (from, to) = (portal.from.scale(w, h), portal.to.scale(w, h));
+                [(from, to), (to, from)]
+            })
+            .collect();
+    }
+
+    /// Teleports the moving lanes of `particle` inside of a portal.
+    #[inline(always)]
+    pub fn teleport(&self, particle: &mut Particle) {
+        let zero = F32s::splat(0.0);
+        let moving = particle.dx.simd_ne(zero) | particle.dy.simd_ne(zero);
+        for (from, to) in &self.links {
+            let (x0, y0) = (splat(from.x), splat(from.y));
+            let (x1, y1) = (x0 + splat(from.width), y0 + splat(from.height));
+            let inside = moving
+                & particle.x.simd_ge(x0)
+                & particle.x.simd_lt(x1)
+                & particle.y.simd_ge(y0)
+                & particle.y.simd_lt(y1);
+            if !inside.any() {
+                continue;
+            }
+            // The same relative position in the exit.
+            let sx = splat(to.width / from.width.max(f32::MIN_POSITIVE));
+            let sy = splat(to.height / from.height.max(f32::MIN_POSITIVE));
+            let x = splat(to.x) + (particle.x - x0) * sx;
+            let y = splat(to.y) + (particle.y - y0) * sy;
+            // Frames until the particle leaves the exit along each axis.
+            let (left, right) = (splat(to.x), splat(to.x + to.width));
+            let (top, bottom) = (splat(to.y), splat(to.y + to.height));
+            let exit_time = |pos: F32s, v: F32s, low: F32s, high: F32s| {
+                let edge = v.simd_gt(zero).select(high, low);
+                v.simd_eq(zero)
+                    .select(splat(f32::INFINITY), (edge - pos) / v)
+            };
+            let tx = exit_time(x, particle.dx, left, right);
+            let ty = exit_time(y, particle.dy, top, bottom);
+            let t = tx.simd_min(ty);
+            let skin = splat(SKIN);
+            let beyond =
+                |v: F32s, low: F32s, high: F32s| v.simd_gt(zero).select(high + skin, low - skin);
+            let x = tx
+                .simd_le(ty)
+                .select(beyond(particle.dx, left, right), x + particle.dx * t);
+            let y = ty
+                .simd_lt(tx)
+                .select(beyond(particle.dy, top, bottom), y + particle.dy * t);
+            particle.x = inside.select(x, particle.x);
+            particle.y = inside.select(y, particle.y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn teleports_through_exit() {
+        let portals = Portals::new(
+            vec![Portal {
+                from: Rect {
+                    x: 0.4,
+                    y: 0.9,
+                    width: 0.2,
+                    height: 0.1,
+                },
+                to: Rect {
+                    x: 0.4,
+                    y: 0.0,
+                    width: 0.2,
+                    height: 0.1,
+                },
+            }],
+            100,
+            100,
+        );
+        let mut particle = Particle::ZERO;
+        // Falling into the floor portal.
+        particle.x[0] = 45.0;
+        particle.y[0] = 91.0;
+        particle.dy[0] = 2.0;
+        // At rest inside of it.
+        particle.x[1] = 45.0;
+        particle.y[1] = 91.0;
+        // Moving left next to it.
+        particle.x[2] = 30.0;
+        particle.y[2] = 95.0;
+        particle.dx[2] = -1.0;
+        portals.teleport(&mut particle);
+
+        // Carried through the ceiling portal, leaving it downwards.
+        assert_eq!(particle.x[0], 45.0);
+        assert_eq!(particle.y[0], 10.0 + SKIN as Real);
+        assert_eq!(particle.dy[0], 2.0);
+        assert_eq!((particle.x[1], particle.y[1]), (45.0, 91.0));
+        assert_eq!((particle.x[2], particle.y[2]), (30.0, 95.0));
+
+        let mut drawn = Portals::new(Vec::new(), 200, 100);
+        let rect = Rect::from_corners((40.0, 50.0), (20.0, 10.0));
+        drawn.push_pixels(rect, rect);
+        assert_eq!(
+            drawn.portals()[0].from,
+            Rect {
+                x: 0.1,
+                y: 0.1,
+                width: 0.1,
+                height: 0.4
+            }
+        );
+    }
+}
@@ -0,0 +1,1485 @@
+use crate::simd::cmp::SimdPartialOrd;
+use crate::simd::num::{SimdFloat, SimdUint};
+use crate::simd::{Select, Simd, SimdElement, StdFloat, u8x4};
+
+use serde::{Deserialize, Serialize};
+
+use crate::motion::MotionField;
+use crate::palette::Palette;
+use crate::particles::MAX_TAGS;
+use crate::scoped_threadpool::Pool;
+
+const LANES: usize = 16;
+
+/// Element type of a count buffer, `u16` or saturated `u8` counts.
+pub trait Count: SimdElement + Sync {
+    fn to_f32<const N: usize>(counts: Simd<Self, N>) -> Simd<f32, N>;
+}
+
+impl Count for u8 {
+    #[inline(always)]
+    fn to_f32<const N: usize>(counts: Simd<Self, N>) -> Simd<f32, N> {
+        counts.cast()
+    }
+}
+
+impl Count for u16 {
+    #[inline(always)]
+    fn to_f32<const N: usize>(counts: Simd<Self, N>) -> Simd<f32, N> {
+        counts.cast()
+    }
+}
+
+impl Count for f32 {
+    #[inline(always)]
+    fn to_f32<const N: usize>(counts: Simd<Self, N>) -> Simd<f32, N> {
+        counts
+    }
+}
+
+/// How the counts are turned into colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// Multiplier of the counts, 255 after it being full color.
+    pub brightness: f32,
+    pub palette: Palette,
+    /// Offset of the palette along both axes, in screen sizes. Animating it
+    /// cycles the colors; the palette is mirrored at every screen edge so
+    /// that it stays seamless.
+    pub phase: f32,
+    /// Rounds the channels with an ordered dither instead of truncating
+    /// them, which hides the banding of dim gradients.
+    pub dither: bool,
+    /// Treats the tone-mapped channels as linear light and encodes them
+    /// with the sRGB transfer function displays expect, instead of showing
+    /// them as they are, which crushes the midtones of dim particles.
+    pub srgb: bool,
+}
+
+impl Tone {
+    pub fn new(brightness: f32, palette: Palette) -> Self {
+        Tone {
+            brightness,
+            palette,
+            phase: 0.0,
+            dither: false,
+            srgb: false,
+        }
+    }
+}
+
+/// Vertical gradient added below the particles, colors as `[r, g, b]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Background {
+    pub top: [u8; 3],
+    pub bottom: [u8; 3],
+}
+
+/// Tone-maps the per-pixel particle counts into 0x00RRGGBB pixels.
+///
+/// Both buffers hold `width * height` values row by row.
+pub fn colorize<T: Count>(
+    threadpool: &Pool,
+    count_buffer: &[T],
+    pixel_buffer: &mut [u32],
+    width: u32,
+    height: u32,
+    tone: Tone,
+) {
+    colorize_bits(
+        threadpool,
+        count_buffer,
+        pixel_buffer,
+        (width, height),
+        tone,
+        8,
+    );
+}
+
+/// Same as `colorize`, but packs 10 bits per channel into X2R10G10B10
+/// pixels, `0bXXRRRRRRRRRRGGGGGGGGGGBBBBBBBBBB`, the layout of 10-bit
+/// surfaces. The finer steps remove the banding of dim gradients.
+pub fn colorize_10bit<T: Count>(
+    threadpool: &Pool,
+    count_buffer: &[T],
+    pixel_buffer: &mut [u32],
+    width: u32,
+    height: u32,
+    tone: Tone,
+) {
+    colorize_bits(
+        threadpool,
+        count_buffer,
+        pixel_buffer,
+        (width, height),
+        tone,
+        10,
+    );
+}
+
+/// Widens the 0x00RRGGBB `pixel` to X2R10G10B10 by repeating the top bits
+/// of every channel, which maps 0xFF to 0x3FF.
+pub fn widen_10bit(pixel: u32) -> u32 {
+    let [_, r, g, b] = pixel
+        .to_be_bytes()
+        .map(|c| (c as u32) << 2 | (c as u32) >> 6);
+    r << 20 | g << 10 | b
+}
+
+/// Carries the passes after the colorization over to the X2R10G10B10
+/// `deep` pixels, rendered with `colorize_10bit` alongside the 0x00RRGGBB
+/// `base` ones: wherever the final `pixels` differ from `base`, e.g. under
+/// the background or the trails, the deep pixel is replaced by the widened
+/// final one.
+pub fn merge_10bit(threadpool: &Pool, base: &[u32], pixels: &[u32], deep: &mut [u32]) {
+    let chunk_len = usize::max(deep.len() / threadpool.thread_count() as usize / 10, 1);
+    threadpool.scoped(|scope| {
+        for ((deep, base), pixels) in deep
+            .chunks_mut(chunk_len)
+            .zip(base.chunks(chunk_len))
+            .zip(pixels.chunks(chunk_len))
+        {
+            scope.execute(move |_| {
+                for ((deep, &base), &pixel) in deep.iter_mut().zip(base).zip(pixels) {
+                    if pixel != base {
+                        *deep = widen_10bit(pixel);
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Colors `pixels`, which start at index `start` of a `width` x `height`
+/// frame, like `colorize` but on the calling thread, for callers that
+/// spread the chunks of several frames over the pool themselves.
+pub fn colorize_chunk<T: Count>(
+    start: usize,
+    counts: &[T],
+    pixels: &mut [u32],
+    (width, height): (u32, u32),
+    tone: Tone,
+) {
+    let shader = Shader {
+        width,
+        height,
+        tone,
+        bits: 8,
+    };
+    shader.colorize_chunk(start, counts, pixels);
+}
+
+fn colorize_bits<T: Count>(
+    threadpool: &Pool,
+    count_buffer: &[T],
+    pixel_buffer: &mut [u32],
+    (width, height): (u32, u32),
+    tone: Tone,
+    bits: u32,
+) {
+    let pixel_chunk_len = usize::max(
+        pixel_buffer.len() / threadpool.thread_count() as usize / 10,
+        1,
+    );
+
+    // The last chunk may be shorter, `colorize_chunk` handles any length.
+    threadpool.scoped(|scope| {
+        scope.execute_chunks(pixel_buffer, pixel_chunk_len, |_| {
+            let shader = Shader {
+                width,
+                height,
+                tone,
+                bits,
+            };
+            move |i_chunk, pixel_buffer_chunk| {
+                let start = i_chunk * pixel_chunk_len;
+                let end = usize::min(start + pixel_chunk_len, count_buffer.len());
+                if let Some(count_buffer_chunk) = count_buffer.get(start..end) {
+                    shader.colorize_chunk(start, count_buffer_chunk, pixel_buffer_chunk);
+                }
+            }
+        });
+    });
+}
+
+/// Adds `background` to the `width` x `height` pixels, saturating every
+/// channel.
+pub fn add_background(
+    threadpool: &Pool,
+    pixel_buffer: &mut [u32],
+    width: u32,
+    height: u32,
+    background: Background,
+) {
+    if background == Background::default() || width == 0 {
+        return;
+    }
+    let w = width as usize;
+    let rows_per_chunk = usize::max(height as usize / threadpool.thread_count() as usize / 10, 1);
+    let [top, bottom] =
+        [background.top, background.bottom].map(|[r, g, b]| u32::from_be_bytes([0, r, g, b]));
+    threadpool.scoped(|scope| {
+        for (i_chunk, pixels) in pixel_buffer.chunks_mut(w * rows_per_chunk).enumerate() {
+            scope.execute(move |_| {
+                for (y, row) in (i_chunk * rows_per_chunk..).zip(pixels.chunks_mut(w)) {
+                    let t = (y * 256 / (height as usize - 1).max(1)) as u32;
+                    let color = u8x4::from_array(blend(top, bottom, t).to_le_bytes());
+                    for pixel in row {
+                        let sum = u8x4::from_array(pixel.to_le_bytes()).saturating_add(color);
+                        *pixel = u32::from_le_bytes(sum.to_array());
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// How the pixels of a layer are combined with the pixels below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    /// Sums the channels, saturating at white.
+    #[default]
+    Add,
+    /// Inverts, multiplies and inverts again, which brightens like `Add`
+    /// but approaches white gently instead of clipping.
+    Screen,
+    /// Covers the pixels below by the alpha in the top byte of the
+    /// 0xAARRGGBB layer, see `key` for layers without one.
+    Over,
+}
+
+/// How a layer is composited, see `composite`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Blend {
+    pub mode: BlendMode,
+    /// Fades the blended layer in from `0`, leaving the pixels below as
+    /// they are, to `1`.
+    pub opacity: f32,
+}
+
+impl Blend {
+    pub const fn new(mode: BlendMode) -> Self {
+        Blend { mode, opacity: 1.0 }
+    }
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Blend::new(BlendMode::Add)
+    }
+}
+
+/// The layers of a frame besides the density, each rendered into its own
+/// buffer and composited in this order:
+///
+/// 1. the density of the particles, the base of the frame,
+/// 2. the trails, with `trails`,
+/// 3. the second simulation of `Scene::layer`, with its own blend,
+/// 4. the background, added below all of them, after which the frame is
+///    recolored by `accessibility`,
+/// 5. the logo of `--watermark`, see `watermark`,
+/// 6. the overlay of text such as the stats and the help, with `overlay`,
+///    after the frame went to the sinks.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layers {
+    pub trails: Blend,
+    pub overlay: Blend,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            trails: Blend::new(BlendMode::Add),
+            overlay: Blend::new(BlendMode::Over),
+        }
+    }
+}
+
+/// Blends the pixels of `layer` onto the 0x00RRGGBB `pixels` with `blend`.
+/// Only `BlendMode::Over` reads the alpha of `layer`.
+pub fn composite(threadpool: &Pool, layer: &[u32], pixels: &mut [u32], blend: Blend) {
+    if blend.opacity <= 0.0 {
+        return;
+    }
+    let chunk_len = usize::max(pixels.len() / threadpool.thread_count() as usize / 10, 1);
+    threadpool.scoped(|scope| {
+        for (pixels, layer) in pixels.chunks_mut(chunk_len).zip(layer.chunks(chunk_len)) {
+            scope.execute(move |_| blend_pixels(layer, pixels, blend));
+        }
+    });
+}
+
+/// `composite` on the calling thread, for small layers such as a row of
+/// an image.
+pub fn blend_pixels(layer: &[u32], pixels: &mut [u32], blend: Blend) {
+    // In 1/256, so a full opacity keeps the blended channels exactly.
+    let opacity = (blend.opacity.clamp(0.0, 1.0) * 256.0) as u16;
+    for (pixel, &top) in pixels.iter_mut().zip(layer) {
+        let below = u8x4::from_array(pixel.to_le_bytes());
+        let (blended, weight) = match blend.mode {
+            BlendMode::Add => {
+                let top = u8x4::from_array(top.to_le_bytes());
+                (below.saturating_add(top), opacity)
+            }
+            BlendMode::Screen => {
+                let max = Simd::splat(255);
+                let top = u8x4::from_array(top.to_le_bytes());
+                let (below, top) = (max - below.cast::<u16>(), max - top.cast::<u16>());
+                ((max - below * top / max).cast(), opacity)
+            }
+            BlendMode::Over => {
+                let alpha = (top >> 24) as u16;
+                (u8x4::from_array(top.to_le_bytes()), alpha * opacity / 255)
+            }
+        };
+        let blended = if weight == 256 {
+            blended
+        } else {
+            let weight = Simd::splat(weight);
+            ((below.cast::<u16>() * (Simd::splat(256) - weight) + blended.cast::<u16>() * weight)
+                >> 8)
+                .cast()
+        };
+        *pixel = u32::from_le_bytes(blended.to_array()) & 0xFFFFFF;
+    }
+}
+
+/// Colors of the frame, for color-blind and low-vision users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    /// The colors of the palette.
+    #[default]
+    Full,
+    /// The brightness alone.
+    Grayscale,
+    /// `HIGH_CONTRAST` where the brightness reaches `CONTRAST_THRESHOLD`,
+    /// black elsewhere.
+    HighContrast,
+}
+
+impl ColorMode {
+    pub fn next(self) -> Self {
+        match self {
+            ColorMode::Full => ColorMode::Grayscale,
+            ColorMode::Grayscale => ColorMode::HighContrast,
+            ColorMode::HighContrast => ColorMode::Full,
+        }
+    }
+}
+
+/// Foreground of `ColorMode::HighContrast`, a yellow that every kind of
+/// color blindness tells from black, and inverted, a blue from white.
+pub const HIGH_CONTRAST: u32 = 0xFFD700;
+
+/// Brightness, from 0 to 255, from which pixels are drawn in
+/// `HIGH_CONTRAST`.
+pub const CONTRAST_THRESHOLD: u32 = 24;
+
+/// Render modes for color-blind and low-vision users, applied to the
+/// finished frame by `accessibility`, e.g.
+/// `{ "colors": "high_contrast", "inverted": true }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Accessibility {
+    pub colors: ColorMode,
+    /// Inverts the frame into dark particles on a light background.
+    pub inverted: bool,
+}
+
+/// Recolors the 0x00RRGGBB `pixels` of a frame as set in `settings`.
+pub fn accessibility(threadpool: &Pool, pixels: &mut [u32], settings: Accessibility) {
+    if settings == Accessibility::default() {
+        return;
+    }
+    let invert = if settings.inverted { 0xFFFFFF } else { 0 };
+    let chunk_len = usize::max(pixels.len() / threadpool.thread_count() as usize / 10, 1);
+    threadpool.scoped(|scope| {
+        for pixels in pixels.chunks_mut(chunk_len) {
+            scope.execute(move |_| {
+                for pixel in pixels {
+                    let color = match settings.colors {
+                        ColorMode::Full => *pixel,
+                        ColorMode::Grayscale => luma(*pixel) * 0x010101,
+                        ColorMode::HighContrast if luma(*pixel) >= CONTRAST_THRESHOLD => {
+                            HIGH_CONTRAST
+                        }
+                        ColorMode::HighContrast => 0,
+                    };
+                    *pixel = (color ^ invert) & 0xFFFFFF;
+                }
+            });
+        }
+    });
+}
+
+/// Sets the alpha of the 0x00RRGGBB `pixels` of a layer to their brightest
+/// channel, so black turns transparent and the layer can be blended with
+/// `BlendMode::Over`.
+pub fn key(threadpool: &Pool, pixels: &mut [u32]) {
+    let chunk_len = usize::max(pixels.len() / threadpool.thread_count() as usize / 10, 1);
+    threadpool.scoped(|scope| {
+        for pixels in pixels.chunks_mut(chunk_len) {
+            scope.execute(move |_| {
+                for pixel in pixels {
+                    let [b, g, r, _] = pixel.to_le_bytes();
+                    *pixel = u32::from_le_bytes([b, g, r, r.max(g).max(b)]);
+                }
+            });
+        }
+    });
+}
+
+/// Rec. 601 luma of a 0x00RRGGBB pixel.
+#[inline(always)]
+fn luma(pixel: u32) -> u32 {
+    let [_, r, g, b] = pixel.to_be_bytes();
+    (r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8
+}
+
+/// Blends the 0x00RRGGBB pixels of `from` into `to`, `t = 0` keeping
+/// `from` and `t = 1` keeping `to`.
+pub fn crossfade(threadpool: &Pool, from: &[u32], to: &mut [u32], t: f32) {
+    let chunk_len = usize::max(to.len() / threadpool.thread_count() as usize / 10, 1);
+    let t = (t.clamp(0.0, 1.0) * 256.0) as u32;
+    threadpool.scoped(|scope| {
+        for (to, from) in to.chunks_mut(chunk_len).zip(from.chunks(chunk_len)) {
+            scope.execute(move |_| {
+                for (to, &from) in to.iter_mut().zip(from) {
+                    *to = blend(from, *to, t);
+                }
+            });
+        }
+    });
+}
+
+/// Per-channel `a + (b - a) * t / 256`, on red and blue at once and then
+/// on green.
+#[inline(always)]
+fn blend(a: u32, b: u32, t: u32) -> u32 {
+    let lerp = |a: u32, b: u32, mask: u32| {
+        let (a, b) = (a & mask, b & mask);
+        ((a * (256 - t) + b * t) >> 8) & mask
+    };
+    lerp(a, b, 0xFF00FF) | lerp(a, b, 0x00FF00)
+}
+
+/// Settings of the metaball render mode, see `metaballs`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metaballs {
+    /// Radius of the box blur in pixels.
+    pub radius: u32,
+    /// Blurred density, times the brightness, at which the blobs begin.
+    pub threshold: f32,
+    /// Draw the blob contours on top.
+    pub outline: bool,
+}
+
+impl Default for Metaballs {
+    fn default() -> Self {
+        Metaballs {
+            radius: 4,
+            threshold: 128.0,
+            outline: false,
+        }
+    }
+}
+
+/// Settings of the glow render mode, see `glow`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Glow {
+    /// Radius of each box blur in pixels.
+    pub radius: u32,
+    /// Number of box blurs, three already come close to a Gaussian.
+    pub passes: u32,
+}
+
+impl Default for Glow {
+    fn default() -> Self {
+        Glow {
+            radius: 2,
+            passes: 3,
+        }
+    }
+}
+
+/// Settings of the direction hue render mode, see `direction_hue`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectionHue {
+    /// Speed in pixels per 60 Hz frame at which pixels are fully bright.
+    pub max_speed: f32,
+}
+
+impl Default for DirectionHue {
+    fn default() -> Self {
+        DirectionHue { max_speed: 4.0 }
+    }
+}
+
+/// A box-blurred density field, kept across frames to avoid reallocating
+/// it.
+#[derive(Default)]
+pub struct BlurField {
+    width: u32,
+    height: u32,
+    /// Result of the horizontal pass.
+    rows: Vec<f32>,
+    values: Vec<f32>,
+    /// Input of the repeated passes of `blur_passes`.
+    previous: Vec<f32>,
+}
+
+impl BlurField {
+    /// Blurs the `width` x `height` counts with a box of `2 * radius + 1`
+    /// pixels, averaging to the mean count per pixel. Pixels outside the
+    /// buffer count as empty.
+    pub fn blur<T: Count>(
+        &mut self,
+        threadpool: &Pool,
+        count_buffer: &[T],
+        width: u32,
+        height: u32,
+        radius: u32,
+    ) {
+        let (w, h, r) = (width as usize, height as usize, radius as usize);
+        self.width = width;
+        self.height = height;
+        self.rows.resize(w * h, 0.0);
+        self.values.resize(w * h, 0.0);
+        if w == 0 || h == 0 {
+            return;
+        }
+        let rows_per_chunk = usize::max(h / threadpool.thread_count() as usize / 4, 1);
+        let scale = 1.0 / (2 * r + 1) as f32;
+
+        threadpool.scoped(|scope| {
+            let rows = self.rows.chunks_mut(w * rows_per_chunk);
+            for (rows, counts) in rows.zip(count_buffer.chunks(w * rows_per_chunk)) {
+                scope.execute(move |_| {
+                    for (row, counts) in rows.chunks_mut(w).zip(counts.chunks(w)) {
+                        let count = |x: usize| {
+                            counts
+                                .get(x)
+                                .map_or(0.0, |&c| T::to_f32(Simd::<T, 1>::splat(c))[0])
+                        };
+                        let mut sum = (0..=r).map(count).sum::<f32>();
+                        for (x, value) in row.iter_mut().enumerate() {
+                            *value = sum * scale;
+                            sum += count(x + r + 1);
+                            if x >= r {
+                                sum -= count(x - r);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let rows = &self.rows;
+        threadpool.scoped(|scope| {
+            for (i_chunk, values) in self.values.chunks_mut(w * rows_per_chunk).enumerate() {
+                scope.execute(move |_| {
+                    let row = |y: usize| &rows[y * w..(y + 1) * w];
+                    let y0 = i_chunk * rows_per_chunk;
+                    let mut sum = vec![0.0; w];
+                    for y in y0.saturating_sub(r)..(y0 + r + 1).min(h) {
+                        sum.iter_mut().zip(row(y)).for_each(|(s, v)| *s += v);
+                    }
+                    for (y, values) in (y0..).zip(values.chunks_mut(w)) {
+                        for (value, s) in values.iter_mut().zip(&sum) {
+                            *value = s * scale;
+                        }
+                        if y + r + 1 < h {
+                            sum.iter_mut()
+                                .zip(row(y + r + 1))
+                                .for_each(|(s, v)| *s += v);
+                        }
+                        if y >= r {
+                            sum.iter_mut().zip(row(y - r)).for_each(|(s, v)| *s -= v);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Blurs the counts `passes` times, see `blur`.
+    pub fn blur_passes<T: Count>(
+        &mut self,
+        threadpool: &Pool,
+        count_buffer: &[T],
+        width: u32,
+        height: u32,
+        radius: u32,
+        passes: u32,
+    ) {
+        self.blur(threadpool, count_buffer, width, height, radius);
+        for _ in 1..passes {
+            std::mem::swap(&mut self.values, &mut self.previous);
+            let previous = std::mem::take(&mut self.previous);
+            self.blur(threadpool, &previous, width, height, radius);
+            self.previous = previous;
+        }
+    }
+
+    /// The blurred counts, row by row.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+}
+
+/// Highest count with its own histogram bin in `Equalizer`, higher counts
+/// share the last bin.
+const EQUALIZER_BINS: usize = 4096;
+
+/// Histogram equalization of the counts, an automatic exposure that keeps
+/// both sparse halos and dense cores visible. Kept across frames to avoid
+/// reallocating its buffers.
+#[derive(Default)]
+pub struct Equalizer {
+    /// Equalized value of every count.
+    lut: Vec<f32>,
+    values: Vec<f32>,
+}
+
+impl Equalizer {
+    /// Remaps the counts through the cumulative histogram of the non-zero
+    /// ones, to `0..=255` for a brightness of `1`. Empty pixels stay at
+    /// zero.
+    pub fn equalize<T: Count>(&mut self, threadpool: &Pool, count_buffer: &[T]) {
+        let bin =
+            |count: T| (T::to_f32(Simd::<T, 1>::splat(count))[0] as usize).min(EQUALIZER_BINS - 1);
+        let n_chunks = threadpool.thread_count() as usize * 4;
+        let chunk_len = usize::max(count_buffer.len().div_ceil(n_chunks), 1);
+
+        // Every chunk counts into its own histogram, which are summed after.
+        let mut histograms = vec![[0_u32; EQUALIZER_BINS]; count_buffer.len().div_ceil(chunk_len)];
+        threadpool.scoped(|scope| {
+            for (histogram, counts) in histograms.iter_mut().zip(count_buffer.chunks(chunk_len)) {
+                scope.execute(move |_| {
+                    for &count in counts {
+                        histogram[bin(count)] += 1;
+                    }
+                });
+            }
+        });
+        let mut histogram = [0_u64; EQUALIZER_BINS];
+        for partial in &histograms {
+            histogram
+                .iter_mut()
+                .zip(partial)
+                .for_each(|(sum, &n)| *sum += n as u64);
+        }
+
+        let total = histogram[1..].iter().sum::<u64>().max(1) as f32;
+        self.lut.clear();
+        self.lut.push(0.0);
+        let mut below = 0;
+        for &n in &histogram[1..] {
+            below += n;
+            self.lut.push(255.0 * below as f32 / total);
+        }
+
+        self.values.resize(count_buffer.len(), 0.0);
+        let lut = &self.lut;
+        threadpool.scoped(|scope| {
+            for (values, counts) in self
+                .values
+                .chunks_mut(chunk_len)
+                .zip(count_buffer.chunks(chunk_len))
+            {
+                scope.execute(move |_| {
+                    for (value, &count) in values.iter_mut().zip(counts) {
+                        *value = lut[bin(count)];
+                    }
+                });
+            }
+        });
+    }
+
+    /// The equalized counts, row by row, ready for `colorize`.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+}
+
+/// Renders the blurred density as smooth blobs: pixels fade in around
+/// `settings.threshold` and are tone-mapped like in `colorize` above it.
+///
+/// With `settings.outline`, the cells whose four corners do not lie on
+/// the same side of the threshold, the non-trivial marching-squares cases,
+/// are drawn inverted.
+pub fn metaballs(
+    threadpool: &Pool,
+    field: &BlurField,
+    pixel_buffer: &mut [u32],
+    tone: Tone,
+    settings: Metaballs,
+) {
+    let (width, height) = (field.width, field.height);
+    let w = width as usize;
+    if w == 0 {
+        return;
+    }
+    let rows_per_chunk = usize::max(height as usize / threadpool.thread_count() as usize / 10, 1);
+    let values = &field.values;
+
+    threadpool.scoped(|scope| {
+        for (i_chunk, pixels) in pixel_buffer.chunks_mut(w * rows_per_chunk).enumerate() {
+            scope.execute(move |_| {
+                let shader = Shader {
+                    width,
+                    height,
+                    tone,
+                    bits: 8,
+                };
+                let start = i_chunk * w * rows_per_chunk;
+                let field = &values[start..start + pixels.len()];
+                shader.metaballs_chunk(start, field, pixels, settings.threshold);
+                if settings.outline {
+                    let inside = |i: usize| values[i] * tone.brightness >= settings.threshold;
+                    for (i, pixel) in (start..).zip(pixels.iter_mut()) {
+                        let (x, y) = (i % w, i / w);
+                        if x + 1 == w || y + 1 == height as usize {
+                            continue;
+                        }
+                        let corners = [i, i + 1, i + w, i + w + 1].map(inside);
+                        if corners.contains(&true) && corners.contains(&false) {
+                            *pixel = !*pixel & 0xFFFFFF;
+                        }
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Tone-maps the blurred density like `colorize`, which softens the
+/// particles into a glow and hides the noise of sparse particles.
+pub fn glow(threadpool: &Pool, field: &BlurField, pixel_buffer: &mut [u32], tone: Tone) {
+    let (width, height) = (field.width, field.height);
+    let w = width as usize;
+    if w == 0 {
+        return;
+    }
+    let rows_per_chunk = usize::max(height as usize / threadpool.thread_count() as usize / 10, 1);
+    let values = &field.values;
+
+    threadpool.scoped(|scope| {
+        for (i_chunk, pixels) in pixel_buffer.chunks_mut(w * rows_per_chunk).enumerate() {
+            scope.execute(move |_| {
+                let shader = Shader {
+                    width,
+                    height,
+                    tone,
+                    bits: 8,
+                };
+                let start = i_chunk * w * rows_per_chunk;
+                shader.colorize_chunk(start, &values[start..start + pixels.len()], pixels);
+            });
+        }
+    });
+}
+
+/// Colors every pixel by the average velocity in `motion`: the direction
+/// picks the hue, starting at red for particles moving right, and the
+/// speed the brightness.
+pub fn direction_hue(
+    threadpool: &Pool,
+    motion: &MotionField,
+    pixel_buffer: &mut [u32],
+    settings: DirectionHue,
+) {
+    let chunk_len = usize::max(
+        pixel_buffer.len() / threadpool.thread_count() as usize / 10,
+        1,
+    );
+    let max_speed = settings.max_speed.max(f32::MIN_POSITIVE);
+    threadpool.scoped(|scope| {
+        for (i_chunk, pixels) in pixel_buffer.chunks_mut(chunk_len).enumerate() {
+            scope.execute(move |_| {
+                for (index, pixel) in (i_chunk * chunk_len..).zip(pixels) {
+                    let (dx, dy) = motion.velocity(index);
+                    let hue = dy.atan2(dx) / std::f32::consts::TAU;
+                    let value = (dx.hypot(dy) / max_speed).min(1.0);
+                    *pixel = hsv(hue - hue.floor(), value);
+                }
+            });
+        }
+    });
+}
+
+/// Fully saturated 0x00RRGGBB color of `hue` and `value`, both in `0..=1`.
+fn hsv(hue: f32, value: f32) -> u32 {
+    let channel = |n: f32| {
+        let k = (n + hue * 6.0) % 6.0;
+        let amount = (k.min(4.0 - k)).clamp(0.0, 1.0);
+        ((value - value * amount) * 255.0).round() as u32
+    };
+    (channel(5.0) << 16) | (channel(3.0) << 8) | channel(1.0)
+}
+
+#[derive(Clone, Copy)]
+struct Shader {
+    width: u32,
+    height: u32,
+    tone: Tone,
+    /// Bits per channel of the pixels, 8 or 10, see `colorize_10bit`.
+    bits: u32,
+}
+
+impl Shader {
+    /// Colors the pixels starting at index `start`, `LANES` at a time.
+    fn colorize_chunk<T: Count>(self, start: usize, counts: &[T], pixels: &mut [u32]) {
+        let mut pixel_chunks = pixels.chunks_exact_mut(LANES);
+        let mut count_chunks = counts.chunks_exact(LANES);
+        let mut index = start;
+        for (pixels, counts) in (&mut pixel_chunks).zip(&mut count_chunks) {
+            let (x, y) = self.coords::<LANES>(index);
+            let colors = self.colorize(Simd::from_slice(counts), x, y);
+            colors.copy_to_slice(pixels);
+            index += LANES;
+        }
+        let pixels = pixel_chunks.into_remainder();
+        for (pixel, &count) in pixels.iter_mut().zip(count_chunks.remainder()) {
+            let (x, y) = self.coords::<1>(index);
+            *pixel = self.colorize(Simd::splat(count), x, y)[0];
+            index += 1;
+        }
+    }
+
+    /// Shades the blurred `field` starting at index `start` as metaballs.
+    fn metaballs_chunk(self, start: usize, field: &[f32], pixels: &mut [u32], threshold: f32) {
+        let mut pixel_chunks = pixels.chunks_exact_mut(LANES);
+        let mut field_chunks = field.chunks_exact(LANES);
+        let mut index = start;
+        for (pixels, field) in (&mut pixel_chunks).zip(&mut field_chunks) {
+            let (x, y) = self.coords::<LANES>(index);
+            let colors = self.metaball(Simd::from_slice(field), x, y, threshold);
+            colors.copy_to_slice(pixels);
+            index += LANES;
+        }
+        let pixels = pixel_chunks.into_remainder();
+        for (pixel, &value) in pixels.iter_mut().zip(field_chunks.remainder()) {
+            let (x, y) = self.coords::<1>(index);
+            *pixel = self.metaball(Simd::splat(value), x, y, threshold)[0];
+            index += 1;
+        }
+    }
+
+    /// Smoothly steps the brightness-scaled `value` from zero below 3/4 of
+    /// `threshold` to itself above 5/4 of it.
+    #[inline(always)]
+    fn metaball<const N: usize>(
+        self,
+        value: Simd<f32, N>,
+        x: Simd<u32, N>,
+        y: Simd<u32, N>,
+        threshold: f32,
+    ) -> Simd<u32, N> {
+        let value = value * Simd::splat(self.tone.brightness);
+        let t = ((value - Simd::splat(0.75 * threshold)) / Simd::splat(0.5 * threshold))
+            .simd_clamp(Simd::splat(0.0), Simd::splat(1.0));
+        let step = t * t * (Simd::splat(3.0) - Simd::splat(2.0) * t);
+        self.shade(step * value, x, y)
+    }
+
+    /// Pixel coordinates of the `N` pixels starting at `index`.
+    #[inline(always)]
+    fn coords<const N: usize>(self, index: usize) -> (Simd<u32, N>, Simd<u32, N>) {
+        let width = self.width as usize;
+        let iota = Simd::from_array(std::array::from_fn(|i| i as u32));
+        if width < N {
+            let index = Simd::splat(index as u32) + iota;
+            let width = Simd::splat(self.width);
+            return (index % width, index / width);
+        }
+        // The lanes span at most two rows.
+        let x = Simd::splat((index % width) as u32) + iota;
+        let y = Simd::splat((index / width) as u32);
+        let wrapped = x.simd_ge(Simd::splat(self.width));
+        let one = Simd::splat(1);
+        (
+            wrapped.select(x - Simd::splat(self.width), x),
+            wrapped.select(y + one, y),
+        )
+    }
+
+    #[inline(always)]
+    fn colorize<T: Count, const N: usize>(
+        self,
+        counts: Simd<T, N>,
+        x: Simd<u32, N>,
+        y: Simd<u32, N>,
+    ) -> Simd<u32, N> {
+        self.shade(T::to_f32(counts) * Simd::splat(self.tone.brightness), x, y)
+    }
+
+    /// Tone-maps `count`, already scaled by the brightness.
+    #[inline(always)]
+    fn shade<const N: usize>(
+        self,
+        count: Simd<f32, N>,
+        x: Simd<u32, N>,
+        y: Simd<u32, N>,
+    ) -> Simd<u32, N> {
+        let offset = if self.tone.dither {
+            bayer(x, y)
+        } else {
+            Simd::splat(0.0)
+        };
+        let mut x = x.cast::<f32>() / Simd::splat(self.width as f32);
+        let mut y = y.cast::<f32>() / Simd::splat(self.height as f32);
+        if self.tone.phase != 0.0 {
+            let phase = Simd::splat(self.tone.phase);
+            x = mirrored(x + phase);
+            y = mirrored(y + phase);
+        }
+        let weights = self.tone.palette.weights(x, y);
+        tone_map(count, weights, offset, self.tone.srgb, self.bits)
+    }
+}
+
+/// Threshold of the 8x8 Bayer matrix at pixel (`x`, `y`), evenly spread
+/// over `0..1`.
+#[inline(always)]
+fn bayer<const N: usize>(x: Simd<u32, N>, y: Simd<u32, N>) -> Simd<f32, N> {
+    let a = x ^ y;
+    let bit = |v: Simd<u32, N>, from: u32, to: u32| {
+        ((v >> Simd::splat(from)) & Simd::splat(1)) << Simd::splat(to)
+    };
+    let index =
+        bit(a, 0, 5) | bit(y, 0, 4) | bit(a, 1, 3) | bit(y, 1, 2) | bit(a, 2, 1) | bit(y, 2, 0);
+    (index.cast::<f32>() + Simd::splat(0.5)) / Simd::splat(64.0)
+}
+
+/// Triangle wave through `(0, 0)`, `(1, 1)` and `(2, 0)` with a period
+/// of 2.
+#[inline(always)]
+fn mirrored<const N: usize>(t: Simd<f32, N>) -> Simd<f32, N> {
+    let two = Simd::splat(2.0);
+    let t = t - (t / two).floor() * two;
+    Simd::splat(1.0) - (t - Simd::splat(1.0)).abs()
+}
+
+/// Maps `count`, already scaled by the brightness, to pixels of `bits`
+/// per channel tinted with the per-channel `weights`, 0x00RRGGBB for 8.
+/// Counts above 255 brighten all channels towards white. The channels are
+/// sRGB encoded if `srgb` is set and rounded down after adding `offset`.
+#[inline(always)]
+fn tone_map<const N: usize>(
+    count: Simd<f32, N>,
+    weights: [Simd<f32, N>; 3],
+    offset: Simd<f32, N>,
+    srgb: bool,
+    bits: u32,
+) -> Simd<u32, N> {
+    let count_upper = (count - Simd::splat(255.0)).simd_max(Simd::splat(0.0)) / Simd::splat(5.0);
+    let count = count.simd_min(Simd::splat(255.0));
+    let max = ((1 << bits) - 1) as f32;
+    let channel = |weight: Simd<f32, N>| {
+        let linear = weight * count + count_upper;
+        let encoded = if srgb {
+            let scale = Simd::splat(255.0);
+            srgb_encode(linear / scale) * scale
+        } else {
+            linear
+        };
+        let scaled = if bits == 8 {
+            encoded
+        } else {
+            encoded * Simd::splat(max) / Simd::splat(255.0)
+        };
+        // Clamping NaN gives NaN, which casts to zero.
+        (scaled + offset)
+            .simd_clamp(Simd::splat(0.0), Simd::splat(max))
+            .cast::<u32>()
+    };
+    let [wr, wg, wb] = weights;
+    let bits = Simd::splat(bits);
+    (channel(wr) << (bits + bits)) | (channel(wg) << bits) | channel(wb)
+}
+
+/// The sRGB transfer function, from linear light in `0..=1` to the encoded
+/// value in `0..=1`.
+#[inline(always)]
+pub fn srgb_encode<const N: usize>(linear: Simd<f32, N>) -> Simd<f32, N> {
+    let linear = linear.simd_clamp(Simd::splat(0.0), Simd::splat(1.0));
+    // `powf(1 / 2.4)`, which the SIMD types lack, as `exp2(log2(x) / 2.4)`.
+    let power = (linear.log2() / Simd::splat(2.4)).exp2();
+    // `1.055 * power - 0.055`, arranged to map one exactly to one.
+    let curve = power + Simd::splat(0.055) * (power - Simd::splat(1.0));
+    linear
+        .simd_le(Simd::splat(0.0031308))
+        .select(linear * Simd::splat(12.92), curve)
+}
+
+/// Tone-maps tagged counts from `Particles::count_tagged`: each tag is
+/// tinted with its entry of `colors` and the tints are mixed by count.
+pub fn colorize_tagged(
+    threadpool: &Pool,
+    count_buffer: &[u64],
+    pixel_buffer: &mut [u32],
+    brightness: f32,
+    colors: &[[f32; 3]; MAX_TAGS],
+) {
+    let chunk_len = usize::max(
+        pixel_buffer.len() / threadpool.thread_count() as usize / 10,
+        1,
+    );
+    threadpool.scoped(|scope| {
+        for (pixels, counts) in pixel_buffer
+            .chunks_mut(chunk_len)
+            .zip(count_buffer.chunks(chunk_len))
+        {
+            scope.execute(move |_| {
+                let mut pixel_chunks = pixels.chunks_exact_mut(LANES);
+                let mut count_chunks = counts.chunks_exact(LANES);
+                for (pixels, counts) in (&mut pixel_chunks).zip(&mut count_chunks) {
+                    let colors = tagged(Simd::<_, LANES>::from_slice(counts), brightness, colors);
+                    colors.copy_to_slice(pixels);
+                }
+                let pixels = pixel_chunks.into_remainder();
+                for (pixel, &count) in pixels.iter_mut().zip(count_chunks.remainder()) {
+                    *pixel = tagged(Simd::<_, 1>::splat(count), brightness, colors)[0];
+                }
+            });
+        }
+    });
+}
+
+#[inline(always)]
+fn tagged<const N: usize>(
+    counts: Simd<u64, N>,
+    brightness: f32,
+    colors: &[[f32; 3]; MAX_TAGS],
+) -> Simd<u32, N> {
+    let mut total = Simd::splat(0.0);
+    let mut weights = [Simd::splat(0.0); 3];
+    for (tag, color) in colors.iter().enumerate() {
+        let count = ((counts >> Simd::splat(16 * tag as u64)) & Simd::splat(0xFFFF)).cast::<f32>();
+        total += count;
+        for (weight, channel) in weights.iter_mut().zip(color) {
+            *weight += count * Simd::splat(*channel);
+        }
+    }
+    let weights = weights.map(|weight| weight / total.simd_max(Simd::splat(1.0)));
+    tone_map(
+        total * Simd::splat(brightness),
+        weights,
+        Simd::splat(0.0),
+        false,
+        8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The per-pixel formula the vectorized shader has to reproduce.
+    fn colorize_scalar(shader: Shader, index: usize, count: u16) -> u32 {
+        let count = count as f32 * shader.tone.brightness;
+        let count_upper = (count - 255.0).max(0.0) / 5.0;
+        let count = count.min(255.0);
+
+        let width = shader.width as usize;
+        let x = (index % width) as f32 / shader.width as f32;
+        let y = (index / width) as f32 / shader.height as f32;
+        let [wr, wg, wb] = shader
+            .tone
+            .palette
+            .weights(Simd::<f32, 1>::splat(x), Simd::splat(y))
+            .map(|w| w[0]);
+        let red = (wr * count + count_upper) as u8;
+        let green = (wg * count + count_upper) as u8;
+        let blue = (wb * count + count_upper) as u8;
+        ((red as u32) << 16) + ((green as u32) << 8) + (blue as u32)
+    }
+
+    #[test]
+    fn colors_every_pixel() {
+        let pool = Pool::new(2);
+        // 721 pixels do not split evenly into chunks of 36.
+        let (width, height) = (103, 7);
+        let counts = vec![1_u16; width * height];
+        let mut pixels = vec![u32::MAX; width * height];
+        colorize(
+            &pool,
+            &counts,
+            &mut pixels,
+            width as u32,
+            height as u32,
+            Tone::new(10.0, Palette::Mono),
+        );
+        assert!(pixels.iter().all(|&pixel| pixel == 0x0A0A0A));
+    }
+
+    #[test]
+    fn crossfade_blends_channels() {
+        let pool = Pool::new(2);
+        let from = [0xFF0080, 0x000000, 0x123456];
+        let mut to = [0x00FF80, 0xFFFFFF, 0x123456];
+        crossfade(&pool, &from, &mut to, 0.5);
+        assert_eq!(to, [0x7F7F80, 0x7F7F7F, 0x123456]);
+
+        let mut to = [0x00FF80, 0xFFFFFF, 0x123456];
+        crossfade(&pool, &from, &mut to, 0.0);
+        assert_eq!(to, from);
+        crossfade(&pool, &[0; 3], &mut to, 1.0);
+        assert_eq!(to, from);
+    }
+
+    #[test]
+    fn composites_layers() {
+        let pool = Pool::new(2);
+        let layer = [0x80FF00, 0x404040, 0x000000];
+        let mut pixels = [0x80FF10, 0x808080, 0x123456];
+        composite(&pool, &layer, &mut pixels, Blend::new(BlendMode::Add));
+        assert_eq!(pixels, [0xFFFF10, 0xC0C0C0, 0x123456]);
+
+        let mut pixels = [0x80FF10, 0x808080, 0x123456];
+        composite(&pool, &layer, &mut pixels, Blend::new(BlendMode::Screen));
+        assert_eq!(pixels, [0xC0FF10, 0xA0A0A0, 0x123456]);
+
+        // Half the opacity goes half the way.
+        let mut pixels = [0x80FF10, 0x808080, 0x123456];
+        let blend = Blend {
+            mode: BlendMode::Add,
+            opacity: 0.5,
+        };
+        composite(&pool, &layer, &mut pixels, blend);
+        assert_eq!(pixels, [0xBFFF10, 0xA0A0A0, 0x123456]);
+
+        // Over covers by the alpha, keyed layers are clear where black.
+        let overlay = [0xFF0000FF, 0x80FFFFFF, 0x00FFFFFF];
+        let mut pixels = [0x808080, 0x000000, 0x123456];
+        composite(&pool, &overlay, &mut pixels, Blend::new(BlendMode::Over));
+        assert_eq!(pixels, [0x0000FF, 0x7F7F7F, 0x123456]);
+        let mut keyed = layer;
+        key(&pool, &mut keyed);
+        assert_eq!(keyed, [0xFF80FF00, 0x40404040, 0x00000000]);
+        let mut pixels = [0x000000, 0x000000, 0x123456];
+        composite(&pool, &keyed, &mut pixels, Blend::new(BlendMode::Over));
+        assert_eq!(pixels, [0x80FF00, 0x101010, 0x123456]);
+    }
+
+    #[test]
+    fn saturated_counts_match_below_255() {
+        let pool = Pool::new(2);
+        let (width, height) = (61, 5);
+        let wide = (0..width * height).map(|i| i as u16).collect::<Vec<_>>();
+        let narrow = wide.iter().map(|&c| c.min(255) as u8).collect::<Vec<_>>();
+        let mut wide_pixels = vec![0; wide.len()];
+        let mut narrow_pixels = vec![0; wide.len()];
+        colorize(
+            &pool,
+            &wide,
+            &mut wide_pixels,
+            width,
+            height,
+            Tone::new(1.0, Palette::Fire),
+        );
+        colorize(
+            &pool,
+            &narrow,
+            &mut narrow_pixels,
+            width,
+            height,
+            Tone::new(1.0, Palette::Fire),
+        );
+        assert_eq!(wide_pixels[..256], narrow_pixels[..256]);
+    }
+
+    #[test]
+    fn blur_averages_box() {
+        let pool = Pool::new(2);
+        let (width, height) = (9, 7);
+        let mut counts = vec![0_u16; 9 * 7];
+        counts[3 * 9 + 4] = 9;
+        let mut field = BlurField::default();
+        field.blur(&pool, &counts, width, height, 1);
+        for (i, &value) in field.values().iter().enumerate() {
+            let (x, y) = (i % 9, i / 9);
+            let inside = x.abs_diff(4) <= 1 && y.abs_diff(3) <= 1;
+            assert_eq!(value, if inside { 1.0 } else { 0.0 }, "pixel ({x}, {y})");
+        }
+
+        counts.fill(4);
+        field.blur(&pool, &counts, width, height, 2);
+        assert_eq!(field.values()[3 * 9 + 4], 4.0);
+        assert!(field.values()[0] < 4.0);
+    }
+
+    #[test]
+    fn glow_spreads_counts() {
+        let pool = Pool::new(2);
+        let (width, height) = (21, 21);
+        let mut counts = vec![0_u16; 21 * 21];
+        counts[10 * 21 + 10] = 1000;
+        let mut field = BlurField::default();
+        field.blur_passes(&pool, &counts, width, height, 2, 3);
+        let values = field.values();
+        let total = values.iter().sum::<f32>();
+        assert!((total - 1000.0).abs() < 0.1);
+        // Falls off smoothly from the center, unlike a single box.
+        let row = &values[10 * 21..11 * 21];
+        assert!(row[10..17].windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(row[14] < row[12] / 2.0 && row[16] > 0.0);
+
+        let mut pixels = vec![0; counts.len()];
+        glow(&pool, &field, &mut pixels, Tone::new(1.0, Palette::Mono));
+        assert!(pixels[10 * 21 + 10] & 0xFF > 0);
+        assert!(pixels[10 * 21 + 14] & 0xFF > 0);
+        assert_eq!(pixels[0], 0);
+    }
+
+    #[test]
+    fn direction_picks_hue() {
+        let pool = Pool::new(2);
+        let mut motion = MotionField::default();
+        motion.clear(4, 1);
+        motion.add(0, 8.0, 0.0);
+        motion.add(1, 0.0, 8.0);
+        motion.add(2, -2.0, 0.0);
+        let mut pixels = vec![0x123456; 4];
+        direction_hue(&pool, &motion, &mut pixels, DirectionHue::default());
+        // Right is red and down, a quarter turn further, yellow-green.
+        assert_eq!(pixels[0], 0xFF0000);
+        assert_eq!(pixels[1] >> 16, 0x80);
+        assert_eq!(pixels[1] & 0xFFFF, 0xFF00);
+        // Half the speed at half brightness, left is cyan.
+        assert_eq!(pixels[2], 0x008080);
+        assert_eq!(pixels[3], 0);
+    }
+
+    #[test]
+    fn equalizer_spreads_counts() {
+        let pool = Pool::new(2);
+        // Mostly sparse pixels and a few dense ones.
+        let mut counts = vec![0_u16; 100];
+        counts[..60].fill(1);
+        counts[60..90].fill(2);
+        counts[90..99].fill(500);
+        counts[99] = 60000;
+        let mut equalizer = Equalizer::default();
+        equalizer.equalize(&pool, &counts);
+        let values = equalizer.values();
+        assert_eq!(values[0], 255.0 * 0.6);
+        assert_eq!(values[60], 255.0 * 0.9);
+        assert_eq!(values[90], 255.0 * 0.99);
+        assert_eq!(values[99], 255.0);
+
+        counts.fill(0);
+        equalizer.equalize(&pool, &counts);
+        assert!(equalizer.values().iter().all(|&value| value == 0.0));
+    }
+
+    #[test]
+    fn metaballs_threshold_blobs() {
+        let pool = Pool::new(2);
+        let (width, height) = (40, 30);
+        let mut counts = vec![0_u8; 40 * 30];
+        for y in 10..20 {
+            counts[y * 40 + 10..y * 40 + 20].fill(20);
+        }
+        let mut field = BlurField::default();
+        field.blur(&pool, &counts, width, height, 2);
+        let settings = Metaballs::default();
+        let mut pixels = vec![0; counts.len()];
+        let tone = Tone::new(10.0, Palette::Mono);
+        metaballs(&pool, &field, &mut pixels, tone, settings);
+        assert_eq!(pixels[15 * 40 + 15], 0xC8C8C8);
+        assert_eq!(pixels[15 * 40 + 30], 0);
+        assert_eq!(pixels[25 * 40 + 15], 0);
+
+        let settings = Metaballs {
+            outline: true,
+            ..settings
+        };
+        let mut outlined = vec![0; counts.len()];
+        metaballs(&pool, &field, &mut outlined, tone, settings);
+        let changed = (0..counts.len())
+            .filter(|&i| outlined[i] != pixels[i])
+            .collect::<Vec<_>>();
+        assert!(!changed.is_empty());
+        assert!(changed.iter().all(|&i| (8..22).contains(&(i % 40))));
+        assert_eq!(outlined[15 * 40 + 15], 0xC8C8C8);
+    }
+
+    #[test]
+    fn phase_cycles_palette() {
+        let pool = Pool::new(2);
+        let counts = vec![25_u16; 8];
+        let render = |phase| {
+            let mut pixels = vec![0; 8];
+            let tone = Tone {
+                phase,
+                ..Tone::new(10.0, Palette::Gradient)
+            };
+            colorize(&pool, &counts, &mut pixels, 8, 1, tone);
+            pixels
+        };
+        let still = render(0.0);
+        assert_eq!(render(2.0), still);
+        assert_ne!(render(0.25), still);
+    }
+
+    #[test]
+    fn dither_spreads_rounding() {
+        let pool = Pool::new(2);
+        // Half a step of every channel, which truncating rounds to black.
+        let counts = vec![1_u16; 64];
+        let render = |dither| {
+            let mut pixels = vec![0; 64];
+            let tone = Tone {
+                dither,
+                ..Tone::new(0.5, Palette::Mono)
+            };
+            colorize(&pool, &counts, &mut pixels, 8, 8, tone);
+            pixels
+        };
+        assert!(render(false).iter().all(|&pixel| pixel == 0));
+        let dithered = render(true);
+        let lit = dithered.iter().filter(|&&pixel| pixel == 0x010101).count();
+        assert_eq!(lit, 32);
+        assert_eq!(dithered.iter().filter(|&&pixel| pixel == 0).count(), 32);
+        // Neighbors alternate, so there are no large flat patches.
+        assert_ne!(dithered[0], dithered[1]);
+        assert_ne!(dithered[0], dithered[8]);
+    }
+
+    #[test]
+    fn srgb_lifts_midtones() {
+        let pool = Pool::new(2);
+        let counts = [0_u16, 1, 64, 128, 255, 1000];
+        let render = |srgb| {
+            let mut pixels = vec![0; counts.len()];
+            let tone = Tone {
+                srgb,
+                ..Tone::new(1.0, Palette::Mono)
+            };
+            colorize(&pool, &counts, &mut pixels, counts.len() as u32, 1, tone);
+            pixels.iter().map(|pixel| pixel & 0xFF).collect::<Vec<_>>()
+        };
+        assert_eq!(render(false), [0, 1, 64, 128, 255, 255]);
+        // Black and white stay, linear half gray is encoded as 187.
+        assert_eq!(render(true), [0, 12, 137, 187, 255, 255]);
+
+        let encoded = srgb_encode(Simd::from_array([0.0, 0.002, 0.5, 1.0, 2.0]));
+        let expected = [0.0, 0.02584, 0.73536, 1.0, 1.0];
+        for (encoded, expected) in encoded.to_array().into_iter().zip(expected) {
+            assert!((encoded - expected).abs() < 1e-4, "{encoded} {expected}");
+        }
+    }
+
+    #[test]
+    fn packs_ten_bits() {
+        let pool = Pool::new(2);
+        let counts = [0_u16, 1, 2, 3, 4, 1020, 2000];
+        let render = |colorize: fn(&Pool, &[u16], &mut [u32], u32, u32, Tone)| {
+            let mut pixels = vec![0; counts.len()];
+            let tone = Tone::new(0.25, Palette::Mono);
+            colorize(&pool, &counts, &mut pixels, counts.len() as u32, 1, tone);
+            pixels
+        };
+        let gray = |channel: u32| channel << 20 | channel << 10 | channel;
+        // Quarter steps, which 8 bits round away.
+        assert_eq!(
+            render(colorize::<u16>),
+            [0, 0, 0, 0, 0x010101, 0xFFFFFF, 0xFFFFFF]
+        );
+        assert_eq!(
+            render(colorize_10bit::<u16>),
+            [0, 1, 2, 3, 4, 1023, 1023].map(gray)
+        );
+
+        let base = render(colorize::<u16>);
+        let mut deep = render(colorize_10bit::<u16>);
+        let mut pixels = base.clone();
+        pixels[1] = 0x0000FF;
+        merge_10bit(&pool, &base, &pixels, &mut deep);
+        assert_eq!(deep[..3], [0, 0x3FF, gray(2)]);
+    }
+
+    #[test]
+    fn adapts_colors() {
+        let pool = Pool::new(2);
+        let frame = [0x000000, 0xFF0000, 0x0000FF, 0x101010, 0xFFFFFF];
+        let render = |colors, inverted| {
+            let mut pixels = frame;
+            accessibility(&pool, &mut pixels, Accessibility { colors, inverted });
+            pixels
+        };
+        assert_eq!(render(ColorMode::Full, false), frame);
+        assert_eq!(
+            render(ColorMode::Grayscale, false),
+            [0x000000, 0x4C4C4C, 0x1C1C1C, 0x101010, 0xFFFFFF]
+        );
+        let (on, off) = (HIGH_CONTRAST, 0);
+        assert_eq!(
+            render(ColorMode::HighContrast, false),
+            [off, on, on, off, on]
+        );
+        assert_eq!(
+            render(ColorMode::HighContrast, true),
+            [!off, !on, !on, !off, !on].map(|pixel| pixel & 0xFFFFFF)
+        );
+        assert_eq!(
+            render(ColorMode::Full, true),
+            [0xFFFFFF, 0x00FFFF, 0xFFFF00, 0xEFEFEF, 0x000000]
+        );
+        assert_eq!(ColorMode::HighContrast.next(), ColorMode::Full);
+    }
+
+    #[test]
+    fn background_gradient_saturates() {
+        let pool = Pool::new(2);
+        let background = Background {
+            top: [0, 0, 200],
+            bottom: [200, 0, 0],
+        };
+        let mut pixels = vec![0x0000FF, 0x000010, 0x100000, 0x000000];
+        add_background(&pool, &mut pixels, 2, 2, background);
+        assert_eq!(pixels, [0x0000FF, 0x0000D8, 0xD80000, 0xC80000]);
+    }
+
+    #[test]
+    fn tagged_counts_mix_colors() {
+        let pool = Pool::new(2);
+        let colors = [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0; 3], [0.0; 3]];
+        let counts = [0, 10, 10 << 16, 5 | (5 << 16), 100];
+        let mut pixels = [0; 5];
+        colorize_tagged(&pool, &counts, &mut pixels, 10.0, &colors);
+        assert_eq!(pixels, [0x000000, 0x640000, 0x000064, 0x320032, 0xFF9595]);
+    }
+
+    #[test]
+    fn matches_scalar_conversion() {
+        for (width, height) in [(37, 5), (7, 9), (16, 3)] {
+            for palette in Palette::ALL {
+                let shader = Shader {
+                    width,
+                    height,
+                    tone: Tone::new(10.0, palette),
+                    bits: 8,
+                };
+                let n = (width * height) as usize;
+                let counts = (0..n).map(|i| (i * 7 % 200) as u16).collect::<Vec<_>>();
+                let mut pixels = vec![0; n];
+                shader.colorize_chunk(0, &counts, &mut pixels);
+
+                for (i, (&pixel, &count)) in pixels.iter().zip(&counts).enumerate() {
+                    assert_eq!(pixel, colorize_scalar(shader, i, count), "pixel {i}");
+                }
+            }
+        }
+    }
+}
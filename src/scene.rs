@@ -0,0 +1,651 @@
+//! Scene files describing a complete starting configuration.
+//!
+//! Scenes are JSON documents, all fields are optional:
+//!
+//! ```json
+//! {
+//!     "seed": 42,
+//!     "max_particles": 2000000,
+//!     "emitters": [
+//!         { "pattern": "ring", "count": 500000 },
+//!         { "pattern": "center", "count": 100000, "x": 0.25, "y": 0.5 }
+//!     ],
+//!     "forces": {
+//!         "gravity": 1.0,
+//!         "friction": 0.988,
+//!         "time_scale": 1.0,
+//!         "attractors": [{ "x": 0.75, "y": 0.5, "strength": 0.5 }],
+//!         "sdf": { "push": 0.5, "flow": 0.2 },
+//!         "integrator": "verlet"
+//!     },
+//!     "obstacles": [
+//!         { "circle": { "x": 0.5, "y": 0.5, "radius": 0.1 } },
+//!         { "polygon": [[0.1, 0.9], [0.3, 0.9], [0.2, 0.7]] }
+//!     ],
+//!     "portals": [{
+//!         "from": { "x": 0.4, "y": 0.95, "width": 0.2, "height": 0.05 },
+//!         "to": { "x": 0.4, "y": 0.0, "width": 0.2, "height": 0.05 }
+//!     }],
+//!     "boundary": "bounce",
+//!     "render": {
+//!         "palette": "fire",
+//!         "brightness": 10.0,
+//!         "srgb": true,
+//!         "symmetry": { "radial": 6 },
+//!         "layers": { "trails": { "mode": "screen", "opacity": 0.6 } }
+//!     },
+//!     "layer": {
+//!         "blend": "screen",
+//!         "opacity": 0.8,
+//!         "scene": {
+//!             "emitters": [{ "pattern": "uniform", "count": 200000 }],
+//!             "forces": { "time_scale": 0.2, "attractors": [{ "x": 0.5, "y": 0.5, "strength": 0.2 }] },
+//!             "render": { "palette": "ice", "brightness": 4.0 }
+//!         }
+//!     },
+//!     "timeline": {
+//!         "keyframes": [
+//!             { "time": 0, "gravity": 1.0 },
+//!             { "time": 30, "gravity": -0.5, "palette": "ice", "easing": "ease_out" }
+//!         ]
+//!     }
+//! }
+//! ```
+//!
+//! Positions are normalized to the window, `(0, 0)` is the top left and
+//! `(1, 1)` the bottom right corner.
+//!
+//! The optional `layer` runs a second, independent simulation in the same
+//! window, e.g. a slow nebula behind fast interactive particles, and blends
+//! its pixels onto the ones of the scene. How the trails and the overlay
+//! are blended is set in the `layers` of the render settings, see
+//! `render::Layers` for the order of all layers.
+//!
+//! The optional `timeline` animates the gravity, time scale, spawn rate and
+//! palette over time with keyframes, see `timeline`.
+//!
+//! The boundary, forces and render settings alone form the `Parameters`
+//! of a simulation, which are copied and pasted as TOML to share them
+//! without any particles.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::field::{Currents, Flow, Heat, HeatField};
+use crate::obstacle::{Obstacles, Shape};
+use crate::palette::Palette;
+use crate::particles::{
+    Attractor, Boundary, Inflow, Integrator, Lanes, Lfo, MAX_TAGS, Particles, SpawnPattern,
+    Symmetry,
+};
+use crate::portal::{Portal, Portals};
+use crate::render::{Accessibility, Background, BlendMode, DirectionHue, Glow, Layers, Metaballs};
+use crate::sdf::{DistanceField, Image, SdfForce, Source};
+use crate::timeline::Timeline;
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    /// Seed making the spawned particles reproducible.
+    pub seed: Option<u64>,
+    /// Upper bound on the particle count, see `Particles::reserve_budget`.
+    pub max_particles: Option<usize>,
+    pub emitters: Vec<Emitter>,
+    pub forces: Forces,
+    /// Circles and polygons the particles bounce off.
+    pub obstacles: Vec<Shape>,
+    /// Pairs of rectangles teleporting particles between each other.
+    pub portals: Vec<Portal>,
+    /// Currents carrying the particles, e.g. `{ "eddies": 3, "speed": 2.0 }`,
+    /// instead of a still field for painting.
+    pub currents: Option<Currents>,
+    pub boundary: Boundary,
+    pub render: RenderSettings,
+    /// Image the distance field of `Forces::sdf` is generated from instead
+    /// of the obstacles, see `--sdf-image`.
+    #[serde(skip)]
+    pub sdf_image: Option<Image>,
+    /// A second simulation rendered into the same window.
+    pub layer: Option<Box<Layer>>,
+    /// Keyframes animating the parameters after the scene was applied.
+    pub timeline: Option<Timeline>,
+}
+
+/// An independent simulation blended onto the particles of a scene, see
+/// `Scene::layer`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layer {
+    /// How its pixels combine with the ones of the scene.
+    pub blend: BlendMode,
+    /// From `0`, hidden, to `1`, see `render::Blend`.
+    pub opacity: f32,
+    /// Whether the mouse attracts its particles as well.
+    pub interactive: bool,
+    /// Its emitters, forces, obstacles, portals, boundary, palette and
+    /// brightness. The layer of this scene is ignored.
+    pub scene: Scene,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Layer {
+            blend: BlendMode::default(),
+            opacity: 1.0,
+            interactive: false,
+            scene: Scene::default(),
+        }
+    }
+}
+
+/// The part of a scene that does not depend on the particles, shared as
+/// TOML through the clipboard.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Parameters {
+    pub boundary: Boundary,
+    pub forces: Forces,
+    pub render: RenderSettings,
+}
+
+impl Parameters {
+    pub fn from_toml(text: &str) -> io::Result<Self> {
+        toml::from_str(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("parameters always serialize")
+    }
+}
+
+/// A batch of particles spawned when the scene is applied.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Emitter {
+    pub pattern: SpawnPattern,
+    /// Number of particles.
+    pub count: usize,
+    /// Normalized center of the center and ring patterns, and position of
+    /// the line pattern.
+    pub x: f32,
+    pub y: f32,
+    /// Particles per second spawned continuously after the initial `count`.
+    pub rate: f32,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Emitter {
+            pattern: SpawnPattern::default(),
+            count: 64_000,
+            x: 0.5,
+            y: 0.5,
+            rate: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Forces {
+    pub gravity: f32,
+    pub friction: f32,
+    /// Vertical friction, `friction` if unset.
+    pub friction_y: Option<f32>,
+    /// Velocity of the air in pixels per frame, e.g. `[4.0, 0.0]`, see
+    /// `Particles::wind`.
+    pub wind: Option<(f32, f32)>,
+    /// Variation of the friction between particles, see
+    /// `Particles::friction_spread`.
+    pub friction_spread: f32,
+    pub time_scale: f32,
+    /// Attractors at normalized positions, in addition to the mouse.
+    pub attractors: Vec<Attractor>,
+    /// Pulses the mouse attractor, e.g. `{ "shape": "square", "frequency": 2.0 }`.
+    pub lfo: Option<Lfo>,
+    /// Strength of the Brownian jitter, see `Particles::temperature`.
+    pub temperature: f32,
+    /// Steers particles around the obstacles, e.g. `{ "push": 0.5, "flow": 0.2 }`.
+    pub sdf: Option<SdfForce>,
+    /// `"semi_implicit_euler"` or `"verlet"` for stable orbits.
+    pub integrator: Integrator,
+    /// Convection, e.g. `{ "heater": 4.0, "buoyancy": 0.002 }`.
+    pub heat: Option<Heat>,
+    /// Evolution of painted currents, e.g. `{ "viscosity": 0.05, "vorticity": 0.1 }`.
+    pub flow: Flow,
+}
+
+impl Default for Forces {
+    fn default() -> Self {
+        Forces {
+            gravity: 1.0,
+            friction: 0.988,
+            friction_y: None,
+            wind: None,
+            friction_spread: 0.0,
+            time_scale: 1.0,
+            attractors: Vec::new(),
+            lfo: None,
+            temperature: 0.0,
+            sdf: None,
+            integrator: Integrator::default(),
+            heat: None,
+            flow: Flow::default(),
+        }
+    }
+}
+
+impl Forces {
+    /// The forces currently acting on `particles` in a `width` x `height`
+    /// window.
+    pub fn of(particles: &Particles, width: u32, height: u32) -> Self {
+        Forces {
+            gravity: particles.gravity,
+            friction: particles.friction,
+            friction_y: particles.friction_y,
+            wind: particles.wind,
+            friction_spread: particles.friction_spread,
+            time_scale: particles.time_scale,
+            attractors: particles
+                .attractors
+                .iter()
+                .map(|a| Attractor {
+                    x: a.x / width as f32,
+                    y: a.y / height as f32,
+                    ..*a
+                })
+                .collect(),
+            lfo: particles.gravity_lfo,
+            temperature: particles.temperature,
+            sdf: particles.distance_field.as_ref().map(|field| field.force),
+            integrator: particles.integrator,
+            heat: particles.heat_field.as_ref().map(|field| field.heat),
+            flow: particles.flow,
+        }
+    }
+
+    /// Sets the forces of `particles` in a `width` x `height` window. An
+    /// existing distance field keeps its shapes, a new one is generated
+    /// from the obstacles.
+    pub fn apply(&self, particles: &mut Particles, width: u32, height: u32) {
+        particles.friction_spread = self.friction_spread;
+        particles.gravity = self.gravity;
+        particles.friction = self.friction;
+        particles.friction_y = self.friction_y;
+        particles.wind = self.wind;
+        particles.time_scale = self.time_scale;
+        particles.gravity_lfo = self.lfo;
+        particles.temperature = self.temperature;
+        particles.integrator = self.integrator;
+        particles.flow = self.flow;
+        particles.heat_field = self.heat.map(|heat| HeatField::new(width, height, heat));
+        particles.distance_field = match (self.sdf, particles.distance_field.take()) {
+            (Some(force), Some(mut field)) => {
+                field.force = force;
+                Some(field)
+            }
+            (Some(force), None) => {
+                let shapes = match &particles.obstacles {
+                    Some(obstacles) => obstacles.shapes().to_vec(),
+                    None => Vec::new(),
+                };
+                Some(DistanceField::new(
+                    Source::Shapes(shapes),
+                    force,
+                    width,
+                    height,
+                ))
+            }
+            (None, _) => None,
+        };
+        particles.attractors = self
+            .attractors
+            .iter()
+            .map(|a| Attractor {
+                x: a.x * width as f32,
+                y: a.y * height as f32,
+                ..*a
+            })
+            .collect();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub palette: Palette,
+    pub brightness: f32,
+    /// `"mirror"`, `"quad"`, `{ "radial": 6 }` or `{ "kaleidoscope": 6 }`.
+    pub symmetry: Symmetry,
+    /// Renders blobs instead of dots, e.g. `{ "radius": 4, "outline": true }`.
+    pub metaballs: Option<Metaballs>,
+    /// Blurs the density into a soft glow, e.g. `{ "radius": 2, "passes": 3 }`.
+    pub glow: Option<Glow>,
+    /// Colors by the flow direction instead of the density, e.g.
+    /// `{ "max_speed": 4 }`.
+    pub direction_hue: Option<DirectionHue>,
+    /// e.g. `{ "top": [0, 0, 40], "bottom": [30, 0, 20] }`, black by default.
+    pub background: Background,
+    /// Palette cycles per second, see `Tone::phase`; `0` keeps it still.
+    pub cycle: f32,
+    /// See `Tone::dither`.
+    pub dither: bool,
+    /// Encodes the pixels as sRGB, see `Tone::srgb`.
+    pub srgb: bool,
+    /// Equalizes the density histogram instead of applying the brightness.
+    pub equalize: bool,
+    /// Blending of the trails and the overlay, e.g.
+    /// `{ "trails": { "mode": "screen", "opacity": 0.5 } }`.
+    pub layers: Layers,
+    /// Grayscale, high-contrast and inverted modes, e.g.
+    /// `{ "colors": "grayscale", "inverted": true }`.
+    pub accessibility: Accessibility,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            palette: Palette::default(),
+            brightness: 10.0,
+            symmetry: Symmetry::None,
+            metaballs: None,
+            glow: None,
+            direction_hue: None,
+            background: Background::default(),
+            cycle: 0.0,
+            dither: false,
+            srgb: false,
+            equalize: false,
+            layers: Layers::default(),
+            accessibility: Accessibility::default(),
+        }
+    }
+}
+
+impl Scene {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        serde_json::from_str(json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("scenes always serialize")
+    }
+
+    /// Replaces the particles with the ones of the scene's emitters and sets
+    /// its forces, obstacles, portals and symmetry, for a `width` x `height`
+    /// window. Boundary, palette, brightness and the layer are left to the
+    /// caller.
+    ///
+    /// The particles of every emitter are tagged with its index, wrapping
+    /// around after `MAX_TAGS` emitters.
+    pub fn apply(&self, particles: &mut Particles, width: u32, height: u32) {
+        if let Some(seed) = self.seed {
+            particles.seed(seed);
+        }
+        particles.particles.clear();
+        particles.clear_queue();
+        if let Some(max_particles) = self.max_particles {
+            particles.reserve_budget(Some(max_particles.div_ceil(64)));
+        }
+        self.apply_forces(particles, width, height);
+        for (i, emitter) in self.emitters.iter().enumerate() {
+            let center = (emitter.x * width as f32, emitter.y * height as f32);
+            let n = emitter.count.div_ceil(64);
+            let blocks = particles.particles.len() + n;
+            particles.spawn_tag = (i % MAX_TAGS) as u8;
+            particles.spawn_at(n, emitter.pattern, center, width, height);
+            if particles.particles.len() == blocks {
+                particles.fill_last_block(emitter.count % 64);
+            }
+        }
+        particles.spawn_tag = 0;
+        // Moves the inactive lanes of all but the last emitter to the end.
+        if self.emitters.len() > 1 {
+            particles.retain(|_| Lanes::splat(true));
+        }
+    }
+
+    /// Re-applies the scene after `previous` was edited into it. The
+    /// particles are kept unless the emitters, the seed or the particle
+    /// budget changed.
+    pub fn reapply(&self, previous: &Scene, particles: &mut Particles, width: u32, height: u32) {
+        let spawning = |scene: &Scene| (scene.seed, scene.max_particles, scene.emitters.clone());
+        if spawning(self) == spawning(previous) {
+            self.apply_forces(particles, width, height);
+        } else {
+            self.apply(particles, width, height);
+        }
+    }
+
+    /// Sets everything but the particles, see `apply`. Emitters with a rate
+    /// become the inflows.
+    fn apply_forces(&self, particles: &mut Particles, width: u32, height: u32) {
+        particles.symmetry = self.render.symmetry;
+        particles.obstacles = (!self.obstacles.is_empty())
+            .then(|| Obstacles::new(self.obstacles.clone(), width, height));
+        particles.portals =
+            (!self.portals.is_empty()).then(|| Portals::new(self.portals.clone(), width, height));
+        particles.velocity_field = self.currents.map(|currents| currents.field(width, height));
+        particles.distance_field = self.forces.sdf.map(|force| {
+            let source = match &self.sdf_image {
+                Some(image) => Source::Image(image.clone()),
+                None => Source::Shapes(self.obstacles.clone()),
+            };
+            DistanceField::new(source, force, width, height)
+        });
+        self.forces.apply(particles, width, height);
+        particles.inflows = self
+            .emitters
+            .iter()
+            .enumerate()
+            .filter(|(_, emitter)| emitter.rate > 0.0)
+            .map(|(i, emitter)| {
+                let center = (emitter.x * width as f32, emitter.y * height as f32);
+                Inflow::new(emitter.pattern, center, emitter.rate, (i % MAX_TAGS) as u8)
+            })
+            .collect();
+    }
+}
+
+/// Reloads a scene file whenever it is modified.
+#[derive(Debug)]
+pub struct SceneWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl SceneWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let modified = modified(&path);
+        SceneWatcher { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The scene, if the file was modified since the last call or since
+    /// the watcher was created.
+    pub fn poll(&mut self) -> Option<io::Result<Scene>> {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Scene::load(&self.path))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoped_threadpool::Pool;
+    use crate::simd::Simd;
+
+    #[test]
+    fn parses_documented_example() {
+        let docs = include_str!("scene.rs")
+            .lines()
+            .skip_while(|line| !line.starts_with("//! ```json"))
+            .skip(1)
+            .take_while(|line| !line.starts_with("//! ```"))
+            .map(|line| line.trim_start_matches("//!"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let scene = Scene::from_json(&docs).unwrap();
+        assert_eq!(scene.emitters.len(), 2);
+        assert_eq!(scene.emitters[0].pattern, SpawnPattern::Ring);
+        assert_eq!(scene.obstacles.len(), 2);
+        assert_eq!(scene.portals.len(), 1);
+        assert_eq!(scene.boundary, Boundary::Bounce);
+        assert_eq!(scene.render.palette, Palette::Fire);
+        assert!(scene.render.srgb);
+        let layer = scene.layer.as_ref().unwrap();
+        assert_eq!(layer.blend, BlendMode::Screen);
+        assert_eq!(layer.opacity, 0.8);
+        assert_eq!(scene.render.layers.trails.opacity, 0.6);
+        assert_eq!(scene.render.layers.overlay, Layers::default().overlay);
+        assert!(!layer.interactive);
+        assert_eq!(layer.scene.forces.time_scale, 0.2);
+        assert_eq!(layer.scene.render.palette, Palette::Ice);
+        let timeline = scene.timeline.as_ref().unwrap();
+        assert_eq!(timeline.at(15.0).gravity, Some(-0.125));
+        assert_eq!(timeline.at(15.0).palette, Some(Palette::Ice));
+        assert_eq!(Scene::from_json(&scene.to_json()).unwrap(), scene);
+    }
+
+    #[test]
+    fn bundled_scenes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            Scene::load(&path).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(Scene::from_json(r#"{ "emiters": [] }"#).is_err());
+        assert_eq!(Scene::from_json("{}").unwrap(), Scene::default());
+    }
+
+    #[test]
+    fn applies_emitters_and_forces() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        particles.add_particles(10, 100, 100);
+        let scene = Scene::from_json(
+            r#"{
+                "emitters": [
+                    { "pattern": "center", "count": 100, "x": 0.2, "y": 0.1 },
+                    { "pattern": "center", "count": 30, "x": 0.5, "y": 0.5 }
+                ],
+                "forces": { "gravity": 2.0, "attractors": [{ "x": 0.5, "y": 1.0, "strength": 1.0 }] }
+            }"#,
+        )
+        .unwrap();
+        scene.apply(&mut particles, 200, 100);
+
+        assert_eq!(particles.len(), 130);
+        assert_eq!(particles.particles.len(), 3);
+        let positions = particles.iter_positions().collect::<Vec<_>>();
+        assert!(positions[..100].iter().all(|&p| p == (40.0, 10.0)));
+        assert!(positions[100..].iter().all(|&p| p == (100.0, 50.0)));
+        assert_eq!(particles.gravity, 2.0);
+        assert_eq!(particles.attractors[0].x, 100.0);
+        assert_eq!(particles.attractors[0].y, 100.0);
+    }
+
+    #[test]
+    fn reapplies_edited_scenes() {
+        let pool = Pool::new(2);
+        let mut particles = Particles::new(&pool);
+        let path = std::env::temp_dir().join(format!("scene-{}.json", std::process::id()));
+        let write = |json: &str, seconds: u64| {
+            fs::write(&path, json).unwrap();
+            let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        let emitters = r#""emitters": [{ "pattern": "center", "count": 64 }]"#;
+        write(&format!("{{ {emitters} }}"), 1);
+        let mut watcher = SceneWatcher::new(&path);
+        assert!(watcher.poll().is_none());
+        let scene = Scene::load(watcher.path()).unwrap();
+        scene.apply(&mut particles, 100, 100);
+        particles.particles[0].x += Simd::splat(1.0);
+
+        // Only the forces changed, the particles stay where they are.
+        write(
+            &format!(r#"{{ {emitters}, "forces": {{ "gravity": 3.0 }} }}"#),
+            2,
+        );
+        let edited = watcher.poll().unwrap().unwrap();
+        edited.reapply(&scene, &mut particles, 100, 100);
+        assert_eq!(particles.gravity, 3.0);
+        assert!(particles.iter_positions().all(|p| p == (51.0, 50.0)));
+        assert!(watcher.poll().is_none());
+
+        // New emitters respawn the particles.
+        let emitters = r#""emitters": [{ "pattern": "center", "count": 128 }]"#;
+        write(&format!("{{ {emitters} }}"), 3);
+        watcher
+            .poll()
+            .unwrap()
+            .unwrap()
+            .reapply(&edited, &mut particles, 100, 100);
+        assert_eq!(particles.len(), 128);
+        assert!(particles.iter_positions().all(|p| p == (50.0, 50.0)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn copies_parameters_as_toml() {
+        let pool = Pool::new(1);
+        let mut particles = Particles::new(&pool);
+        let forces = Forces {
+            gravity: 2.5,
+            wind: Some((4.0, 0.0)),
+            attractors: vec![Attractor {
+                x: 0.25,
+                y: 0.5,
+                strength: 0.5,
+            }],
+            lfo: Some(Lfo::default()),
+            sdf: Some(SdfForce::default()),
+            integrator: Integrator::Verlet,
+            heat: Some(Heat::default()),
+            ..Forces::default()
+        };
+        forces.apply(&mut particles, 200, 100);
+        let parameters = Parameters {
+            boundary: Boundary::Bounce,
+            forces: Forces::of(&particles, 200, 100),
+            render: RenderSettings {
+                symmetry: Symmetry::Radial(6),
+                ..RenderSettings::default()
+            },
+        };
+        assert_eq!(parameters.forces, forces);
+        let toml = parameters.to_toml();
+        assert_eq!(Parameters::from_toml(&toml).unwrap(), parameters);
+        assert!(Parameters::from_toml("gravity = 1.0").is_err());
+    }
+}
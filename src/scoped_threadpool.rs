@@ -2,12 +2,58 @@
 
 use std::marker::PhantomData;
 use std::mem;
-use std::sync::{mpmc, mpsc};
+use std::slice;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(nightly)]
+use std::sync::mpmc;
+use std::sync::{Arc, mpsc};
 use std::thread;
+use std::time::{Duration, Instant};
+
+/// Stand-in for the unstable `std::sync::mpmc` on stable compilers, with
+/// the workers taking turns on a shared `mpsc` receiver.
+#[cfg(not(nightly))]
+mod mpmc {
+    use std::sync::mpsc::{self, RecvError, TryRecvError};
+    use std::sync::{Arc, Mutex};
+
+    pub use std::sync::mpsc::Sender;
+
+    pub struct Receiver<T>(Arc<Mutex<mpsc::Receiver<T>>>);
+
+    impl<T> Clone for Receiver<T> {
+        fn clone(&self) -> Self {
+            Receiver(Arc::clone(&self.0))
+        }
+    }
+
+    impl<T> Receiver<T> {
+        pub fn recv(&self) -> Result<T, RecvError> {
+            self.0.lock().map_err(|_| RecvError)?.recv()
+        }
+
+        pub fn try_recv(&self) -> Result<T, TryRecvError> {
+            self.0
+                .lock()
+                .map_err(|_| TryRecvError::Disconnected)?
+                .try_recv()
+        }
+    }
+
+    pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+        let (sender, receiver) = mpsc::channel();
+        (sender, Receiver(Arc::new(Mutex::new(receiver))))
+    }
+}
 
 enum Message {
     NewJob(Thunk<'static>),
+    /// Sent along with every urgent job, so that a blocked worker checks
+    /// the urgent queue.
+    Wake,
     Join,
+    /// Ends the worker that receives it.
+    Shutdown,
 }
 
 trait FnBox {
@@ -24,9 +70,79 @@ type Thunk<'a> = Box<dyn FnBox + Send + 'a>;
 
 /// A threadpool that acts as a handle to a number
 /// of threads spawned at construction.
+///
+/// Where threads can't be spawned, e.g. on wasm without threads, the pool
+/// has no workers and runs every job right away on the calling thread.
 pub struct Pool {
     job_sender: mpmc::Sender<Message>,
+    /// Jobs that the workers take before any of `job_sender`.
+    urgent_sender: mpmc::Sender<Thunk<'static>>,
     threads: Vec<ThreadData>,
+    handles: Vec<thread::JoinHandle<()>>,
+    counters: Arc<Counters>,
+    created: Instant,
+    /// Nanoseconds after `created` at which the counters were reset.
+    counted_since: AtomicU64,
+}
+
+/// Utilization of the pool since its creation or the last
+/// `Pool::reset_stats`.
+#[derive(Debug, Clone, Default)]
+pub struct PoolStats {
+    /// Per worker, by id.
+    pub threads: Vec<ThreadStats>,
+    /// Most jobs queued at once.
+    pub max_queued: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThreadStats {
+    pub jobs: u64,
+    /// Time spent running jobs.
+    pub busy: Duration,
+    /// Time spent waiting for jobs or for the other workers to join.
+    pub idle: Duration,
+}
+
+impl PoolStats {
+    /// Fraction of the time the workers were busy.
+    pub fn utilization(&self) -> f32 {
+        let (busy, total) = self
+            .threads
+            .iter()
+            .fold((0.0, 0.0), |(busy, total), thread| {
+                let busy_secs = thread.busy.as_secs_f32();
+                (
+                    busy + busy_secs,
+                    total + busy_secs + thread.idle.as_secs_f32(),
+                )
+            });
+        if total > 0.0 { busy / total } else { 0.0 }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    jobs: Vec<AtomicU64>,
+    busy_nanos: Vec<AtomicU64>,
+    queued: AtomicUsize,
+    max_queued: AtomicUsize,
+}
+
+impl Counters {
+    fn queue(&self) {
+        let queued = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_queued.fetch_max(queued, Ordering::Relaxed);
+    }
+
+    fn run(&self, job: Thunk<'static>, id: usize) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        let start = Instant::now();
+        job.call_box(id);
+        self.jobs[id].fetch_add(1, Ordering::Relaxed);
+        let nanos = start.elapsed().as_nanos() as u64;
+        self.busy_nanos[id].fetch_add(nanos, Ordering::Relaxed);
+    }
 }
 
 struct ThreadData {
@@ -38,11 +154,26 @@ impl Pool {
     /// Construct a threadpool with the given number of threads.
     /// Minimum value is `1`.
     pub fn new(n: usize) -> Pool {
+        Pool::with_thread_start(n, |_| {})
+    }
+
+    /// Like `new`, but every thread calls `on_start` with its id before
+    /// taking jobs, e.g. to pin itself to a core.
+    pub fn with_thread_start(n: usize, on_start: impl Fn(usize) + Send + Sync + 'static) -> Pool {
         assert!(n >= 1);
 
+        let on_start = Arc::new(on_start);
+
         let (job_sender, job_receiver) = mpmc::channel();
+        let (urgent_sender, urgent_receiver) = mpmc::channel::<Thunk<'static>>();
 
         let mut threads = Vec::with_capacity(n);
+        let mut handles = Vec::with_capacity(n);
+        let counters = Arc::new(Counters {
+            jobs: (0..n).map(|_| AtomicU64::new(0)).collect(),
+            busy_nanos: (0..n).map(|_| AtomicU64::new(0)).collect(),
+            ..Counters::default()
+        });
 
         // spawn n threads, put them in waiting mode
         for id in 0..n {
@@ -50,16 +181,31 @@ impl Pool {
             let (thread_sync_tx, thread_sync_rx) = mpsc::sync_channel::<()>(0);
 
             let job_receiver = job_receiver.clone();
-
-            let _ = thread::spawn(move || {
+            let urgent_receiver = urgent_receiver.clone();
+            let on_start = Arc::clone(&on_start);
+            let counters = Arc::clone(&counters);
+
+            let spawned = thread::Builder::new().spawn(move || {
+                on_start(id);
+                let run_urgent = || {
+                    while let Ok(job) = urgent_receiver.try_recv() {
+                        counters.run(job, id);
+                    }
+                };
                 loop {
+                    run_urgent();
                     let message = job_receiver.recv();
 
                     match message {
                         Ok(Message::NewJob(job)) => {
-                            job.call_box(id);
+                            run_urgent();
+                            counters.run(job, id);
                         }
+                        Ok(Message::Wake) => {}
                         Ok(Message::Join) => {
+                            // Urgent jobs were queued before the join.
+                            run_urgent();
+
                             // Syncronize/Join with pool.
                             // This has to be a two step
                             // process to ensure that all threads
@@ -78,23 +224,33 @@ impl Pool {
                                 break;
                             }
                         }
-                        Err(..) => {
+                        Ok(Message::Shutdown) | Err(..) => {
                             // The pool was dropped.
                             break;
                         }
                     }
                 }
             });
+            let Ok(handle) = spawned else {
+                // Carry on with the workers spawned so far, or inline.
+                break;
+            };
 
             threads.push(ThreadData {
                 pool_sync_rx,
                 thread_sync_tx,
             });
+            handles.push(handle);
         }
 
         Pool {
             threads,
+            handles,
             job_sender,
+            urgent_sender,
+            counters,
+            created: Instant::now(),
+            counted_since: AtomicU64::new(0),
         }
     }
 
@@ -114,9 +270,77 @@ impl Pool {
         f(&scope)
     }
 
-    /// Returns the number of threads inside this pool.
+    /// Returns the number of threads inside this pool, 1 if it runs the
+    /// jobs inline.
     pub fn thread_count(&self) -> u32 {
-        self.threads.len() as u32
+        self.threads.len().max(1) as u32
+    }
+
+    fn is_inline(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    /// Utilization of the workers since the pool was created or the stats
+    /// were last reset.
+    pub fn stats(&self) -> PoolStats {
+        let since = Duration::from_nanos(self.counted_since.load(Ordering::Relaxed));
+        let elapsed = self.created.elapsed().saturating_sub(since);
+        let counters = &self.counters;
+        let threads = counters.jobs.iter().zip(&counters.busy_nanos);
+        PoolStats {
+            threads: threads
+                .map(|(jobs, busy)| {
+                    let busy = Duration::from_nanos(busy.load(Ordering::Relaxed));
+                    ThreadStats {
+                        jobs: jobs.load(Ordering::Relaxed),
+                        busy,
+                        idle: elapsed.saturating_sub(busy),
+                    }
+                })
+                .collect(),
+            max_queued: counters.max_queued.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stops the workers once they finished their jobs and waits for them
+    /// to exit, returning their final stats. Dropping the pool does the
+    /// same.
+    pub fn shutdown(mut self) -> PoolStats {
+        self.join_workers();
+        self.stats()
+    }
+
+    fn join_workers(&mut self) {
+        for _ in &self.handles {
+            // Fails only if every worker is gone already.
+            let _ = self.job_sender.send(Message::Shutdown);
+        }
+        // Releases the workers still waiting in a join after another one
+        // panicked.
+        self.threads.clear();
+        for handle in self.handles.drain(..) {
+            // A panic was already reported by the scope that ran the job.
+            let _ = handle.join();
+        }
+    }
+
+    /// Starts counting the stats from zero.
+    pub fn reset_stats(&self) {
+        let counters = &self.counters;
+        for (jobs, busy) in counters.jobs.iter().zip(&counters.busy_nanos) {
+            jobs.store(0, Ordering::Relaxed);
+            busy.store(0, Ordering::Relaxed);
+        }
+        let queued = counters.queued.load(Ordering::Relaxed);
+        counters.max_queued.store(queued, Ordering::Relaxed);
+        let since = self.created.elapsed().as_nanos() as u64;
+        self.counted_since.store(since, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.join_workers();
     }
 }
 
@@ -140,9 +364,83 @@ impl<'scope> Scope<'_, 'scope> {
         F: FnOnce(usize) + Send + 'scope,
     {
         let b = unsafe { mem::transmute::<Thunk<'scope>, Thunk<'static>>(Box::new(f)) };
+        self.pool.counters.queue();
+        if self.pool.is_inline() {
+            self.pool.counters.run(b, 0);
+            return;
+        }
         self.pool.job_sender.send(Message::NewJob(b)).unwrap();
     }
 
+    /// Like `execute`, but the job runs before all jobs queued with
+    /// `execute` that no worker has started yet, e.g. for work the next
+    /// present waits on.
+    pub fn execute_urgent<F>(&self, f: F)
+    where
+        F: FnOnce(usize) + Send + 'scope,
+    {
+        let b = unsafe { mem::transmute::<Thunk<'scope>, Thunk<'static>>(Box::new(f)) };
+        self.pool.counters.queue();
+        if self.pool.is_inline() {
+            self.pool.counters.run(b, 0);
+            return;
+        }
+        self.pool.urgent_sender.send(b).unwrap();
+        self.pool.job_sender.send(Message::Wake).unwrap();
+    }
+
+    /// Runs `f(index)` for every index in `0..n`, where every worker
+    /// builds its `f` once with `make(thread id)` and then takes the next
+    /// index until none are left. Unlike executing a job per index, this
+    /// allocates once per worker instead of once per index.
+    pub fn execute_batch<F, G>(&self, n: usize, make: F)
+    where
+        F: Fn(usize) -> G + Send + Sync + 'scope,
+        G: FnMut(usize),
+    {
+        let batch = Arc::new((AtomicUsize::new(0), make));
+        for _ in 0..(self.pool.thread_count() as usize).min(n) {
+            let batch = Arc::clone(&batch);
+            self.execute(move |id| {
+                let (next, make) = &*batch;
+                let mut f = make(id);
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= n {
+                        break;
+                    }
+                    f(index);
+                }
+            });
+        }
+    }
+
+    /// Like `execute_batch`, but over the chunks of `chunk_len` elements of
+    /// `items`, calling `f(chunk index, chunk)`. The last chunk may be
+    /// shorter.
+    pub fn execute_chunks<T, F, G>(&self, items: &'scope mut [T], chunk_len: usize, make: F)
+    where
+        T: Send,
+        F: Fn(usize) -> G + Send + Sync + 'scope,
+        G: FnMut(usize, &mut [T]),
+    {
+        let chunk_len = chunk_len.max(1);
+        let len = items.len();
+        let items = SendPtr(items.as_mut_ptr());
+        self.execute_batch(len.div_ceil(chunk_len), move |id| {
+            let mut f = make(id);
+            move |i_chunk| {
+                let start = i_chunk * chunk_len;
+                let end = usize::min(start + chunk_len, len);
+                // Every index is handed out once, so no two chunks overlap,
+                // and `items` is borrowed for the whole scope.
+                let chunk =
+                    unsafe { slice::from_raw_parts_mut(items.get().add(start), end - start) };
+                f(i_chunk, chunk);
+            }
+        });
+    }
+
     /// Blocks until all currently queued jobs have run to completion.
     pub fn join_all(&self) {
         for _ in 0..self.pool.threads.len() {
@@ -173,6 +471,27 @@ impl<'scope> Scope<'_, 'scope> {
     }
 }
 
+/// Pointer to the items of `Scope::execute_chunks`, shared by the workers.
+struct SendPtr<T>(*mut T);
+
+unsafe impl<T: Send> Send for SendPtr<T> {}
+unsafe impl<T: Send> Sync for SendPtr<T> {}
+
+impl<T> Clone for SendPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SendPtr<T> {}
+
+impl<T> SendPtr<T> {
+    // A method, so that closures capture the whole `SendPtr`.
+    fn get(&self) -> *mut T {
+        self.0
+    }
+}
+
 impl Drop for Scope<'_, '_> {
     fn drop(&mut self) {
         self.join_all();
@@ -181,6 +500,89 @@ impl Drop for Scope<'_, '_> {
 
 /////////////////////////////////////////////////////////////////////////////
 
+/// Bounds of `ChunkTuner::per_thread`.
+const MIN_CHUNKS_PER_THREAD: usize = 4;
+const MAX_CHUNKS_PER_THREAD: usize = 64;
+
+/// Number of chunks per thread a pass is split into, adapted to the
+/// measured chunk times.
+///
+/// Idle workers take the next queued chunk, so a pass ends at the latest
+/// one slow chunk after its fair share. Where the work is uneven, e.g.
+/// when some chunks count into contended pixels, the chunks are split
+/// further until the slowest one is short compared to a worker's share,
+/// and merged again once the work evens out.
+#[derive(Debug)]
+pub struct ChunkTuner {
+    per_thread: usize,
+    /// Nanoseconds spent on every chunk of the last pass.
+    times: Vec<AtomicU64>,
+}
+
+impl Default for ChunkTuner {
+    fn default() -> Self {
+        ChunkTuner {
+            per_thread: 10,
+            times: Vec::new(),
+        }
+    }
+}
+
+impl ChunkTuner {
+    pub fn per_thread(&self) -> usize {
+        self.per_thread
+    }
+
+    /// Clears the times for a pass of `n_chunks` chunks.
+    pub fn start(&mut self, n_chunks: usize) {
+        self.times.resize_with(n_chunks, AtomicU64::default);
+        for time in &mut self.times {
+            *time.get_mut() = 0;
+        }
+    }
+
+    /// Starts timing the `i_chunk`th chunk of the pass, until the returned
+    /// guard is dropped.
+    pub fn time(&self, i_chunk: usize) -> ChunkTimer<'_> {
+        ChunkTimer {
+            time: self.times.get(i_chunk),
+            start: Instant::now(),
+        }
+    }
+
+    /// Adapts the chunks per thread to the times of the pass on `threads`
+    /// workers.
+    pub fn adapt(&mut self, threads: u32) {
+        let times = self.times.iter_mut().map(|time| *time.get_mut());
+        let (total, max) = times.fold((0, 0), |(total, max), time| (total + time, max.max(time)));
+        if total == 0 {
+            return;
+        }
+        let share = total / threads.max(1) as u64;
+        if max * 4 > share {
+            self.per_thread = (self.per_thread * 2).min(MAX_CHUNKS_PER_THREAD);
+        } else if max * 16 < share {
+            self.per_thread = (self.per_thread / 2).max(MIN_CHUNKS_PER_THREAD);
+        }
+    }
+}
+
+/// Records the time of a chunk when dropped, see `ChunkTuner::time`.
+pub struct ChunkTimer<'a> {
+    time: Option<&'a AtomicU64>,
+    start: Instant,
+}
+
+impl Drop for ChunkTimer<'_> {
+    fn drop(&mut self) {
+        if let Some(time) = self.time {
+            time.store(self.start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
 #[cfg(test)]
 mod tests {
 
@@ -308,6 +710,124 @@ mod tests {
         assert_eq!(&values[..], &[0, 0, 0, 0, 0, 0, 0, 1]);
     }
 
+    #[test]
+    fn urgent_jobs_first() {
+        let pool = Pool::new(1);
+        let (gate_tx, gate_rx) = sync::mpsc::channel::<()>();
+        let (tx, rx) = sync::mpsc::channel();
+        pool.scoped(|scoped| {
+            // Keeps the only worker busy until all jobs are queued.
+            scoped.execute(move |_| gate_rx.recv().unwrap());
+            for i in 0..3 {
+                let tx = tx.clone();
+                scoped.execute(move |_| tx.send(i).unwrap());
+            }
+            let tx = tx.clone();
+            scoped.execute_urgent(move |_| tx.send(9).unwrap());
+            gate_tx.send(()).unwrap();
+        });
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [9, 0, 1, 2]);
+    }
+
+    #[test]
+    fn batch_jobs() {
+        let pool = Pool::new(3);
+        let built = sync::atomic::AtomicUsize::new(0);
+        let sum = sync::atomic::AtomicUsize::new(0);
+        let mut items = vec![0; 103];
+        pool.scoped(|scoped| {
+            scoped.execute_batch(100, |_| {
+                built.fetch_add(1, sync::atomic::Ordering::Relaxed);
+                |i| {
+                    sum.fetch_add(i, sync::atomic::Ordering::Relaxed);
+                }
+            });
+            scoped.execute_chunks(&mut items, 10, |_| {
+                |i_chunk, chunk: &mut [usize]| chunk.fill(i_chunk + 1)
+            });
+        });
+        assert_eq!(sum.into_inner(), 4950);
+        assert!(built.into_inner() <= 3);
+        assert!(
+            items
+                .iter()
+                .enumerate()
+                .all(|(i, &item)| item == i / 10 + 1)
+        );
+    }
+
+    #[test]
+    fn shutdown_joins_workers() {
+        let pool = Pool::new(3);
+        let (tx, rx) = sync::mpsc::channel();
+        pool.scoped(|scoped| {
+            for i in 0..3 {
+                let tx = tx.clone();
+                scoped.execute(move |_| tx.send(i).unwrap());
+            }
+        });
+        // Returns only once every worker has exited.
+        let stats = pool.shutdown();
+        assert_eq!(
+            stats.threads.iter().map(|thread| thread.jobs).sum::<u64>(),
+            3
+        );
+        assert_eq!(rx.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn counts_stats() {
+        let pool = Pool::new(2);
+        pool.scoped(|scoped| {
+            for _ in 0..6 {
+                scoped.execute(move |_| sleep_ms(10));
+            }
+        });
+        let stats = pool.stats();
+        assert_eq!(
+            stats.threads.iter().map(|thread| thread.jobs).sum::<u64>(),
+            6
+        );
+        assert!(stats.max_queued >= 1 && stats.max_queued <= 6);
+        let busy = stats
+            .threads
+            .iter()
+            .map(|thread| thread.busy)
+            .sum::<time::Duration>();
+        assert!(busy >= time::Duration::from_millis(60));
+        assert!(stats.utilization() > 0.0 && stats.utilization() <= 1.0);
+
+        pool.reset_stats();
+        let stats = pool.stats();
+        assert!(stats.threads.iter().all(|thread| thread.jobs == 0));
+        assert_eq!(stats.max_queued, 0);
+    }
+
+    #[test]
+    fn tunes_chunks() {
+        use super::ChunkTuner;
+        use std::sync::atomic::Ordering;
+
+        let mut tuner = ChunkTuner::default();
+        let mut pass = |times: &[u64]| {
+            tuner.start(times.len());
+            for (time, &nanos) in tuner.times.iter().zip(times) {
+                time.store(nanos, Ordering::Relaxed);
+            }
+            tuner.adapt(2);
+            tuner.per_thread()
+        };
+        // Even work keeps the default, one slow chunk splits them further.
+        assert_eq!(pass(&[100; 20]), 10);
+        let mut uneven = [100; 20];
+        uneven[3] = 400;
+        assert_eq!(pass(&uneven), 20);
+        // Many tiny chunks are merged, down to the minimum.
+        assert_eq!(pass(&[100; 80]), 10);
+        assert_eq!(pass(&[100; 200]), 5);
+        assert_eq!(pass(&[100; 400]), 4);
+    }
+
     #[test]
     fn safe_execute() {
         let pool = Pool::new(4);
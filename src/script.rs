@@ -0,0 +1,281 @@
+//! Custom forces written in Rhai.
+//!
+//! A script defines `fn force(x, y, mx, my, t, density)`, which returns the
+//! acceleration `[fx, fy]` in pixels per 60 Hz frame:
+//!
+//! ```rhai
+//! // swirl around the mouse, weaker where the particles are dense
+//! fn force(x, y, mx, my, t, density) {
+//!     let r = hypot(x - mx, y - my) + 0.05;
+//!     let s = 1.0 / (r * (1.0 + density));
+//!     [-(y - my) * s, (x - mx) * s]
+//! }
+//! ```
+//!
+//! The arguments are floats:
+//!
+//! - `x`, `y`: center of the cell, normalized to the window
+//! - `mx`, `my`: mouse position, normalized to the window
+//! - `t`: seconds since the script was loaded
+//! - `density`: mean particle count per pixel of the cell in the last frame
+//!
+//! The whole Rhai language and its standard library are available, e.g.
+//! `sin`, `atan`, `hypot` or `PI()`. A call runs at most `MAX_OPERATIONS`
+//! operations, so a runaway loop cannot hang the app.
+//!
+//! `ScriptForce` evaluates the script once per frame on a coarse grid,
+//! which the update samples like the other force fields.
+
+use crate::simd::Simd;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, FLOAT, Scope};
+
+use crate::field;
+use crate::output::Density;
+use crate::particles::Real;
+use crate::scoped_threadpool::Pool;
+
+type F32s = Simd<Real, 64>;
+
+/// Size of the cells in pixels.
+const CELL: f32 = 16.0;
+
+/// Most operations of one call of `force`.
+pub const MAX_OPERATIONS: u64 = 100_000;
+
+/// Name of the function the script has to define.
+const FORCE: &str = "force";
+
+/// Parameters of `FORCE`, in the order of the arguments.
+const PARAMETERS: [&str; 6] = ["x", "y", "mx", "my", "t", "density"];
+
+/// A compiled script, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Script {
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+}
+
+impl Script {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(source).map_err(|err| err.to_string())?;
+        let defines_force = ast
+            .iter_functions()
+            .any(|f| f.name == FORCE && f.params.len() == PARAMETERS.len());
+        if !defines_force {
+            return Err(format!(
+                "the script defines no `fn {FORCE}({})`",
+                PARAMETERS.join(", ")
+            ));
+        }
+        Ok(Script {
+            engine: Arc::new(engine),
+            ast: Arc::new(ast),
+        })
+    }
+
+    /// Calls `force` with the arguments in the order of `PARAMETERS` and
+    /// returns (`fx`, `fy`). `scope` is scratch space.
+    fn run(
+        &self,
+        inputs: [f32; PARAMETERS.len()],
+        scope: &mut Scope,
+    ) -> Result<(f32, f32), String> {
+        let [x, y, mx, my, t, density] = inputs.map(FLOAT::from);
+        let result = self
+            .engine
+            .call_fn_with_options::<Array>(
+                CallFnOptions::new().eval_ast(false),
+                scope,
+                &self.ast,
+                FORCE,
+                (x, y, mx, my, t, density),
+            )
+            .map_err(|err| err.to_string())?;
+        let component = |value: &Dynamic| {
+            let value = value
+                .as_float()
+                .or_else(|_| value.as_int().map(|value| value as FLOAT))
+                .ok()? as f32;
+            // Keeps a single division by zero from breaking every particle.
+            Some(if value.is_finite() { value } else { 0.0 })
+        };
+        match result.as_slice() {
+            [fx, fy] => component(fx).zip(component(fy)),
+            _ => None,
+        }
+        .ok_or_else(|| format!("`{FORCE}` has to return two numbers `[fx, fy]`"))
+    }
+}
+
+/// A `Script` evaluated on a grid over the window, see the module
+/// documentation.
+#[derive(Debug, Clone)]
+pub struct ScriptForce {
+    script: Script,
+    size: (u32, u32),
+    cols: usize,
+    rows: usize,
+    /// Seconds since the force was created, advanced by `evaluate`.
+    time: f32,
+    density: Vec<f32>,
+    fx: Vec<f32>,
+    fy: Vec<f32>,
+    /// Last error reported by `evaluate`, to report every error once.
+    error: Option<String>,
+}
+
+impl ScriptForce {
+    /// The force of `script` in a `width` x `height` window.
+    pub fn new(script: Script, width: u32, height: u32) -> Self {
+        let mut force = ScriptForce {
+            script,
+            size: (0, 0),
+            cols: 0,
+            rows: 0,
+            time: 0.0,
+            density: Vec::new(),
+            fx: Vec::new(),
+            fy: Vec::new(),
+            error: None,
+        };
+        force.resize(width, height);
+        force
+    }
+
+    /// Resizes the grid to a `width` x `height` window, clearing it.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.size = (width, height);
+        self.cols = (width as f32 / CELL).ceil().max(1.0) as usize;
+        self.rows = (height as f32 / CELL).ceil().max(1.0) as usize;
+        let len = self.cols * self.rows;
+        for grid in [&mut self.density, &mut self.fx, &mut self.fy] {
+            grid.clear();
+            grid.resize(len, 0.0);
+        }
+    }
+
+    /// Sets the density input from the counts of the last frame.
+    pub fn set_density(&mut self, density: Density, width: u32, height: u32) {
+        if (width, height) != self.size || density.len() != (width * height) as usize {
+            return;
+        }
+        self.density.fill(0.0);
+        let cols = self.cols;
+        for (i, count) in density.iter().enumerate() {
+            let (x, y) = (i % width as usize, i / width as usize);
+            let cell = (y as f32 / CELL) as usize * cols + (x as f32 / CELL) as usize;
+            self.density[cell] += count as f32;
+        }
+        let scale = 1.0 / (CELL * CELL);
+        self.density
+            .iter_mut()
+            .for_each(|density| *density *= scale);
+    }
+
+    /// Advances the time by `seconds` and evaluates the script on every
+    /// cell, for the mouse at `mouse` in pixels. Cells the script fails on
+    /// get no force, and the error is printed once.
+    pub fn evaluate(&mut self, threadpool: &Pool, seconds: f32, mouse: (f32, f32)) {
+        self.time += seconds;
+        let (w, h) = (self.size.0.max(1) as f32, self.size.1.max(1) as f32);
+        let cols = self.cols;
+        let rows_per_chunk = usize::max(self.rows / threadpool.thread_count() as usize / 4, 1);
+        let (script, density, time) = (&self.script, &self.density, self.time);
+        let error = &Mutex::new(None);
+        threadpool.scoped(|scope| {
+            let chunks = self
+                .fx
+                .chunks_mut(cols * rows_per_chunk)
+                .zip(self.fy.chunks_mut(cols * rows_per_chunk));
+            for (i_chunk, (fx, fy)) in chunks.enumerate() {
+                scope.execute(move |_| {
+                    let mut rhai_scope = Scope::new();
+                    let start = i_chunk * cols * rows_per_chunk;
+                    for (i, (fx, fy)) in (start..).zip(fx.iter_mut().zip(fy)) {
+                        let x = ((i % cols) as f32 + 0.5) * CELL / w;
+                        let y = ((i / cols) as f32 + 0.5) * CELL / h;
+                        let inputs = [x, y, mouse.0 / w, mouse.1 / h, time, density[i]];
+                        (*fx, *fy) = match script.run(inputs, &mut rhai_scope) {
+                            Ok(force) => force,
+                            Err(err) => {
+                                error.lock().unwrap().get_or_insert(err);
+                                (0.0, 0.0)
+                            }
+                        };
+                    }
+                });
+            }
+        });
+        let error = error.lock().unwrap().take();
+        if let Some(err) = &error
+            && self.error.as_ref() != Some(err)
+        {
+            eprintln!("script: {err}");
+        }
+        self.error = error;
+    }
+
+    /// Acceleration at every lane of (`x`, `y`) per 60 Hz frame.
+    #[inline(always)]
+    pub fn sample(&self, x: F32s, y: F32s) -> (F32s, F32s) {
+        let [fx, fy] = field::sample(CELL, self.cols, self.rows, [&self.fx, &self.fy], x, y);
+        (fx, fy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_scripts() {
+        let script = Script::parse(
+            "// comment\n fn force(x, y, mx, my, t, density) { let r = 2.0 ** 3; [x + r % 3, density + t - (my - 0.5)] }",
+        )
+        .unwrap();
+        let mut scope = Scope::new();
+        let (fx, fy) = script
+            .run([0.25, 0.0, 0.0, 1.0, 2.0, 3.0], &mut scope)
+            .unwrap();
+        assert_eq!(fx, 2.25);
+        assert_eq!(fy, 4.5);
+        assert_eq!(script.run([0.0; 6], &mut scope).unwrap().1, 0.5);
+
+        assert!(Script::parse("fn other(x) { [x, x] }").is_err());
+        assert!(Script::parse("fn force(x, y) { [x, y] }").is_err());
+        assert!(Script::parse("fn force(x, y, mx, my, t, density) { [x, y]").is_err());
+        let script = Script::parse("fn force(x, y, mx, my, t, density) { x }").unwrap();
+        assert!(script.run([0.0; 6], &mut scope).is_err());
+        let script = Script::parse("fn force(x, y, mx, my, t, density) { loop {} }").unwrap();
+        assert!(script.run([0.0; 6], &mut scope).is_err());
+
+        let pool = Pool::new(2);
+        let script =
+            Script::parse("fn force(x, y, mx, my, t, density) { [x - mx, 1 / (y - 0.5)] }")
+                .unwrap();
+        let mut force = ScriptForce::new(script, 64, 32);
+        force.evaluate(&pool, 0.5, (32.0, 0.0));
+        let (ax, ay) = force.sample(F32s::splat(8.0), F32s::splat(8.0));
+        assert_eq!(ax[0], 0.125 - 0.5);
+        assert_eq!(ay[0], -4.0);
+        let (ax, _) = force.sample(F32s::splat(56.0), F32s::splat(24.0));
+        assert_eq!(ax[0], 0.375);
+        assert_eq!(force.error, None);
+
+        let counts = vec![2_u8; 64 * 32];
+        force.set_density(Density::U8(&counts), 64, 32);
+        assert!(force.density.iter().all(|&density| density == 2.0));
+    }
+}
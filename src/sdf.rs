@@ -0,0 +1,380 @@
+//! Signed distance fields steering particles around shapes.
+//!
+//! `DistanceField` stores the distance from every cell to the nearest edge
+//! of a set of shapes, negative inside of them, along with its normalized
+//! gradient. Particles close to the shapes are pushed along the gradient,
+//! away from them, and along the tangent, so they flow around the
+//! outline. The shapes are either obstacles or the bright pixels of a
+//! grayscale image stretched over the window.
+
+use crate::simd::cmp::SimdPartialOrd;
+use crate::simd::num::SimdFloat;
+use crate::simd::{Simd, StdFloat};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::field;
+use crate::obstacle::{Obstacles, Shape};
+use crate::particles::{Lanes, Real, splat};
+use crate::watermark::Logo;
+
+type F32s = Simd<Real, 64>;
+
+/// Size of the cells in pixels.
+const CELL: f32 = 8.0;
+
+/// Strength and reach of the force of a `DistanceField`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SdfForce {
+    /// Acceleration away from the shapes, negative to pull particles onto
+    /// the outlines.
+    pub push: f32,
+    /// Acceleration along the outlines, clockwise for positive values.
+    pub flow: f32,
+    /// Distance in pixels over which the force fades out.
+    pub range: f32,
+}
+
+impl Default for SdfForce {
+    fn default() -> Self {
+        SdfForce {
+            push: 0.5,
+            flow: 0.0,
+            range: 48.0,
+        }
+    }
+}
+
+/// A grayscale image whose pixels brighter than half of the maximum are
+/// inside.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Reads a portable graymap (`.pgm`), or a `.png`, see `from_logo`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        {
+            return Ok(Self::from_logo(&Logo::load(path)?));
+        }
+        Self::from_pgm(&fs::read(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The brightness of the pixels of `logo`, weighted by their alpha.
+    pub fn from_logo(logo: &Logo) -> Self {
+        let pixels = logo
+            .pixels
+            .iter()
+            .map(|&pixel| {
+                let [b, g, r, a] = pixel.to_le_bytes().map(u32::from);
+                ((r * 77 + g * 150 + b * 29) * a / (256 * 255)) as u8
+            })
+            .collect();
+        Image {
+            width: logo.width as usize,
+            height: logo.height as usize,
+            pixels,
+        }
+    }
+
+    /// Parses a binary (`P5`) or plain (`P2`) portable graymap.
+    pub fn from_pgm(bytes: &[u8]) -> Result<Self, String> {
+        let mut rest = bytes;
+        let mut header = [0; 4];
+        for (i, field) in header.iter_mut().enumerate() {
+            loop {
+                rest = rest.trim_ascii_start();
+                if rest.first() != Some(&b'#') {
+                    break;
+                }
+                let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+                rest = &rest[end..];
+            }
+            let end = rest
+                .iter()
+                .position(u8::is_ascii_whitespace)
+                .unwrap_or(rest.len());
+            let token = std::str::from_utf8(&rest[..end]).unwrap_or_default();
+            *field = match (i, token) {
+                (0, "P5") => 5,
+                (0, "P2") => 2,
+                (0, _) => return Err("not a P2 or P5 graymap".to_string()),
+                _ => token
+                    .parse()
+                    .map_err(|_| format!("invalid graymap header `{token}`"))?,
+            };
+            rest = &rest[end..];
+        }
+        let [format, width, height, max] = header;
+        if max == 0 || max > 255 {
+            return Err("only graymaps with up to 8 bits are supported".to_string());
+        }
+        let len = width * height;
+        let pixels = if format == 5 {
+            // A single whitespace byte separates the header from the pixels.
+            rest.get(1..len + 1).ok_or("truncated graymap")?.to_vec()
+        } else {
+            std::str::from_utf8(rest)
+                .map_err(|_| "invalid plain graymap")?
+                .split_ascii_whitespace()
+                .take(len)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid pixel `{value}`"))
+                })
+                .collect::<Result<Vec<u8>, _>>()?
+        };
+        if pixels.len() != len {
+            return Err("truncated graymap".to_string());
+        }
+        let pixels = pixels
+            .into_iter()
+            .map(|value| (value as usize * 255 / max) as u8)
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Lanes of the normalized positions (`u`, `v`) on bright pixels.
+    fn contains(&self, u: F32s, v: F32s) -> Lanes {
+        let col = (u * splat(self.width as f32))
+            .simd_clamp(F32s::splat(0.0), splat(self.width.saturating_sub(1) as f32));
+        let row = (v * splat(self.height as f32)).simd_clamp(
+            F32s::splat(0.0),
+            splat(self.height.saturating_sub(1) as f32),
+        );
+        let index = (row.floor() * splat(self.width as f32) + col.floor()).cast::<usize>();
+        let value = Simd::<u8, 64>::gather_or_default(&self.pixels, index);
+        value.simd_gt(Simd::splat(127)).cast()
+    }
+}
+
+/// The shapes a `DistanceField` is generated from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// Obstacle shapes in normalized window coordinates.
+    Shapes(Vec<Shape>),
+    /// An image stretched over the window.
+    Image(Image),
+}
+
+/// Distance to the shapes of a `Source` at window resolution, see the
+/// module documentation.
+#[derive(Debug, Clone)]
+pub struct DistanceField {
+    pub force: SdfForce,
+    source: Source,
+    size: (u32, u32),
+    cols: usize,
+    rows: usize,
+    /// Signed distance in pixels.
+    distance: Vec<f32>,
+    /// Normalized gradient of `distance`, pointing away from the shapes.
+    gx: Vec<f32>,
+    gy: Vec<f32>,
+}
+
+impl DistanceField {
+    /// The field of `source` in a `width` x `height` window.
+    pub fn new(source: Source, force: SdfForce, width: u32, height: u32) -> Self {
+        let mut field = DistanceField {
+            force,
+            source,
+            size: (0, 0),
+            cols: 0,
+            rows: 0,
+            distance: Vec::new(),
+            gx: Vec::new(),
+            gy: Vec::new(),
+        };
+        field.resize(width, height);
+        field
+    }
+
+    /// Regenerates the field for a `width` x `height` window.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == self.size {
+            return;
+        }
+        self.size = (width, height);
+        let (w, h) = (width as f32, height as f32);
+        let (cols, rows) = (
+            (w / CELL).ceil().max(1.0) as usize,
+            (h / CELL).ceil().max(1.0) as usize,
+        );
+        (self.cols, self.rows) = (cols, rows);
+
+        let contains: Box<dyn Fn(F32s, F32s) -> Lanes> = match &self.source {
+            Source::Shapes(shapes) => {
+                let obstacles = Obstacles::new(shapes.clone(), width, height);
+                Box::new(move |x, y| obstacles.contains(x, y))
+            }
+            Source::Image(image) => {
+                Box::new(move |x, y| image.contains(x / splat(w), y / splat(h)))
+            }
+        };
+        let lane = F32s::from_array(std::array::from_fn(|i| i as Real));
+        let mut inside = vec![false; cols * rows];
+        for (i_block, block) in inside.chunks_mut(F32s::LEN).enumerate() {
+            let index = splat((i_block * F32s::LEN) as f32) + lane;
+            let row = (index / splat(cols as f32)).floor();
+            let col = index - row * splat(cols as f32);
+            let x = (col + splat(0.5)) * splat(CELL);
+            let y = (row + splat(0.5)) * splat(CELL);
+            let mask = contains(x, y);
+            block.copy_from_slice(&mask.to_array()[..block.len()]);
+        }
+
+        // Distances between cell centers, the edge lies half a cell closer.
+        let outside = chamfer(&inside, cols, rows, true);
+        let within = chamfer(&inside, cols, rows, false);
+        let cap = (cols + rows) as f32 * CELL;
+        self.distance = inside
+            .iter()
+            .zip(outside.iter().zip(&within))
+            .map(|(&inside, (outside, within))| {
+                let distance = if inside { 0.5 - within } else { outside - 0.5 };
+                (distance * CELL).clamp(-cap, cap)
+            })
+            .collect();
+
+        let at = |col: usize, row: usize| self.distance[row * cols + col];
+        (self.gx, self.gy) = (0..cols * rows)
+            .map(|i| {
+                let (col, row) = (i % cols, i / cols);
+                let gx = at((col + 1).min(cols - 1), row) - at(col.saturating_sub(1), row);
+                let gy = at(col, (row + 1).min(rows - 1)) - at(col, row.saturating_sub(1));
+                let len = gx.hypot(gy);
+                if len > 0.0 {
+                    (gx / len, gy / len)
+                } else {
+                    (0.0, 0.0)
+                }
+            })
+            .unzip();
+    }
+
+    /// Acceleration at every lane of (`x`, `y`) per 60 Hz frame.
+    #[inline(always)]
+    pub fn sample(&self, x: F32s, y: F32s) -> (F32s, F32s) {
+        let [distance, gx, gy] = field::sample(
+            CELL,
+            self.cols,
+            self.rows,
+            [&self.distance, &self.gx, &self.gy],
+            x,
+            y,
+        );
+        let falloff = (splat(1.0) - distance / splat(self.force.range.max(1.0)))
+            .simd_clamp(F32s::splat(0.0), F32s::splat(1.0));
+        let (push, flow) = (
+            falloff * splat(self.force.push),
+            falloff * splat(self.force.flow),
+        );
+        (push * gx - flow * gy, push * gy + flow * gx)
+    }
+}
+
+/// Distance in cells from every cell to the nearest cell whose `inside`
+/// equals `target`, approximated with a two-pass chamfer transform.
+fn chamfer(inside: &[bool], cols: usize, rows: usize, target: bool) -> Vec<f32> {
+    const DIAGONAL: f32 = std::f32::consts::SQRT_2;
+    let mut distance = inside
+        .iter()
+        .map(|&inside| if inside == target { 0.0 } else { f32::INFINITY })
+        .collect::<Vec<_>>();
+    let forward = [
+        (-1, -1, DIAGONAL),
+        (0, -1, 1.0),
+        (1, -1, DIAGONAL),
+        (-1, 0, 1.0),
+    ];
+    let cells = (0..rows).flat_map(|row| (0..cols).map(move |col| (col, row)));
+    for (pass, cells) in [
+        (1, cells.clone().collect::<Vec<_>>()),
+        (-1, cells.rev().collect()),
+    ] {
+        for (col, row) in cells {
+            let mut best = distance[row * cols + col];
+            for (dc, dr, step) in forward {
+                let (c, r) = (col as isize + dc * pass, row as isize + dr * pass);
+                if (0..cols as isize).contains(&c) && (0..rows as isize).contains(&r) {
+                    best = best.min(distance[r as usize * cols + c as usize] + step);
+                }
+            }
+            distance[row * cols + col] = best;
+        }
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushes_away_from_shapes() {
+        let circle = Source::Shapes(vec![Shape::Circle {
+            x: 0.5,
+            y: 0.5,
+            radius: 0.2,
+        }]);
+        let force = SdfForce {
+            push: 1.0,
+            flow: 0.5,
+            range: 32.0,
+        };
+        let field = DistanceField::new(circle, force, 160, 160);
+        let distance = |x: f32, y: f32| {
+            let [d] = field::sample(
+                CELL,
+                field.cols,
+                field.rows,
+                [&field.distance],
+                splat(x),
+                splat(y),
+            );
+            d[0]
+        };
+        assert!(distance(80.0, 80.0) < -24.0);
+        assert!((distance(124.0, 80.0) - 12.0).abs() < 4.0);
+
+        // Right of the circle the push points right and the flow down.
+        let (ax, ay) = field.sample(F32s::splat(124.0), F32s::splat(80.0));
+        assert!(ax[0] > 0.3 && ay[0] > 0.1);
+        // Beyond the range the force vanishes.
+        let (ax, ay) = field.sample(F32s::splat(156.0), F32s::splat(80.0));
+        assert_eq!((ax[0], ay[0]), (0.0, 0.0));
+
+        let image = Image::from_pgm(b"P2\n# right half\n4 1\n1\n0 0 1 1\n").unwrap();
+        assert_eq!(
+            Image::from_pgm(b"P5 4 1 255 \x00\x00\xff\xff").unwrap(),
+            image
+        );
+        let logo = Logo {
+            width: 4,
+            height: 1,
+            pixels: vec![0xFF00_0000, 0x00FF_FFFF, 0xFFFF_FFFF, 0xFFFF_FFFF],
+        };
+        assert_eq!(Image::from_logo(&logo), image);
+        let field = DistanceField::new(Source::Image(image), force, 64, 8);
+        let (ax, _) = field.sample(F32s::splat(24.0), F32s::splat(4.0));
+        assert!(ax[0] < 0.0);
+    }
+}
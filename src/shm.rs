@@ -0,0 +1,285 @@
+//! Publishes the density field, or the pixels, into a named shared-memory
+//! segment.
+//!
+//! The segment is the file `/dev/shm/<name>` and starts with a 32 byte
+//! header, all fields little endian:
+//!
+//! | offset | type      | field                                          |
+//! |--------|-----------|------------------------------------------------|
+//! | 0      | `[u8; 4]` | magic, `PDEN` for densities, `PPIX` for pixels |
+//! | 4      | `u32`     | version, currently `1`                         |
+//! | 8      | `u32`     | width                                          |
+//! | 12     | `u32`     | height                                         |
+//! | 16     | `u64`     | frame id                                       |
+//! | 24     | `u32`     | sequence, odd while a frame is being written   |
+//! | 28     | `u32`     | pixel format, reserved for densities           |
+//!
+//! followed by `width * height` `u16` particle counts, or pixels, row by
+//! row. The pixel format is the index of the `--shm-format` it was written
+//! in: `0` for `xrgb8888`, `1` for `xbgr8888`, `2` for `rgba8`, `3` for
+//! `rgba16` and `4` for `xrgb2101010`, see `PixelFormat`.
+//!
+//! Readers should read the sequence, copy the data, and retry if the
+//! sequence was odd or changed in the meantime. The segment never shrinks,
+//! so an existing mapping stays valid, but it grows when a frame is larger
+//! than any before: readers have to remap when `width * height` no longer
+//! fits into their mapping. The segment may be longer than the frame.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering, fence};
+
+use memmap2::MmapMut;
+
+use crate::output::{Frame, FrameSink, PixelFormat};
+
+pub const MAGIC: [u8; 4] = *b"PDEN";
+pub const PIXELS_MAGIC: [u8; 4] = *b"PPIX";
+pub const VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 32;
+
+const SEQUENCE_OFFSET: usize = 24;
+const FORMAT_OFFSET: usize = 28;
+
+/// A segment with the header above, written under its sequence.
+struct Segment {
+    path: PathBuf,
+    file: File,
+    map: MmapMut,
+    magic: [u8; 4],
+    sequence: u32,
+}
+
+impl Segment {
+    /// Creates (or takes over) the segment `/dev/shm/<name>`.
+    fn create(name: &str, magic: [u8; 4]) -> io::Result<Self> {
+        let path = PathBuf::from("/dev/shm").join(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        let map = Self::map(&file, magic, 0)?;
+        Ok(Segment {
+            path,
+            file,
+            map,
+            magic,
+            sequence: 0,
+        })
+    }
+
+    /// Maps `file`, growing it to hold `len` bytes of data first.
+    fn map(file: &File, magic: [u8; 4], len: usize) -> io::Result<MmapMut> {
+        file.set_len((HEADER_LEN + len) as u64)?;
+        let mut map = unsafe { MmapMut::map_mut(file)? };
+        map[0..4].copy_from_slice(&magic);
+        map[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        Ok(map)
+    }
+
+    fn bump_sequence(&mut self, order: Ordering) {
+        self.sequence = self.sequence.wrapping_add(1);
+        let ptr = self.map[SEQUENCE_OFFSET..].as_mut_ptr().cast::<u32>();
+        // The mapping is page aligned, so the header fields are aligned.
+        unsafe { AtomicU32::from_ptr(ptr) }.store(self.sequence, order);
+    }
+
+    /// Writes the header of `frame` and `len` bytes of data with `write`,
+    /// with the sequence odd in between, growing the segment if needed.
+    fn write(&mut self, frame: &Frame, len: usize, write: impl FnOnce(&mut [u8])) {
+        self.bump_sequence(Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        if self.map.len() < HEADER_LEN + len {
+            match Self::map(&self.file, self.magic, len) {
+                Ok(map) => self.map = map,
+                Err(err) => {
+                    eprintln!("shm: failed to resize {}: {err}", self.path.display());
+                    // The previous frame is still intact.
+                    self.bump_sequence(Ordering::Release);
+                    return;
+                }
+            }
+        }
+
+        self.map[8..12].copy_from_slice(&frame.width.to_le_bytes());
+        self.map[12..16].copy_from_slice(&frame.height.to_le_bytes());
+        self.map[16..24].copy_from_slice(&frame.id.to_le_bytes());
+        write(&mut self.map[HEADER_LEN..HEADER_LEN + len]);
+
+        self.bump_sequence(Ordering::Release);
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// `FrameSink` writing the density of every frame into shared memory.
+pub struct SharedDensity {
+    segment: Segment,
+}
+
+impl SharedDensity {
+    /// Creates (or takes over) the segment `/dev/shm/<name>`.
+    pub fn create(name: &str) -> io::Result<Self> {
+        Ok(SharedDensity {
+            segment: Segment::create(name, MAGIC)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.segment.path
+    }
+}
+
+impl FrameSink for SharedDensity {
+    fn publish(&mut self, frame: &Frame) {
+        self.segment.write(frame, frame.density.len() * 2, |data| {
+            for (dst, count) in data.chunks_exact_mut(2).zip(frame.density.iter()) {
+                dst.copy_from_slice(&count.to_le_bytes());
+            }
+        });
+    }
+}
+
+/// `FrameSink` writing the pixels of every frame into shared memory,
+/// encoded in the format readers asked for with `--shm-format`.
+pub struct SharedPixels {
+    segment: Segment,
+    format: PixelFormat,
+    buffer: Vec<u8>,
+}
+
+impl SharedPixels {
+    /// Creates (or takes over) the segment `/dev/shm/<name>`.
+    pub fn create(name: &str, format: PixelFormat) -> io::Result<Self> {
+        let mut segment = Segment::create(name, PIXELS_MAGIC)?;
+        segment.map[FORMAT_OFFSET..HEADER_LEN].copy_from_slice(&(format as u32).to_le_bytes());
+        Ok(SharedPixels {
+            segment,
+            format,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.segment.path
+    }
+}
+
+impl FrameSink for SharedPixels {
+    fn publish(&mut self, frame: &Frame) {
+        self.buffer.clear();
+        match frame.pixels_10bit {
+            Some(pixels) if self.format == PixelFormat::Xrgb2101010 => self
+                .buffer
+                .extend(pixels.iter().flat_map(|pixel| pixel.to_ne_bytes())),
+            _ => self.format.encode(frame.pixels, &mut self.buffer),
+        }
+        let buffer = &self.buffer;
+        self.segment
+            .write(frame, buffer.len(), |data| data.copy_from_slice(buffer));
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.format
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU16;
+
+    use super::*;
+    use crate::output::Density;
+
+    #[test]
+    fn publishes_header_and_counts() {
+        let name = format!("particles-test-{}", std::process::id());
+        let mut shm = SharedDensity::create(&name).unwrap();
+        let density = [1, 2, 3, 4, 5, 6].map(AtomicU16::new);
+        shm.publish(&Frame {
+            id: 7,
+            width: 3,
+            height: 2,
+            pixels: &[0; 6],
+            density: Density::U16(&density),
+            motion: None,
+            pixels_10bit: None,
+        });
+
+        let bytes = std::fs::read(shm.path()).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + 12);
+        assert_eq!(bytes[0..4], MAGIC);
+        assert_eq!(bytes[8..12], 3u32.to_le_bytes());
+        assert_eq!(bytes[12..16], 2u32.to_le_bytes());
+        assert_eq!(bytes[16..24], 7u64.to_le_bytes());
+        assert_eq!(bytes[24..28], 2u32.to_le_bytes());
+        assert_eq!(bytes[HEADER_LEN..HEADER_LEN + 4], [1, 0, 2, 0]);
+
+        // Smaller frames keep the segment at its size.
+        shm.publish(&Frame {
+            id: 8,
+            width: 1,
+            height: 1,
+            pixels: &[0],
+            density: Density::U16(&density[5..]),
+            motion: None,
+            pixels_10bit: None,
+        });
+        let bytes = std::fs::read(shm.path()).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + 12);
+        assert_eq!(bytes[8..16], [1, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(bytes[24..28], 4u32.to_le_bytes());
+        assert_eq!(bytes[HEADER_LEN..HEADER_LEN + 4], [6, 0, 2, 0]);
+
+        let path = shm.path().to_path_buf();
+        drop(shm);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn publishes_encoded_pixels() {
+        let name = format!("particles-test-pixels-{}", std::process::id());
+        let mut shm = SharedPixels::create(&name, PixelFormat::Rgba8).unwrap();
+        let density = [0, 0].map(AtomicU16::new);
+        shm.publish(&Frame {
+            id: 1,
+            width: 2,
+            height: 1,
+            pixels: &[0x123456, 0xFF0001],
+            density: Density::U16(&density),
+            motion: None,
+            pixels_10bit: None,
+        });
+
+        let bytes = std::fs::read(shm.path()).unwrap();
+        assert_eq!(bytes[0..4], PIXELS_MAGIC);
+        assert_eq!(bytes[28..32], 2u32.to_le_bytes());
+        assert_eq!(
+            bytes[HEADER_LEN..],
+            [0x12, 0x34, 0x56, 0xFF, 0xFF, 0x00, 0x01, 0xFF]
+        );
+
+        let mut shm = SharedPixels::create(&name, PixelFormat::Xrgb2101010).unwrap();
+        assert_eq!(shm.pixel_format(), PixelFormat::Xrgb2101010);
+        shm.publish(&Frame {
+            id: 2,
+            width: 2,
+            height: 1,
+            pixels: &[0, 0],
+            density: Density::U16(&density),
+            motion: None,
+            pixels_10bit: Some(&[1, 0x3FF]),
+        });
+        let bytes = std::fs::read(shm.path()).unwrap();
+        assert_eq!(bytes[28..32], 4u32.to_le_bytes());
+        assert_eq!(bytes[HEADER_LEN..HEADER_LEN + 4], 1u32.to_ne_bytes());
+    }
+}
@@ -0,0 +1,1013 @@
+//! SIMD vectors of the particle lanes.
+//!
+//! On nightly compilers this is `std::simd`. Elsewhere, e.g. on stable, a
+//! scalar stand-in with the same API is used instead: the vectors are plain
+//! arrays and every operation loops over their elements, which the
+//! autovectorizer still turns into SIMD code in most passes. `build.rs`
+//! sets the `nightly` cfg.
+
+#[cfg(nightly)]
+pub use std::simd::*;
+
+#[cfg(not(nightly))]
+pub use scalar::*;
+
+#[cfg(not(nightly))]
+mod scalar {
+    use std::fmt;
+    use std::marker::PhantomData;
+    use std::ops::{
+        Add, AddAssign, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Div,
+        DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Not, Rem, RemAssign, Shl, ShlAssign, Shr,
+        ShrAssign, Sub, SubAssign,
+    };
+
+    /// Element type of a `Simd`.
+    pub trait SimdElement: Copy + Default + PartialEq + PartialOrd + Send + Sync + 'static {
+        /// Element of the masks that comparisons of these elements yield.
+        type Mask: MaskElement;
+    }
+
+    /// Element type of a `Mask`, the signed integer as wide as the
+    /// compared elements.
+    pub trait MaskElement: Copy + PartialEq + fmt::Debug + Send + Sync + 'static {}
+
+    /// Conversion of a `Simd` element with `as`, see `num::SimdFloat::cast`.
+    pub trait CastTo<U> {
+        fn cast(self) -> U;
+    }
+
+    /// Element-wise arithmetic, wrapping for integers like `std::simd`.
+    pub trait Arith: Copy {
+        fn add(self, rhs: Self) -> Self;
+        fn sub(self, rhs: Self) -> Self;
+        fn mul(self, rhs: Self) -> Self;
+        fn div(self, rhs: Self) -> Self;
+        fn rem(self, rhs: Self) -> Self;
+        fn neg(self) -> Self;
+    }
+
+    /// Element-wise bit operations of integers.
+    pub trait Bits: Copy {
+        fn and(self, rhs: Self) -> Self;
+        fn or(self, rhs: Self) -> Self;
+        fn xor(self, rhs: Self) -> Self;
+        fn not(self) -> Self;
+        fn shl(self, rhs: Self) -> Self;
+        fn shr(self, rhs: Self) -> Self;
+    }
+
+    macro_rules! mask_elements {
+        ($($t:ty),*) => {$(
+            impl MaskElement for $t {}
+        )*};
+    }
+    mask_elements!(i8, i16, i32, i64, isize);
+
+    macro_rules! elements {
+        ($($t:ty => $mask:ty),*) => {$(
+            impl SimdElement for $t {
+                type Mask = $mask;
+            }
+            cast_from!($t => f32, f64, u8, i8, u16, i16, u32, i32, u64, i64, usize, isize);
+        )*};
+    }
+
+    macro_rules! cast_from {
+        ($from:ty => $($to:ty),*) => {$(
+            impl CastTo<$to> for $from {
+                #[inline(always)]
+                fn cast(self) -> $to {
+                    self as $to
+                }
+            }
+        )*};
+    }
+
+    elements!(
+        f32 => i32, f64 => i64, u8 => i8, i8 => i8, u16 => i16, i16 => i16, u32 => i32,
+        i32 => i32, u64 => i64, i64 => i64, usize => isize, isize => isize
+    );
+
+    macro_rules! float_arith {
+        ($($t:ty),*) => {$(
+            impl Arith for $t {
+                #[inline(always)]
+                fn add(self, rhs: Self) -> Self { self + rhs }
+                #[inline(always)]
+                fn sub(self, rhs: Self) -> Self { self - rhs }
+                #[inline(always)]
+                fn mul(self, rhs: Self) -> Self { self * rhs }
+                #[inline(always)]
+                fn div(self, rhs: Self) -> Self { self / rhs }
+                #[inline(always)]
+                fn rem(self, rhs: Self) -> Self { self % rhs }
+                #[inline(always)]
+                fn neg(self) -> Self { -self }
+            }
+        )*};
+    }
+    float_arith!(f32, f64);
+
+    macro_rules! int_arith {
+        ($($t:ty),*) => {$(
+            impl Arith for $t {
+                #[inline(always)]
+                fn add(self, rhs: Self) -> Self { self.wrapping_add(rhs) }
+                #[inline(always)]
+                fn sub(self, rhs: Self) -> Self { self.wrapping_sub(rhs) }
+                #[inline(always)]
+                fn mul(self, rhs: Self) -> Self { self.wrapping_mul(rhs) }
+                #[inline(always)]
+                fn div(self, rhs: Self) -> Self { self / rhs }
+                #[inline(always)]
+                fn rem(self, rhs: Self) -> Self { self % rhs }
+                #[inline(always)]
+                fn neg(self) -> Self { self.wrapping_neg() }
+            }
+
+            impl Bits for $t {
+                #[inline(always)]
+                fn and(self, rhs: Self) -> Self { self & rhs }
+                #[inline(always)]
+                fn or(self, rhs: Self) -> Self { self | rhs }
+                #[inline(always)]
+                fn xor(self, rhs: Self) -> Self { self ^ rhs }
+                #[inline(always)]
+                fn not(self) -> Self { !self }
+                #[inline(always)]
+                fn shl(self, rhs: Self) -> Self { self.wrapping_shl(rhs as u32) }
+                #[inline(always)]
+                fn shr(self, rhs: Self) -> Self { self.wrapping_shr(rhs as u32) }
+            }
+        )*};
+    }
+    int_arith!(u8, i8, u16, i16, u32, i32, u64, i64, usize, isize);
+
+    /// A vector of `N` elements of type `T`.
+    #[derive(Clone, Copy, PartialEq)]
+    #[repr(transparent)]
+    pub struct Simd<T, const N: usize>([T; N]);
+
+    impl<T: SimdElement, const N: usize> Simd<T, N> {
+        pub const LEN: usize = N;
+
+        #[inline(always)]
+        pub fn splat(value: T) -> Self {
+            Simd([value; N])
+        }
+
+        #[inline(always)]
+        pub const fn from_array(array: [T; N]) -> Self {
+            Simd(array)
+        }
+
+        #[inline(always)]
+        pub const fn to_array(self) -> [T; N] {
+            self.0
+        }
+
+        #[inline(always)]
+        pub const fn as_array(&self) -> &[T; N] {
+            &self.0
+        }
+
+        #[inline(always)]
+        pub fn as_mut_array(&mut self) -> &mut [T; N] {
+            &mut self.0
+        }
+
+        /// The first `N` elements of `slice`, which must be long enough.
+        #[inline(always)]
+        pub fn from_slice(slice: &[T]) -> Self {
+            Simd(slice[..N].try_into().unwrap())
+        }
+
+        #[inline(always)]
+        pub fn copy_to_slice(self, slice: &mut [T]) {
+            slice[..N].copy_from_slice(&self.0);
+        }
+
+        /// Elements of `slice` at `idxs`, or the default where out of bounds.
+        #[inline(always)]
+        pub fn gather_or_default(slice: &[T], idxs: Simd<usize, N>) -> Self {
+            Self::gather_or(slice, idxs, Self::default())
+        }
+
+        /// Elements of `slice` at `idxs`, or those of `or` where out of
+        /// bounds.
+        #[inline(always)]
+        pub fn gather_or(slice: &[T], idxs: Simd<usize, N>, or: Self) -> Self {
+            Self::gather_select(slice, Mask::splat(true), idxs, or)
+        }
+
+        /// Elements of `slice` at `idxs`, or those of `or` where out of
+        /// bounds or not enabled.
+        #[inline(always)]
+        pub fn gather_select(
+            slice: &[T],
+            enable: Mask<isize, N>,
+            idxs: Simd<usize, N>,
+            or: Self,
+        ) -> Self {
+            let mut out = or;
+            for i in 0..N {
+                if enable.0[i]
+                    && let Some(&value) = slice.get(idxs.0[i])
+                {
+                    out.0[i] = value;
+                }
+            }
+            out
+        }
+
+        #[inline(always)]
+        fn map<U: SimdElement>(self, f: impl Fn(T) -> U) -> Simd<U, N> {
+            Simd(self.0.map(f))
+        }
+
+        #[inline(always)]
+        fn zip<U: SimdElement>(self, other: Self, f: impl Fn(T, T) -> U) -> Simd<U, N> {
+            Simd(std::array::from_fn(|i| f(self.0[i], other.0[i])))
+        }
+
+        #[inline(always)]
+        fn test(self, other: Self, f: impl Fn(T, T) -> bool) -> Mask<T::Mask, N> {
+            Mask(
+                std::array::from_fn(|i| f(self.0[i], other.0[i])),
+                PhantomData,
+            )
+        }
+
+        #[inline(always)]
+        fn fold(self, f: impl Fn(T, T) -> T) -> T {
+            self.0.into_iter().reduce(f).unwrap()
+        }
+    }
+
+    impl<T: SimdElement, const N: usize> Default for Simd<T, N> {
+        fn default() -> Self {
+            Self::splat(T::default())
+        }
+    }
+
+    impl<T: fmt::Debug, const N: usize> fmt::Debug for Simd<T, N> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl<T: SimdElement, const N: usize> From<[T; N]> for Simd<T, N> {
+        fn from(array: [T; N]) -> Self {
+            Simd(array)
+        }
+    }
+
+    impl<T: SimdElement, const N: usize> From<Simd<T, N>> for [T; N] {
+        fn from(vector: Simd<T, N>) -> Self {
+            vector.0
+        }
+    }
+
+    impl<T, const N: usize> Index<usize> for Simd<T, N> {
+        type Output = T;
+
+        fn index(&self, index: usize) -> &T {
+            &self.0[index]
+        }
+    }
+
+    impl<T, const N: usize> IndexMut<usize> for Simd<T, N> {
+        fn index_mut(&mut self, index: usize) -> &mut T {
+            &mut self.0[index]
+        }
+    }
+
+    macro_rules! binary_ops {
+        ($bound:ident: $($op:ident $method:ident $assign:ident $assign_method:ident $f:ident),*) => {$(
+            impl<T: SimdElement + $bound, const N: usize> $op for Simd<T, N> {
+                type Output = Self;
+
+                #[inline(always)]
+                fn $method(self, rhs: Self) -> Self {
+                    self.zip(rhs, $bound::$f)
+                }
+            }
+
+            impl<T: SimdElement + $bound, const N: usize> $op<T> for Simd<T, N> {
+                type Output = Self;
+
+                #[inline(always)]
+                fn $method(self, rhs: T) -> Self {
+                    self.zip(Self::splat(rhs), $bound::$f)
+                }
+            }
+
+            impl<T: SimdElement + $bound, const N: usize> $op<&Simd<T, N>> for Simd<T, N> {
+                type Output = Self;
+
+                #[inline(always)]
+                fn $method(self, rhs: &Self) -> Self {
+                    self.zip(*rhs, $bound::$f)
+                }
+            }
+
+            impl<T: SimdElement + $bound, const N: usize> $op<Simd<T, N>> for &Simd<T, N> {
+                type Output = Simd<T, N>;
+
+                #[inline(always)]
+                fn $method(self, rhs: Simd<T, N>) -> Simd<T, N> {
+                    self.zip(rhs, $bound::$f)
+                }
+            }
+
+            impl<T: SimdElement + $bound, const N: usize> $op for &Simd<T, N> {
+                type Output = Simd<T, N>;
+
+                #[inline(always)]
+                fn $method(self, rhs: Self) -> Simd<T, N> {
+                    self.zip(*rhs, $bound::$f)
+                }
+            }
+
+            impl<T: SimdElement + $bound, const N: usize> $assign for Simd<T, N> {
+                #[inline(always)]
+                fn $assign_method(&mut self, rhs: Self) {
+                    *self = self.zip(rhs, $bound::$f);
+                }
+            }
+        )*};
+    }
+    binary_ops!(Arith:
+        Add add AddAssign add_assign add,
+        Sub sub SubAssign sub_assign sub,
+        Mul mul MulAssign mul_assign mul,
+        Div div DivAssign div_assign div,
+        Rem rem RemAssign rem_assign rem
+    );
+    binary_ops!(Bits:
+        BitAnd bitand BitAndAssign bitand_assign and,
+        BitOr bitor BitOrAssign bitor_assign or,
+        BitXor bitxor BitXorAssign bitxor_assign xor,
+        Shl shl ShlAssign shl_assign shl,
+        Shr shr ShrAssign shr_assign shr
+    );
+
+    impl<T: SimdElement + Arith, const N: usize> Neg for Simd<T, N> {
+        type Output = Self;
+
+        #[inline(always)]
+        fn neg(self) -> Self {
+            self.map(Arith::neg)
+        }
+    }
+
+    impl<T: SimdElement + Bits, const N: usize> Not for Simd<T, N> {
+        type Output = Self;
+
+        #[inline(always)]
+        fn not(self) -> Self {
+            self.map(Bits::not)
+        }
+    }
+
+    /// A vector of `N` booleans, the result of comparing `Simd`s.
+    pub struct Mask<T, const N: usize>([bool; N], PhantomData<T>);
+
+    impl<T, const N: usize> Clone for Mask<T, N> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+
+    impl<T, const N: usize> Copy for Mask<T, N> {}
+
+    impl<T, const N: usize> PartialEq for Mask<T, N> {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl<T, const N: usize> fmt::Debug for Mask<T, N> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl<T: MaskElement, const N: usize> Default for Mask<T, N> {
+        fn default() -> Self {
+            Self::splat(false)
+        }
+    }
+
+    impl<T: MaskElement, const N: usize> Mask<T, N> {
+        #[inline(always)]
+        pub fn splat(value: bool) -> Self {
+            Mask([value; N], PhantomData)
+        }
+
+        #[inline(always)]
+        pub fn from_array(array: [bool; N]) -> Self {
+            Mask(array, PhantomData)
+        }
+
+        #[inline(always)]
+        pub fn to_array(self) -> [bool; N] {
+            self.0
+        }
+
+        #[inline(always)]
+        pub fn test(&self, index: usize) -> bool {
+            self.0[index]
+        }
+
+        #[inline(always)]
+        pub fn set(&mut self, index: usize, value: bool) {
+            self.0[index] = value;
+        }
+
+        #[inline(always)]
+        pub fn any(self) -> bool {
+            self.0.iter().any(|&lane| lane)
+        }
+
+        #[inline(always)]
+        pub fn all(self) -> bool {
+            self.0.iter().all(|&lane| lane)
+        }
+
+        /// Lane `i` in bit `i`.
+        #[inline(always)]
+        pub fn to_bitmask(self) -> u64 {
+            (0..N).fold(0, |bits, i| bits | (self.0[i] as u64) << i)
+        }
+
+        #[inline(always)]
+        pub fn from_bitmask(bits: u64) -> Self {
+            Mask(std::array::from_fn(|i| bits >> i & 1 != 0), PhantomData)
+        }
+
+        #[inline(always)]
+        pub fn cast<U: MaskElement>(self) -> Mask<U, N> {
+            Mask(self.0, PhantomData)
+        }
+
+        #[inline(always)]
+        fn zip(self, other: Self, f: impl Fn(bool, bool) -> bool) -> Self {
+            Mask(
+                std::array::from_fn(|i| f(self.0[i], other.0[i])),
+                PhantomData,
+            )
+        }
+    }
+
+    macro_rules! mask_ops {
+        ($($op:ident $method:ident $assign:ident $assign_method:ident $f:expr),*) => {$(
+            impl<T: MaskElement, const N: usize> $op for Mask<T, N> {
+                type Output = Self;
+
+                #[inline(always)]
+                fn $method(self, rhs: Self) -> Self {
+                    self.zip(rhs, $f)
+                }
+            }
+
+            impl<T: MaskElement, const N: usize> $op<bool> for Mask<T, N> {
+                type Output = Self;
+
+                #[inline(always)]
+                fn $method(self, rhs: bool) -> Self {
+                    self.zip(Self::splat(rhs), $f)
+                }
+            }
+
+            impl<T: MaskElement, const N: usize> $assign for Mask<T, N> {
+                #[inline(always)]
+                fn $assign_method(&mut self, rhs: Self) {
+                    *self = self.zip(rhs, $f);
+                }
+            }
+        )*};
+    }
+    mask_ops!(
+        BitAnd bitand BitAndAssign bitand_assign |a, b| a & b,
+        BitOr bitor BitOrAssign bitor_assign |a, b| a | b,
+        BitXor bitxor BitXorAssign bitxor_assign |a, b| a ^ b
+    );
+
+    impl<T: MaskElement, const N: usize> Not for Mask<T, N> {
+        type Output = Self;
+
+        #[inline(always)]
+        fn not(self) -> Self {
+            Mask(self.0.map(|lane| !lane), PhantomData)
+        }
+    }
+
+    /// Lane-wise choice between two vectors, see `std::simd::Select`.
+    pub trait Select<T> {
+        fn select(self, true_values: T, false_values: T) -> T;
+    }
+
+    impl<M: MaskElement, T: SimdElement, const N: usize> Select<Simd<T, N>> for Mask<M, N> {
+        #[inline(always)]
+        fn select(self, true_values: Simd<T, N>, false_values: Simd<T, N>) -> Simd<T, N> {
+            Simd(std::array::from_fn(|i| {
+                if self.0[i] {
+                    true_values.0[i]
+                } else {
+                    false_values.0[i]
+                }
+            }))
+        }
+    }
+
+    impl<M: MaskElement, T: MaskElement, const N: usize> Select<Mask<T, N>> for Mask<M, N> {
+        #[inline(always)]
+        fn select(self, true_values: Mask<T, N>, false_values: Mask<T, N>) -> Mask<T, N> {
+            Mask(
+                std::array::from_fn(|i| {
+                    if self.0[i] {
+                        true_values.0[i]
+                    } else {
+                        false_values.0[i]
+                    }
+                }),
+                PhantomData,
+            )
+        }
+    }
+
+    pub mod cmp {
+        use super::{Mask, Simd, SimdElement};
+
+        pub trait SimdPartialEq {
+            type Mask;
+
+            fn simd_eq(self, other: Self) -> Self::Mask;
+            fn simd_ne(self, other: Self) -> Self::Mask;
+        }
+
+        pub trait SimdPartialOrd: SimdPartialEq {
+            fn simd_lt(self, other: Self) -> Self::Mask;
+            fn simd_le(self, other: Self) -> Self::Mask;
+            fn simd_gt(self, other: Self) -> Self::Mask;
+            fn simd_ge(self, other: Self) -> Self::Mask;
+        }
+
+        /// Minimum and maximum of integers, see `num::SimdFloat` for floats.
+        pub trait SimdOrd: SimdPartialOrd {
+            fn simd_min(self, other: Self) -> Self;
+            fn simd_max(self, other: Self) -> Self;
+            fn simd_clamp(self, min: Self, max: Self) -> Self;
+        }
+
+        impl<T: SimdElement, const N: usize> SimdPartialEq for Simd<T, N> {
+            type Mask = Mask<T::Mask, N>;
+
+            #[inline(always)]
+            fn simd_eq(self, other: Self) -> Self::Mask {
+                self.test(other, |a, b| a == b)
+            }
+
+            #[inline(always)]
+            fn simd_ne(self, other: Self) -> Self::Mask {
+                self.test(other, |a, b| a != b)
+            }
+        }
+
+        impl<T: SimdElement, const N: usize> SimdPartialOrd for Simd<T, N> {
+            #[inline(always)]
+            fn simd_lt(self, other: Self) -> Self::Mask {
+                self.test(other, |a, b| a < b)
+            }
+
+            #[inline(always)]
+            fn simd_le(self, other: Self) -> Self::Mask {
+                self.test(other, |a, b| a <= b)
+            }
+
+            #[inline(always)]
+            fn simd_gt(self, other: Self) -> Self::Mask {
+                self.test(other, |a, b| a > b)
+            }
+
+            #[inline(always)]
+            fn simd_ge(self, other: Self) -> Self::Mask {
+                self.test(other, |a, b| a >= b)
+            }
+        }
+
+        macro_rules! ord {
+            ($($t:ty),*) => {$(
+                impl<const N: usize> SimdOrd for Simd<$t, N> {
+                    #[inline(always)]
+                    fn simd_min(self, other: Self) -> Self {
+                        self.zip(other, Ord::min)
+                    }
+
+                    #[inline(always)]
+                    fn simd_max(self, other: Self) -> Self {
+                        self.zip(other, Ord::max)
+                    }
+
+                    #[inline(always)]
+                    fn simd_clamp(self, min: Self, max: Self) -> Self {
+                        self.simd_max(min).simd_min(max)
+                    }
+                }
+            )*};
+        }
+        ord!(u8, i8, u16, i16, u32, i32, u64, i64, usize, isize);
+    }
+
+    pub mod num {
+        use super::{CastTo, Mask, Simd, SimdElement};
+
+        macro_rules! cast {
+            ($t:ty) => {
+                type Cast<U: SimdElement> = Simd<U, N>;
+
+                #[inline(always)]
+                fn cast<U: SimdElement>(self) -> Simd<U, N>
+                where
+                    $t: CastTo<U>,
+                {
+                    self.map(CastTo::cast)
+                }
+            };
+        }
+
+        pub trait SimdFloat: Copy {
+            type Mask;
+            type Scalar;
+            type Bits;
+            type Cast<U: SimdElement>;
+
+            fn cast<U: SimdElement>(self) -> Self::Cast<U>
+            where
+                Self::Scalar: CastTo<U>;
+
+            fn to_bits(self) -> Self::Bits;
+            fn from_bits(bits: Self::Bits) -> Self;
+            fn abs(self) -> Self;
+            fn recip(self) -> Self;
+            fn signum(self) -> Self;
+            fn copysign(self, sign: Self) -> Self;
+            fn is_nan(self) -> Self::Mask;
+            fn is_finite(self) -> Self::Mask;
+            fn is_infinite(self) -> Self::Mask;
+            fn is_sign_negative(self) -> Self::Mask;
+            fn simd_min(self, other: Self) -> Self;
+            fn simd_max(self, other: Self) -> Self;
+            fn simd_clamp(self, min: Self, max: Self) -> Self;
+            fn reduce_sum(self) -> Self::Scalar;
+            fn reduce_product(self) -> Self::Scalar;
+            fn reduce_min(self) -> Self::Scalar;
+            fn reduce_max(self) -> Self::Scalar;
+        }
+
+        macro_rules! float {
+            ($($t:ty => $bits:ty, $mask:ty),*) => {$(
+                impl<const N: usize> SimdFloat for Simd<$t, N> {
+                    type Mask = Mask<$mask, N>;
+                    type Scalar = $t;
+                    type Bits = Simd<$bits, N>;
+
+                    cast!($t);
+
+                    #[inline(always)]
+                    fn to_bits(self) -> Self::Bits {
+                        self.map(<$t>::to_bits)
+                    }
+
+                    #[inline(always)]
+                    fn from_bits(bits: Self::Bits) -> Self {
+                        bits.map(<$t>::from_bits)
+                    }
+
+                    #[inline(always)]
+                    fn abs(self) -> Self {
+                        self.map(<$t>::abs)
+                    }
+
+                    #[inline(always)]
+                    fn recip(self) -> Self {
+                        self.map(<$t>::recip)
+                    }
+
+                    #[inline(always)]
+                    fn signum(self) -> Self {
+                        self.map(<$t>::signum)
+                    }
+
+                    #[inline(always)]
+                    fn copysign(self, sign: Self) -> Self {
+                        self.zip(sign, <$t>::copysign)
+                    }
+
+                    #[inline(always)]
+                    fn is_nan(self) -> Self::Mask {
+                        self.test(self, |a, _| a.is_nan())
+                    }
+
+                    #[inline(always)]
+                    fn is_finite(self) -> Self::Mask {
+                        self.test(self, |a, _| a.is_finite())
+                    }
+
+                    #[inline(always)]
+                    fn is_infinite(self) -> Self::Mask {
+                        self.test(self, |a, _| a.is_infinite())
+                    }
+
+                    #[inline(always)]
+                    fn is_sign_negative(self) -> Self::Mask {
+                        self.test(self, |a, _| a.is_sign_negative())
+                    }
+
+                    #[inline(always)]
+                    fn simd_min(self, other: Self) -> Self {
+                        self.zip(other, <$t>::min)
+                    }
+
+                    #[inline(always)]
+                    fn simd_max(self, other: Self) -> Self {
+                        self.zip(other, <$t>::max)
+                    }
+
+                    #[inline(always)]
+                    fn simd_clamp(self, min: Self, max: Self) -> Self {
+                        self.simd_max(min).simd_min(max)
+                    }
+
+                    #[inline(always)]
+                    fn reduce_sum(self) -> $t {
+                        self.fold(|a, b| a + b)
+                    }
+
+                    #[inline(always)]
+                    fn reduce_product(self) -> $t {
+                        self.fold(|a, b| a * b)
+                    }
+
+                    #[inline(always)]
+                    fn reduce_min(self) -> $t {
+                        self.fold(<$t>::min)
+                    }
+
+                    #[inline(always)]
+                    fn reduce_max(self) -> $t {
+                        self.fold(<$t>::max)
+                    }
+                }
+            )*};
+        }
+        float!(f32 => u32, i32, f64 => u64, i64);
+
+        pub trait SimdUint: Copy {
+            type Scalar;
+            type Cast<U: SimdElement>;
+
+            fn cast<U: SimdElement>(self) -> Self::Cast<U>
+            where
+                Self::Scalar: CastTo<U>;
+
+            fn saturating_add(self, other: Self) -> Self;
+            fn saturating_sub(self, other: Self) -> Self;
+            fn wrapping_neg(self) -> Self;
+            fn reduce_sum(self) -> Self::Scalar;
+            fn reduce_min(self) -> Self::Scalar;
+            fn reduce_max(self) -> Self::Scalar;
+            fn reduce_and(self) -> Self::Scalar;
+            fn reduce_or(self) -> Self::Scalar;
+            fn reduce_xor(self) -> Self::Scalar;
+        }
+
+        pub trait SimdInt: Copy {
+            type Scalar;
+            type Cast<U: SimdElement>;
+
+            fn cast<U: SimdElement>(self) -> Self::Cast<U>
+            where
+                Self::Scalar: CastTo<U>;
+
+            fn saturating_add(self, other: Self) -> Self;
+            fn saturating_sub(self, other: Self) -> Self;
+            fn abs(self) -> Self;
+            fn signum(self) -> Self;
+            fn reduce_sum(self) -> Self::Scalar;
+            fn reduce_min(self) -> Self::Scalar;
+            fn reduce_max(self) -> Self::Scalar;
+            fn reduce_and(self) -> Self::Scalar;
+            fn reduce_or(self) -> Self::Scalar;
+            fn reduce_xor(self) -> Self::Scalar;
+        }
+
+        macro_rules! uint {
+            ($($t:ty),*) => {$(
+                impl<const N: usize> SimdUint for Simd<$t, N> {
+                    type Scalar = $t;
+
+                    cast!($t);
+
+                    #[inline(always)]
+                    fn saturating_add(self, other: Self) -> Self {
+                        self.zip(other, <$t>::saturating_add)
+                    }
+
+                    #[inline(always)]
+                    fn saturating_sub(self, other: Self) -> Self {
+                        self.zip(other, <$t>::saturating_sub)
+                    }
+
+                    #[inline(always)]
+                    fn wrapping_neg(self) -> Self {
+                        self.map(<$t>::wrapping_neg)
+                    }
+
+                    reductions!($t);
+                }
+            )*};
+        }
+
+        macro_rules! int {
+            ($($t:ty),*) => {$(
+                impl<const N: usize> SimdInt for Simd<$t, N> {
+                    type Scalar = $t;
+
+                    cast!($t);
+
+                    #[inline(always)]
+                    fn saturating_add(self, other: Self) -> Self {
+                        self.zip(other, <$t>::saturating_add)
+                    }
+
+                    #[inline(always)]
+                    fn saturating_sub(self, other: Self) -> Self {
+                        self.zip(other, <$t>::saturating_sub)
+                    }
+
+                    #[inline(always)]
+                    fn abs(self) -> Self {
+                        self.map(<$t>::wrapping_abs)
+                    }
+
+                    #[inline(always)]
+                    fn signum(self) -> Self {
+                        self.map(<$t>::signum)
+                    }
+
+                    reductions!($t);
+                }
+            )*};
+        }
+
+        macro_rules! reductions {
+            ($t:ty) => {
+                #[inline(always)]
+                fn reduce_sum(self) -> $t {
+                    self.fold(<$t>::wrapping_add)
+                }
+
+                #[inline(always)]
+                fn reduce_min(self) -> $t {
+                    self.fold(Ord::min)
+                }
+
+                #[inline(always)]
+                fn reduce_max(self) -> $t {
+                    self.fold(Ord::max)
+                }
+
+                #[inline(always)]
+                fn reduce_and(self) -> $t {
+                    self.fold(|a, b| a & b)
+                }
+
+                #[inline(always)]
+                fn reduce_or(self) -> $t {
+                    self.fold(|a, b| a | b)
+                }
+
+                #[inline(always)]
+                fn reduce_xor(self) -> $t {
+                    self.fold(|a, b| a ^ b)
+                }
+            };
+        }
+
+        uint!(u8, u16, u32, u64, usize);
+        int!(i8, i16, i32, i64, isize);
+    }
+
+    /// Float functions of the standard library, lane by lane.
+    pub trait StdFloat: Copy {
+        fn floor(self) -> Self;
+        fn ceil(self) -> Self;
+        fn round(self) -> Self;
+        fn trunc(self) -> Self;
+        fn fract(self) -> Self;
+        fn sqrt(self) -> Self;
+        fn mul_add(self, a: Self, b: Self) -> Self;
+        fn sin(self) -> Self;
+        fn cos(self) -> Self;
+        fn exp(self) -> Self;
+        fn exp2(self) -> Self;
+        fn ln(self) -> Self;
+        fn log2(self) -> Self;
+        fn log10(self) -> Self;
+    }
+
+    macro_rules! std_float {
+        ($($t:ty),*) => {$(
+            impl<const N: usize> StdFloat for Simd<$t, N> {
+                #[inline(always)]
+                fn floor(self) -> Self { self.map(<$t>::floor) }
+                #[inline(always)]
+                fn ceil(self) -> Self { self.map(<$t>::ceil) }
+                #[inline(always)]
+                fn round(self) -> Self { self.map(<$t>::round) }
+                #[inline(always)]
+                fn trunc(self) -> Self { self.map(<$t>::trunc) }
+                #[inline(always)]
+                fn fract(self) -> Self { self.map(<$t>::fract) }
+                #[inline(always)]
+                fn sqrt(self) -> Self { self.map(<$t>::sqrt) }
+                #[inline(always)]
+                fn mul_add(self, a: Self, b: Self) -> Self {
+                    Simd(std::array::from_fn(|i| self.0[i].mul_add(a.0[i], b.0[i])))
+                }
+                #[inline(always)]
+                fn sin(self) -> Self { self.map(<$t>::sin) }
+                #[inline(always)]
+                fn cos(self) -> Self { self.map(<$t>::cos) }
+                #[inline(always)]
+                fn exp(self) -> Self { self.map(<$t>::exp) }
+                #[inline(always)]
+                fn exp2(self) -> Self { self.map(<$t>::exp2) }
+                #[inline(always)]
+                fn ln(self) -> Self { self.map(<$t>::ln) }
+                #[inline(always)]
+                fn log2(self) -> Self { self.map(<$t>::log2) }
+                #[inline(always)]
+                fn log10(self) -> Self { self.map(<$t>::log10) }
+            }
+        )*};
+    }
+    std_float!(f32, f64);
+
+    macro_rules! aliases {
+        ($($t:ident: $($alias:ident $n:literal),*;)*) => {$($(
+            #[allow(non_camel_case_types)]
+            pub type $alias = Simd<$t, $n>;
+        )*)*};
+    }
+    aliases! {
+        f32: f32x1 1, f32x2 2, f32x4 4, f32x8 8, f32x16 16, f32x32 32, f32x64 64;
+        f64: f64x1 1, f64x2 2, f64x4 4, f64x8 8, f64x16 16, f64x32 32, f64x64 64;
+        u8: u8x1 1, u8x2 2, u8x4 4, u8x8 8, u8x16 16, u8x32 32, u8x64 64;
+        i8: i8x1 1, i8x2 2, i8x4 4, i8x8 8, i8x16 16, i8x32 32, i8x64 64;
+        u16: u16x1 1, u16x2 2, u16x4 4, u16x8 8, u16x16 16, u16x32 32, u16x64 64;
+        i16: i16x1 1, i16x2 2, i16x4 4, i16x8 8, i16x16 16, i16x32 32, i16x64 64;
+        u32: u32x1 1, u32x2 2, u32x4 4, u32x8 8, u32x16 16, u32x32 32, u32x64 64;
+        i32: i32x1 1, i32x2 2, i32x4 4, i32x8 8, i32x16 16, i32x32 32, i32x64 64;
+        u64: u64x1 1, u64x2 2, u64x4 4, u64x8 8, u64x16 16, u64x32 32, u64x64 64;
+        i64: i64x1 1, i64x2 2, i64x4 4, i64x8 8, i64x16 16, i64x32 32, i64x64 64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cmp::SimdPartialOrd;
+    use super::num::{SimdFloat, SimdInt, SimdUint};
+    use super::{Mask, Select, Simd, StdFloat, f32x4, u8x4};
+
+    #[test]
+    fn matches_std_simd() {
+        let a = f32x4::from_array([-1.5, 0.25, 2.0, f32::NAN]);
+        let b = f32x4::splat(0.5);
+        assert_eq!((a * b + b).as_array()[..3], [-0.25, 0.625, 1.5]);
+        assert_eq!(a.floor().as_array()[..3], [-2.0, 0.0, 2.0]);
+        assert_eq!(a.is_nan().to_bitmask(), 0b1000);
+        let below = a.simd_lt(b);
+        assert_eq!(below.to_array(), [true, true, false, false]);
+        assert_eq!(below.select(a, b).as_array()[..3], [-1.5, 0.25, 0.5]);
+        assert_eq!(a.cast::<i32>().abs().to_array(), [1, 0, 2, 0]);
+
+        // Integer lanes wrap or saturate like `std::simd`.
+        let x = u8x4::from_array([250, 1, 2, 3]);
+        assert_eq!((x + u8x4::splat(10)).to_array(), [4, 11, 12, 13]);
+        assert_eq!(
+            x.saturating_add(u8x4::splat(10)).to_array(),
+            [255, 11, 12, 13]
+        );
+        assert_eq!(x.reduce_or(), 251);
+        assert_eq!((x >> 1).to_array(), [125, 0, 1, 1]);
+
+        let enable = Mask::from_array([true, false, true, true]);
+        let idxs = Simd::from_array([0, 0, 1, 9]);
+        let gathered = Simd::gather_select(&[7, 8], enable, idxs, u8x4::splat(0));
+        assert_eq!(gathered.to_array(), [7, 0, 8, 0]);
+    }
+}
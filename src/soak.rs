@@ -0,0 +1,163 @@
+//! The `--soak` mode of the app.
+//!
+//! Steps a headless scenario for hours and periodically reports a `Sample`
+//! as one line of JSON: the resident memory, the particle count and
+//! statistics of the particle states. A resident size that keeps growing
+//! points at a leak, e.g. a buffer that is appended to every frame, while
+//! particles that turn non-finite, escape the window or speed up without
+//! bound point at numerical degradation.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::headless::{Headless, Scenario};
+use crate::scoped_threadpool::Pool;
+
+/// Statistics of the particle states that expose numerical drift.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Drift {
+    /// Particles with a non-finite position or velocity.
+    pub non_finite: usize,
+    /// Finite particles outside the window.
+    pub offscreen: usize,
+    /// Mean and maximum speed of the finite particles.
+    pub mean_speed: f32,
+    pub max_speed: f32,
+    /// Mean position of the finite particles.
+    pub centroid: (f32, f32),
+}
+
+impl Drift {
+    /// Statistics of the `[x, y, dx, dy]` `states` in a window of `width`
+    /// by `height` pixels.
+    pub fn of(states: impl Iterator<Item = [f32; 4]>, width: u32, height: u32) -> Self {
+        let mut drift = Drift {
+            non_finite: 0,
+            offscreen: 0,
+            mean_speed: 0.0,
+            max_speed: 0.0,
+            centroid: (0.0, 0.0),
+        };
+        // Summed in f64, millions of particles would swamp an f32.
+        let (mut speed, mut x, mut y, mut n) = (0.0f64, 0.0f64, 0.0f64, 0usize);
+        for state in states {
+            if !state.iter().all(|v| v.is_finite()) {
+                drift.non_finite += 1;
+                continue;
+            }
+            let [px, py, dx, dy] = state;
+            if px < 0.0 || py < 0.0 || px >= width as f32 || py >= height as f32 {
+                drift.offscreen += 1;
+            }
+            let v = dx.hypot(dy);
+            drift.max_speed = drift.max_speed.max(v);
+            speed += v as f64;
+            x += px as f64;
+            y += py as f64;
+            n += 1;
+        }
+        if n > 0 {
+            let n = n as f64;
+            drift.mean_speed = (speed / n) as f32;
+            drift.centroid = ((x / n) as f32, (y / n) as f32);
+        }
+        drift
+    }
+}
+
+/// One report of the soak test.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sample {
+    pub seconds: f32,
+    pub frames: u32,
+    /// Resident set size, `None` where it cannot be read.
+    pub rss_bytes: Option<u64>,
+    pub particles: usize,
+    pub drift: Drift,
+}
+
+/// The resident set size of the process, read from `/proc` on Linux.
+pub fn rss() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Steps `scenario` like the app does for `duration`, or forever if `None`,
+/// passing a `Sample` to `report` before the first frame, every `interval`
+/// and after the last frame.
+pub fn run(
+    pool: &Pool,
+    scenario: Scenario,
+    duration: Option<Duration>,
+    interval: Duration,
+    mut report: impl FnMut(&Sample),
+) {
+    let (width, height) = (scenario.width, scenario.height);
+    let mut sim = Headless::new(pool, scenario);
+    let start = Instant::now();
+    let mut sample = |sim: &Headless| {
+        report(&Sample {
+            seconds: start.elapsed().as_secs_f32(),
+            frames: sim.frame(),
+            rss_bytes: rss(),
+            particles: sim.particles.len(),
+            drift: Drift::of(sim.particles.snapshot().states(), width, height),
+        })
+    };
+    sample(&sim);
+    let mut last = Instant::now();
+    while duration.is_none_or(|duration| start.elapsed() < duration) {
+        sim.step();
+        if last.elapsed() >= interval {
+            sample(&sim);
+            last = Instant::now();
+        }
+    }
+    sample(&sim);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_drift() {
+        let states = [
+            [10.0, 20.0, 3.0, 4.0],
+            [30.0, 40.0, 0.0, 0.0],
+            [-5.0, 0.0, 0.0, 1.0],
+            [f32::NAN, 0.0, 0.0, 0.0],
+            [0.0, 0.0, f32::INFINITY, 0.0],
+        ];
+        let drift = Drift::of(states.into_iter(), 100, 100);
+        assert_eq!(drift.non_finite, 2);
+        assert_eq!(drift.offscreen, 1);
+        assert_eq!(drift.max_speed, 5.0);
+        assert_eq!(drift.mean_speed, 2.0);
+        assert!((drift.centroid.0 - 35.0 / 3.0).abs() < 1e-5);
+        assert_eq!(drift.centroid.1, 20.0);
+
+        let pool = Pool::new(2);
+        let mut samples = Vec::new();
+        run(
+            &pool,
+            Scenario::small(),
+            Some(Duration::from_millis(50)),
+            Duration::ZERO,
+            |sample| samples.push(sample.clone()),
+        );
+        assert_eq!(samples[0].frames, 0);
+        assert!(samples.len() >= 3);
+        let last = &samples[samples.len() - 1];
+        assert!(last.frames > 0);
+        assert_eq!(last.particles, samples[0].particles);
+        assert_eq!(last.drift.non_finite, 0);
+        if cfg!(target_os = "linux") {
+            assert!(last.rss_bytes.unwrap() > 0);
+        }
+    }
+}
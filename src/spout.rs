@@ -0,0 +1,248 @@
+//! Shares the frames with Spout receivers on Windows.
+//!
+//! With `--spout <name>` every frame is sent as the Spout sender `<name>`,
+//! so OBS (with its Spout2 plugin), Resolume or TouchDesigner can composite
+//! the simulation live without capturing the screen.
+//!
+//! The frames go through SpoutLibrary, the C-compatible interface of the
+//! Spout 2.007 SDK. Build with `SPOUT_LIBRARY_DIR` set to the directory of
+//! `SpoutLibrary.lib` and ship `SpoutLibrary.dll` next to the executable.
+//! SpoutLibrary uploads the pixels through OpenGL into the shared DirectX
+//! texture, so the sender makes a context of its own current on a hidden
+//! window of the event loop thread.
+
+#[cfg(target_pointer_width = "32")]
+compile_error!(
+    "the `spout` feature needs a 64-bit target, where SpoutLibrary methods use the C calling convention"
+);
+
+use std::ffi::{CString, c_char, c_void};
+use std::io;
+use std::ptr;
+
+use crate::output::{Frame, FrameSink, PixelFormat};
+
+/// `GL_RGBA`, the layout of `PixelFormat::Rgba8`.
+const GL_RGBA: u32 = 0x1908;
+
+/// The leading methods of the `SPOUTLIBRARY` interface of
+/// `SpoutLibrary.h`, in declaration order. Only the sender methods up to
+/// `SendImage` are called, so the rest of the table is left out.
+#[repr(C)]
+struct SpoutVtable {
+    set_sender_name: unsafe extern "C" fn(*mut SpoutLibrary, *const c_char),
+    _set_sender_format: unsafe extern "C" fn(*mut SpoutLibrary, u32),
+    release_sender: unsafe extern "C" fn(*mut SpoutLibrary, u32),
+    _send_fbo: *const c_void,
+    _send_texture: *const c_void,
+    send_image:
+        unsafe extern "C" fn(*mut SpoutLibrary, *const u8, u32, u32, u32, bool, u32) -> bool,
+}
+
+/// A `SPOUTLIBRARY` object.
+#[repr(C)]
+struct SpoutLibrary {
+    vtable: *const SpoutVtable,
+}
+
+#[link(name = "SpoutLibrary")]
+unsafe extern "system" {
+    fn GetSpout() -> *mut SpoutLibrary;
+}
+
+/// `PIXELFORMATDESCRIPTOR` of `wingdi.h`, with the fields that are left zero
+/// merged into arrays.
+#[repr(C)]
+struct PixelFormatDescriptor {
+    size: u16,
+    version: u16,
+    flags: u32,
+    pixel_type: u8,
+    color_bits: u8,
+    other_bits: [u8; 18],
+    masks: [u32; 3],
+}
+
+const PFD_DOUBLEBUFFER: u32 = 0x1;
+const PFD_DRAW_TO_WINDOW: u32 = 0x4;
+const PFD_SUPPORT_OPENGL: u32 = 0x20;
+const PFD_TYPE_RGBA: u8 = 0;
+
+type Handle = *mut c_void;
+
+#[link(name = "user32")]
+unsafe extern "system" {
+    fn CreateWindowExW(
+        ex_style: u32,
+        class: *const u16,
+        name: *const u16,
+        style: u32,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        parent: Handle,
+        menu: Handle,
+        instance: Handle,
+        param: *mut c_void,
+    ) -> Handle;
+    fn DestroyWindow(window: Handle) -> i32;
+    fn GetDC(window: Handle) -> Handle;
+    fn ReleaseDC(window: Handle, dc: Handle) -> i32;
+}
+
+#[link(name = "gdi32")]
+unsafe extern "system" {
+    fn ChoosePixelFormat(dc: Handle, descriptor: *const PixelFormatDescriptor) -> i32;
+    fn SetPixelFormat(dc: Handle, format: i32, descriptor: *const PixelFormatDescriptor) -> i32;
+}
+
+#[link(name = "opengl32")]
+unsafe extern "system" {
+    fn wglCreateContext(dc: Handle) -> Handle;
+    fn wglMakeCurrent(dc: Handle, context: Handle) -> i32;
+    fn wglDeleteContext(context: Handle) -> i32;
+}
+
+/// A hidden window with a current OpenGL context.
+struct GlContext {
+    window: Handle,
+    dc: Handle,
+    context: Handle,
+}
+
+impl GlContext {
+    fn new() -> io::Result<Self> {
+        // The predefined `STATIC` class needs no registration.
+        let class = "STATIC\0".encode_utf16().collect::<Vec<_>>();
+        // SAFETY: the strings are null terminated and the handles are
+        // checked before they are used.
+        unsafe {
+            let window = CreateWindowExW(
+                0,
+                class.as_ptr(),
+                ptr::null(),
+                0,
+                0,
+                0,
+                1,
+                1,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            if window.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let mut gl = GlContext {
+                window,
+                dc: GetDC(window),
+                context: ptr::null_mut(),
+            };
+            let descriptor = PixelFormatDescriptor {
+                size: size_of::<PixelFormatDescriptor>() as u16,
+                version: 1,
+                flags: PFD_DRAW_TO_WINDOW | PFD_SUPPORT_OPENGL | PFD_DOUBLEBUFFER,
+                pixel_type: PFD_TYPE_RGBA,
+                color_bits: 32,
+                other_bits: [0; 18],
+                masks: [0; 3],
+            };
+            let format = ChoosePixelFormat(gl.dc, &descriptor);
+            if format == 0 || SetPixelFormat(gl.dc, format, &descriptor) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            gl.context = wglCreateContext(gl.dc);
+            if gl.context.is_null() || wglMakeCurrent(gl.dc, gl.context) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(gl)
+        }
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        // SAFETY: the handles were created by `new` and are released once.
+        unsafe {
+            if !self.context.is_null() {
+                wglMakeCurrent(ptr::null_mut(), ptr::null_mut());
+                wglDeleteContext(self.context);
+            }
+            ReleaseDC(self.window, self.dc);
+            DestroyWindow(self.window);
+        }
+    }
+}
+
+/// `FrameSink` sending every frame to Spout receivers.
+pub struct SpoutSender {
+    name: String,
+    spout: *mut SpoutLibrary,
+    buffer: Vec<u8>,
+    // Dropped after the sender was released.
+    _gl: GlContext,
+}
+
+impl SpoutSender {
+    /// Creates the sender `name`. Receivers see it once the first frame
+    /// was sent.
+    pub fn new(name: &str) -> io::Result<Self> {
+        let c_name = CString::new(name).map_err(io::Error::other)?;
+        let gl = GlContext::new()?;
+        // SAFETY: `GetSpout` has no preconditions and the object it returns
+        // is checked for null.
+        let spout = unsafe { GetSpout() };
+        if spout.is_null() {
+            return Err(io::Error::other("SpoutLibrary is not available"));
+        }
+        // SAFETY: `spout` is a valid object and SpoutLibrary copies the
+        // name.
+        unsafe { ((*(*spout).vtable).set_sender_name)(spout, c_name.as_ptr()) };
+        Ok(SpoutSender {
+            name: name.to_string(),
+            spout,
+            buffer: Vec::new(),
+            _gl: gl,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl FrameSink for SpoutSender {
+    fn publish(&mut self, frame: &Frame) {
+        if frame.pixels.is_empty() {
+            return;
+        }
+        self.buffer.clear();
+        PixelFormat::Rgba8.encode(frame.pixels, &mut self.buffer);
+        // SAFETY: `buffer` holds `width * height` RGBA pixels, and the
+        // context SpoutLibrary draws with is current on this thread.
+        let sent = unsafe {
+            ((*(*self.spout).vtable).send_image)(
+                self.spout,
+                self.buffer.as_ptr(),
+                frame.width,
+                frame.height,
+                GL_RGBA,
+                false,
+                0,
+            )
+        };
+        if !sent {
+            eprintln!("spout: failed to send frame {}", frame.id);
+        }
+    }
+}
+
+impl Drop for SpoutSender {
+    fn drop(&mut self) {
+        // SAFETY: `spout` is valid, the library object itself lives for the
+        // rest of the process.
+        unsafe { ((*(*self.spout).vtable).release_sender)(self.spout, 0) };
+    }
+}
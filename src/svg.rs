@@ -0,0 +1,193 @@
+//! Import of obstacle shapes from SVG files.
+//!
+//! The document is parsed and simplified by `usvg`, which resolves
+//! styles, `<use>` references and transforms and turns every basic shape
+//! into path data. Every subpath of the resulting paths becomes a closed
+//! polygon, with curves flattened into line segments, so the obstacles and
+//! their distance field are rasterized from the same outlines. Strokes,
+//! text and images are ignored. Coordinates are normalized to the size of
+//! the document, so the drawing stretches over the whole window.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use usvg::tiny_skia_path::{PathSegment, Point};
+use usvg::{Group, Node, Tree};
+
+use crate::obstacle::Shape;
+use crate::particles::Attractor;
+
+/// Line segments per flattened curve.
+const CURVE_SEGMENTS: usize = 8;
+
+/// Reads the shapes of the SVG file at `path`.
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<Shape>> {
+    parse(&fs::read_to_string(path)?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Parses the shapes of an SVG document, in normalized coordinates.
+pub fn parse(svg: &str) -> Result<Vec<Shape>, String> {
+    let tree = Tree::from_str(svg, &usvg::Options::default()).map_err(|err| err.to_string())?;
+    let size = tree.size();
+    let mut polygons = Vec::new();
+    flatten(tree.root(), &mut polygons);
+    Ok(polygons
+        .into_iter()
+        .filter(|polygon| polygon.len() >= 3)
+        .map(|polygon| {
+            Shape::Polygon(
+                polygon
+                    .into_iter()
+                    .map(|point| [point.x / size.width(), point.y / size.height()])
+                    .collect(),
+            )
+        })
+        .collect())
+}
+
+/// `n` attractors of `strength` evenly spaced along the outlines of
+/// `shapes`, in normalized coordinates like the shapes.
+pub fn outline_attractors(shapes: &[Shape], n: usize, strength: f32) -> Vec<Attractor> {
+    let outlines = shapes
+        .iter()
+        .map(|shape| match shape {
+            Shape::Polygon(points) => points.iter().map(|&[x, y]| (x, y)).collect(),
+            &Shape::Circle { x, y, radius } => (0..4 * CURVE_SEGMENTS)
+                .map(|i| {
+                    let angle = i as f32 / (4 * CURVE_SEGMENTS) as f32 * std::f32::consts::TAU;
+                    (x + radius * angle.cos(), y + radius * angle.sin())
+                })
+                .collect(),
+        })
+        .collect::<Vec<Vec<(f32, f32)>>>();
+    let edges = || {
+        outlines
+            .iter()
+            .flat_map(|points| points.iter().zip(points.iter().cycle().skip(1)))
+    };
+    let perimeter = edges()
+        .map(|(a, b)| (b.0 - a.0).hypot(b.1 - a.1))
+        .sum::<f32>();
+    if n == 0 || perimeter == 0.0 {
+        return Vec::new();
+    }
+    let spacing = perimeter / n as f32;
+    let mut attractors = Vec::with_capacity(n);
+    // Distance along the outlines to the next attractor.
+    let mut next = spacing / 2.0;
+    for (a, b) in edges() {
+        let len = (b.0 - a.0).hypot(b.1 - a.1);
+        while next < len && attractors.len() < n {
+            let t = next / len;
+            attractors.push(Attractor {
+                x: a.0 + (b.0 - a.0) * t,
+                y: a.1 + (b.1 - a.1) * t,
+                strength,
+            });
+            next += spacing;
+        }
+        next -= len;
+    }
+    attractors
+}
+
+/// Appends the subpaths of every path in `group` to `polygons`, in the
+/// coordinates of the document.
+fn flatten(group: &Group, polygons: &mut Vec<Vec<Point>>) {
+    for node in group.children() {
+        let path = match node {
+            Node::Group(group) => {
+                flatten(group, polygons);
+                continue;
+            }
+            Node::Path(path) => path,
+            Node::Image(_) | Node::Text(_) => continue,
+        };
+        let Some(data) = path.data().clone().transform(path.abs_transform()) else {
+            continue;
+        };
+        let mut polygon = Vec::new();
+        let mut pos = Point::zero();
+        for segment in data.segments() {
+            match segment {
+                PathSegment::MoveTo(point) => {
+                    polygons.push(std::mem::take(&mut polygon));
+                    polygon.push(point);
+                }
+                PathSegment::LineTo(point) => polygon.push(point),
+                PathSegment::QuadTo(c1, end) => polygon.extend((1..=CURVE_SEGMENTS).map(|i| {
+                    let t = i as f32 / CURVE_SEGMENTS as f32;
+                    let s = 1.0 - t;
+                    let (a, b, e) = (s * s, 2.0 * s * t, t * t);
+                    Point::from_xy(
+                        a * pos.x + b * c1.x + e * end.x,
+                        a * pos.y + b * c1.y + e * end.y,
+                    )
+                })),
+                PathSegment::CubicTo(c1, c2, end) => {
+                    polygon.extend((1..=CURVE_SEGMENTS).map(|i| {
+                        let t = i as f32 / CURVE_SEGMENTS as f32;
+                        let s = 1.0 - t;
+                        let (a, b, c, e) = (s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t);
+                        Point::from_xy(
+                            a * pos.x + b * c1.x + c * c2.x + e * end.x,
+                            a * pos.y + b * c1.y + c * c2.y + e * end.y,
+                        )
+                    }))
+                }
+                PathSegment::Close => polygons.push(std::mem::take(&mut polygon)),
+            }
+            pos = polygon.last().copied().unwrap_or(pos);
+        }
+        polygons.push(polygon);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_shapes() {
+        let svg = r#"<?xml version="1.0"?>
+            <svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 200 100">
+                <!-- a comment -->
+                <rect x="20" y="10" width="40" height="20"/>
+                <g transform="translate(100 50)"><circle r="25"/></g>
+                <path d="M150,10 l40,0 v40 h-40z m0,50 q20-20 40,0 Z"/>
+            </svg>"#;
+        let shapes = parse(svg).unwrap();
+        assert_eq!(shapes.len(), 4);
+        assert_eq!(
+            shapes[0],
+            Shape::Polygon(vec![[0.1, 0.1], [0.3, 0.1], [0.3, 0.3], [0.1, 0.3]])
+        );
+        let Shape::Polygon(circle) = &shapes[1] else {
+            panic!("expected a polygon");
+        };
+        assert!(circle.len() > 4 * CURVE_SEGMENTS);
+        for &[x, y] in circle {
+            let r = ((x - 0.5) * 200.0).hypot((y - 0.5) * 100.0);
+            assert!((r - 25.0).abs() < 0.1, "{r}");
+        }
+        assert_eq!(
+            shapes[2],
+            Shape::Polygon(vec![[0.75, 0.1], [0.95, 0.1], [0.95, 0.5], [0.75, 0.5]])
+        );
+        let Shape::Polygon(curve) = &shapes[3] else {
+            panic!("expected a polygon");
+        };
+        assert_eq!(curve.len(), CURVE_SEGMENTS + 1);
+        assert_eq!(curve.last(), Some(&[0.95, 0.6]));
+
+        let attractors = outline_attractors(&shapes[..1], 4, 0.5);
+        let expected = [(0.2, 0.1), (0.3, 0.2), (0.2, 0.3), (0.1, 0.2)];
+        assert_eq!(attractors.len(), expected.len());
+        for (attractor, (x, y)) in attractors.iter().zip(expected) {
+            assert!((attractor.x - x).abs() < 1e-5 && (attractor.y - y).abs() < 1e-5);
+            assert_eq!(attractor.strength, 0.5);
+        }
+        assert!(parse("<svg><path d='M 0 0 X'/>").is_err());
+    }
+}
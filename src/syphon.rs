@@ -0,0 +1,256 @@
+//! Shares the frames with Syphon clients on macOS.
+//!
+//! With `--syphon <name>` every frame is published by a Syphon server called
+//! `<name>`, so OBS (with its Syphon client source), Resolume or VDMX can
+//! composite the simulation live without capturing the screen.
+//!
+//! The pixels are uploaded into a Metal texture that `SyphonMetalServer`
+//! shares. Build with `SYPHON_FRAMEWORK_DIR` set to the directory holding
+//! `Syphon.framework`, `/Library/Frameworks` by default.
+//!
+//! The Objective-C APIs are called through `objc_msgSend`, cast to the C
+//! signature of every method.
+
+use std::ffi::{CStr, c_char, c_void};
+use std::io;
+use std::ptr;
+
+use crate::output::{Frame, FrameSink, PixelFormat};
+
+type Id = *mut c_void;
+type Sel = *const c_void;
+
+/// `MTLPixelFormatRGBA8Unorm`, the layout of `PixelFormat::Rgba8`.
+const MTL_PIXEL_FORMAT_RGBA8_UNORM: usize = 70;
+
+/// `NSUTF8StringEncoding`.
+const NS_UTF8_STRING_ENCODING: usize = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MtlRegion {
+    origin: [usize; 3],
+    size: [usize; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NsRect {
+    origin: [f64; 2],
+    size: [f64; 2],
+}
+
+#[link(name = "objc")]
+unsafe extern "C" {
+    fn objc_getClass(name: *const c_char) -> Id;
+    fn sel_registerName(name: *const c_char) -> Sel;
+    fn objc_msgSend();
+    fn objc_autoreleasePoolPush() -> *mut c_void;
+    fn objc_autoreleasePoolPop(pool: *mut c_void);
+}
+
+#[link(name = "Metal", kind = "framework")]
+unsafe extern "C" {
+    fn MTLCreateSystemDefaultDevice() -> Id;
+}
+
+#[link(name = "Foundation", kind = "framework")]
+unsafe extern "C" {}
+
+#[link(name = "Syphon", kind = "framework")]
+unsafe extern "C" {}
+
+/// `objc_msgSend` as a function of type `F`, which has to be the C
+/// signature of the method it is called with, receiver and selector first.
+unsafe fn msg_send<F: Copy>() -> F {
+    let send = objc_msgSend as unsafe extern "C" fn();
+    assert_eq!(size_of::<F>(), size_of_val(&send));
+    // SAFETY: `F` is a function pointer, see above.
+    unsafe { std::mem::transmute_copy(&send) }
+}
+
+fn sel(name: &CStr) -> Sel {
+    // SAFETY: `name` is null terminated.
+    unsafe { sel_registerName(name.as_ptr()) }
+}
+
+/// Sends the message `name`, without arguments, to `receiver`.
+unsafe fn send(receiver: Id, name: &CStr) -> Id {
+    // SAFETY: as guaranteed by the caller.
+    unsafe { msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>()(receiver, sel(name)) }
+}
+
+/// Releases `object` unless it is nil.
+unsafe fn release(object: Id) {
+    if !object.is_null() {
+        // SAFETY: as guaranteed by the caller.
+        unsafe { msg_send::<unsafe extern "C" fn(Id, Sel)>()(object, sel(c"release")) };
+    }
+}
+
+/// A retained `NSString` with the contents of `s`.
+unsafe fn ns_string(s: &str) -> Id {
+    type Init = unsafe extern "C" fn(Id, Sel, *const c_void, usize, usize) -> Id;
+    // SAFETY: `NSString` exists in Foundation, and the bytes are valid
+    // UTF-8 of the given length.
+    unsafe {
+        let string = send(objc_getClass(c"NSString".as_ptr()), c"alloc");
+        msg_send::<Init>()(
+            string,
+            sel(c"initWithBytes:length:encoding:"),
+            s.as_ptr().cast(),
+            s.len(),
+            NS_UTF8_STRING_ENCODING,
+        )
+    }
+}
+
+/// `FrameSink` publishing every frame to Syphon clients.
+pub struct SyphonServer {
+    name: String,
+    device: Id,
+    queue: Id,
+    server: Id,
+    /// Texture the frames are uploaded to, recreated when the size changes.
+    texture: Id,
+    size: (u32, u32),
+    buffer: Vec<u8>,
+}
+
+impl SyphonServer {
+    /// Starts the server `name` on the default Metal device.
+    pub fn new(name: &str) -> io::Result<Self> {
+        type Init = unsafe extern "C" fn(Id, Sel, Id, Id, Id) -> Id;
+        // SAFETY: the objects are checked for nil before they are used, and
+        // released in `drop`.
+        unsafe {
+            let device = MTLCreateSystemDefaultDevice();
+            if device.is_null() {
+                return Err(io::Error::other("no Metal device"));
+            }
+            let mut syphon = SyphonServer {
+                name: name.to_string(),
+                device,
+                queue: send(device, c"newCommandQueue"),
+                server: ptr::null_mut(),
+                texture: ptr::null_mut(),
+                size: (0, 0),
+                buffer: Vec::new(),
+            };
+            let class = objc_getClass(c"SyphonMetalServer".as_ptr());
+            if class.is_null() {
+                return Err(io::Error::other(
+                    "Syphon.framework has no SyphonMetalServer",
+                ));
+            }
+            let ns_name = ns_string(name);
+            syphon.server = msg_send::<Init>()(
+                send(class, c"alloc"),
+                sel(c"initWithName:device:options:"),
+                ns_name,
+                device,
+                ptr::null_mut(),
+            );
+            release(ns_name);
+            if syphon.queue.is_null() || syphon.server.is_null() {
+                return Err(io::Error::other("failed to start the Syphon server"));
+            }
+            Ok(syphon)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Replaces the texture by one of `size`.
+    unsafe fn resize(&mut self, (width, height): (u32, u32)) {
+        type Descriptor = unsafe extern "C" fn(Id, Sel, usize, usize, usize, bool) -> Id;
+        type NewTexture = unsafe extern "C" fn(Id, Sel, Id) -> Id;
+        // SAFETY: `device` is valid and the descriptor is autoreleased into
+        // the pool of `publish`.
+        unsafe {
+            release(self.texture);
+            let descriptor = msg_send::<Descriptor>()(
+                objc_getClass(c"MTLTextureDescriptor".as_ptr()),
+                sel(c"texture2DDescriptorWithPixelFormat:width:height:mipmapped:"),
+                MTL_PIXEL_FORMAT_RGBA8_UNORM,
+                width as usize,
+                height as usize,
+                false,
+            );
+            self.texture = msg_send::<NewTexture>()(
+                self.device,
+                sel(c"newTextureWithDescriptor:"),
+                descriptor,
+            );
+        }
+        self.size = (width, height);
+    }
+}
+
+impl FrameSink for SyphonServer {
+    fn publish(&mut self, frame: &Frame) {
+        type Replace = unsafe extern "C" fn(Id, Sel, MtlRegion, usize, *const c_void, usize);
+        type Publish = unsafe extern "C" fn(Id, Sel, Id, Id, NsRect, bool);
+        if frame.pixels.is_empty() {
+            return;
+        }
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        self.buffer.clear();
+        PixelFormat::Rgba8.encode(frame.pixels, &mut self.buffer);
+        // SAFETY: the objects are valid, `buffer` holds `width * height`
+        // RGBA pixels, and the autoreleased objects are released with the
+        // pool.
+        unsafe {
+            let pool = objc_autoreleasePoolPush();
+            if self.size != (frame.width, frame.height) {
+                self.resize((frame.width, frame.height));
+            }
+            if self.texture.is_null() {
+                eprintln!("syphon: failed to create a {width}x{height} texture");
+            } else {
+                msg_send::<Replace>()(
+                    self.texture,
+                    sel(c"replaceRegion:mipmapLevel:withBytes:bytesPerRow:"),
+                    MtlRegion {
+                        origin: [0; 3],
+                        size: [width, height, 1],
+                    },
+                    0,
+                    self.buffer.as_ptr().cast(),
+                    width * 4,
+                );
+                let commands = send(self.queue, c"commandBuffer");
+                msg_send::<Publish>()(
+                    self.server,
+                    sel(c"publishFrameTexture:onCommandBuffer:imageRegion:flipped:"),
+                    self.texture,
+                    commands,
+                    NsRect {
+                        origin: [0.0; 2],
+                        size: [width as f64, height as f64],
+                    },
+                    false,
+                );
+                send(commands, c"commit");
+            }
+            objc_autoreleasePoolPop(pool);
+        }
+    }
+}
+
+impl Drop for SyphonServer {
+    fn drop(&mut self) {
+        // SAFETY: the objects are owned by `self` and released once.
+        unsafe {
+            if !self.server.is_null() {
+                send(self.server, c"stop");
+            }
+            release(self.server);
+            release(self.texture);
+            release(self.queue);
+            release(self.device);
+        }
+    }
+}
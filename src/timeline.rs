@@ -0,0 +1,204 @@
+//! Keyframed animation of the parameters, for choreographed sequences.
+//!
+//! The `timeline` of a scene lists keyframes, each setting some of the
+//! animated parameters at a time in seconds after the scene was applied:
+//!
+//! ```json
+//! "timeline": {
+//!     "loop": true,
+//!     "keyframes": [
+//!         { "time": 0, "gravity": 0.5, "palette": "ice" },
+//!         { "time": 10, "gravity": 4.0, "time_scale": 0.5, "easing": "ease_in_out" },
+//!         { "time": 12, "palette": "fire", "spawn_rate": 20000 },
+//!         { "time": 20, "gravity": 0.5, "time_scale": 1.0, "spawn_rate": 0 }
+//!     ]
+//! }
+//! ```
+//!
+//! Every parameter moves between the keyframes that set it, approaching a
+//! keyframe with its `easing`, and holds its value before its first and
+//! after its last keyframe. The palette switches at its keyframes. The
+//! spawn rate is the particles per second of every emitter with a `rate`.
+//! The clock is the frame time, so recordings of a sequence match its
+//! times.
+
+use serde::{Deserialize, Serialize};
+
+use crate::palette::Palette;
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeline {
+    /// Starts over after the last keyframe.
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    /// In any order.
+    pub keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keyframe {
+    /// Seconds after the start.
+    pub time: f32,
+    /// How the parameters approach the values of this keyframe.
+    pub easing: Easing,
+    pub gravity: Option<f32>,
+    pub time_scale: Option<f32>,
+    pub spawn_rate: Option<f32>,
+    pub palette: Option<Palette>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slowly.
+    EaseIn,
+    /// Ends slowly.
+    EaseOut,
+    /// Starts and ends slowly.
+    EaseInOut,
+    /// Holds the previous value until the keyframe.
+    Step,
+}
+
+impl Easing {
+    /// Progress at `t` from `0` to `1` of the way between two keyframes.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Easing::Step if t < 1.0 => 0.0,
+            Easing::Step => 1.0,
+        }
+    }
+}
+
+/// The parameters at a point of a timeline, `None` where no keyframe sets
+/// them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Values {
+    pub gravity: Option<f32>,
+    pub time_scale: Option<f32>,
+    pub spawn_rate: Option<f32>,
+    pub palette: Option<Palette>,
+}
+
+impl Timeline {
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes
+            .iter()
+            .map(|keyframe| keyframe.time)
+            .fold(0.0, f32::max)
+    }
+
+    pub fn at(&self, seconds: f32) -> Values {
+        let duration = self.duration();
+        let seconds = if self.looping && duration > 0.0 {
+            seconds.rem_euclid(duration)
+        } else {
+            seconds
+        };
+        let mut keyframes = self.keyframes.iter().collect::<Vec<_>>();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let interpolate = |value: fn(&Keyframe) -> Option<f32>| {
+            let mut set = keyframes
+                .iter()
+                .filter_map(|&keyframe| Some((keyframe, value(keyframe)?)));
+            let (mut before, mut from) = set.next()?;
+            if seconds <= before.time {
+                return Some(from);
+            }
+            for (after, to) in set {
+                if seconds < after.time {
+                    let t = (seconds - before.time) / (after.time - before.time);
+                    return Some(from + (to - from) * after.easing.apply(t));
+                }
+                (before, from) = (after, to);
+            }
+            Some(from)
+        };
+        Values {
+            gravity: interpolate(|keyframe| keyframe.gravity),
+            time_scale: interpolate(|keyframe| keyframe.time_scale),
+            spawn_rate: interpolate(|keyframe| keyframe.spawn_rate),
+            palette: keyframes
+                .iter()
+                .filter(|keyframe| keyframe.palette.is_some())
+                .take_while(|keyframe| keyframe.time <= seconds)
+                .last()
+                .or_else(|| keyframes.iter().find(|keyframe| keyframe.palette.is_some()))
+                .and_then(|keyframe| keyframe.palette),
+        }
+    }
+}
+
+/// A timeline being played.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    pub timeline: Timeline,
+    pub seconds: f32,
+}
+
+impl Playback {
+    pub fn new(timeline: Timeline) -> Self {
+        Playback {
+            timeline,
+            seconds: 0.0,
+        }
+    }
+
+    /// Advances by `seconds` and returns the values at the new time.
+    pub fn advance(&mut self, seconds: f32) -> Values {
+        self.seconds += seconds;
+        self.timeline.at(self.seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_keyframes() {
+        let docs = include_str!("timeline.rs")
+            .lines()
+            .skip_while(|line| !line.starts_with("//! ```json"))
+            .skip(1)
+            .take_while(|line| !line.starts_with("//! ```"))
+            .map(|line| line.trim_start_matches("//!"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let json = format!("{{{docs}}}");
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let timeline: Timeline = serde_json::from_value(value["timeline"].clone()).unwrap();
+        assert!(timeline.looping);
+        assert_eq!(timeline.duration(), 20.0);
+
+        let at = |seconds| timeline.at(seconds);
+        assert_eq!(at(0.0).gravity, Some(0.5));
+        // Halfway with ease-in-out is halfway.
+        assert_eq!(at(5.0).gravity, Some(2.25));
+        assert_eq!(at(2.0).time_scale, Some(0.5));
+        assert_eq!(at(15.0).time_scale, Some(0.75));
+        assert_eq!(at(11.0).spawn_rate, Some(20000.0));
+        assert_eq!(at(16.0).spawn_rate, Some(10000.0));
+        assert_eq!(at(11.9).palette, Some(Palette::Ice));
+        assert_eq!(at(12.0).palette, Some(Palette::Fire));
+        // Looped.
+        assert_eq!(at(25.0), at(5.0));
+        assert_eq!(Timeline::default().at(3.0), Values::default());
+
+        let mut playback = Playback::new(timeline);
+        playback.advance(4.0);
+        assert_eq!(playback.advance(1.0).gravity, Some(2.25));
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+        assert_eq!(Easing::EaseIn.apply(0.5), 0.25);
+    }
+}
@@ -0,0 +1,184 @@
+//! Fading trails behind the particles.
+//!
+//! `Trails` keeps the positions of the last `length` frames in a ring and
+//! draws line segments between consecutive positions into counts of their
+//! own, which the app colors like the particles and blends onto the frame
+//! as the trails layer, see `render::Layers`. Older
+//! segments are dithered more sparsely, which makes the trails fade out.
+//! To bound the memory, only the first blocks of particles get trails.
+
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use crate::particles::{Particle, narrow};
+use crate::scoped_threadpool::Pool;
+
+/// Longest segment in pixels that is drawn. Longer ones connect unrelated
+/// positions, e.g. after particles wrapped around or were removed.
+const MAX_SEGMENT: f32 = 64.0;
+
+/// Position history of the particles, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Trails {
+    length: usize,
+    max_blocks: usize,
+    /// `length` snapshots of the positions of up to `max_blocks` blocks,
+    /// as lane-interleaved x and y.
+    history: Vec<Vec<[f32; 2]>>,
+    /// Index of the newest snapshot in `history`.
+    head: usize,
+    /// Number of snapshots recorded so far, up to `length`.
+    filled: usize,
+}
+
+impl Trails {
+    /// Trails of `length` positions, using at most about `max_bytes` of
+    /// memory.
+    pub fn new(length: usize, max_bytes: usize) -> Self {
+        let length = length.max(2);
+        let block_bytes = 64 * size_of::<[f32; 2]>();
+        Trails {
+            length,
+            max_blocks: max_bytes / (length * block_bytes),
+            history: vec![Vec::new(); length],
+            head: 0,
+            filled: 0,
+        }
+    }
+
+    /// Number of positions per trail.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn clear(&mut self) {
+        self.filled = 0;
+    }
+
+    /// Appends the current positions of `particles`, dropping the oldest.
+    pub fn record(&mut self, particles: &[Particle]) {
+        self.head = (self.head + 1) % self.length;
+        self.filled = (self.filled + 1).min(self.length);
+        let snapshot = &mut self.history[self.head];
+        snapshot.clear();
+        for particle in particles.iter().take(self.max_blocks) {
+            let lanes = particle.x.as_array().iter().zip(particle.y.as_array());
+            // Inactive lanes leave no trail, like NaN positions.
+            snapshot.extend(lanes.enumerate().map(|(lane, (&x, &y))| {
+                if particle.is_active(lane) {
+                    [narrow(x), narrow(y)]
+                } else {
+                    [f32::NAN; 2]
+                }
+            }));
+        }
+    }
+
+    /// Adds the trails to the counts of a `width` x `height` buffer.
+    pub fn draw(&self, threadpool: &Pool, count_buffer: &[AtomicU16], width: u32, height: u32) {
+        let snapshots = (0..self.filled)
+            .map(|age| &self.history[(self.head + self.length - age) % self.length])
+            .collect::<Vec<_>>();
+        let Some(newest) = snapshots.first() else {
+            return;
+        };
+        let n_lanes = newest.len();
+        let chunk_len = usize::max(n_lanes / threadpool.thread_count() as usize / 4, 1);
+        // Segments per trail, the newest one is drawn solid.
+        let segments = self.length as u32 - 1;
+        threadpool.scoped(|scope| {
+            for start in (0..n_lanes).step_by(chunk_len) {
+                let snapshots = &snapshots;
+                scope.execute(move |_| {
+                    for lane in start..(start + chunk_len).min(n_lanes) {
+                        for (age, pair) in snapshots.windows(2).enumerate() {
+                            let (Some(&a), Some(&b)) = (pair[0].get(lane), pair[1].get(lane))
+                            else {
+                                break;
+                            };
+                            // Older segments keep fewer of their pixels.
+                            let keep = segments - age as u32;
+                            segment(a, b, |x, y| {
+                                if x < width && y < height && dither(x, y) % segments < keep {
+                                    count_buffer[(y * width + x) as usize]
+                                        .fetch_add(1, Ordering::Relaxed);
+                                }
+                            });
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// Calls `plot` for the pixels of the line from `a` to `b`, excluding `b`
+/// which the next segment starts at.
+fn segment(a: [f32; 2], b: [f32; 2], mut plot: impl FnMut(u32, u32)) {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let steps = dx.abs().max(dy.abs());
+    // Also skips segments to or from NaN positions.
+    if steps.is_nan() || steps > MAX_SEGMENT {
+        return;
+    }
+    let steps = steps.ceil().max(1.0);
+    for i in 0..steps as u32 {
+        let t = i as f32 / steps;
+        let (x, y) = (a[0] + dx * t, a[1] + dy * t);
+        if x >= 0.0 && y >= 0.0 {
+            plot(x as u32, y as u32);
+        }
+    }
+}
+
+/// Pseudo-random value per pixel.
+fn dither(x: u32, y: u32) -> u32 {
+    let h = (x ^ y.wrapping_mul(0x9E37_79B9)).wrapping_mul(0x85EB_CA6B);
+    (h ^ (h >> 15)).wrapping_mul(0xC2B2_AE35) >> 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::Real;
+    use crate::simd::Simd;
+
+    #[test]
+    fn draws_fading_trails() {
+        let pool = Pool::new(2);
+        let mut trails = Trails::new(4, 1 << 20);
+        let mut particle = Particle::ZERO;
+        particle.y += Simd::splat(2.0);
+        for x in [2.0, 10.0, 18.0, 26.0, 34.0] {
+            particle.x = Simd::splat(x);
+            trails.record(std::slice::from_ref(&particle));
+        }
+        let counts = (0..40 * 4).map(|_| AtomicU16::new(0)).collect::<Vec<_>>();
+        trails.draw(&pool, &counts, 40, 4);
+        let row = counts[80..120]
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        // The newest segment is solid, with every lane drawn on top.
+        assert!(row[27..=34].iter().all(|&count| count == 64));
+        // Older ones are sparser and the oldest position is forgotten.
+        let drawn = |range: std::ops::Range<usize>| row[range].iter().filter(|&&c| c > 0).count();
+        assert!(drawn(11..19) < 8 && drawn(11..19) <= drawn(19..27));
+        assert_eq!(drawn(0..10), 0);
+
+        // Jumps are not connected.
+        trails.clear();
+        counts
+            .iter()
+            .for_each(|count| count.store(0, Ordering::Relaxed));
+        particle.x = Simd::splat(0.0);
+        trails.record(std::slice::from_ref(&particle));
+        particle.x = Simd::splat(Real::NAN);
+        trails.record(std::slice::from_ref(&particle));
+        trails.draw(&pool, &counts, 40, 4);
+        assert!(
+            counts
+                .iter()
+                .all(|count| count.load(Ordering::Relaxed) == 0)
+        );
+    }
+}
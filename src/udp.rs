@@ -0,0 +1,126 @@
+//! UDP attractor feed.
+//!
+//! Every datagram describes the complete set of external attractors as
+//! text, one attractor per line as `x y strength` (separated by spaces or
+//! commas). Coordinates are normalized to the window, `0 0` being the top
+//! left and `1 1` the bottom right corner; negative strengths repel:
+//!
+//! ```text
+//! 0.25 0.5 1.0
+//! 0.75 0.5 -0.5
+//! ```
+//!
+//! Strengths are clamped to [`GRAVITY_RANGE`] and lines after the first
+//! [`MAX_ATTRACTORS`] are ignored.
+//!
+//! Senders are expected to stream updates continuously, the attractors
+//! expire after [`ATTRACTOR_TIMEOUT`](crate::command::ATTRACTOR_TIMEOUT)
+//! without a datagram.
+
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::thread::{self, JoinHandle};
+
+use crate::command::{GRAVITY_RANGE, MAX_ATTRACTORS, SimCommand};
+use crate::particles::Attractor;
+
+pub const DEFAULT_ADDR: &str = "0.0.0.0:9001";
+
+/// Binds `addr` and spawns a thread forwarding received attractors to
+/// `commands`. The thread exits once the receiving side is dropped.
+pub fn spawn_listener(
+    addr: impl ToSocketAddrs,
+    commands: Sender<SimCommand>,
+) -> io::Result<JoinHandle<()>> {
+    let socket = UdpSocket::bind(addr)?;
+    let handle = thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let Ok(len) = socket.recv(&mut buf) else {
+                continue;
+            };
+            let Some(attractors) = parse_attractors(&buf[..len]) else {
+                eprintln!("udp: invalid attractor packet");
+                continue;
+            };
+            if commands
+                .send(SimCommand::SetAttractors(attractors))
+                .is_err()
+            {
+                // The app was dropped.
+                return;
+            }
+        }
+    });
+    Ok(handle)
+}
+
+/// The attractors of `packet`, or `None` if a line is malformed or has a
+/// value that is not finite.
+fn parse_attractors(packet: &[u8]) -> Option<Vec<Attractor>> {
+    let text = std::str::from_utf8(packet).ok()?;
+    let (min, max) = GRAVITY_RANGE;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(MAX_ATTRACTORS)
+        .map(|line| {
+            let mut values = line
+                .split([' ', ',', '\t'])
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse::<f32>().ok().filter(|v| v.is_finite()));
+            let attractor = Attractor {
+                x: values.next()??,
+                y: values.next()??,
+                strength: values.next()??.clamp(min, max),
+            };
+            values.next().is_none().then_some(attractor)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lines() {
+        let attractors = parse_attractors(b"0.25 0.5 1\n0.75,0.5,-0.5\n\n").unwrap();
+        assert_eq!(
+            attractors,
+            [
+                Attractor {
+                    x: 0.25,
+                    y: 0.5,
+                    strength: 1.0
+                },
+                Attractor {
+                    x: 0.75,
+                    y: 0.5,
+                    strength: -0.5
+                },
+            ]
+        );
+        assert_eq!(parse_attractors(b""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(parse_attractors(b"0.5 0.5"), None);
+        assert_eq!(parse_attractors(b"0.5 0.5 1 2"), None);
+        assert_eq!(parse_attractors(b"0.5 NaN 1"), None);
+        assert_eq!(parse_attractors(b"a b c"), None);
+        assert_eq!(parse_attractors(b"0.5 inf 1"), None);
+    }
+
+    #[test]
+    fn clamps_and_truncates() {
+        let attractors = parse_attractors(b"0.5 0.5 1e9\n0.5 0.5 -1e9").unwrap();
+        assert_eq!(attractors[0].strength, GRAVITY_RANGE.1);
+        assert_eq!(attractors[1].strength, GRAVITY_RANGE.0);
+
+        let packet = "0.5 0.5 1\n".repeat(MAX_ATTRACTORS + 10);
+        let attractors = parse_attractors(packet.as_bytes()).unwrap();
+        assert_eq!(attractors.len(), MAX_ATTRACTORS);
+    }
+}
@@ -0,0 +1,84 @@
+//! Numbers as shown to the user.
+//!
+//! Values are rounded to three significant digits with a K, M or G suffix,
+//! e.g. `1.25M` particles, and always use a decimal point, whatever the
+//! locale, so screenshots and logs read the same everywhere.
+
+/// How frame times are shown, toggled in the app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameUnit {
+    #[default]
+    Millis,
+    Fps,
+}
+
+impl FrameUnit {
+    pub fn toggled(self) -> Self {
+        match self {
+            FrameUnit::Millis => FrameUnit::Fps,
+            FrameUnit::Fps => FrameUnit::Millis,
+        }
+    }
+}
+
+/// `value` with three significant digits and a K, M or G suffix.
+pub fn si(value: f32) -> String {
+    let magnitude = value.abs();
+    let (scaled, suffix) = [(1e9, "G"), (1e6, "M"), (1e3, "K")]
+        .into_iter()
+        // Rounding may carry into the next suffix, e.g. 999.7K.
+        .find(|&(scale, _)| magnitude >= scale * 0.9995)
+        .map_or((value, ""), |(scale, suffix)| (value / scale, suffix));
+    let decimals = match scaled.abs() {
+        x if x >= 99.95 => 0,
+        x if x >= 9.995 => 1,
+        _ => 2,
+    };
+    // Whole numbers below a thousand need no decimals.
+    if suffix.is_empty() && scaled.fract() == 0.0 {
+        return format!("{scaled}");
+    }
+    format!("{scaled:.decimals$}{suffix}")
+}
+
+/// A particle count, e.g. `1.25M`.
+pub fn count(n: usize) -> String {
+    si(n as f32)
+}
+
+/// The duration of a frame of `millis` milliseconds in `unit`.
+pub fn frametime(millis: f32, unit: FrameUnit) -> String {
+    match unit {
+        FrameUnit::Millis => format!("{millis:.1} ms"),
+        FrameUnit::Fps => format!("{:.0} fps", 1000.0 / millis),
+    }
+}
+
+/// The pull of the mouse attractor at `gravity`, which accelerates the
+/// particles by `gravity` pixels per 60 Hz frame, every frame.
+pub fn gravity(gravity: f32) -> String {
+    format!("{} px/s^2", si(gravity * 60.0 * 60.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_numbers() {
+        assert_eq!(count(0), "0");
+        assert_eq!(count(980), "980");
+        assert_eq!(count(1_234), "1.23K");
+        assert_eq!(count(12_345), "12.3K");
+        assert_eq!(count(999_700), "1.00M");
+        assert_eq!(count(1_250_000), "1.25M");
+        assert_eq!(count(3_000_000_000), "3.00G");
+        assert_eq!(si(0.25), "0.25");
+        assert_eq!(si(-1500.0), "-1.50K");
+
+        assert_eq!(frametime(16.667, FrameUnit::Millis), "16.7 ms");
+        assert_eq!(frametime(16.667, FrameUnit::Fps), "60 fps");
+        assert_eq!(FrameUnit::default().toggled(), FrameUnit::Fps);
+        assert_eq!(gravity(1.0), "3.60K px/s^2");
+    }
+}
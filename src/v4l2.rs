@@ -0,0 +1,188 @@
+//! Feeds the frames into a virtual camera.
+//!
+//! With `--v4l2 <device>` every frame is written to a v4l2loopback output
+//! device, e.g. the `OBS Virtual Camera` one created by
+//! `modprobe v4l2loopback video_nr=10 card_label="OBS Virtual Camera"`,
+//! so meeting and streaming apps can pick up the simulation as a webcam
+//! without capturing the screen.
+//!
+//! Readers cannot follow size changes, so the camera has a fixed size and
+//! the frames are scaled to it, nearest neighbour, and converted to YUYV,
+//! the 4:2:2 format every webcam consumer reads, with the BT.601 limited
+//! range coefficients.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::output::{Frame, FrameSink};
+
+/// Size of the camera unless set with `--v4l2-size`.
+pub const DEFAULT_SIZE: (u32, u32) = (1280, 720);
+
+const V4L2_BUF_TYPE_VIDEO_OUTPUT: u32 = 2;
+const V4L2_FIELD_NONE: u32 = 1;
+const V4L2_COLORSPACE_SRGB: u32 = 8;
+const V4L2_PIX_FMT_YUYV: u32 = u32::from_le_bytes(*b"YUYV");
+
+/// `struct v4l2_pix_format` of `linux/videodev2.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct PixFormat {
+    width: u32,
+    height: u32,
+    pixelformat: u32,
+    field: u32,
+    bytesperline: u32,
+    sizeimage: u32,
+    colorspace: u32,
+    private: u32,
+    flags: u32,
+    ycbcr_enc: u32,
+    quantization: u32,
+    xfer_func: u32,
+}
+
+/// `struct v4l2_format`.
+#[repr(C)]
+struct Format {
+    kind: u32,
+    fmt: FormatUnion,
+}
+
+/// The union of `struct v4l2_format`, 200 bytes aligned like the pointers
+/// of `struct v4l2_window`.
+#[repr(C)]
+union FormatUnion {
+    pix: PixFormat,
+    raw: [u8; 200],
+    _align: *const u8,
+}
+
+/// `_IOWR('V', 5, struct v4l2_format)`.
+const VIDIOC_S_FMT: u64 =
+    (3 << 30) | ((mem::size_of::<Format>() as u64) << 16) | (b'V' as u64) << 8 | 5;
+
+/// `FrameSink` writing every frame to a v4l2loopback device.
+pub struct VirtualCamera {
+    path: PathBuf,
+    device: File,
+    size: (u32, u32),
+    buffer: Vec<u8>,
+}
+
+impl VirtualCamera {
+    /// Opens `path` and sets it up as a camera of `size`, which must have
+    /// an even width.
+    pub fn open(path: impl AsRef<Path>, size: (u32, u32)) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let device = OpenOptions::new().write(true).open(&path)?;
+        let (width, height) = size;
+        let mut format = Format {
+            kind: V4L2_BUF_TYPE_VIDEO_OUTPUT,
+            fmt: FormatUnion { raw: [0; 200] },
+        };
+        format.fmt.pix = PixFormat {
+            width,
+            height,
+            pixelformat: V4L2_PIX_FMT_YUYV,
+            field: V4L2_FIELD_NONE,
+            bytesperline: width * 2,
+            sizeimage: width * height * 2,
+            colorspace: V4L2_COLORSPACE_SRGB,
+            ..PixFormat::default()
+        };
+        // SAFETY: `format` matches `struct v4l2_format` and outlives the
+        // call.
+        let result = unsafe { libc::ioctl(device.as_raw_fd(), VIDIOC_S_FMT as _, &mut format) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(VirtualCamera {
+            path,
+            device,
+            size,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl FrameSink for VirtualCamera {
+    fn publish(&mut self, frame: &Frame) {
+        self.buffer.clear();
+        yuyv(
+            frame.pixels,
+            (frame.width, frame.height),
+            self.size,
+            &mut self.buffer,
+        );
+        if let Err(err) = self.device.write_all(&self.buffer) {
+            eprintln!("v4l2: failed to write to {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Appends the `from` sized 0x00RRGGBB `pixels`, scaled to `to`, to `out`
+/// as YUYV: two pixels share their chroma, averaged, in four bytes.
+pub fn yuyv(pixels: &[u32], from: (u32, u32), to: (u32, u32), out: &mut Vec<u8>) {
+    let (width, height) = to;
+    out.reserve((width * height * 2) as usize);
+    if pixels.is_empty() || from.0 == 0 || from.1 == 0 {
+        // Black.
+        out.extend([16, 128].repeat((width * height) as usize));
+        return;
+    }
+    let at = |x: u32, y: u32| {
+        let (x, y) = (x * from.0 / width, y * from.1 / height);
+        let [_, r, g, b] = pixels[(y * from.0 + x) as usize].to_be_bytes();
+        [r as i32, g as i32, b as i32]
+    };
+    for y in 0..height {
+        for x in (0..width).step_by(2) {
+            let [r0, g0, b0] = at(x, y);
+            let [r1, g1, b1] = at((x + 1).min(width - 1), y);
+            let luma = |r: i32, g: i32, b: i32| ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+            let (r, g, b) = ((r0 + r1) / 2, (g0 + g1) / 2, (b0 + b1) / 2);
+            let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+            let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+            out.extend_from_slice(&[
+                luma(r0, g0, b0) as u8,
+                u as u8,
+                luma(r1, g1, b1) as u8,
+                v as u8,
+            ]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_yuyv() {
+        // White, black, red and blue, scaled up from 2x2 to 4x2.
+        let pixels = [0xFFFFFF, 0x000000, 0xFF0000, 0x0000FF];
+        let mut out = Vec::new();
+        yuyv(&pixels, (2, 2), (4, 2), &mut out);
+        assert_eq!(out.len(), 4 * 2 * 2);
+        assert_eq!(&out[..8], [235, 128, 235, 128, 16, 128, 16, 128]);
+        assert_eq!(&out[8..12], [82, 90, 82, 240]);
+        assert_eq!(&out[12..], [41, 240, 41, 110]);
+
+        let mut out = vec![1];
+        yuyv(&[], (0, 0), (2, 2), &mut out);
+        assert_eq!(out, [1, 16, 128, 16, 128, 16, 128, 16, 128]);
+
+        if cfg!(target_pointer_width = "64") {
+            assert_eq!(mem::size_of::<Format>(), 208);
+            assert_eq!(VIDIOC_S_FMT, 0xC0D0_5605);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Additional views of the simulation.
+//!
+//! A `View` shows a region of the particle world with its own palette, for
+//! example in a second window. It samples the counts of the main frame
+//! after the step, so the simulation still runs once per frame however
+//! many views are open, and `render_views` draws all of them at once.
+
+use crate::output::Density;
+use crate::palette::Palette;
+use crate::render::{self, Tone};
+use crate::scoped_threadpool::Pool;
+
+/// Smallest width or height of a viewport in world pixels.
+const MIN_VIEWPORT: f32 = 8.0;
+
+/// Region of the world in world pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    /// Scales the viewport by `factor`, keeping the world point at `anchor`
+    /// in place. `anchor` is given as a fraction of the viewport size.
+    pub fn zoom(self, anchor: (f32, f32), factor: f32) -> Self {
+        let width = (self.width * factor).max(MIN_VIEWPORT);
+        let height = (self.height * factor).max(MIN_VIEWPORT);
+        Viewport {
+            x: self.x + (self.width - width) * anchor.0,
+            y: self.y + (self.height - height) * anchor.1,
+            width,
+            height,
+        }
+    }
+}
+
+/// A view of the particle world, see the module documentation.
+#[derive(Debug, Default)]
+pub struct View {
+    /// Region shown, the whole world if `None`.
+    pub viewport: Option<Viewport>,
+    pub palette: Palette,
+    counts: Vec<u16>,
+}
+
+impl View {
+    pub fn new(palette: Palette) -> Self {
+        View {
+            palette,
+            ..View::default()
+        }
+    }
+
+    /// Region shown of a `width` x `height` world.
+    pub fn viewport(&self, width: u32, height: u32) -> Viewport {
+        self.viewport.unwrap_or(Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: width as f32,
+            height: height as f32,
+        })
+    }
+
+    /// Samples the viewport of the `world` sized `density` into `size`
+    /// counts, row by row, for `render::colorize`. Pixels outside of the
+    /// world are empty.
+    pub fn sample(
+        &mut self,
+        threadpool: &Pool,
+        density: Density,
+        world: (u32, u32),
+        size: (u32, u32),
+    ) -> &[u16] {
+        let sampler = self.sampler(world, size);
+        let w = size.0 as usize;
+        if w == 0 {
+            return &self.counts;
+        }
+        let rows_per_chunk =
+            usize::max(size.1 as usize / threadpool.thread_count() as usize / 10, 1);
+        threadpool.scoped(|scope| {
+            for (i_chunk, counts) in self.counts.chunks_mut(w * rows_per_chunk).enumerate() {
+                scope.execute(move |_| sampler.sample(density, i_chunk * rows_per_chunk, counts));
+            }
+        });
+        &self.counts
+    }
+
+    /// Sizes the counts for `size` and maps them onto the viewport.
+    fn sampler(&mut self, world: (u32, u32), size: (u32, u32)) -> Sampler {
+        let viewport = self.viewport(world.0, world.1);
+        self.counts.resize(size.0 as usize * size.1 as usize, 0);
+        Sampler {
+            viewport,
+            scale: (
+                viewport.width / size.0 as f32,
+                viewport.height / size.1 as f32,
+            ),
+            world,
+            width: size.0 as usize,
+        }
+    }
+}
+
+/// Maps the pixels of a view onto its viewport.
+#[derive(Clone, Copy)]
+struct Sampler {
+    viewport: Viewport,
+    scale: (f32, f32),
+    world: (u32, u32),
+    width: usize,
+}
+
+impl Sampler {
+    /// Samples the rows starting at `first_row` into `counts`.
+    fn sample(self, density: Density, first_row: usize, counts: &mut [u16]) {
+        let Sampler {
+            viewport,
+            scale,
+            world,
+            width,
+        } = self;
+        for (y, row) in (first_row..).zip(counts.chunks_mut(width)) {
+            let wy = (viewport.y + (y as f32 + 0.5) * scale.1).floor();
+            for (x, count) in row.iter_mut().enumerate() {
+                let wx = (viewport.x + (x as f32 + 0.5) * scale.0).floor();
+                let inside = wx >= 0.0 && wy >= 0.0 && wx < world.0 as f32 && wy < world.1 as f32;
+                *count = if inside {
+                    density.get(wy as usize * world.0 as usize + wx as usize)
+                } else {
+                    0
+                };
+            }
+        }
+    }
+}
+
+/// Samples and colorizes every view into the pixels of its size, with the
+/// `brightness` of the main window. The rows of all views are spread over
+/// the pool together, so the views are rendered concurrently.
+pub fn render_views(
+    threadpool: &Pool,
+    views: &mut [(&mut View, &mut [u32], (u32, u32))],
+    density: Density,
+    world: (u32, u32),
+    brightness: f32,
+) {
+    let n_rows = views
+        .iter()
+        .map(|(_, _, size)| size.1 as usize)
+        .sum::<usize>();
+    let rows_per_chunk = usize::max(n_rows / threadpool.thread_count() as usize / 10, 1);
+    threadpool.scoped(|scope| {
+        for (view, pixels, size) in views.iter_mut() {
+            let w = size.0 as usize;
+            if w == 0 {
+                continue;
+            }
+            let sampler = view.sampler(world, *size);
+            let tone = Tone::new(brightness, view.palette);
+            let chunks = view
+                .counts
+                .chunks_mut(w * rows_per_chunk)
+                .zip(pixels.chunks_mut(w * rows_per_chunk));
+            for (i_chunk, (counts, pixels)) in chunks.enumerate() {
+                let size = *size;
+                scope.execute(move |_| {
+                    let first_row = i_chunk * rows_per_chunk;
+                    sampler.sample(density, first_row, counts);
+                    render::colorize_chunk(first_row * w, counts, pixels, size, tone);
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_viewports() {
+        let pool = Pool::new(2);
+        let world = [1, 2, 3, 4, 5, 6, 7, 8_u8];
+        let density = Density::U8(&world);
+        let mut view = View::default();
+        // The whole 4 x 2 world, scaled down and up.
+        assert_eq!(view.sample(&pool, density, (4, 2), (2, 1)), [6, 8]);
+        assert_eq!(
+            view.sample(&pool, density, (4, 2), (8, 2))[..8],
+            [1, 1, 2, 2, 3, 3, 4, 4]
+        );
+        // Zoomed in on the right half, and panned out of the world.
+        let right = Viewport {
+            x: 2.0,
+            y: 0.0,
+            width: 2.0,
+            height: 2.0,
+        };
+        view.viewport = Some(right);
+        assert_eq!(view.sample(&pool, density, (4, 2), (2, 2)), [3, 4, 7, 8]);
+        view.viewport = Some(Viewport { x: 3.0, ..right });
+        assert_eq!(view.sample(&pool, density, (4, 2), (2, 2)), [4, 0, 8, 0]);
+
+        let zoomed = right.zoom((0.5, 0.0), 4.0);
+        assert_eq!(
+            zoomed,
+            Viewport {
+                x: -1.0,
+                y: 0.0,
+                width: 8.0,
+                height: 8.0
+            }
+        );
+    }
+
+    #[test]
+    fn renders_views_like_colorize() {
+        let pool = Pool::new(2);
+        let world = [0, 10, 20, 30, 40, 50, 60, 70_u8];
+        let density = Density::U8(&world);
+        let mut views = [View::new(Palette::Mono), View::new(Palette::Fire)];
+        views[1].viewport = Some(Viewport {
+            x: 1.0,
+            y: 0.0,
+            width: 2.0,
+            height: 2.0,
+        });
+        let sizes = [(4, 2), (3, 5)];
+        let mut pixels = sizes.map(|(w, h)| vec![0; (w * h) as usize]);
+        let expected = std::array::from_fn::<_, 2, _>(|i| {
+            let counts = views[i].sample(&pool, density, (4, 2), sizes[i]).to_vec();
+            let mut expected = vec![0; counts.len()];
+            let (width, height) = sizes[i];
+            let tone = Tone::new(2.0, views[i].palette);
+            render::colorize(&pool, &counts, &mut expected, width, height, tone);
+            expected
+        });
+
+        let [first, second] = &mut views;
+        let [first_pixels, second_pixels] = &mut pixels;
+        render_views(
+            &pool,
+            &mut [
+                (first, first_pixels, sizes[0]),
+                (second, second_pixels, sizes[1]),
+            ],
+            density,
+            (4, 2),
+            2.0,
+        );
+        assert_eq!(pixels, expected);
+    }
+}
@@ -0,0 +1,142 @@
+//! Watchdog of the frame loop.
+//!
+//! Kiosk deployments must not freeze silently. The app calls
+//! `Watchdog::beat` every frame, and a thread checks that the beats keep
+//! coming. Once none came for `timeout`, it calls `recover`, which in the
+//! app wakes the event loop to recreate the surface and request a redraw,
+//! and repeats that every `timeout` while the stall lasts.
+//!
+//! A loop that is blocked for good, e.g. in a deadlocked present or on a
+//! worker stuck in a runaway job, cannot be helped from inside the
+//! process. After `GIVE_UP_AFTER` timeouts the watchdog calls `give_up`,
+//! which in the app exits with `EXIT_CODE`, so a supervisor such as
+//! systemd restarts it, continuing from the `--autosave` file with
+//! `--resume`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Number of timeouts without a frame before the watchdog gives up.
+pub const GIVE_UP_AFTER: u32 = 6;
+/// Exit code of the app when the watchdog gave up.
+pub const EXIT_CODE: i32 = 3;
+
+/// Handle to the watchdog thread, see the module documentation. Dropping
+/// it stops the thread.
+pub struct Watchdog {
+    started: Instant,
+    /// Nanoseconds from `started` to the last beat.
+    beat: Arc<AtomicU64>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn spawn(
+        timeout: Duration,
+        recover: impl Fn() + Send + 'static,
+        give_up: impl FnOnce() + Send + 'static,
+    ) -> Self {
+        let started = Instant::now();
+        let beat = Arc::new(AtomicU64::new(0));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::Builder::new()
+            .name("watchdog".into())
+            .spawn({
+                let beat = Arc::clone(&beat);
+                move || {
+                    // Timeouts of the current stall that were acted upon.
+                    let mut handled = 0;
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(timeout / 4) {
+                        let last = started + Duration::from_nanos(beat.load(Ordering::Relaxed));
+                        let stalled = last.elapsed();
+                        let timeouts = stalled.div_duration_f32(timeout) as u32;
+                        if timeouts >= GIVE_UP_AFTER {
+                            eprintln!(
+                                "watchdog: no frame for {:.1} s, giving up",
+                                stalled.as_secs_f32()
+                            );
+                            give_up();
+                            return;
+                        } else if timeouts > handled {
+                            eprintln!(
+                                "watchdog: no frame for {:.1} s, recovering",
+                                stalled.as_secs_f32()
+                            );
+                            recover();
+                        }
+                        handled = timeouts;
+                    }
+                }
+            })
+            .expect("failed to spawn the watchdog thread");
+        Watchdog {
+            started,
+            beat,
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Tells the watchdog that the loop is alive.
+    pub fn beat(&self) {
+        let nanos = self.started.elapsed().as_nanos() as u64;
+        self.beat.store(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+
+    #[test]
+    fn recovers_and_gives_up() {
+        let recovered = Arc::new(AtomicUsize::new(0));
+        let gave_up = Arc::new(AtomicBool::new(false));
+        let timeout = Duration::from_millis(100);
+        let watchdog = Watchdog::spawn(
+            timeout,
+            {
+                let recovered = Arc::clone(&recovered);
+                move || {
+                    recovered.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+            {
+                let gave_up = Arc::clone(&gave_up);
+                move || gave_up.store(true, Ordering::Relaxed)
+            },
+        );
+        // Beating keeps it quiet.
+        for _ in 0..8 {
+            watchdog.beat();
+            thread::sleep(timeout / 8);
+        }
+        assert_eq!(recovered.load(Ordering::Relaxed), 0);
+
+        // A short stall is recovered from.
+        thread::sleep(timeout * 3);
+        watchdog.beat();
+        let recoveries = recovered.load(Ordering::Relaxed);
+        assert!(recoveries >= 1);
+        assert!(!gave_up.load(Ordering::Relaxed));
+
+        // A long one is not.
+        thread::sleep(timeout * (GIVE_UP_AFTER + 3));
+        assert!(gave_up.load(Ordering::Relaxed));
+        assert!(recovered.load(Ordering::Relaxed) > recoveries);
+    }
+}
@@ -0,0 +1,211 @@
+//! A logo in a corner of the frame.
+//!
+//! With `--watermark <png>` the app blends a PNG, e.g. a logo with an alpha
+//! channel, into a corner of every frame, for streams and talks. It goes
+//! onto the frame before the sinks, so recordings and streams carry it as
+//! well, and below the stats and the help.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::render::{self, Blend, BlendMode};
+
+/// Distance in pixels from the edges of the frame.
+pub const MARGIN: u32 = 16;
+
+/// Most bytes a logo may take decoded, which keeps crafted files from
+/// exhausting the memory.
+pub const MAX_DECODED: usize = 64 << 20;
+
+/// Corner of the frame the watermark is placed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("unknown corner `{s}`")),
+        }
+    }
+}
+
+/// A decoded PNG, row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Logo {
+    pub width: u32,
+    pub height: u32,
+    /// 0xAARRGGBB, the alpha not premultiplied.
+    pub pixels: Vec<u32>,
+}
+
+impl Logo {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::decode(BufReader::new(File::open(path)?))
+    }
+
+    /// Decodes a PNG of any color type and bit depth, the palettes and
+    /// transparent colors expanded to alpha.
+    pub fn decode(reader: impl BufRead + Seek) -> io::Result<Self> {
+        let limits = png::Limits { bytes: MAX_DECODED };
+        let mut decoder = png::Decoder::new_with_limits(reader, limits);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let len = reader
+            .output_buffer_size()
+            .filter(|&len| len <= MAX_DECODED)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "image is too large"))?;
+        let mut buffer = vec![0; len];
+        let info = reader.next_frame(&mut buffer)?;
+        let channels = info.color_type.samples();
+        let row_len = info.width as usize * channels;
+        let pixels = buffer
+            .chunks_exact(info.line_size)
+            .take(info.height as usize)
+            .flat_map(|row| row[..row_len].chunks_exact(channels))
+            .map(|pixel| {
+                let [r, g, b, a] = match *pixel {
+                    [l] => [l, l, l, 0xFF],
+                    [l, a] => [l, l, l, a],
+                    [r, g, b] => [r, g, b, 0xFF],
+                    [r, g, b, a] => [r, g, b, a],
+                    _ => unreachable!("PNGs have one to four channels"),
+                };
+                u32::from_be_bytes([a, r, g, b])
+            })
+            .collect();
+        Ok(Logo {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    pub image: Logo,
+    pub corner: Corner,
+    /// Scales the alpha of the image, from `0` to `1`.
+    pub opacity: f32,
+}
+
+impl Watermark {
+    pub fn load(path: impl AsRef<Path>, corner: Corner, opacity: f32) -> io::Result<Self> {
+        Ok(Watermark {
+            image: Logo::load(path)?,
+            corner,
+            opacity,
+        })
+    }
+
+    /// Blends the image onto the `width` x `height` 0x00RRGGBB `pixels`,
+    /// clipped where the frame is too small.
+    pub fn draw(&self, pixels: &mut [u32], width: u32, height: u32) {
+        let (w, h) = (self.image.width, self.image.height);
+        let right = width.saturating_sub(w + MARGIN);
+        let bottom = height.saturating_sub(h + MARGIN);
+        let (left, top) = match self.corner {
+            Corner::TopLeft => (MARGIN, MARGIN),
+            Corner::TopRight => (right, MARGIN),
+            Corner::BottomLeft => (MARGIN, bottom),
+            Corner::BottomRight => (right, bottom),
+        };
+        if left >= width || w == 0 {
+            return;
+        }
+        let columns = w.min(width - left) as usize;
+        let blend = Blend {
+            mode: BlendMode::Over,
+            opacity: self.opacity,
+        };
+        for (row, y) in self.image.pixels.chunks(w as usize).zip(top..height) {
+            let start = (y * width + left) as usize;
+            render::blend_pixels(&row[..columns], &mut pixels[start..start + columns], blend);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_in_corners() {
+        let mut watermark = Watermark {
+            image: Logo {
+                width: 2,
+                height: 2,
+                pixels: vec![0xFFFF0000, 0x8000FF00, 0x00FFFFFF, 0xFF0000FF],
+            },
+            corner: "bottom-right".parse().unwrap(),
+            opacity: 1.0,
+        };
+        let (width, height) = (MARGIN + 4, MARGIN + 4);
+        let mut pixels = vec![0; (width * height) as usize];
+        watermark.draw(&mut pixels, width, height);
+        let at = |pixels: &[u32], x: u32, y: u32| pixels[(y * width + x) as usize];
+        assert_eq!(at(&pixels, 2, 2), 0xFF0000);
+        assert_eq!(at(&pixels, 3, 2), 0x007F00);
+        assert_eq!(at(&pixels, 2, 3), 0x000000);
+        assert_eq!(at(&pixels, 3, 3), 0x0000FF);
+        assert_eq!(pixels.iter().filter(|&&pixel| pixel != 0).count(), 3);
+
+        // Clipped at the edges of a small frame, and faded.
+        watermark.corner = Corner::TopLeft;
+        watermark.opacity = 0.5;
+        let mut pixels = vec![0; (width * height) as usize];
+        watermark.draw(&mut pixels, MARGIN + 1, MARGIN + 1);
+        assert_eq!(pixels[(MARGIN * (MARGIN + 1) + MARGIN) as usize], 0x7F0000);
+        assert_eq!(pixels.iter().filter(|&&pixel| pixel != 0).count(), 1);
+        assert!("middle".parse::<Corner>().is_err());
+    }
+
+    fn encode(width: u32, height: u32, color: png::ColorType, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn decodes_pngs() {
+        let rgba = encode(2, 1, png::ColorType::Rgba, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let logo = Logo::decode(io::Cursor::new(rgba)).unwrap();
+        assert_eq!((logo.width, logo.height), (2, 1));
+        assert_eq!(logo.pixels, [0x04010203, 0x08050607]);
+
+        let gray = encode(1, 2, png::ColorType::Grayscale, &[0x10, 0xFF]);
+        let logo = Logo::decode(io::Cursor::new(gray)).unwrap();
+        assert_eq!(logo.pixels, [0xFF101010, 0xFFFFFFFF]);
+
+        assert!(Logo::decode(io::Cursor::new(b"GIF89a")).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_pngs() {
+        // Claims 60000 x 60000 RGBA pixels, 14 GB decoded.
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 60_000, 60_000);
+        encoder.set_color(png::ColorType::Rgba);
+        let writer = encoder.write_header().unwrap();
+        drop(writer);
+        assert!(Logo::decode(io::Cursor::new(bytes)).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! WebSocket remote control.
+//!
+//! Clients connect to `ws://<addr>/` and send `SimCommand`s as JSON text
+//! messages, e.g. from a phone during a live performance:
+//!
+//! ```json
+//! { "set_gravity": 2.5 }
+//! { "set_palette": 1 }
+//! { "spawn": 100000 }
+//! { "set_attractors": [{ "x": 0.5, "y": 0.5, "strength": 1.0 }] }
+//! ```
+//!
+//! The app clamps the values and ignores non-finite ones, see
+//! `SimCommand::validated`.
+//!
+//! Every `STATS_INTERVAL` the server sends the current `Stats` back as
+//! `{ "stats": { ... } }`, and answers messages it cannot parse with
+//! `{ "error": "..." }`.
+//!
+//! The protocol is handled by tungstenite, which limits messages to
+//! `MAX_MESSAGE` bytes.
+//!
+//! Plain HTTP requests for `/` get `PANEL`, a page with sliders for the
+//! parameters that follow the stats, so anyone on the LAN can control the
+//! simulation from a browser at `http://<addr>/`. Requests longer than
+//! `MAX_REQUEST` bytes, or slower than `REQUEST_TIMEOUT`, are dropped.
+
+use std::io::{self, Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+use crate::command::SimCommand;
+
+pub const DEFAULT_ADDR: &str = "0.0.0.0:9002";
+
+/// Time between the stats sent to every client.
+pub const STATS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Most clients served at the same time, further connections are closed
+/// right away.
+pub const MAX_CLIENTS: usize = 16;
+
+/// Longest message accepted from a client.
+pub const MAX_MESSAGE: usize = 64 * 1024;
+
+/// Longest request head, up to the empty line, accepted from a client.
+pub const MAX_REQUEST: usize = 8 * 1024;
+
+/// Time a client has to send its request head.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The control page, see the module documentation.
+pub const PANEL: &str = include_str!("panel.html");
+
+/// State of the simulation reported to the clients.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub fps: f32,
+    pub particles: usize,
+    pub gravity: f32,
+    pub friction: f32,
+    pub time_scale: f32,
+    pub brightness: f32,
+    /// Index of the palette, see `Palette::from_index`.
+    pub palette: usize,
+}
+
+/// The latest `Stats`, shared between the app and the client threads.
+#[derive(Debug, Default)]
+pub struct StatsFeed {
+    stats: Mutex<Stats>,
+}
+
+impl StatsFeed {
+    pub fn set(&self, stats: Stats) {
+        *self.stats.lock().unwrap() = stats;
+    }
+
+    pub fn get(&self) -> Stats {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+/// Binds `addr` and spawns a thread accepting up to `MAX_CLIENTS` clients,
+/// each served on a thread of its own, which forward their commands to
+/// `commands`.
+pub fn spawn_server(
+    addr: impl ToSocketAddrs,
+    commands: Sender<SimCommand>,
+    stats: Arc<StatsFeed>,
+) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    let handle = thread::spawn(move || {
+        let clients = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if clients.fetch_add(1, Ordering::Relaxed) >= MAX_CLIENTS {
+                clients.fetch_sub(1, Ordering::Relaxed);
+                eprintln!("websocket: too many clients");
+                continue;
+            }
+            let (commands, stats) = (commands.clone(), Arc::clone(&stats));
+            let clients = Arc::clone(&clients);
+            thread::spawn(move || {
+                if let Err(err) = serve(stream, commands, stats) {
+                    eprintln!("websocket: {err}");
+                }
+                clients.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+    Ok(handle)
+}
+
+fn serve(
+    mut stream: TcpStream,
+    commands: Sender<SimCommand>,
+    stats: Arc<StatsFeed>,
+) -> io::Result<()> {
+    let head = read_head(&mut stream)?;
+    let (path, upgrade) = parse_head(&head);
+    if !upgrade {
+        let (status, content_type, body) = match path.as_str() {
+            "/" | "/index.html" => ("200 OK", "text/html; charset=utf-8", PANEL),
+            _ => ("404 Not Found", "text/plain", "not found, try /\n"),
+        };
+        return write!(
+            &stream,
+            "HTTP/1.1 {status}\r\n\
+             Content-Type: {content_type}\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{body}",
+            body.len()
+        );
+    }
+
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE))
+        .max_frame_size(Some(MAX_MESSAGE));
+    let stream = Prefixed {
+        prefix: Cursor::new(head),
+        stream,
+    };
+    let mut socket = tungstenite::accept_with_config(stream, Some(config))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    // Reads time out so that the stats go out while the client is quiet.
+    socket
+        .get_ref()
+        .stream
+        .set_read_timeout(Some(STATS_INTERVAL))?;
+    match talk(&mut socket, &commands, &stats) {
+        Ok(()) | Err(tungstenite::Error::ConnectionClosed) => Ok(()),
+        Err(tungstenite::Error::Io(err)) => Err(err),
+        Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+    }
+}
+
+/// Forwards the commands of the client and sends it the stats every
+/// `STATS_INTERVAL`, until either side is gone.
+fn talk(
+    socket: &mut WebSocket<Prefixed>,
+    commands: &Sender<SimCommand>,
+    stats: &StatsFeed,
+) -> tungstenite::Result<()> {
+    let mut next_stats = Instant::now();
+    loop {
+        if Instant::now() >= next_stats {
+            let message = serde_json::json!({ "stats": stats.get() }).to_string();
+            socket.send(Message::text(message))?;
+            next_stats += STATS_INTERVAL;
+        }
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            // tungstenite answers pings and closes by itself.
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        match serde_json::from_str::<SimCommand>(&text) {
+            Ok(command) => {
+                if commands.send(command).is_err() {
+                    // The app was dropped.
+                    return socket.close(None);
+                }
+            }
+            Err(err) => {
+                let message = serde_json::json!({ "error": err.to_string() }).to_string();
+                socket.send(Message::text(message))?;
+            }
+        }
+    }
+}
+
+/// Reads from `stream` up to the end of the request head, at most
+/// `MAX_REQUEST` bytes within `REQUEST_TIMEOUT`. The result may hold bytes
+/// following the head.
+fn read_head(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request is too long",
+            ));
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request is too slow",
+            ));
+        }
+        stream.set_read_timeout(Some(remaining))?;
+        match stream.read(&mut buffer)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => head.extend_from_slice(&buffer[..n]),
+        }
+    }
+    Ok(head)
+}
+
+/// The path of the request `head` and whether it asks for a WebSocket.
+fn parse_head(head: &[u8]) -> (String, bool) {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+    let path = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("/")
+        .to_string();
+    let upgrade = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("upgrade")
+                && value.trim().eq_ignore_ascii_case("websocket")
+        });
+    (path, upgrade)
+}
+
+/// `stream` with the bytes of `read_head` in front, for tungstenite to
+/// read the request again.
+struct Prefixed {
+    prefix: Cursor<Vec<u8>>,
+    stream: TcpStream,
+}
+
+impl Read for Prefixed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.prefix.read(buf)? {
+            0 => self.stream.read(buf),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for Prefixed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn controls_over_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let (tx, rx) = mpsc::channel();
+        let stats = Arc::new(StatsFeed::default());
+        stats.set(Stats {
+            particles: 1000,
+            ..Stats::default()
+        });
+        spawn_server(addr, tx, stats).unwrap();
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let panel = get("/");
+        assert!(panel.starts_with("HTTP/1.1 200 OK"));
+        assert!(panel.ends_with(PANEL));
+        assert!(get("/favicon.ico").starts_with("HTTP/1.1 404"));
+
+        // A request without end is dropped once it is too long.
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let _ = stream.write_all(&[b'a'; 2 * MAX_REQUEST]);
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        assert!(response.is_empty());
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let (mut socket, _) = tungstenite::client(format!("ws://{addr}/"), stream).unwrap();
+        let read_json = |socket: &mut WebSocket<TcpStream>| {
+            let text = socket.read().unwrap().into_text().unwrap();
+            serde_json::from_str::<serde_json::Value>(&text).unwrap()
+        };
+        assert_eq!(read_json(&mut socket)["stats"]["particles"], 1000);
+
+        socket
+            .send(Message::text(r#"{ "set_gravity": 2.5 }"#))
+            .unwrap();
+        assert_eq!(rx.recv().unwrap(), SimCommand::SetGravity(2.5));
+        socket.send(Message::text(r#"{ "fly": true }"#)).unwrap();
+        let error = loop {
+            if let Some(error) = read_json(&mut socket).get("error") {
+                break error.clone();
+            }
+        };
+        assert!(error.as_str().unwrap().contains("fly"));
+        socket.close(None).unwrap();
+        loop {
+            match socket.read() {
+                Ok(_) => {}
+                Err(tungstenite::Error::ConnectionClosed) => break,
+                Err(err) => panic!("{err}"),
+            }
+        }
+    }
+}